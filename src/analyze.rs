@@ -0,0 +1,94 @@
+use crate::{
+    handler::arangodb::DB,
+    settings::{CommunityAlgorithm, CommunitySettings},
+};
+use anyhow::{anyhow, bail, Result};
+use arangors::client::ClientExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// Pregel job status, see the ArangoDB `_api/control_pregel` docs
+#[derive(Debug, Deserialize)]
+pub struct PregelStatus {
+    pub state: String,
+    #[serde(default)]
+    pub gss: u64,
+    #[serde(rename = "totalRuntime", default)]
+    pub total_runtime: f64,
+}
+
+/// Run a Pregel community detection job over the hotspot graph and wait for it to
+/// complete. Pregel stores each hotspot's community id in its `result_field`,
+/// unless `dry_run`, which only runs the job.
+pub async fn detect_communities(
+    db: &DB,
+    settings: &CommunitySettings,
+    dry_run: bool,
+) -> Result<PregelStatus> {
+    let job_id = start_pregel(db, settings, dry_run).await?;
+    tracing::info!(
+        "started pregel {:?} job {}",
+        pregel_algorithm(settings.algorithm),
+        job_id
+    );
+
+    let poll = Duration::from_secs(settings.poll_secs);
+    loop {
+        tokio::time::sleep(poll).await;
+        let status = pregel_status(db, &job_id).await?;
+        match status.state.as_str() {
+            "done" => return Ok(status),
+            "canceled" | "fatal error" => bail!("pregel job {job_id} {}", status.state),
+            state => tracing::info!("pregel job {} {}, gss: {}", job_id, state, status.gss),
+        }
+    }
+}
+
+async fn start_pregel(db: &DB, settings: &CommunitySettings, dry_run: bool) -> Result<String> {
+    let mut params = json!({
+        "store": !dry_run,
+        "resultField": settings.result_field,
+        "maxGSS": settings.max_gss,
+    });
+    if let Some(parallelism) = settings.parallelism {
+        params["parallelism"] = json!(parallelism);
+    }
+    let body = json!({
+        "algorithm": pregel_algorithm(settings.algorithm),
+        "vertexCollections": [db.names.hotspots],
+        "edgeCollections": [db.names.witnesses],
+        "params": params,
+    });
+
+    let inner = db.inner();
+    let url = inner.url().join("_api/control_pregel")?;
+    let resp = inner.session().post(url, &body.to_string()).await?;
+    if !resp.status().is_success() {
+        bail!("failed to start pregel job: {}", resp.body());
+    }
+    // the job id is returned as a bare json string (or number on older versions)
+    match serde_json::from_str::<Value>(resp.body())? {
+        Value::String(id) => Ok(id),
+        Value::Number(id) => Ok(id.to_string()),
+        other => Err(anyhow!("unexpected pregel job id: {other}")),
+    }
+}
+
+async fn pregel_status(db: &DB, job_id: &str) -> Result<PregelStatus> {
+    let inner = db.inner();
+    let url = inner.url().join(&format!("_api/control_pregel/{job_id}"))?;
+    let resp = inner.session().get(url, "").await?;
+    if !resp.status().is_success() {
+        bail!("failed to get pregel job {job_id} status: {}", resp.body());
+    }
+    Ok(serde_json::from_str(resp.body())?)
+}
+
+fn pregel_algorithm(algorithm: CommunityAlgorithm) -> &'static str {
+    match algorithm {
+        CommunityAlgorithm::LabelPropagation => "labelpropagation",
+        CommunityAlgorithm::Wcc => "wcc",
+        CommunityAlgorithm::Scc => "scc",
+    }
+}
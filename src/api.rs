@@ -0,0 +1,126 @@
+use crate::handler::arangodb::{DBError, DB};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use helium_crypto::PublicKeyBinary;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{str::FromStr, sync::Arc};
+
+/// Witness edges returned by default, and at most
+const DEFAULT_WITNESS_LIMIT: u32 = 100;
+const MAX_WITNESS_LIMIT: u32 = 1000;
+
+/// Read-only REST API over the hotspot graph, see the serve command
+pub fn router(db: Arc<DB>) -> Router {
+    Router::new()
+        .route("/hotspots/:pub_key", get(hotspot))
+        .route("/hotspots/:pub_key/witnesses", get(hotspot_witnesses))
+        .route("/beacons/:poc_id", get(beacon))
+        .with_state(db)
+}
+
+/// Error response, a json `{"error": ...}` body
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn not_found(what: &str) -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND,
+            message: format!("{what} not found"),
+        }
+    }
+}
+
+impl From<DBError> for ApiError {
+    fn from(err: DBError) -> Self {
+        tracing::error!("api query failed: {:?}", err);
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: "query failed".to_string(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(json!({ "error": self.message }))).into_response()
+    }
+}
+
+type ApiResult = Result<Json<Value>, ApiError>;
+
+/// Hotspot document key of a b58 pub_key path segment
+fn hotspot_key(db: &DB, pub_key: &str) -> Result<String, ApiError> {
+    let pub_key = PublicKeyBinary::from_str(pub_key).map_err(|_| ApiError {
+        status: StatusCode::BAD_REQUEST,
+        message: format!("invalid pub_key {pub_key:?}"),
+    })?;
+    Ok(db.hotspot_key(&pub_key))
+}
+
+async fn hotspot(State(db): State<Arc<DB>>, Path(pub_key): Path<String>) -> ApiResult {
+    let key = hotspot_key(&db, &pub_key)?;
+    db.get_document(&db.names.hotspots, &key)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found("hotspot"))
+}
+
+#[derive(Debug, Deserialize)]
+struct WitnessesQuery {
+    limit: Option<u32>,
+}
+
+/// Edge limit of a witnesses request, the default if unset
+fn witness_limit(limit: Option<u32>) -> u32 {
+    limit
+        .unwrap_or(DEFAULT_WITNESS_LIMIT)
+        .clamp(1, MAX_WITNESS_LIMIT)
+}
+
+async fn hotspot_witnesses(
+    State(db): State<Arc<DB>>,
+    Path(pub_key): Path<String>,
+    Query(query): Query<WitnessesQuery>,
+) -> ApiResult {
+    let key = hotspot_key(&db, &pub_key)?;
+    let edges = db
+        .get_hotspot_witnesses(&key, witness_limit(query.limit))
+        .await?;
+    Ok(Json(Value::Array(edges)))
+}
+
+async fn beacon(State(db): State<Arc<DB>>, Path(poc_id): Path<String>) -> ApiResult {
+    db.get_document(&db.names.beacons, &poc_id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found("beacon"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn witness_limits() {
+        assert_eq!(witness_limit(None), DEFAULT_WITNESS_LIMIT);
+        assert_eq!(witness_limit(Some(0)), 1);
+        assert_eq!(witness_limit(Some(25)), 25);
+        assert_eq!(witness_limit(Some(100_000)), MAX_WITNESS_LIMIT);
+    }
+
+    #[test]
+    fn error_status() {
+        let response = ApiError::not_found("hotspot").into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
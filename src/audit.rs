@@ -0,0 +1,101 @@
+use crate::{
+    document::{iot_poc_file::worker_id, timestamp::unix_ms},
+    handler::arangodb::DB,
+    settings::ArangoDBSettings,
+};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+
+/// Operator-initiated CLI action, stored in the audit_log collection
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuditEntry {
+    /// Subcommand, e.g. `purge`
+    pub action: String,
+    /// Full command line, without the binary
+    pub args: Vec<String>,
+    /// OS user running the command, if known
+    pub user: Option<String>,
+    /// Host and pid, see `worker_id`
+    pub worker_id: String,
+    pub started_unix_ms: i64,
+    pub duration_ms: i64,
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn new(
+        action: &str,
+        args: Vec<String>,
+        started: DateTime<Utc>,
+        finished: DateTime<Utc>,
+        result: &Result<()>,
+    ) -> Self {
+        Self {
+            action: action.to_string(),
+            args,
+            user: std::env::var("USER").ok(),
+            worker_id: worker_id().to_string(),
+            started_unix_ms: unix_ms(started),
+            duration_ms: (finished - started).num_milliseconds(),
+            ok: result.is_ok(),
+            error: result.as_ref().err().map(|err| format!("{err:#}")),
+        }
+    }
+}
+
+/// Run the `action` command, then record it in the audit_log collection. The
+/// command's result is returned as is, failing to record it is only logged.
+pub async fn audited(
+    settings: &ArangoDBSettings,
+    action: &str,
+    cmd: impl Future<Output = Result<()>>,
+) -> Result<()> {
+    let started = Utc::now();
+    let result = cmd.await;
+    let entry = AuditEntry::new(
+        action,
+        std::env::args().skip(1).collect(),
+        started,
+        Utc::now(),
+        &result,
+    );
+    if let Err(err) = record(settings, &entry).await {
+        tracing::warn!("failed to record {} in the audit log: {:?}", action, err);
+    }
+    result
+}
+
+async fn record(settings: &ArangoDBSettings, entry: &AuditEntry) -> Result<()> {
+    let db = DB::from_settings(settings).await?;
+    db.record_audit_entry(entry).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn entry_result() {
+        let started = Utc.timestamp_millis_opt(1_687_888_130_980).unwrap();
+        let finished = started + Duration::milliseconds(1500);
+        let args = vec!["purge".to_string(), "--before".to_string()];
+
+        let entry = AuditEntry::new("purge", args.clone(), started, finished, &Ok(()));
+        assert!(entry.ok && entry.error.is_none());
+        assert_eq!(entry.duration_ms, 1500);
+        assert_eq!(entry.started_unix_ms, 1_687_888_130_980);
+
+        let failed = Err(anyhow::anyhow!("connection refused").context("purging beacons"));
+        let entry = AuditEntry::new("purge", args, started, finished, &failed);
+        assert!(!entry.ok);
+        assert_eq!(
+            entry.error.as_deref(),
+            Some("purging beacons: connection refused")
+        );
+    }
+}
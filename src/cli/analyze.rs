@@ -0,0 +1,53 @@
+use crate::{
+    analyze,
+    handler::arangodb::DB,
+    settings::{CommunityAlgorithm, Settings},
+};
+use anyhow::Result;
+
+#[derive(Debug, clap::Args)]
+pub struct Cmd {
+    #[clap(subcommand)]
+    analysis: Analysis,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum Analysis {
+    /// Detect hotspot communities with ArangoDB Pregel, stored on each hotspot
+    Communities(CommunitiesArgs),
+}
+
+#[derive(Debug, clap::Args)]
+pub struct CommunitiesArgs {
+    /// Algorithm to run, default: communities.algorithm setting
+    #[clap(long, value_enum)]
+    algorithm: Option<CommunityAlgorithm>,
+}
+
+impl Cmd {
+    pub async fn run(&self, settings: &Settings) -> Result<()> {
+        let db = DB::from_settings(&settings.arangodb).await?;
+        match &self.analysis {
+            Analysis::Communities(args) => {
+                let mut community_settings = settings.communities.clone();
+                if let Some(algorithm) = args.algorithm {
+                    community_settings.algorithm = algorithm;
+                }
+                let status =
+                    analyze::detect_communities(&db, &community_settings, settings.dry_run).await?;
+                tracing::info!(
+                    "done detecting communities, {} into {:?}, gss: {}, runtime: {:.1}s",
+                    if settings.dry_run {
+                        "dry run: not stored"
+                    } else {
+                        "stored"
+                    },
+                    community_settings.result_field,
+                    status.gss,
+                    status.total_runtime
+                );
+            }
+        }
+        Ok(())
+    }
+}
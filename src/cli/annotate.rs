@@ -0,0 +1,85 @@
+use crate::{
+    document::annotation::{parse_key_val, Annotation},
+    handler::arangodb::DB,
+    settings::Settings,
+};
+use anyhow::{Context, Result};
+use helium_crypto::PublicKeyBinary;
+use serde_json::{Map, Value};
+use std::str::FromStr;
+
+#[derive(Debug, clap::Args)]
+pub struct Cmd {
+    #[clap(subcommand)]
+    target: Target,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum Target {
+    /// Annotate a hotspot by its pub_key
+    Hotspot(Args),
+    /// Annotate a witness edge by its key
+    Edge(Args),
+}
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// Document key to annotate, the pub_key of a hotspot
+    key: String,
+    /// Annotation to set, as key=value (repeatable)
+    #[clap(long = "set", value_parser = parse_key_val)]
+    set: Vec<(String, String)>,
+    /// Annotation key to remove (repeatable)
+    #[clap(long = "unset")]
+    unset: Vec<String>,
+    /// Author recorded on the annotation, default: $USER
+    #[clap(long)]
+    author: Option<String>,
+}
+
+impl Cmd {
+    pub async fn run(&self, settings: &Settings) -> Result<()> {
+        let (collection, args) = match &self.target {
+            Target::Hotspot(args) => (settings.arangodb.collections.hotspots.as_str(), args),
+            Target::Edge(args) => (settings.arangodb.collections.witnesses.as_str(), args),
+        };
+
+        if args.set.is_empty() && args.unset.is_empty() {
+            anyhow::bail!("nothing to do, provide at least one --set or --unset");
+        }
+
+        let author = args
+            .author
+            .clone()
+            .or_else(|| std::env::var("USER").ok())
+            .unwrap_or_else(|| "unknown".to_string());
+
+        let mut annotations = Map::new();
+        for (key, value) in args.set.iter() {
+            let annotation = Annotation::new(value.clone(), author.clone());
+            annotations.insert(key.clone(), serde_json::to_value(annotation)?);
+        }
+        for key in args.unset.iter() {
+            annotations.insert(key.clone(), Value::Null);
+        }
+
+        let db = DB::from_settings(&settings.arangodb).await?;
+        let key = match &self.target {
+            Target::Hotspot(args) => db.hotspot_key(
+                &PublicKeyBinary::from_str(&args.key)
+                    .with_context(|| format!("invalid pub_key {:?}", args.key))?,
+            ),
+            Target::Edge(args) => args.key.clone(),
+        };
+        if settings.dry_run {
+            tracing::info!(
+                "would annotate {collection}/{key}: {}",
+                Value::Object(annotations)
+            );
+            return Ok(());
+        }
+        db.annotate(collection, &key, annotations).await?;
+        tracing::info!("annotated {collection}/{key}");
+        Ok(())
+    }
+}
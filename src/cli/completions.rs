@@ -0,0 +1,18 @@
+use anyhow::Result;
+use clap_complete::Shell;
+
+#[derive(Debug, clap::Args)]
+pub struct Cmd {
+    /// Shell to generate completions for
+    #[clap(value_enum)]
+    shell: Shell,
+}
+
+impl Cmd {
+    /// Write completions for `command` to stdout
+    pub fn run(&self, mut command: clap::Command) -> Result<()> {
+        let name = command.get_name().to_string();
+        clap_complete::generate(self.shell, &mut command, name, &mut std::io::stdout());
+        Ok(())
+    }
+}
@@ -1,27 +1,73 @@
-use crate::{settings::Settings, tracker};
+use crate::{
+    denylist, diagnostics, health::Health, rollup::RollupExporter, settings::Settings, tracker,
+};
 use anyhow::Result;
 use tokio::time::Duration;
 use tokio_graceful_shutdown::{SubsystemHandle, Toplevel};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[derive(Debug, clap::Args)]
-pub struct Server {}
+pub struct Server {
+    /// Start from the configured after timestamp instead of the persisted cursor
+    #[clap(long)]
+    ignore_cursor: bool,
+}
 
 impl Server {
     pub async fn run(&self, settings: &Settings) -> Result<()> {
-        tracing_subscriber::registry()
-            .with(tracing_subscriber::EnvFilter::new(&settings.log))
-            .with(tracing_subscriber::fmt::layer())
-            .init();
-
         let after_utc = settings.current.after_utc();
-        let tracker = tracker::Tracker::new(settings, after_utc).await?;
+        let tracker = tracker::Tracker::new(settings, after_utc, !self.ignore_cursor).await?;
+        let diagnostics = tracker.diagnostics();
+        let paused = tracker.pause_switch();
+        let db = tracker.db();
+        let live_feed = tracker.live_feed();
+        let health = match settings.health.clone() {
+            Some(health) => {
+                Some(Health::new(settings, health, diagnostics.clone(), db.clone()).await?)
+            }
+            None => None,
+        };
+        if settings.current.maintenance {
+            diagnostics::set_maintenance(&diagnostics, &paused, true);
+        }
         let subsystem = |subsys: SubsystemHandle| async { tracker::run(tracker, subsys).await };
-
-        match Toplevel::new()
+        let diagnostics_subsystem =
+            |subsys: SubsystemHandle| async { diagnostics::run(diagnostics, paused, subsys).await };
+        let mut toplevel = Toplevel::new()
             .start("tracker", subsystem)
+            .start("diagnostics", diagnostics_subsystem);
+        if let Some(health) = health {
+            toplevel = toplevel.start("health", |subsys: SubsystemHandle| async move {
+                health.serve(subsys).await
+            });
+        }
+        if let Some(live_feed) = live_feed {
+            toplevel = toplevel.start("live_feed", |subsys: SubsystemHandle| async move {
+                live_feed.serve(subsys).await
+            });
+        }
+        if let Some(db) = db {
+            // NOTE: both write, a dry run skips them
+            if let Some(rollup_export) =
+                settings.rollup_export.clone().filter(|_| !settings.dry_run)
+            {
+                let exporter = RollupExporter::new(db.clone(), rollup_export).await?;
+                toplevel = toplevel.start("rollup_export", |subsys: SubsystemHandle| async move {
+                    exporter.run(subsys).await
+                });
+            }
+            if let Some(denylist) = settings.denylist.clone().filter(|_| !settings.dry_run) {
+                let db = db.clone();
+                toplevel = toplevel.start("denylist", |subsys: SubsystemHandle| async move {
+                    denylist::run(db, denylist, subsys).await
+                });
+            }
+            toplevel = toplevel.start("token_refresh", |subsys: SubsystemHandle| async move {
+                db.refresh_token(subsys).await
+            });
+        }
+        match toplevel
             .catch_signals()
-            .handle_shutdown_requests(Duration::from_millis(500))
+            .handle_shutdown_requests(Duration::from_secs(settings.current.drain_timeout))
             .await
         {
             Ok(_) => Ok(()),
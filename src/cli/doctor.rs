@@ -0,0 +1,233 @@
+#[cfg(feature = "clickhouse")]
+use crate::handler::clickhouse::ClickhouseSink;
+#[cfg(feature = "nats")]
+use crate::handler::nats_handler::NatsHandler;
+#[cfg(feature = "postgres")]
+use crate::handler::postgres::PostgresSink;
+#[cfg(any(feature = "postgres", feature = "clickhouse"))]
+use crate::handler::sink::PocTransforms;
+use crate::{
+    doctor::{self, Report},
+    document::loc_data::CellResolutions,
+    handler::{arangodb::DB, ingest_source, RedisHandler},
+    settings::{ClickhouseSettings, NatsNotifierSettings, PostgresSettings, Settings},
+};
+use anyhow::{Context, Result};
+use chrono::{Duration as ChronoDuration, Utc};
+use file_store::FileType;
+#[cfg(feature = "kafka")]
+use rdkafka::{
+    config::ClientConfig,
+    consumer::{BaseConsumer, Consumer},
+};
+use std::{collections::HashMap, future::Future};
+use tokio::{
+    net::UdpSocket,
+    time::{self, Duration, Instant},
+};
+
+#[derive(Debug, clap::Args)]
+pub struct Cmd {
+    /// NTP server the local clock is compared with
+    #[clap(long, default_value = "pool.ntp.org:123")]
+    ntp_server: String,
+    /// Max tolerated clock skew (ms)
+    #[clap(long, default_value_t = 1000)]
+    max_skew_ms: i64,
+    /// Skip the clock check, e.g. on hosts without outbound NTP
+    #[clap(long)]
+    skip_clock: bool,
+}
+
+impl Cmd {
+    pub async fn run(&self, settings: &Settings) -> Result<()> {
+        let resolutions = CellResolutions::new(settings.parent_resolution, &settings.resolutions)?;
+        let mut report = Report::default();
+        check(&mut report, "binary", binary()).await;
+        check(&mut report, "sample poc", async {
+            doctor::check_sample(doctor::sample_poc()?, resolutions)
+        })
+        .await;
+        if !self.skip_clock {
+            check(
+                &mut report,
+                "clock",
+                clock(&self.ntp_server, self.max_skew_ms),
+            )
+            .await;
+        }
+        check(&mut report, "ingest bucket", bucket(settings)).await;
+        self.check_integrations(&mut report, settings).await;
+
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        if !report.is_ok() {
+            anyhow::bail!(
+                "{} of {} checks failed",
+                report.failed(),
+                report.checks.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Connect to each configured sink, stream and notifier
+    async fn check_integrations(&self, report: &mut Report, settings: &Settings) {
+        check(report, "arangodb", async {
+            DB::from_settings(&settings.arangodb).await?;
+            Ok(format!("connected to {}", settings.arangodb.database))
+        })
+        .await;
+        if let Some(postgres_settings) = &settings.postgres {
+            check(report, "postgres", postgres(postgres_settings)).await;
+        }
+        if let Some(clickhouse_settings) = &settings.clickhouse {
+            check(report, "clickhouse", clickhouse(clickhouse_settings)).await;
+        }
+        if let Some(redis) = &settings.redis {
+            check(report, "redis", async {
+                RedisHandler::from_settings(redis)?.ping().await?;
+                Ok("ping".to_string())
+            })
+            .await;
+        }
+        if let Some(kafka) = &settings.kafka {
+            check(
+                report,
+                "kafka",
+                kafka_topic(&kafka.brokers, &kafka.properties, &kafka.topic),
+            )
+            .await;
+        }
+        if let Some(kafka) = &settings.kafka_notifier {
+            check(
+                report,
+                "kafka notifier",
+                kafka_topic(&kafka.brokers, &kafka.properties, &kafka.poc_topic),
+            )
+            .await;
+        }
+        if let Some(nats_settings) = &settings.nats_notifier {
+            check(report, "nats notifier", nats(nats_settings)).await;
+        }
+    }
+}
+
+async fn check(report: &mut Report, name: &str, fut: impl Future<Output = Result<String>>) {
+    let start = Instant::now();
+    let res = fut.await;
+    let duration_ms = start.elapsed().as_millis() as u64;
+    match &res {
+        Ok(detail) => tracing::info!("{}: {}", name, detail),
+        Err(err) => tracing::error!("{} failed: {:?}", name, err),
+    }
+    report.push(name, duration_ms, res);
+}
+
+async fn binary() -> Result<String> {
+    let exe = std::env::current_exe()?;
+    let size = std::fs::metadata(&exe)?.len();
+    Ok(format!(
+        "{} v{}, {:.1} MiB",
+        exe.display(),
+        env!("CARGO_PKG_VERSION"),
+        size as f64 / (1024.0 * 1024.0)
+    ))
+}
+
+/// Compare the local clock with an NTP server's (single SNTP exchange)
+async fn clock(server: &str, max_skew_ms: i64) -> Result<String> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(server).await?;
+    let sent = Utc::now();
+    socket.send(&doctor::ntp_request()).await?;
+    let mut packet = [0; 48];
+    let len = time::timeout(Duration::from_secs(5), socket.recv(&mut packet))
+        .await
+        .with_context(|| format!("no response from {server}"))??;
+    let offset_ms = doctor::ntp_offset_ms(&packet[..len], sent, Utc::now())?;
+    if offset_ms.abs() > max_skew_ms {
+        anyhow::bail!("clock is {offset_ms}ms off {server}, more than {max_skew_ms}ms");
+    }
+    Ok(format!("{offset_ms}ms off {server}"))
+}
+
+/// List the last hour of iot_poc files, none is fine as long as listing works
+async fn bucket(settings: &Settings) -> Result<String> {
+    let store = ingest_source(settings).await?;
+    let after = Utc::now() - ChronoDuration::hours(1);
+    let files = store.list_all(FileType::IotPoc, after, None).await?;
+    Ok(format!("{} iot_poc files in the last hour", files.len()))
+}
+
+#[cfg(feature = "postgres")]
+async fn postgres(settings: &PostgresSettings) -> Result<String> {
+    PostgresSink::from_settings(settings, PocTransforms::default()).await?;
+    Ok("connected".to_string())
+}
+
+#[cfg(not(feature = "postgres"))]
+async fn postgres(_settings: &PostgresSettings) -> Result<String> {
+    Err(crate::handler::feature_disabled("postgres"))
+}
+
+#[cfg(feature = "clickhouse")]
+async fn clickhouse(settings: &ClickhouseSettings) -> Result<String> {
+    ClickhouseSink::from_settings(settings, false, PocTransforms::default()).await?;
+    Ok("connected".to_string())
+}
+
+#[cfg(not(feature = "clickhouse"))]
+async fn clickhouse(_settings: &ClickhouseSettings) -> Result<String> {
+    Err(crate::handler::feature_disabled("clickhouse"))
+}
+
+#[cfg(feature = "nats")]
+async fn nats(settings: &NatsNotifierSettings) -> Result<String> {
+    NatsHandler::from_settings(settings).await?;
+    Ok(format!("connected to {}", settings.url))
+}
+
+#[cfg(not(feature = "nats"))]
+async fn nats(_settings: &NatsNotifierSettings) -> Result<String> {
+    Err(crate::handler::feature_disabled("nats"))
+}
+
+/// Fetch the metadata of `topic`, which must exist
+#[cfg(feature = "kafka")]
+async fn kafka_topic(
+    brokers: &str,
+    properties: &HashMap<String, String>,
+    topic: &str,
+) -> Result<String> {
+    let mut config = ClientConfig::new();
+    config.set("bootstrap.servers", brokers);
+    for (key, value) in properties {
+        config.set(key, value);
+    }
+    let consumer: BaseConsumer = config.create()?;
+    let topic = topic.to_string();
+    tokio::task::spawn_blocking(move || {
+        let metadata = consumer.fetch_metadata(Some(&topic), Duration::from_secs(10))?;
+        match metadata.topics().first() {
+            Some(meta) if meta.error().is_none() => Ok(format!(
+                "{} has {} partitions",
+                topic,
+                meta.partitions().len()
+            )),
+            _ => anyhow::bail!(
+                "topic {topic:?} not found on {}",
+                metadata.orig_broker_name()
+            ),
+        }
+    })
+    .await?
+}
+
+#[cfg(not(feature = "kafka"))]
+async fn kafka_topic(
+    _brokers: &str,
+    _properties: &HashMap<String, String>,
+    _topic: &str,
+) -> Result<String> {
+    Err(crate::handler::feature_disabled("kafka"))
+}
@@ -0,0 +1,57 @@
+use crate::{
+    export::{self, ExportCollection},
+    handler::arangodb::DB,
+    settings::{ExportFormat, Settings},
+};
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+
+#[derive(Debug, clap::Args)]
+pub struct Cmd {
+    #[clap(subcommand)]
+    format: Format,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum Format {
+    /// Write hotspots.csv and witnesses.csv (one row per witness edge)
+    Csv {
+        /// Directory the files are written to, created if missing
+        #[clap(long, default_value = ".")]
+        out_dir: PathBuf,
+        /// Export only this collection, default: all
+        #[clap(long, value_enum)]
+        only: Option<ExportCollection>,
+        /// Documents read per query
+        #[clap(long, default_value_t = 10_000)]
+        batch_size: u32,
+    },
+}
+
+impl Cmd {
+    pub async fn run(&self, settings: &Settings) -> Result<()> {
+        let db = DB::from_settings(&settings.arangodb).await?;
+        match &self.format {
+            Format::Csv {
+                out_dir,
+                only,
+                batch_size,
+            } => {
+                std::fs::create_dir_all(out_dir)
+                    .with_context(|| format!("failed to create {}", out_dir.display()))?;
+                let collections = match only {
+                    Some(collection) => vec![*collection],
+                    None => ExportCollection::all().to_vec(),
+                };
+                for collection in collections {
+                    let path = out_dir.join(collection.file_name("csv"));
+                    let rows =
+                        export::export_file(&db, collection, ExportFormat::Csv, &path, *batch_size)
+                            .await?;
+                    tracing::info!("exported {} rows to {}", rows, path.display());
+                }
+            }
+        }
+        Ok(())
+    }
+}
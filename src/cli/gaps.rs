@@ -0,0 +1,77 @@
+use crate::{
+    cli::time_arg::{parse_duration, TimeArg},
+    document::timestamp::unix_ms,
+    gaps::{self, GapKind, GapReport},
+    handler::ArangodbHandler,
+    settings::Settings,
+};
+use anyhow::{Context, Result};
+use chrono::{Duration, TimeZone, Utc};
+
+#[derive(Debug, clap::Args)]
+pub struct Cmd {
+    /// Required start time to look for (inclusive).
+    /// RFC3339 (with offset), naive datetime or relative (e.g. now-2h)
+    #[clap(long, value_parser = TimeArg::parse, allow_hyphen_values = true)]
+    after: TimeArg,
+    /// Required before time to look for (inclusive).
+    /// RFC3339 (with offset), naive datetime or relative (e.g. now-2h)
+    #[clap(long, value_parser = TimeArg::parse, allow_hyphen_values = true)]
+    before: TimeArg,
+    /// Interpret naive datetimes in the local time zone instead of UTC
+    #[clap(long)]
+    local: bool,
+    /// Report windows without ingested files longer than this (units: s, m, h, d, w)
+    #[clap(long, value_parser = parse_duration, default_value = "15m")]
+    max_gap: Duration,
+}
+
+impl Cmd {
+    pub async fn run(&self, settings: &Settings) -> Result<()> {
+        let now = Utc::now();
+        let after_utc = self.after.resolve(self.local, now)?;
+        let before_utc = self.before.resolve(self.local, now)?;
+        if after_utc > before_utc {
+            anyhow::bail!("after ({after_utc}) is later than before ({before_utc})");
+        }
+
+        let handler = ArangodbHandler::new(settings).await?;
+        let listed: Vec<_> = handler
+            .list_files(after_utc, Some(before_utc))
+            .await?
+            .into_iter()
+            .map(|fi| fi.timestamp)
+            .collect();
+        let db = handler.db().context("gaps requires the arangodb sink")?;
+        let ingested: Vec<_> = db
+            .get_done_file_timestamps(unix_ms(after_utc), unix_ms(before_utc))
+            .await?
+            .into_iter()
+            .filter_map(|ts| Utc.timestamp_millis_opt(ts).single())
+            .collect();
+
+        let report = GapReport {
+            after: after_utc,
+            before: before_utc,
+            max_gap_secs: self.max_gap.num_seconds(),
+            ingested_files: ingested.len(),
+            bucket_files: listed.len(),
+            gaps: gaps::find_gaps(after_utc, before_utc, &ingested, &listed, self.max_gap),
+        };
+
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        for gap in report
+            .gaps
+            .iter()
+            .filter(|gap| gap.kind == GapKind::NotIngested)
+        {
+            tracing::warn!(
+                "{} files not ingested, rehydrate with: history --after {} --before {}",
+                gap.bucket_files,
+                gap.after.to_rfc3339(),
+                gap.before.to_rfc3339()
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{
+    handler::{arangodb::DB, redis_handler::GeoMember, RedisHandler},
+    settings::Settings,
+};
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+/// Hotspots with a location, paged by key like `export`
+const HOTSPOT_LOCATIONS_QUERY: &str = r#"
+    FOR d IN @@collection
+        FILTER d._key > @after && d.latitude != null && d.longitude != null
+        SORT d._key
+        LIMIT @limit
+        RETURN [d._key, d.pub_key || d._key, d.longitude, d.latitude]"#;
+
+#[derive(Debug, clap::Args)]
+pub struct Cmd {
+    #[clap(subcommand)]
+    action: Action,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum Action {
+    /// Rebuild the GEO set from all located hotspots, replacing it at the end
+    Rebuild {
+        /// Hotspots read per query
+        #[clap(long, default_value_t = 10_000)]
+        batch_size: u32,
+    },
+}
+
+impl Cmd {
+    pub async fn run(&self, settings: &Settings) -> Result<()> {
+        let redis_settings = settings
+            .redis
+            .as_ref()
+            .context("geo needs [redis] settings")?;
+        let key = redis_settings
+            .hotspot_geo_key
+            .as_deref()
+            .context("geo needs redis.hotspot_geo_key")?;
+        let redis = RedisHandler::from_settings(redis_settings)?;
+        let db = DB::from_settings(&settings.arangodb).await?;
+        match self.action {
+            Action::Rebuild { batch_size } if settings.dry_run => {
+                let added = rebuild(&db, &redis, key, batch_size, true).await?;
+                tracing::info!("dry run: would rebuild {:?} with {} hotspots", key, added);
+            }
+            Action::Rebuild { batch_size } => {
+                let added = rebuild(&db, &redis, key, batch_size, false).await?;
+                tracing::info!("rebuilt {:?} with {} hotspots", key, added);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Fill a scratch key with every located hotspot, then rename it over `key`, so
+/// readers never see a partially built set. Returns the number of members.
+/// With `dry_run` the hotspots are only read and counted.
+async fn rebuild(
+    db: &DB,
+    redis: &RedisHandler,
+    key: &str,
+    batch_size: u32,
+    dry_run: bool,
+) -> Result<usize> {
+    let scratch = format!("{key}:rebuild");
+    if !dry_run {
+        redis.del(&scratch).await?;
+    }
+
+    let mut after = String::new();
+    let mut added = 0;
+    loop {
+        let rows = db
+            .export_page(
+                HOTSPOT_LOCATIONS_QUERY,
+                &db.names.hotspots,
+                &after,
+                batch_size,
+            )
+            .await?;
+        let members: Vec<GeoMember> = rows.iter().filter_map(|row| geo_member(row)).collect();
+        if !dry_run {
+            redis.geo_add(&scratch, &members).await?;
+        }
+        added += members.len();
+        match rows
+            .last()
+            .and_then(|row| row.first())
+            .and_then(Value::as_str)
+        {
+            Some(last) if rows.len() == batch_size as usize => after = last.to_string(),
+            _ => break,
+        }
+        tracing::debug!("added {} hotspots to {:?}", added, scratch);
+    }
+    if !dry_run {
+        redis.replace_key(&scratch, key).await?;
+    }
+    Ok(added)
+}
+
+/// GEO set member of a `HOTSPOT_LOCATIONS_QUERY` row
+fn geo_member(row: &[Value]) -> Option<GeoMember> {
+    match row {
+        [_, pub_key, longitude, latitude] => Some((
+            longitude.as_f64()?,
+            latitude.as_f64()?,
+            pub_key.as_str()?.to_string(),
+        )),
+        _ => None,
+    }
+}
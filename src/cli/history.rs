@@ -1,30 +1,52 @@
-use crate::{handler::ArangodbHandler, settings::Settings};
+use crate::{
+    cli::{progress::ProgressArgs, time_arg::TimeArg},
+    handler::ArangodbHandler,
+    settings::{Settings, SinkKind},
+};
 use anyhow::Result;
-use chrono::{NaiveDateTime, TimeZone, Utc};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use chrono::Utc;
 
 #[derive(Debug, clap::Args)]
 pub struct Cmd {
-    /// Required start time to look for (inclusive)
+    /// Required start time to look for (inclusive).
+    /// RFC3339 (with offset), naive datetime or relative (e.g. now-2h)
+    #[clap(long, value_parser = TimeArg::parse, allow_hyphen_values = true)]
+    after: TimeArg,
+    /// Required before time to look for (inclusive).
+    /// RFC3339 (with offset), naive datetime or relative (e.g. now-2h)
+    #[clap(long, value_parser = TimeArg::parse, allow_hyphen_values = true)]
+    before: TimeArg,
+    /// Interpret naive datetimes in the local time zone instead of UTC
     #[clap(long)]
-    after: NaiveDateTime,
-    /// Required before time to look for (inclusive)
-    #[clap(long)]
-    before: NaiveDateTime,
+    local: bool,
+    /// Override the configured sink, e.g. stdout-jsonl to print the beacon
+    /// documents as JSON Lines instead of storing them
+    #[clap(long, value_enum)]
+    sink: Option<SinkKind>,
+    #[clap(flatten)]
+    progress: ProgressArgs,
 }
 
 impl Cmd {
+    /// Sink overriding the configured one, applied before logging is set up
+    pub fn sink(&self) -> Option<SinkKind> {
+        self.sink
+    }
+
     pub async fn run(&self, settings: &Settings) -> Result<()> {
-        tracing_subscriber::registry()
-            .with(tracing_subscriber::EnvFilter::new(&settings.log))
-            .with(tracing_subscriber::fmt::layer())
-            .init();
+        let now = Utc::now();
+        let after_utc = self.after.resolve(self.local, now)?;
+        let before_utc = self.before.resolve(self.local, now)?;
+        if after_utc > before_utc {
+            anyhow::bail!("after ({after_utc}) is later than before ({before_utc})");
+        }
 
-        let after_utc = Utc.from_utc_datetime(&self.after);
-        let before_utc = Utc.from_utc_datetime(&self.before);
+        tracing::info!("resolved utc range: {after_utc} -> {before_utc}");
 
         let handler = ArangodbHandler::new(settings).await?;
-        handler.process(after_utc, Some(before_utc)).await?;
+        self.progress
+            .track(&handler, handler.process(after_utc, Some(before_utc)))
+            .await?;
         Ok(())
     }
 }
@@ -0,0 +1,33 @@
+use crate::{handler::kafka::KafkaIngest, settings::Settings};
+use anyhow::Result;
+use tokio::time::Duration;
+use tokio_graceful_shutdown::{SubsystemHandle, Toplevel};
+
+#[derive(Debug, clap::Args)]
+pub struct Cmd {}
+
+impl Cmd {
+    pub async fn run(&self, settings: &Settings) -> Result<()> {
+        let ingest = KafkaIngest::new(settings).await?;
+        let db = ingest.handler().db();
+        let mut toplevel = Toplevel::new().start("kafka", |subsys: SubsystemHandle| async {
+            ingest.run(subsys).await
+        });
+        if let Some(db) = db {
+            toplevel = toplevel.start("token_refresh", |subsys: SubsystemHandle| async move {
+                db.refresh_token(subsys).await
+            });
+        }
+        match toplevel
+            .catch_signals()
+            .handle_shutdown_requests(Duration::from_secs(settings.current.drain_timeout))
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::error!("error: {:?}", e);
+                Err(e.into())
+            }
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use anyhow::Result;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, clap::Args)]
+pub struct Cmd {
+    /// Write a page per (sub)command into this directory, default: main page to stdout
+    #[clap(long)]
+    out_dir: Option<PathBuf>,
+}
+
+impl Cmd {
+    pub fn run(&self, command: clap::Command) -> Result<()> {
+        match &self.out_dir {
+            Some(out_dir) => {
+                fs::create_dir_all(out_dir)?;
+                let name = command.get_name().to_string();
+                render_all(out_dir, &name, command)
+            }
+            None => Ok(clap_mangen::Man::new(command).render(&mut std::io::stdout())?),
+        }
+    }
+}
+
+/// Render `command` as `<name>.1` and recurse into its subcommands as `<name>-<sub>.1`
+fn render_all(out_dir: &Path, name: &str, command: clap::Command) -> Result<()> {
+    for sub in command.get_subcommands() {
+        let sub_name = format!("{name}-{}", sub.get_name());
+        render_all(out_dir, &sub_name, sub.clone().name(sub_name.clone()))?;
+    }
+    let mut page = fs::File::create(out_dir.join(format!("{name}.1")))?;
+    clap_mangen::Man::new(command).render(&mut page)?;
+    Ok(())
+}
@@ -0,0 +1,68 @@
+use crate::{document::timestamp::RENAMED_FIELDS, handler::arangodb::DB, settings::Settings};
+use anyhow::Result;
+
+#[derive(Debug, clap::Args)]
+pub struct Cmd {
+    #[clap(subcommand)]
+    migration: Migration,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum Migration {
+    /// Rename time fields to the `*_unix_ms` convention, converting seconds to millis
+    Timestamps,
+    /// Re-key hotspots to the configured `hotspot_keys` strategy, updating the
+    /// witness edges, rewards and hotspot stats referencing them
+    HotspotKeys {
+        /// Hotspots re-keyed per batch
+        #[clap(long, default_value_t = 1000)]
+        page_size: u32,
+    },
+    /// Set the witness counts of beacons stored before they were denormalized
+    WitnessCounts,
+}
+
+impl Cmd {
+    pub async fn run(&self, settings: &Settings) -> Result<()> {
+        let db = DB::from_settings(&settings.arangodb).await?;
+        let dry_run = settings.dry_run;
+        let verb = if dry_run { "would migrate" } else { "migrated" };
+        match &self.migration {
+            Migration::Timestamps => {
+                for field in RENAMED_FIELDS {
+                    let migrated = db.migrate_field(field, dry_run).await?;
+                    tracing::info!(
+                        "{} {} documents in {:?}: {:?} -> {:?}",
+                        verb,
+                        migrated,
+                        field.collection,
+                        field.from,
+                        field.to
+                    );
+                }
+                // indexes on the renamed fields
+                if !dry_run {
+                    db.reindex().await?;
+                }
+            }
+            Migration::HotspotKeys { page_size } => {
+                // the pub_key index of hashed keys
+                if !dry_run {
+                    db.reindex().await?;
+                }
+                let migrated = db.migrate_hotspot_keys(*page_size, dry_run).await?;
+                tracing::info!(
+                    "{} {} hotspots to {:?} keys",
+                    verb,
+                    migrated,
+                    settings.arangodb.hotspot_keys
+                );
+            }
+            Migration::WitnessCounts => {
+                let migrated = db.migrate_witness_counts(dry_run).await?;
+                tracing::info!("{} the witness counts of {} beacons", verb, migrated);
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,3 +1,23 @@
+pub mod analyze;
+pub mod annotate;
+pub mod completions;
 pub mod current;
+pub mod doctor;
+pub mod export;
+pub mod gaps;
+pub mod geo;
 pub mod history;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod mangen;
+pub mod migrate;
+pub mod progress;
+pub mod purge;
 pub mod rehydrate;
+pub mod reindex;
+pub mod serve;
+pub mod stats;
+pub mod status;
+pub mod time_arg;
+pub mod verify;
+pub mod watermark;
@@ -0,0 +1,179 @@
+use crate::handler::{
+    arangodb_handler::{IngestProgress, ProcessSummary},
+    ArangodbHandler,
+};
+use anyhow::Result;
+use std::{
+    future::Future,
+    io::{IsTerminal, Write},
+};
+use tokio::time::{self, Duration, Instant};
+
+/// Width of the progress bar, in characters
+const BAR_WIDTH: usize = 30;
+
+#[derive(Debug, clap::Args)]
+pub struct ProgressArgs {
+    /// Log the progress (files done/total, pocs/sec, eta) every this many
+    /// secs, 0 disables it
+    #[clap(long, default_value_t = 30)]
+    progress_secs: u64,
+    /// Draw a progress bar on stderr instead of logging the progress, if it
+    /// is a terminal
+    #[clap(long)]
+    progress_bar: bool,
+}
+
+impl ProgressArgs {
+    /// Run `process`, a run of `handler`, reporting its progress meanwhile and
+    /// logging a summary report once it is done
+    pub async fn track(
+        &self,
+        handler: &ArangodbHandler,
+        process: impl Future<Output = Result<ProcessSummary>>,
+    ) -> Result<ProcessSummary> {
+        let bar = self.progress_bar && std::io::stderr().is_terminal();
+        let summary = if self.progress_secs == 0 && !bar {
+            process.await?
+        } else {
+            // redraw the bar more often than the progress is logged
+            let period = if bar {
+                Duration::from_secs(1)
+            } else {
+                Duration::from_secs(self.progress_secs)
+            };
+            let start = Instant::now();
+            let mut ticker = time::interval_at(start + period, period);
+            tokio::pin!(process);
+            let res = loop {
+                tokio::select! {
+                    res = &mut process => break res,
+                    _ = ticker.tick() => {
+                        let progress = handler.progress();
+                        let line = progress_line(&progress, start.elapsed());
+                        if bar {
+                            draw_bar(&progress, &line);
+                        } else {
+                            tracing::info!("progress: {}", line);
+                        }
+                    }
+                }
+            };
+            if bar {
+                // leave the last drawn bar on its own line
+                eprintln!();
+            }
+            res?
+        };
+        report(&summary);
+        Ok(summary)
+    }
+}
+
+/// Log the final report of a run
+fn report(summary: &ProcessSummary) {
+    let secs = summary.duration_ms as f64 / 1000.0;
+    tracing::info!(
+        "done: {}/{} files ({} failed, {} skipped), {} pocs in {:.1}s ({:.1} pocs/s), watermark {}",
+        summary.files_done,
+        summary.files,
+        summary.files_failed,
+        summary.files_skipped,
+        summary.pocs,
+        secs,
+        rate(summary.pocs as u64, secs),
+        summary.watermark
+    );
+    tracing::debug!("summary: {:?}", summary);
+}
+
+/// `files done/total (failed), pocs, pocs/s, eta` line of the progress after
+/// `elapsed`
+fn progress_line(progress: &IngestProgress, elapsed: Duration) -> String {
+    let secs = elapsed.as_secs_f64();
+    let eta = eta(progress, elapsed)
+        .map(|eta| format!("{}s", eta.as_secs()))
+        .unwrap_or_else(|| "-".to_string());
+    format!(
+        "{}/{} files ({} failed), {} pocs, {:.1} pocs/s, eta {}",
+        progress.files_done,
+        progress.files,
+        progress.files_failed,
+        progress.pocs,
+        rate(progress.pocs, secs),
+        eta
+    )
+}
+
+fn rate(count: u64, secs: f64) -> f64 {
+    if secs > 0.0 {
+        count as f64 / secs
+    } else {
+        0.0
+    }
+}
+
+/// Time left at the pace of the files done so far, None before the first one
+fn eta(progress: &IngestProgress, elapsed: Duration) -> Option<Duration> {
+    let done = progress.files_done as usize;
+    if done == 0 {
+        return None;
+    }
+    let left = progress.files.saturating_sub(done);
+    Some(elapsed.mul_f64(left as f64 / done as f64))
+}
+
+/// `[#####     ]` bar of the files done
+fn bar(progress: &IngestProgress) -> String {
+    let filled = if progress.files == 0 {
+        BAR_WIDTH
+    } else {
+        (progress.files_done as usize * BAR_WIDTH / progress.files).min(BAR_WIDTH)
+    };
+    format!("[{}{}]", "#".repeat(filled), " ".repeat(BAR_WIDTH - filled))
+}
+
+/// Redraw the bar and `line` in place on stderr
+fn draw_bar(progress: &IngestProgress, line: &str) {
+    let mut stderr = std::io::stderr().lock();
+    let _ = write!(stderr, "\r\x1b[2K{} {}", bar(progress), line);
+    let _ = stderr.flush();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn progress(files: usize, files_done: u64) -> IngestProgress {
+        IngestProgress {
+            files,
+            files_done,
+            files_failed: 0,
+            pocs: 0,
+        }
+    }
+
+    #[test]
+    fn eta_from_pace() {
+        let elapsed = Duration::from_secs(60);
+        assert_eq!(eta(&progress(10, 0), elapsed), None);
+        assert_eq!(
+            eta(&progress(10, 2), elapsed),
+            Some(Duration::from_secs(240))
+        );
+        assert_eq!(eta(&progress(10, 10), elapsed), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn progress_bar() {
+        assert_eq!(
+            bar(&progress(10, 0)),
+            format!("[{}]", " ".repeat(BAR_WIDTH))
+        );
+        assert_eq!(
+            bar(&progress(10, 5)),
+            format!("[{}{}]", "#".repeat(15), " ".repeat(15))
+        );
+        assert_eq!(bar(&progress(0, 0)), format!("[{}]", "#".repeat(BAR_WIDTH)));
+    }
+}
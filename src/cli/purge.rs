@@ -0,0 +1,61 @@
+use crate::{
+    cli::time_arg::TimeArg,
+    document::timestamp::unix_ms,
+    handler::arangodb::{PurgeTarget, DB},
+    settings::Settings,
+};
+use anyhow::Result;
+use chrono::Utc;
+
+#[derive(Debug, clap::Args)]
+pub struct Cmd {
+    /// Optional start time to purge from (inclusive), default: beginning of time.
+    /// RFC3339 (with offset), naive datetime or relative (e.g. now-2h)
+    #[clap(long, value_parser = TimeArg::parse, allow_hyphen_values = true)]
+    after: Option<TimeArg>,
+    /// Required time to purge up to (exclusive).
+    /// RFC3339 (with offset), naive datetime or relative (e.g. now-2h)
+    #[clap(long, value_parser = TimeArg::parse, allow_hyphen_values = true)]
+    before: TimeArg,
+    /// Interpret naive datetimes in the local time zone instead of UTC
+    #[clap(long)]
+    local: bool,
+}
+
+impl Cmd {
+    pub async fn run(&self, settings: &Settings) -> Result<()> {
+        let now = Utc::now();
+        let before_utc = self.before.resolve(self.local, now)?;
+        let after_unix_ms = match &self.after {
+            Some(after) => {
+                let after_utc = after.resolve(self.local, now)?;
+                if after_utc > before_utc {
+                    anyhow::bail!("after ({after_utc}) is later than before ({before_utc})");
+                }
+                unix_ms(after_utc)
+            }
+            None => 0,
+        };
+
+        let db = DB::from_settings(&settings.arangodb).await?;
+        for target in PurgeTarget::ALL {
+            let purged = db
+                .purge(target, after_unix_ms, unix_ms(before_utc), settings.dry_run)
+                .await?;
+            if settings.dry_run {
+                tracing::info!(
+                    "would purge {} documents in {:?}",
+                    purged,
+                    target.collection(&db.names)
+                );
+            } else {
+                tracing::info!(
+                    "purged {} documents in {:?}",
+                    purged,
+                    target.collection(&db.names)
+                );
+            }
+        }
+        Ok(())
+    }
+}
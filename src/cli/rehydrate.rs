@@ -1,40 +1,43 @@
-use crate::{handler::ArangodbHandler, settings::Settings};
-use anyhow::{Context, Result};
-use chrono::{Days, NaiveDate, TimeZone, Utc};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use crate::{
+    cli::{progress::ProgressArgs, time_arg::DateArg},
+    handler::ArangodbHandler,
+    settings::{Settings, SinkKind},
+};
+use anyhow::Result;
+use chrono::Utc;
 
 #[derive(Debug, clap::Args)]
 pub struct Cmd {
-    /// Required date to rehydrate
+    /// Required date to rehydrate, YYYY-MM-DD, today or yesterday
+    #[clap(long, value_parser = DateArg::parse)]
+    date: DateArg,
+    /// Interpret the date in the local time zone instead of UTC
     #[clap(long)]
-    date: NaiveDate,
+    local: bool,
+    /// Override the configured sink, e.g. stdout-jsonl to print the beacon
+    /// documents as JSON Lines instead of storing them
+    #[clap(long, value_enum)]
+    sink: Option<SinkKind>,
+    #[clap(flatten)]
+    progress: ProgressArgs,
 }
 
 impl Cmd {
-    pub async fn run(&self, settings: &Settings) -> Result<()> {
-        tracing_subscriber::registry()
-            .with(tracing_subscriber::EnvFilter::new(&settings.log))
-            .with(tracing_subscriber::fmt::layer())
-            .init();
+    /// Sink overriding the configured one, applied before logging is set up
+    pub fn sink(&self) -> Option<SinkKind> {
+        self.sink
+    }
 
-        let after = self
-            .date
-            .and_hms_opt(00, 00, 00)
-            .context("unable to get after date")?;
-        let before = self
-            .date
-            .checked_add_days(Days::new(1))
-            .context("unable to add 1 day")?
-            .and_hms_opt(00, 00, 00)
-            .context("unable to get before date")?;
-        let after_utc = Utc.from_utc_datetime(&after);
-        let before_utc = Utc.from_utc_datetime(&before);
+    pub async fn run(&self, settings: &Settings) -> Result<()> {
+        let (after_utc, before_utc) = self.date.resolve(self.local, Utc::now())?;
 
         tracing::info!("after_utc: {:?}", after_utc);
         tracing::info!("before_utc: {:?}", before_utc);
 
         let handler = ArangodbHandler::new(settings).await?;
-        handler.process(after_utc, Some(before_utc)).await?;
+        self.progress
+            .track(&handler, handler.process(after_utc, Some(before_utc)))
+            .await?;
         Ok(())
     }
 }
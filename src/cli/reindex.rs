@@ -0,0 +1,28 @@
+use crate::{handler::arangodb::DB, settings::Settings};
+use anyhow::Result;
+
+#[derive(Debug, clap::Args)]
+pub struct Cmd {}
+
+impl Cmd {
+    pub async fn run(&self, settings: &Settings) -> Result<()> {
+        if settings.dry_run {
+            for index in settings.arangodb.all_indexes() {
+                tracing::info!(
+                    "would create index {:?} on {:?}: {:?}",
+                    index.name,
+                    index.collection,
+                    index.fields
+                );
+            }
+            return Ok(());
+        }
+        let db = DB::from_settings(&settings.arangodb).await?;
+        db.reindex().await?;
+        tracing::info!(
+            "created {} configured indexes",
+            settings.arangodb.indexes.len()
+        );
+        Ok(())
+    }
+}
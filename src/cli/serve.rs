@@ -0,0 +1,43 @@
+use crate::{api, handler::arangodb::DB, settings::Settings};
+use anyhow::Result;
+use std::{net::SocketAddr, sync::Arc};
+use tokio::time::Duration;
+use tokio_graceful_shutdown::{SubsystemHandle, Toplevel};
+
+#[derive(Debug, clap::Args)]
+pub struct Cmd {
+    /// Address the API listens on
+    #[clap(long, default_value = "127.0.0.1:8080")]
+    listen: SocketAddr,
+}
+
+impl Cmd {
+    pub async fn run(&self, settings: &Settings) -> Result<()> {
+        let db = Arc::new(DB::from_settings(&settings.arangodb).await?);
+        let app = api::router(db.clone());
+        let listen = self.listen;
+        let serve = |subsys: SubsystemHandle| async move {
+            tracing::info!("serving api on {}", listen);
+            axum::Server::try_bind(&listen)?
+                .serve(app.into_make_service())
+                .with_graceful_shutdown(subsys.on_shutdown_requested())
+                .await?;
+            anyhow::Ok(())
+        };
+        match Toplevel::new()
+            .start("serve", serve)
+            .start("token_refresh", |subsys: SubsystemHandle| async move {
+                db.refresh_token(subsys).await
+            })
+            .catch_signals()
+            .handle_shutdown_requests(Duration::from_secs(settings.current.drain_timeout))
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::error!("error: {:?}", e);
+                Err(e.into())
+            }
+        }
+    }
+}
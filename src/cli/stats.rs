@@ -0,0 +1,26 @@
+use crate::{handler::arangodb::DB, settings::Settings, stats};
+use anyhow::Result;
+
+#[derive(Debug, clap::Args)]
+pub struct Cmd {}
+
+impl Cmd {
+    pub async fn run(&self, settings: &Settings) -> Result<()> {
+        let db = DB::from_settings(&settings.arangodb).await?;
+        tracing::info!("computing hotspot stats");
+        stats::compute_hotspot_stats(&db, settings.dry_run).await?;
+        tracing::info!("done computing hotspot stats");
+        tracing::info!("computing hotspot overlaps");
+        stats::compute_hotspot_overlaps(&db, settings.dry_run).await?;
+        tracing::info!("done computing hotspot overlaps");
+        tracing::info!("computing beacon intervals");
+        stats::compute_beacon_intervals(&db, settings.dry_run).await?;
+        tracing::info!("done computing beacon intervals");
+        if let Some(edge_weight) = &settings.edge_weight {
+            tracing::info!("computing edge weights");
+            stats::compute_edge_weights(&db, edge_weight, settings.dry_run).await?;
+            tracing::info!("done computing edge weights");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{handler::ArangodbHandler, settings::Settings};
+use anyhow::{Context, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use serde::Serialize;
+
+#[derive(Debug, clap::Args)]
+pub struct Cmd {}
+
+/// Ingest progress of the files collection against the bucket
+#[derive(Debug, Serialize)]
+struct StatusReport {
+    /// Latest file record, done or not
+    latest_file: Option<DateTime<Utc>>,
+    latest_done_file: Option<DateTime<Utc>>,
+    /// Latest file in the bucket
+    latest_bucket_file: Option<DateTime<Utc>>,
+    not_done_files: u64,
+    /// Files not done whose retries are used up, see `max_retries`
+    exhausted_files: u64,
+    /// Time from the latest done file to the latest bucket file
+    ingest_lag_secs: Option<i64>,
+}
+
+impl Cmd {
+    pub async fn run(&self, settings: &Settings) -> Result<()> {
+        let handler = ArangodbHandler::new(settings).await?;
+        let db = handler.db().context("status requires the arangodb sink")?;
+        let files = db.get_file_status(settings.max_retries).await?;
+        let latest_done_file = files
+            .latest_done_unix_ms
+            .and_then(|ts| Utc.timestamp_millis_opt(ts).single());
+        // NOTE: only files from the latest done one on are listed, without
+        // newer ones the latest done file is the latest of the bucket
+        let after = latest_done_file.unwrap_or_else(|| settings.current.after_utc());
+        let latest_bucket_file = handler.newest_file(after).await?.max(latest_done_file);
+
+        let report = StatusReport {
+            latest_file: files
+                .latest_unix_ms
+                .and_then(|ts| Utc.timestamp_millis_opt(ts).single()),
+            latest_done_file,
+            latest_bucket_file,
+            not_done_files: files.not_done,
+            exhausted_files: files.exhausted,
+            ingest_lag_secs: ingest_lag(latest_done_file, latest_bucket_file),
+        };
+
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        if report.exhausted_files > 0 {
+            tracing::warn!(
+                "{} files exhausted their retries, see the files collection",
+                report.exhausted_files
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Seconds the latest done file is behind the latest bucket file, None
+/// without either
+fn ingest_lag(
+    latest_done: Option<DateTime<Utc>>,
+    latest_bucket: Option<DateTime<Utc>>,
+) -> Option<i64> {
+    Some((latest_bucket? - latest_done?).num_seconds().max(0))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn lag() {
+        let done = Utc.timestamp_millis_opt(1687888130980).unwrap();
+        assert_eq!(ingest_lag(Some(done), None), None);
+        assert_eq!(ingest_lag(None, Some(done)), None);
+        assert_eq!(ingest_lag(Some(done), Some(done)), Some(0));
+        assert_eq!(
+            ingest_lag(Some(done), Some(done + Duration::minutes(5))),
+            Some(300)
+        );
+    }
+}
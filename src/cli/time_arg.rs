@@ -0,0 +1,189 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+const NAIVE_FORMATS: [&str; 4] = [
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M",
+];
+
+/// Timestamp given on the command line.
+///
+/// Accepted forms:
+/// - RFC3339 with an explicit offset, e.g. `2023-05-01T00:00:00+02:00` or `...Z`
+/// - naive datetime, e.g. `2023-05-01T00:00:00` (UTC unless `--local` is given)
+/// - relative to now, e.g. `now`, `now-2h`, `-30m`, `3d ago` (units: s, m, h, d, w)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimeArg {
+    Absolute(DateTime<Utc>),
+    Naive(NaiveDateTime),
+    Relative(Duration),
+}
+
+impl TimeArg {
+    pub fn parse(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+            return Ok(Self::Absolute(dt.with_timezone(&Utc)));
+        }
+        for fmt in NAIVE_FORMATS {
+            if let Ok(ndt) = NaiveDateTime::parse_from_str(s, fmt) {
+                return Ok(Self::Naive(ndt));
+            }
+        }
+        parse_relative(s).map(Self::Relative)
+    }
+
+    /// Resolve to a UTC timestamp, naive values are interpreted in the local
+    /// time zone if `local` is set, otherwise as UTC.
+    pub fn resolve(&self, local: bool, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+        match self {
+            Self::Absolute(dt) => Ok(*dt),
+            Self::Naive(ndt) if local => naive_local_to_utc(ndt),
+            Self::Naive(ndt) => Ok(Utc.from_utc_datetime(ndt)),
+            Self::Relative(offset) => now
+                .checked_sub_signed(*offset)
+                .context("relative time out of range"),
+        }
+    }
+}
+
+/// Date given on the command line, either `YYYY-MM-DD`, `today` or `yesterday`.
+/// Relative dates are evaluated in the local time zone if `local` is set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DateArg {
+    Date(NaiveDate),
+    DaysAgo(u64),
+}
+
+impl DateArg {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.trim() {
+            "today" => Ok(Self::DaysAgo(0)),
+            "yesterday" => Ok(Self::DaysAgo(1)),
+            s => NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .map(Self::Date)
+                .context(format!("invalid date {s:?}, expected YYYY-MM-DD")),
+        }
+    }
+
+    /// Resolve to the UTC [start, end) range covering the whole day
+    pub fn resolve(
+        &self,
+        local: bool,
+        now: DateTime<Utc>,
+    ) -> Result<(DateTime<Utc>, DateTime<Utc>)> {
+        let date = match self {
+            Self::Date(date) => *date,
+            Self::DaysAgo(days) => {
+                let today = if local {
+                    now.with_timezone(&Local).date_naive()
+                } else {
+                    now.date_naive()
+                };
+                today
+                    .checked_sub_days(chrono::Days::new(*days))
+                    .context("date out of range")?
+            }
+        };
+        let start = date.and_hms_opt(0, 0, 0).context("unable to get start")?;
+        let end = date
+            .checked_add_days(chrono::Days::new(1))
+            .context("unable to add 1 day")?
+            .and_hms_opt(0, 0, 0)
+            .context("unable to get end")?;
+        if local {
+            Ok((naive_local_to_utc(&start)?, naive_local_to_utc(&end)?))
+        } else {
+            Ok((Utc.from_utc_datetime(&start), Utc.from_utc_datetime(&end)))
+        }
+    }
+}
+
+fn naive_local_to_utc(ndt: &NaiveDateTime) -> Result<DateTime<Utc>> {
+    // NOTE: ambiguous local times (DST fall back) resolve to the earlier instant
+    Local
+        .from_local_datetime(ndt)
+        .earliest()
+        .map(|dt| dt.with_timezone(&Utc))
+        .context(format!("{ndt} does not exist in the local time zone"))
+}
+
+fn parse_relative(s: &str) -> Result<Duration> {
+    let expr = if s == "now" {
+        return Ok(Duration::zero());
+    } else if let Some(rest) = s.strip_prefix("now-") {
+        rest
+    } else if let Some(rest) = s.strip_prefix('-') {
+        rest
+    } else if let Some(rest) = s.strip_suffix("ago") {
+        rest
+    } else {
+        bail!("invalid time {s:?}, expected RFC3339, naive datetime or relative (e.g. now-2h)");
+    };
+
+    parse_duration(expr)
+}
+
+/// Duration given on the command line, e.g. `15m` (units: s, m, h, d, w)
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let expr = s.trim();
+    let split = expr
+        .find(|c: char| !c.is_ascii_digit())
+        .context(format!("missing unit in {s:?}"))?;
+    let (amount, unit) = expr.split_at(split);
+    let amount: i64 = amount.parse().context(format!("invalid amount in {s:?}"))?;
+    match unit.trim() {
+        "s" => Ok(Duration::seconds(amount)),
+        "m" => Ok(Duration::minutes(amount)),
+        "h" => Ok(Duration::hours(amount)),
+        "d" => Ok(Duration::days(amount)),
+        "w" => Ok(Duration::weeks(amount)),
+        unit => bail!("invalid unit {unit:?} in {s:?}, expected one of s, m, h, d, w"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_forms() {
+        let now = Utc.with_ymd_and_hms(2023, 5, 2, 12, 0, 0).unwrap();
+
+        let offset = TimeArg::parse("2023-05-01T02:00:00+02:00").unwrap();
+        assert_eq!(
+            offset.resolve(false, now).unwrap(),
+            Utc.with_ymd_and_hms(2023, 5, 1, 0, 0, 0).unwrap()
+        );
+
+        let naive = TimeArg::parse("2023-05-01T00:00:00").unwrap();
+        assert_eq!(
+            naive.resolve(false, now).unwrap(),
+            Utc.with_ymd_and_hms(2023, 5, 1, 0, 0, 0).unwrap()
+        );
+
+        for arg in ["now-2h", "-2h", "2h ago"] {
+            let relative = TimeArg::parse(arg).unwrap();
+            assert_eq!(
+                relative.resolve(false, now).unwrap(),
+                Utc.with_ymd_and_hms(2023, 5, 2, 10, 0, 0).unwrap()
+            );
+        }
+
+        assert!(TimeArg::parse("2h").is_err());
+        assert!(TimeArg::parse("now-2y").is_err());
+    }
+
+    #[test]
+    fn resolve_date() {
+        let now = Utc.with_ymd_and_hms(2023, 5, 2, 12, 0, 0).unwrap();
+        let (start, end) = DateArg::parse("yesterday")
+            .unwrap()
+            .resolve(false, now)
+            .unwrap();
+        assert_eq!(start, Utc.with_ymd_and_hms(2023, 5, 1, 0, 0, 0).unwrap());
+        assert_eq!(end, Utc.with_ymd_and_hms(2023, 5, 2, 0, 0, 0).unwrap());
+    }
+}
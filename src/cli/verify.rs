@@ -0,0 +1,62 @@
+use crate::{cli::time_arg::TimeArg, handler::ArangodbHandler, settings::Settings, verify};
+use anyhow::{Context, Result};
+use chrono::Utc;
+
+#[derive(Debug, clap::Args)]
+pub struct Cmd {
+    /// Required start time to look for (inclusive).
+    /// RFC3339 (with offset), naive datetime or relative (e.g. now-2h)
+    #[clap(long, value_parser = TimeArg::parse, allow_hyphen_values = true)]
+    after: TimeArg,
+    /// Required before time to look for (inclusive).
+    /// RFC3339 (with offset), naive datetime or relative (e.g. now-2h)
+    #[clap(long, value_parser = TimeArg::parse, allow_hyphen_values = true)]
+    before: TimeArg,
+    /// Interpret naive datetimes in the local time zone instead of UTC
+    #[clap(long)]
+    local: bool,
+    /// Also store the report in the verification_runs collection
+    #[clap(long)]
+    record: bool,
+}
+
+impl Cmd {
+    pub async fn run(&self, settings: &Settings) -> Result<()> {
+        let now = Utc::now();
+        let after_utc = self.after.resolve(self.local, now)?;
+        let before_utc = self.before.resolve(self.local, now)?;
+        if after_utc > before_utc {
+            anyhow::bail!("after ({after_utc}) is later than before ({before_utc})");
+        }
+
+        let handler = ArangodbHandler::new(settings).await?;
+        let file_infos = handler.list_files(after_utc, Some(before_utc)).await?;
+        let keys: Vec<String> = file_infos.iter().map(|fi| fi.key.clone()).collect();
+        let db = handler.db().context("verify requires the arangodb sink")?;
+        let records = db.get_file_records(&keys).await?;
+        let report = verify::verify(
+            after_utc,
+            before_utc,
+            &file_infos,
+            records,
+            settings.max_retries,
+        );
+
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        if self.record && settings.dry_run {
+            tracing::info!("would record the verification run");
+        } else if self.record {
+            db.record_verification_run(&report).await?;
+        }
+        if !report.is_ok() {
+            anyhow::bail!(
+                "{} missing, {} incomplete, {} retry exhausted of {} files",
+                report.missing.len(),
+                report.incomplete.len(),
+                report.retry_exhausted.len(),
+                report.files
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use crate::{cli::time_arg::TimeArg, handler::ArangodbHandler, settings::Settings};
+use anyhow::Result;
+use chrono::Utc;
+
+/// Checkpoint used when no --name is given
+const DEFAULT_CHECKPOINT: &str = "default";
+
+#[derive(Debug, clap::Args)]
+pub struct Cmd {
+    #[clap(subcommand)]
+    action: Action,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum Action {
+    /// Print the stored watermark (RFC3339), nothing if it was never set
+    Get {
+        /// Checkpoint name
+        #[clap(long, default_value = DEFAULT_CHECKPOINT)]
+        name: String,
+    },
+    /// Store a watermark
+    Set {
+        /// RFC3339 (with offset), naive datetime or relative (e.g. now-2h)
+        #[clap(value_parser = TimeArg::parse, allow_hyphen_values = true)]
+        watermark: TimeArg,
+        /// Checkpoint name
+        #[clap(long, default_value = DEFAULT_CHECKPOINT)]
+        name: String,
+        /// Interpret naive datetimes in the local time zone instead of UTC
+        #[clap(long)]
+        local: bool,
+    },
+}
+
+impl Cmd {
+    pub async fn run(&self, settings: &Settings) -> Result<()> {
+        let handler = ArangodbHandler::new(settings).await?;
+        match &self.action {
+            Action::Get { name } => {
+                if let Some(watermark) = handler.get_watermark(name).await? {
+                    println!("{}", watermark.to_rfc3339());
+                }
+            }
+            Action::Set {
+                watermark,
+                name,
+                local,
+            } => {
+                let watermark = watermark.resolve(*local, Utc::now())?;
+                if settings.dry_run {
+                    tracing::info!("would set watermark {:?} to {}", name, watermark);
+                    return Ok(());
+                }
+                handler.set_watermark(name, watermark).await?;
+                tracing::info!("set watermark {:?} to {}", name, watermark);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+use anyhow::Result;
+use arrow::{
+    datatypes::{DataType, Field, FieldRef},
+    ipc::writer::StreamWriter,
+    record_batch::RecordBatch,
+};
+use serde::Serialize;
+use std::sync::Arc;
+
+/// Arrow fields of a row type given as (name, type, nullable), in row order
+pub fn fields(columns: &[(&str, DataType, bool)]) -> Vec<FieldRef> {
+    columns
+        .iter()
+        .map(|(name, data_type, nullable)| {
+            Arc::new(Field::new(*name, data_type.clone(), *nullable))
+        })
+        .collect()
+}
+
+/// Record batch of `rows` built directly from the row structs (no json in
+/// between), with the columns `fields`
+pub fn record_batch<T: Serialize>(fields: &[FieldRef], rows: &[T]) -> Result<RecordBatch> {
+    Ok(serde_arrow::to_record_batch(fields, rows)?)
+}
+
+/// `rows` encoded as an arrow IPC stream of a single record batch, e.g. for
+/// ClickHouse `FORMAT ArrowStream` inserts
+pub fn arrow_stream<T: Serialize>(fields: &[FieldRef], rows: &[T]) -> Result<Vec<u8>> {
+    let batch = record_batch(fields, rows)?;
+    let mut buf = vec![];
+    {
+        let mut writer = StreamWriter::try_new(&mut buf, &batch.schema())?;
+        writer.write(&batch)?;
+        writer.finish()?;
+    }
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use arrow::{
+        array::{Array, Float64Array, StringArray},
+        ipc::reader::StreamReader,
+    };
+
+    #[derive(Serialize)]
+    struct Row<'a> {
+        name: &'a str,
+        distance: Option<f64>,
+    }
+
+    #[test]
+    fn roundtrip() {
+        let fields = fields(&[
+            ("name", DataType::Utf8, false),
+            ("distance", DataType::Float64, true),
+        ]);
+        let rows = [
+            Row {
+                name: "a",
+                distance: Some(1.5),
+            },
+            Row {
+                name: "b",
+                distance: None,
+            },
+        ];
+        let stream = arrow_stream(&fields, &rows).unwrap();
+        let batches: Vec<RecordBatch> = StreamReader::try_new(stream.as_slice(), None)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 2);
+        let names = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(names.value(1), "b");
+        let distances = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(distances.value(0), 1.5);
+        assert!(distances.is_null(1));
+    }
+}
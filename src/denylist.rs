@@ -0,0 +1,123 @@
+use crate::{handler::arangodb::DB, settings::DenylistSettings};
+use anyhow::{Context, Result};
+use helium_crypto::PublicKeyBinary;
+use std::{str::FromStr, sync::Arc};
+use tokio::time::{self, Duration, Instant};
+use tokio_graceful_shutdown::SubsystemHandle;
+
+/// The denylisted hotspots and the version they are tagged with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Denylist {
+    pub version: String,
+    pub pub_keys: Vec<PublicKeyBinary>,
+}
+
+impl Denylist {
+    /// Parse a denylist csv, the b58 pub_key in the first column (like the
+    /// helium/denylist `denylist.csv`). Lines that aren't a pub_key, e.g. a
+    /// header, are skipped. Without a configured version the list is versioned
+    /// by its content hash, so it only changes when the list does.
+    pub fn parse(data: &[u8], version: Option<&str>) -> Result<Self> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(data);
+        let mut pub_keys = vec![];
+        let mut skipped = 0;
+        for record in reader.records() {
+            let record = record?;
+            let Some(field) = record.get(0).map(str::trim).filter(|f| !f.is_empty()) else {
+                continue;
+            };
+            match PublicKeyBinary::from_str(field) {
+                Ok(pub_key) => pub_keys.push(pub_key),
+                Err(_) => skipped += 1,
+            }
+        }
+        if skipped > 0 {
+            tracing::warn!("skipped {} denylist lines without a pub_key", skipped);
+        }
+        let version = match version {
+            Some(version) => version.to_string(),
+            None => blake3::hash(data).to_hex()[..16].to_string(),
+        };
+        Ok(Self { version, pub_keys })
+    }
+
+    /// Fetch the denylist from its url or read it from its file
+    pub async fn load(settings: &DenylistSettings) -> Result<Self> {
+        let data = match (&settings.url, &settings.file) {
+            (Some(url), _) => reqwest::get(url)
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await?
+                .to_vec(),
+            (None, Some(file)) => tokio::fs::read(file)
+                .await
+                .with_context(|| format!("reading denylist {}", file.display()))?,
+            (None, None) => anyhow::bail!("denylist needs a url or a file"),
+        };
+        Self::parse(&data, settings.version.as_deref())
+    }
+}
+
+/// Load the denylist and tag the stored hotspots with it
+pub async fn refresh(db: &DB, settings: &DenylistSettings) -> Result<()> {
+    let denylist = Denylist::load(settings).await?;
+    let keys = denylist
+        .pub_keys
+        .iter()
+        .map(|pub_key| db.hotspot_key(pub_key))
+        .collect();
+    let (tagged, cleared) = db.tag_denylisted(keys, &denylist.version).await?;
+    tracing::info!(
+        "denylist {} ({} pub_keys): {} hotspots tagged, {} cleared",
+        denylist.version,
+        denylist.pub_keys.len(),
+        tagged,
+        cleared
+    );
+    Ok(())
+}
+
+/// Refresh the denylist tags on start and every `refresh_secs`, so hotspots
+/// first seen since the last refresh are tagged too
+pub async fn run(db: Arc<DB>, settings: DenylistSettings, subsys: SubsystemHandle) -> Result<()> {
+    let period = Duration::from_secs(settings.refresh_secs.max(1));
+    let mut refresh = time::interval_at(Instant::now(), period);
+    loop {
+        tokio::select! {
+            _ = subsys.on_shutdown_requested() => break,
+            _ = refresh.tick() => {
+                if let Err(err) = self::refresh(&db, &settings).await {
+                    tracing::error!("error refreshing denylist: {:?}", err);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_denylist() {
+        let mut bytes = vec![0x01];
+        bytes.extend([7; 32]);
+        let key = PublicKeyBinary::from(bytes).to_string();
+        let data = format!("pub_key\n{key}\n\n{key},extra\nnot a key\n");
+        let denylist = Denylist::parse(data.as_bytes(), None).unwrap();
+        assert_eq!(denylist.pub_keys.len(), 2);
+        assert_eq!(denylist.pub_keys[0].to_string(), key);
+        assert_eq!(denylist.version.len(), 16);
+        // same content, same version
+        let again = Denylist::parse(data.as_bytes(), None).unwrap();
+        assert_eq!(again.version, denylist.version);
+
+        let pinned = Denylist::parse(data.as_bytes(), Some("v42")).unwrap();
+        assert_eq!(pinned.version, "v42");
+    }
+}
@@ -0,0 +1,118 @@
+use crate::handler::{
+    arangodb_handler::{IngestCounts, ProcessSummary},
+    redis_handler::StreamMetrics,
+    shadow::ShadowMetrics,
+};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+use tokio_graceful_shutdown::SubsystemHandle;
+
+/// Snapshot of the current tracker state, dumped to the log on SIGUSR1
+#[derive(Debug, Serialize, Clone)]
+pub struct Diagnostics {
+    pub pid: u32,
+    pub started_at: DateTime<Utc>,
+    pub after_utc: DateTime<Utc>,
+    pub interval_secs: i64,
+    pub ticks: u64,
+    pub tick_started_at: Option<DateTime<Utc>>,
+    /// When the last tick finished, None before the first
+    pub last_tick_at: Option<DateTime<Utc>>,
+    pub last_summary: Option<ProcessSummary>,
+    /// Since when ingestion is paused, None unless in maintenance mode
+    pub maintenance_since: Option<DateTime<Utc>>,
+    /// poc_id stream publish and trim counts, None without redis
+    pub poc_id_stream: Option<StreamMetrics>,
+    /// Shadow verification counts, None unless enabled
+    pub shadow_verify: Option<ShadowMetrics>,
+    /// Stored pocs per file type and beacons and witnesses per region since start
+    pub ingest_counts: IngestCounts,
+}
+
+pub type SharedDiagnostics = Arc<Mutex<Diagnostics>>;
+
+impl Diagnostics {
+    pub fn new(after_utc: DateTime<Utc>, interval_secs: i64) -> SharedDiagnostics {
+        Arc::new(Mutex::new(Self {
+            pid: std::process::id(),
+            started_at: Utc::now(),
+            after_utc,
+            interval_secs,
+            ticks: 0,
+            tick_started_at: None,
+            last_tick_at: None,
+            last_summary: None,
+            maintenance_since: None,
+            poc_id_stream: None,
+            shadow_verify: None,
+            ingest_counts: IngestCounts::default(),
+        }))
+    }
+}
+
+fn dump(diagnostics: &SharedDiagnostics) {
+    let snapshot = match diagnostics.lock() {
+        Ok(diagnostics) => diagnostics.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    };
+    match serde_json::to_string_pretty(&snapshot) {
+        Ok(dump) => tracing::info!("diagnostic dump:\n{}", dump),
+        Err(err) => tracing::error!("error serializing diagnostics: {:?}", err),
+    }
+}
+
+/// Enter or leave maintenance mode
+pub fn set_maintenance(diagnostics: &SharedDiagnostics, paused: &AtomicBool, on: bool) {
+    paused.store(on, Ordering::Relaxed);
+    let since = on.then(Utc::now);
+    match diagnostics.lock() {
+        Ok(mut diagnostics) => diagnostics.maintenance_since = since,
+        Err(poisoned) => poisoned.into_inner().maintenance_since = since,
+    }
+    if on {
+        tracing::info!("entered maintenance mode, finishing in-flight files and pausing ingestion");
+    } else {
+        tracing::info!("left maintenance mode, resuming ingestion");
+    }
+}
+
+/// Dump diagnostics to the log whenever SIGUSR1 is received, toggle maintenance
+/// mode (pausing ingestion) whenever SIGUSR2 is received
+#[cfg(unix)]
+pub async fn run(
+    diagnostics: SharedDiagnostics,
+    paused: Arc<AtomicBool>,
+    subsys: SubsystemHandle,
+) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut usr1 = signal(SignalKind::user_defined1())?;
+    let mut usr2 = signal(SignalKind::user_defined2())?;
+    loop {
+        tokio::select! {
+            _ = subsys.on_shutdown_requested() => break,
+            _ = usr1.recv() => dump(&diagnostics),
+            _ = usr2.recv() => {
+                let on = !paused.load(Ordering::Relaxed);
+                set_maintenance(&diagnostics, &paused, on);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub async fn run(
+    diagnostics: SharedDiagnostics,
+    _paused: Arc<AtomicBool>,
+    subsys: SubsystemHandle,
+) -> Result<()> {
+    subsys.on_shutdown_requested().await;
+    dump(&diagnostics);
+    Ok(())
+}
@@ -0,0 +1,202 @@
+use crate::{
+    document::{beacon::Beacon, loc_data::CellResolutions, source::Source},
+    handler::sink::{decode_beacons, PocTransforms},
+};
+use anyhow::{Context, Result};
+use bytes::BytesMut;
+use chrono::{DateTime, TimeZone, Utc};
+use file_store::FileInfo;
+use h3o::{LatLng, Resolution};
+use helium_proto::{
+    services::poc_lora::{
+        LoraBeaconReportReqV1, LoraPocV1, LoraValidBeaconReportV1, LoraVerifiedWitnessReportV1,
+        LoraWitnessReportReqV1,
+    },
+    Message,
+};
+use serde::Serialize;
+use std::str::FromStr;
+
+/// Seconds between the NTP (1900) and unix epochs
+const NTP_UNIX_OFFSET_SECS: u64 = 2_208_988_800;
+
+/// Outcome of one preflight check
+#[derive(Debug, Serialize, Clone)]
+pub struct Check {
+    pub name: String,
+    pub ok: bool,
+    pub detail: String,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    pub fn push(&mut self, name: &str, duration_ms: u64, res: Result<String>) {
+        let (ok, detail) = match res {
+            Ok(detail) => (true, detail),
+            Err(err) => (false, format!("{err:#}")),
+        };
+        self.checks.push(Check {
+            name: name.to_string(),
+            ok,
+            detail,
+            duration_ms,
+        });
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.checks.iter().all(|check| check.ok)
+    }
+
+    pub fn failed(&self) -> usize {
+        self.checks.iter().filter(|check| !check.ok).count()
+    }
+}
+
+/// Encoded `LoraPocV1` with a located beacon and one located selected witness,
+/// shipped with the binary so decoding can be checked without a bucket
+pub fn sample_poc() -> Result<Vec<u8>> {
+    let beacon_cell = LatLng::new(37.769377, -122.388903)?.to_cell(Resolution::Twelve);
+    let witness_cell = LatLng::new(37.781, -122.401)?.to_cell(Resolution::Twelve);
+    let poc = LoraPocV1 {
+        poc_id: vec![7; 32],
+        beacon_report: Some(LoraValidBeaconReportV1 {
+            received_timestamp: 1_687_888_130_980,
+            location: u64::from(beacon_cell).to_string(),
+            report: Some(LoraBeaconReportReqV1 {
+                pub_key: sample_key(1),
+                frequency: 904_300_000,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        selected_witnesses: vec![LoraVerifiedWitnessReportV1 {
+            received_timestamp: 1_687_888_131_120,
+            location: u64::from(witness_cell).to_string(),
+            report: Some(LoraWitnessReportReqV1 {
+                pub_key: sample_key(2),
+                frequency: 904_300_000,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    Ok(poc.encode_to_vec())
+}
+
+/// Binary ed25519 mainnet key (tag byte and 32 key bytes); names are derived
+/// from its b58 string so it needn't be a valid curve point
+fn sample_key(fill: u8) -> Vec<u8> {
+    let mut key = vec![0x01];
+    key.extend([fill; 32]);
+    key
+}
+
+/// Decode the sample poc through the ingest path, checking its locations got
+/// their H3 strings and geojson cells (at `resolutions`) and the witness its distance
+pub fn check_sample(raw: Vec<u8>, resolutions: CellResolutions) -> Result<String> {
+    let source = Source::new(
+        &FileInfo::from_str("iot_poc.1687888130980.gz")?,
+        "LoraPocV1",
+    );
+    let beacon: Beacon = decode_beacons(
+        vec![BytesMut::from(raw.as_slice())],
+        &source,
+        &PocTransforms::default().with_resolutions(resolutions),
+    )
+    .pop()
+    .context("sample poc did not decode to a beacon")?;
+    let witness = beacon
+        .witnesses
+        .first()
+        .context("sample beacon has no witness")?;
+    if beacon.str_location.is_none() || beacon.geo.is_none() || beacon.parent_geo.is_none() {
+        anyhow::bail!("beacon location of the sample has no H3 or geojson data");
+    }
+    if witness.geo.is_none() || witness.parent_geo.is_none() {
+        anyhow::bail!("witness location of the sample has no geojson data");
+    }
+    if witness.distance <= 0.0 {
+        anyhow::bail!("witness distance of the sample was not computed");
+    }
+    Ok(format!(
+        "decoded {} at {}, witness {} at {:.2}km",
+        beacon.name,
+        beacon.str_location.unwrap_or_default(),
+        witness.name,
+        witness.distance
+    ))
+}
+
+/// SNTP (v4, client mode) request packet
+pub fn ntp_request() -> [u8; 48] {
+    let mut packet = [0; 48];
+    packet[0] = 0x23;
+    packet
+}
+
+/// Time of the 64 bit NTP timestamp at `offset` of a packet
+fn ntp_time(packet: &[u8], offset: usize) -> Result<DateTime<Utc>> {
+    let field = |at: usize| -> Result<u64> {
+        let bytes: [u8; 4] = packet
+            .get(at..at + 4)
+            .context("truncated ntp packet")?
+            .try_into()?;
+        Ok(u32::from_be_bytes(bytes) as u64)
+    };
+    let secs = field(offset)?
+        .checked_sub(NTP_UNIX_OFFSET_SECS)
+        .context("ntp timestamp before the unix epoch")?;
+    let nanos = (field(offset + 4)? * 1_000_000_000) >> 32;
+    Utc.timestamp_opt(secs as i64, nanos as u32)
+        .single()
+        .context("invalid ntp timestamp")
+}
+
+/// Offset (ms) of the server clock from the local one, from an NTP response
+/// sent at `sent` and received at `received` (local times)
+pub fn ntp_offset_ms(packet: &[u8], sent: DateTime<Utc>, received: DateTime<Utc>) -> Result<i64> {
+    let server_received = ntp_time(packet, 32)?;
+    let server_sent = ntp_time(packet, 40)?;
+    let offset = ((server_received - sent) + (server_sent - received)) / 2;
+    Ok(offset.num_milliseconds())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Duration;
+
+    fn ntp_bytes(time: DateTime<Utc>) -> [u8; 8] {
+        let secs = (time.timestamp() as u64 + NTP_UNIX_OFFSET_SECS) as u32;
+        let fraction = ((time.timestamp_subsec_nanos() as u64) << 32) / 1_000_000_000;
+        let mut bytes = [0; 8];
+        bytes[..4].copy_from_slice(&secs.to_be_bytes());
+        bytes[4..].copy_from_slice(&(fraction as u32).to_be_bytes());
+        bytes
+    }
+
+    #[test]
+    fn clock_offset() {
+        let sent = Utc.timestamp_millis_opt(1_687_888_130_000).unwrap();
+        let received = sent + Duration::seconds(2);
+        // server clock 2s ahead, 1s each way
+        let server = sent + Duration::seconds(3);
+        let mut packet = ntp_request();
+        packet[32..40].copy_from_slice(&ntp_bytes(server));
+        packet[40..48].copy_from_slice(&ntp_bytes(server));
+        assert_eq!(ntp_offset_ms(&packet, sent, received).unwrap(), 2_000);
+        assert!(ntp_offset_ms(&packet[..40], sent, received).is_err());
+    }
+
+    #[test]
+    fn bundled_sample() {
+        let detail = check_sample(sample_poc().unwrap(), CellResolutions::default()).unwrap();
+        assert!(detail.starts_with("decoded "));
+    }
+}
@@ -0,0 +1,42 @@
+use crate::document::timestamp::unix_ms;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Operator supplied annotation stored under a document's `annotations` sub-document.
+/// Ingestion never writes to `annotations`, so these survive re-processing.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Annotation {
+    pub value: String,
+    pub author: String,
+    pub updated_at: DateTime<Utc>,
+    #[serde(alias = "updated_at_unix")]
+    pub updated_at_unix_ms: i64,
+}
+
+impl Annotation {
+    pub fn new(value: String, author: String) -> Self {
+        let updated_at = Utc::now();
+        Self {
+            value,
+            author,
+            updated_at,
+            updated_at_unix_ms: unix_ms(updated_at),
+        }
+    }
+}
+
+pub type Annotations = HashMap<String, Annotation>;
+
+/// Parse a `key=value` pair as given to `annotate --set`
+pub fn parse_key_val(s: &str) -> Result<(String, String)> {
+    let (key, value) = s
+        .split_once('=')
+        .context(format!("invalid key=value: no `=` found in {s:?}"))?;
+    let key = key.trim();
+    if key.is_empty() {
+        anyhow::bail!("invalid key=value: empty key in {s:?}");
+    }
+    Ok((key.to_string(), value.trim().to_string()))
+}
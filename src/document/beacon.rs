@@ -1,7 +1,13 @@
-use crate::document::{
-    get_name,
-    loc_data::{LocData, ParentLocData},
-    Witnesses,
+use crate::{
+    document::{
+        get_name,
+        loc_data::{CellResolutions, LocData, ParentLocData},
+        privacy::PreciseLocation,
+        source::Source,
+        timestamp::unix_ms,
+        Witnesses,
+    },
+    settings::{OverflowStrategy, PrivacySettings},
 };
 use anyhow::Result;
 use base64::{engine::general_purpose, Engine as _};
@@ -12,13 +18,15 @@ use h3o::LatLng;
 use helium_crypto::PublicKeyBinary;
 use rust_decimal::prelude::ToPrimitive;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Beacon {
     pub _key: String,
     pub poc_id: String,
     pub ingest_time: DateTime<Utc>,
-    pub ingest_time_unix: i64,
+    #[serde(alias = "ingest_time_unix")]
+    pub ingest_time_unix_ms: i64,
     pub location: Option<u64>,
     pub str_location: Option<String>,
     pub latitude: Option<f64>,
@@ -29,6 +37,9 @@ pub struct Beacon {
     pub parent_latitude: Option<f64>,
     pub parent_longitude: Option<f64>,
     pub parent_geo: Option<Geometry>,
+    /// Cells at the configured extra resolutions, as `loc_res{N}` fields
+    #[serde(flatten)]
+    pub loc_res: BTreeMap<String, String>,
     pub gain: i32,
     pub elevation: i32,
     pub hex_scale: Option<f64>,
@@ -41,9 +52,101 @@ pub struct Beacon {
     pub timestamp: DateTime<Utc>,
     pub tmst: u32,
     pub witnesses: Witnesses,
+    pub witness_count: usize,
+    pub selected_witness_count: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overflow: Option<Overflow>,
+    /// Base64 raw LoraPocV1, only with `raw_pocs = "inline"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_poc: Option<String>,
+    /// Producing oracle file and ETL version, absent on documents of earlier versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<Source>,
+}
+
+/// Records what was done to a beacon document which exceeded the max document size
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Overflow {
+    pub strategy: OverflowStrategy,
+    pub original_size: usize,
+    pub final_size: usize,
+    pub dropped_witnesses: usize,
 }
 
 impl Beacon {
+    /// Apply location data minimization to the beacon and all of its witnesses.
+    /// Must run after witness distances have been computed.
+    pub fn minimize_locations(&mut self, privacy: &PrivacySettings) -> Result<()> {
+        PreciseLocation {
+            location: &mut self.location,
+            str_location: &mut self.str_location,
+            latitude: &mut self.latitude,
+            longitude: &mut self.longitude,
+            geo: &mut self.geo,
+        }
+        .minimize(privacy)?;
+        for witness in self.witnesses.iter_mut() {
+            witness.minimize_locations(privacy)?;
+        }
+        Ok(())
+    }
+
+    /// Serialized size of the beacon document in bytes
+    pub fn doc_size(&self) -> Result<usize> {
+        Ok(serde_json::to_vec(self)?.len())
+    }
+
+    /// Shrink the beacon document below `max_size` using `strategy`.
+    ///
+    /// Returns Ok(false) if the document is still too large (always the case for
+    /// `OverflowStrategy::DeadLetter`), in which case it should not be inserted.
+    pub fn fit_to_size(&mut self, max_size: usize, strategy: OverflowStrategy) -> Result<bool> {
+        let original_size = self.doc_size()?;
+        if original_size <= max_size {
+            return Ok(true);
+        }
+
+        let num_witnesses = self.witnesses.len();
+        match strategy {
+            OverflowStrategy::DeadLetter => return Ok(false),
+            OverflowStrategy::StripGeo => {
+                for witness in self.witnesses.iter_mut() {
+                    witness.geo = None;
+                    witness.parent_geo = None;
+                }
+                let size = self.doc_size()?;
+                if size > max_size {
+                    self.truncate_witnesses(size, max_size)?;
+                }
+            }
+            OverflowStrategy::Truncate => self.truncate_witnesses(original_size, max_size)?,
+        }
+
+        // NOTE: final_size excludes the overflow record itself
+        let final_size = self.doc_size()?;
+        self.overflow = Some(Overflow {
+            strategy,
+            original_size,
+            final_size,
+            dropped_witnesses: num_witnesses - self.witnesses.len(),
+        });
+        Ok(final_size <= max_size)
+    }
+
+    // witnesses are ordered selected first, so popping drops unselected ones first
+    fn truncate_witnesses(&mut self, mut size: usize, max_size: usize) -> Result<()> {
+        while size > max_size {
+            match self.witnesses.pop() {
+                // account for the separating comma as well
+                Some(witness) => {
+                    size = size.saturating_sub(serde_json::to_vec(&witness)?.len() + 1)
+                }
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
     fn set_witness_distance(&mut self) -> Result<()> {
         // attach distance to each witness in the beacon
         for mut witness in self.witnesses.iter_mut() {
@@ -60,24 +163,24 @@ impl Beacon {
     }
 }
 
-impl TryFrom<&IotPoc> for Beacon {
-    type Error = anyhow::Error;
-
-    fn try_from(iot_poc: &IotPoc) -> Result<Self> {
+impl Beacon {
+    /// Beacon of a poc, with the cells derived from its and its witnesses'
+    /// locations at `resolutions`
+    pub fn from_poc(iot_poc: &IotPoc, resolutions: &CellResolutions) -> Result<Self> {
         let beacon_report = &iot_poc.beacon_report;
         let enc_poc_id = general_purpose::URL_SAFE_NO_PAD.encode(iot_poc.poc_id.clone());
         let location = beacon_report.location;
         let beacon_ts = beacon_report.received_timestamp;
-        let beacon_ingest_unix = beacon_ts.timestamp_millis();
+        let beacon_ingest_unix_ms = unix_ms(beacon_ts);
         let loc_data = LocData::from_h3(location)?;
-        let parent_loc_data = ParentLocData::from_h3(location)?;
+        let parent_loc_data = ParentLocData::from_h3_at(location, resolutions.parent)?;
         let name = get_name(&beacon_report.report.pub_key)?;
 
         let mut beacon = Self {
             _key: enc_poc_id.clone(),
             poc_id: enc_poc_id,
             ingest_time: beacon_ts,
-            ingest_time_unix: beacon_ingest_unix,
+            ingest_time_unix_ms: beacon_ingest_unix_ms,
             location,
             str_location: loc_data.str_loc,
             latitude: loc_data.lat,
@@ -88,6 +191,7 @@ impl TryFrom<&IotPoc> for Beacon {
             parent_latitude: parent_loc_data.lat,
             parent_longitude: parent_loc_data.lng,
             parent_geo: parent_loc_data.geo,
+            loc_res: resolutions.loc_res(location)?,
             name,
             hex_scale: beacon_report.hex_scale.to_f64(),
             reward_unit: beacon_report.reward_unit.to_f64(),
@@ -99,7 +203,12 @@ impl TryFrom<&IotPoc> for Beacon {
             tmst: beacon_report.report.tmst,
             gain: beacon_report.gain,
             elevation: beacon_report.elevation,
-            witnesses: Witnesses::try_from(iot_poc)?,
+            witnesses: Witnesses::from_poc(iot_poc, resolutions)?,
+            witness_count: iot_poc.selected_witnesses.len() + iot_poc.unselected_witnesses.len(),
+            selected_witness_count: iot_poc.selected_witnesses.len(),
+            overflow: None,
+            raw_poc: None,
+            source: None,
         };
         beacon.set_witness_distance()?;
         Ok(beacon)
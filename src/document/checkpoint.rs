@@ -0,0 +1,24 @@
+use crate::document::timestamp::{now_unix_ms, unix_ms};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Named ingest watermark, e.g. maintained by an external orchestrator
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Checkpoint {
+    /// Checkpoint name
+    pub _key: String,
+    pub watermark: DateTime<Utc>,
+    pub watermark_unix_ms: i64,
+    pub updated_unix_ms: i64,
+}
+
+impl Checkpoint {
+    pub fn new(name: &str, watermark: DateTime<Utc>) -> Self {
+        Self {
+            _key: name.to_string(),
+            watermark,
+            watermark_unix_ms: unix_ms(watermark),
+            updated_unix_ms: now_unix_ms(),
+        }
+    }
+}
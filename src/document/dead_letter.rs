@@ -0,0 +1,30 @@
+use crate::document::Beacon;
+use helium_crypto::PublicKeyBinary;
+use serde::{Deserialize, Serialize};
+
+/// Beacon which could not be stored, kept so it can be inspected or replayed later
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeadLetter {
+    pub _key: String,
+    pub poc_id: String,
+    pub pub_key: PublicKeyBinary,
+    #[serde(alias = "ingest_time_unix")]
+    pub ingest_time_unix_ms: i64,
+    pub reason: String,
+    pub size: usize,
+    pub num_witnesses: usize,
+}
+
+impl DeadLetter {
+    pub fn new(beacon: &Beacon, reason: &str, size: usize) -> Self {
+        Self {
+            _key: beacon._key.clone(),
+            poc_id: beacon.poc_id.clone(),
+            pub_key: beacon.pub_key.clone(),
+            ingest_time_unix_ms: beacon.ingest_time_unix_ms,
+            reason: reason.to_string(),
+            size,
+            num_witnesses: beacon.witnesses.len(),
+        }
+    }
+}
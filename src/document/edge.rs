@@ -1,43 +1,220 @@
-use crate::document::{Beacon, Witness};
+use crate::{
+    document::{hotspot::hotspot_key, region, Beacon, Witness},
+    settings::{KeyStrategy, WindowPeriod},
+};
 use anyhow::Result;
+use chrono::{Datelike, TimeZone, Utc};
 use helium_crypto::PublicKeyBinary;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Edge {
     pub _key: String,
     pub beacon_pub_key: PublicKeyBinary,
     pub witness_pub_key: PublicKeyBinary,
+    /// Keys of the beacon and witness hotspot documents, see `hotspot_key`
+    pub beacon_hotspot_key: String,
+    pub witness_hotspot_key: String,
     pub distance: f64,
     pub witness_snr: i32,
     pub witness_signal: i32,
-    pub ingest_latency: i64,
+    pub ingest_latency_ms: i64,
+    pub seen_unix_ms: i64,
+    /// Regions whose band contains the beacon frequency
+    pub beacon_regions: Vec<String>,
+    /// Whether the witness frequency is outside the beacon's region bands
+    pub band_mismatch: bool,
 }
 
 impl Edge {
     pub fn new(beacon: &Beacon, witness: &Witness) -> Result<Self> {
-        let _key = witness_edge_key(beacon.location, witness.location);
-        let ingest_latency = witness
-            .ingest_time_unix
-            .checked_sub(beacon.ingest_time_unix)
+        let _key = witness_edge_key(
+            &location_id(beacon.location, &beacon.str_location, &beacon.pub_key),
+            &location_id(witness.location, &witness.str_location, &witness.pub_key),
+        );
+        let ingest_latency_ms = witness
+            .ingest_time_unix_ms
+            .checked_sub(beacon.ingest_time_unix_ms)
             .unwrap_or_default();
         Ok(Self {
             _key,
             beacon_pub_key: beacon.pub_key.clone(),
             witness_pub_key: witness.pub_key.clone(),
+            beacon_hotspot_key: beacon.pub_key.to_string(),
+            witness_hotspot_key: witness.pub_key.to_string(),
             witness_snr: witness.snr,
             witness_signal: witness.signal,
             distance: witness.distance,
-            ingest_latency,
+            ingest_latency_ms,
+            seen_unix_ms: witness.ingest_time_unix_ms,
+            beacon_regions: region::regions(beacon.frequency)
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+            band_mismatch: region::band_mismatch(beacon.frequency, witness.frequency),
         })
     }
+
+    /// Link the hotspots keyed with `strategy`, edges are built linking pub_keys
+    pub fn with_keys(mut self, strategy: KeyStrategy) -> Self {
+        self.beacon_hotspot_key = hotspot_key(strategy, &self.beacon_pub_key);
+        self.witness_hotspot_key = hotspot_key(strategy, &self.witness_pub_key);
+        self
+    }
+}
+
+/// Edges sharing a key aggregated within a batch, so the batch can be
+/// upserted with a single document per key.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EdgeAggregate {
+    pub _key: String,
+    pub beacon_pub_key: String,
+    pub witness_pub_key: String,
+    pub beacon_hotspot_key: String,
+    pub witness_hotspot_key: String,
+    pub distance: f64,
+    pub count: u64,
+    pub snr_hist: HashMap<i32, u64>,
+    pub signal_hist: HashMap<i32, u64>,
+    pub ingest_latency_hist: HashMap<i64, u64>,
+    pub first_seen_unix_ms: i64,
+    pub last_seen_unix_ms: i64,
+    pub beacon_regions: Vec<String>,
+    /// Number of witnesses reported outside the beacon's region bands
+    pub band_mismatch_count: u64,
+    /// Histograms per day or week (see `window_key`), empty unless windowed
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub windows: BTreeMap<String, EdgeWindow>,
 }
 
-fn witness_edge_key(beacon_loc: Option<u64>, witness_loc: Option<u64>) -> String {
-    match (beacon_loc, witness_loc) {
-        (Some(b_loc), Some(w_loc)) => format!("beacon_{:?}_witness_{:?}", b_loc, w_loc),
-        (Some(b_loc), None) => format!("beacon_{:?}_witness_unknown", b_loc),
-        (None, Some(w_loc)) => format!("beacon_unknown_witness_{:?}", w_loc),
-        (None, None) => "beacon_unknown_witness_unknown".to_string(),
+/// Witness counts and histograms of an edge within one day or week
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct EdgeWindow {
+    pub count: u64,
+    pub snr_hist: HashMap<i32, u64>,
+    pub signal_hist: HashMap<i32, u64>,
+}
+
+impl From<Edge> for EdgeAggregate {
+    fn from(edge: Edge) -> Self {
+        Self {
+            _key: edge._key,
+            beacon_pub_key: edge.beacon_pub_key.to_string(),
+            witness_pub_key: edge.witness_pub_key.to_string(),
+            beacon_hotspot_key: edge.beacon_hotspot_key,
+            witness_hotspot_key: edge.witness_hotspot_key,
+            distance: edge.distance,
+            count: 1,
+            snr_hist: HashMap::from([(edge.witness_snr, 1)]),
+            signal_hist: HashMap::from([(edge.witness_signal, 1)]),
+            ingest_latency_hist: HashMap::from([(edge.ingest_latency_ms, 1)]),
+            first_seen_unix_ms: edge.seen_unix_ms,
+            last_seen_unix_ms: edge.seen_unix_ms,
+            beacon_regions: edge.beacon_regions,
+            band_mismatch_count: u64::from(edge.band_mismatch),
+            windows: BTreeMap::new(),
+        }
+    }
+}
+
+impl EdgeAggregate {
+    pub fn add(&mut self, edge: Edge) {
+        self.count += 1;
+        *self.snr_hist.entry(edge.witness_snr).or_default() += 1;
+        *self.signal_hist.entry(edge.witness_signal).or_default() += 1;
+        *self
+            .ingest_latency_hist
+            .entry(edge.ingest_latency_ms)
+            .or_default() += 1;
+        self.first_seen_unix_ms = self.first_seen_unix_ms.min(edge.seen_unix_ms);
+        self.last_seen_unix_ms = self.last_seen_unix_ms.max(edge.seen_unix_ms);
+        self.band_mismatch_count += u64::from(edge.band_mismatch);
+    }
+
+    /// Count `edge` in the histograms of its `period` window
+    pub fn add_window(&mut self, period: WindowPeriod, edge: &Edge) {
+        let window = self
+            .windows
+            .entry(window_key(period, edge.seen_unix_ms))
+            .or_default();
+        window.count += 1;
+        *window.snr_hist.entry(edge.witness_snr).or_default() += 1;
+        *window.signal_hist.entry(edge.witness_signal).or_default() += 1;
+    }
+}
+
+/// Key of the day (2023-06-27) or ISO week (2023-W26) of a unix ms timestamp,
+/// ordered like the windows
+pub fn window_key(period: WindowPeriod, unix_ms: i64) -> String {
+    let time = Utc
+        .timestamp_millis_opt(unix_ms)
+        .single()
+        .unwrap_or_default();
+    match period {
+        WindowPeriod::Day => time.format("%Y-%m-%d").to_string(),
+        WindowPeriod::Week => {
+            let week = time.iso_week();
+            format!("{}-W{:02}", week.year(), week.week())
+        }
+    }
+}
+
+/// Id of an edge end: its location, the hashed str_location when the precise
+/// location was minimized, or its pub_key without (or with an omitted)
+/// location, so unlocated edges don't collapse into a single document
+fn location_id(
+    location: Option<u64>,
+    str_location: &Option<String>,
+    pub_key: &PublicKeyBinary,
+) -> String {
+    location
+        .map(|loc| loc.to_string())
+        .or_else(|| str_location.clone())
+        .unwrap_or_else(|| pub_key.to_string())
+}
+
+fn witness_edge_key(beacon_id: &str, witness_id: &str) -> String {
+    format!("beacon_{}_witness_{}", beacon_id, witness_id)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn edge_keys() {
+        let beacon = PublicKeyBinary::from(vec![1; 33]);
+        let witness = PublicKeyBinary::from(vec![2; 33]);
+        assert_eq!(
+            witness_edge_key(
+                &location_id(Some(631210968840182271), &None, &beacon),
+                &location_id(None, &Some("hash".to_string()), &witness)
+            ),
+            "beacon_631210968840182271_witness_hash"
+        );
+        // omitted locations fall back to the pub_keys
+        assert_eq!(location_id(None, &None, &witness), witness.to_string());
+        assert_ne!(
+            location_id(None, &None, &beacon),
+            location_id(None, &None, &witness)
+        );
+    }
+
+    #[test]
+    fn window_keys() {
+        // 2023-06-27 (a Tuesday), 2024-12-30 (ISO week 1 of 2025)
+        assert_eq!(
+            window_key(WindowPeriod::Day, 1_687_888_130_980),
+            "2023-06-27"
+        );
+        assert_eq!(
+            window_key(WindowPeriod::Week, 1_687_888_130_980),
+            "2023-W26"
+        );
+        assert_eq!(
+            window_key(WindowPeriod::Week, 1_735_560_000_000),
+            "2025-W01"
+        );
     }
 }
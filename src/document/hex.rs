@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Activity within a parent H3 cell aggregated within a batch, so the batch can
+/// be upserted into the hexes collection with a single document per cell.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct HexAggregate {
+    /// Parent cell (`parent_str_location`)
+    pub _key: String,
+    /// Keys of the hotspots in the hex which beaconed
+    pub beaconers: BTreeSet<String>,
+    /// Keys of the hotspots in the hex which witnessed a beacon
+    pub witnessers: BTreeSet<String>,
+    pub beacon_count: u64,
+    pub last_activity_unix_ms: i64,
+}
+
+impl HexAggregate {
+    pub fn new(key: &str) -> Self {
+        Self {
+            _key: key.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn add_beacon(&mut self, hotspot_key: &str, ingest_time_unix_ms: i64) {
+        self.beacon_count += 1;
+        self.beaconers.insert(hotspot_key.to_string());
+        self.seen(ingest_time_unix_ms);
+    }
+
+    pub fn add_witness(&mut self, hotspot_key: &str, ingest_time_unix_ms: i64) {
+        self.witnessers.insert(hotspot_key.to_string());
+        self.seen(ingest_time_unix_ms);
+    }
+
+    fn seen(&mut self, ingest_time_unix_ms: i64) {
+        self.last_activity_unix_ms = self.last_activity_unix_ms.max(ingest_time_unix_ms);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn aggregates() {
+        let mut hex = HexAggregate::new("85283473fffffff");
+        hex.add_beacon("a", 2_000);
+        hex.add_beacon("a", 1_000);
+        hex.add_witness("b", 3_000);
+        hex.add_witness("a", 2_500);
+        assert_eq!(hex.beacon_count, 2);
+        assert_eq!(hex.beaconers.len(), 1);
+        assert_eq!(hex.witnessers.len(), 2);
+        assert_eq!(hex.last_activity_unix_ms, 3_000);
+    }
+}
@@ -1,13 +1,20 @@
-use crate::document::{get_name, Beacon, Witness};
+use crate::{
+    document::{get_name, timestamp::now_unix_ms, Beacon, Witness},
+    settings::KeyStrategy,
+};
 use anyhow::{Error, Result};
-use chrono::Utc;
+use data_encoding::BASE32_NOPAD;
 use geojson::Geometry;
 use helium_crypto::PublicKeyBinary;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Hotspot {
-    pub _key: PublicKeyBinary,
+    /// See `hotspot_key`
+    pub _key: String,
+    pub pub_key: PublicKeyBinary,
     pub poc_ids: Vec<String>,
     str_location: Option<String>,
     location: Option<u64>,
@@ -19,10 +26,118 @@ pub struct Hotspot {
     parent_latitude: Option<f64>,
     parent_longitude: Option<f64>,
     parent_geo: Option<Geometry>,
+    #[serde(flatten)]
+    loc_res: BTreeMap<String, String>,
     name: String,
-    last_updated_at: Option<u64>,
+    #[serde(alias = "last_updated_at")]
+    last_updated_unix_ms: Option<i64>,
     pub gain: Option<i32>,
     pub elevation: Option<i32>,
+    /// Ingest time of the poc the hotspot was first seen in (only set on insert)
+    #[serde(alias = "first_seen_unix")]
+    first_seen_unix_ms: i64,
+    /// Maker and asserted metadata, see `with_metadata`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<GatewayMetadata>,
+}
+
+/// Maker and asserted (rather than reported) data of a hotspot, from the
+/// configured metadata csv
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct GatewayMetadata {
+    pub maker: Option<String>,
+    /// Asserted region, e.g. US915
+    pub region: Option<String>,
+    /// Asserted antenna gain (dBi * 10)
+    pub gain: Option<i32>,
+    /// Asserted elevation (m)
+    pub elevation: Option<i32>,
+}
+
+/// Fields of the hotspot location, written as a whole when it moved (along
+/// with the `loc_res{N}` ones)
+const LOCATION_FIELDS: &[&str] = &[
+    "str_location",
+    "location",
+    "latitude",
+    "longitude",
+    "geo",
+    "parent_str_location",
+    "parent_location",
+    "parent_latitude",
+    "parent_longitude",
+    "parent_geo",
+];
+
+/// Document key of the hotspot with `pub_key`, the pub_key string itself or a
+/// short hash of it
+pub fn hotspot_key(strategy: KeyStrategy, pub_key: &PublicKeyBinary) -> String {
+    match strategy {
+        KeyStrategy::PubKey => pub_key.to_string(),
+        KeyStrategy::Blake3 => {
+            let hash = blake3::hash(pub_key.as_ref());
+            BASE32_NOPAD
+                .encode(&hash.as_bytes()[..16])
+                .to_ascii_lowercase()
+        }
+    }
+}
+
+impl Hotspot {
+    /// Key the hotspot with `strategy`, hotspots are built keyed by pub_key
+    pub fn with_key(mut self, strategy: KeyStrategy) -> Self {
+        self._key = hotspot_key(strategy, &self.pub_key);
+        self
+    }
+
+    /// Attach the hotspot's metadata, if any is known
+    pub fn with_metadata(mut self, metadata: Option<&GatewayMetadata>) -> Self {
+        self.metadata = metadata.cloned();
+        self
+    }
+
+    pub fn str_location(&self) -> Option<&str> {
+        self.str_location.as_deref()
+    }
+
+    /// Ingest time of the poc the hotspot was built from
+    pub fn seen_unix_ms(&self) -> i64 {
+        self.first_seen_unix_ms
+    }
+
+    /// The location fields of the hotspot document
+    pub fn location_fields(&self) -> serde_json::Result<Value> {
+        let mut doc = serde_json::to_value(self)?;
+        if let Value::Object(fields) = &mut doc {
+            fields.retain(|field, _| {
+                LOCATION_FIELDS.contains(&field.as_str()) || field.starts_with("loc_res")
+            });
+        }
+        Ok(doc)
+    }
+}
+
+/// Event emitted when a hotspot is seen for the first time ever
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HotspotFirstSeen {
+    pub pub_key: PublicKeyBinary,
+    pub name: String,
+    pub str_location: Option<String>,
+    pub first_seen_unix_ms: i64,
+    /// Lineage id of the message the hotspot was first seen in, see `Source`
+    pub lineage_id: Option<String>,
+}
+
+impl From<&Hotspot> for HotspotFirstSeen {
+    fn from(hotspot: &Hotspot) -> Self {
+        Self {
+            pub_key: hotspot.pub_key.clone(),
+            name: hotspot.name.clone(),
+            str_location: hotspot.str_location.clone(),
+            first_seen_unix_ms: hotspot.first_seen_unix_ms,
+            lineage_id: None,
+        }
+    }
 }
 
 impl TryFrom<&Beacon> for Hotspot {
@@ -31,7 +146,8 @@ impl TryFrom<&Beacon> for Hotspot {
     fn try_from(beacon: &Beacon) -> Result<Self> {
         let name = get_name(&beacon.pub_key)?;
         Ok(Self {
-            _key: beacon.pub_key.clone(),
+            _key: beacon.pub_key.to_string(),
+            pub_key: beacon.pub_key.clone(),
             str_location: beacon.str_location.clone(),
             location: beacon.location,
             latitude: beacon.latitude,
@@ -42,11 +158,14 @@ impl TryFrom<&Beacon> for Hotspot {
             parent_latitude: beacon.parent_latitude,
             parent_longitude: beacon.parent_longitude,
             parent_geo: beacon.parent_geo.clone(),
+            loc_res: beacon.loc_res.clone(),
             name,
             poc_ids: vec![beacon.poc_id.clone()],
-            last_updated_at: Some(Utc::now().timestamp_millis() as u64),
+            last_updated_unix_ms: Some(now_unix_ms()),
             gain: Some(beacon.gain),
             elevation: Some(beacon.elevation),
+            first_seen_unix_ms: beacon.ingest_time_unix_ms,
+            metadata: None,
         })
     }
 }
@@ -57,7 +176,8 @@ impl TryFrom<&Witness> for Hotspot {
     fn try_from(witness: &Witness) -> Result<Self> {
         let name = get_name(&witness.pub_key)?;
         Ok(Self {
-            _key: witness.pub_key.clone(),
+            _key: witness.pub_key.to_string(),
+            pub_key: witness.pub_key.clone(),
             str_location: witness.str_location.clone(),
             location: witness.location,
             latitude: witness.latitude,
@@ -68,11 +188,67 @@ impl TryFrom<&Witness> for Hotspot {
             parent_latitude: witness.parent_latitude,
             parent_longitude: witness.parent_longitude,
             parent_geo: witness.parent_geo.clone(),
+            loc_res: witness.loc_res.clone(),
             name,
             poc_ids: vec![],
-            last_updated_at: Some(Utc::now().timestamp_millis() as u64),
+            last_updated_unix_ms: Some(now_unix_ms()),
             gain: Some(witness.gain),
             elevation: Some(witness.elevation),
+            first_seen_unix_ms: witness.ingest_time_unix_ms,
+            metadata: None,
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        doctor,
+        document::source::Source,
+        handler::sink::{decode_beacons, PocTransforms},
+    };
+    use bytes::BytesMut;
+    use file_store::FileInfo;
+    use std::str::FromStr;
+
+    #[test]
+    fn hashed_keys() {
+        let pub_key = PublicKeyBinary::from(vec![1; 33]);
+        assert_eq!(
+            hotspot_key(KeyStrategy::PubKey, &pub_key),
+            pub_key.to_string()
+        );
+        let key = hotspot_key(KeyStrategy::Blake3, &pub_key);
+        assert_eq!(key.len(), 26);
+        assert!(key
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || ('2'..='7').contains(&c)));
+        assert_eq!(key, hotspot_key(KeyStrategy::Blake3, &pub_key));
+
+        let other = PublicKeyBinary::from(vec![2; 33]);
+        assert_ne!(key, hotspot_key(KeyStrategy::Blake3, &other));
+    }
+
+    #[test]
+    fn location_fields() {
+        let source = Source::new(
+            &FileInfo::from_str("iot_poc.1687888130980.gz").unwrap(),
+            "LoraPocV1",
+        );
+        let raw = doctor::sample_poc().unwrap();
+        let beacon = decode_beacons(
+            vec![BytesMut::from(raw.as_slice())],
+            &source,
+            &PocTransforms::default(),
+        )
+        .pop()
+        .unwrap();
+        let hotspot = Hotspot::try_from(&beacon).unwrap();
+        let fields = hotspot.location_fields().unwrap();
+        assert_eq!(fields["str_location"].as_str(), hotspot.str_location());
+        assert!(fields["latitude"].is_f64());
+        assert!(fields.get("name").is_none());
+        assert!(fields.get("poc_ids").is_none());
+    }
+}
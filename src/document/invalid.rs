@@ -0,0 +1,90 @@
+use crate::document::{get_name, timestamp::unix_ms};
+use anyhow::Result;
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, Utc};
+use file_store::iot_invalid_poc::{IotInvalidBeaconReport, IotInvalidWitnessReport};
+use helium_crypto::PublicKeyBinary;
+use helium_proto::services::poc_lora::{InvalidParticipantSide, InvalidReason};
+use serde::{Deserialize, Serialize};
+
+/// Beacon report which failed verification
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InvalidBeacon {
+    /// `<pub_key>_<received_unix_ms>`
+    pub _key: String,
+    pub pub_key: PublicKeyBinary,
+    pub name: String,
+    pub reason: InvalidReason,
+    pub received_timestamp: DateTime<Utc>,
+    pub received_unix_ms: i64,
+    /// Base64 beacon data, matches the `data` of witnesses of this beacon
+    pub data: String,
+    pub frequency: u64,
+    pub channel: i32,
+    pub tx_power: i32,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl TryFrom<&IotInvalidBeaconReport> for InvalidBeacon {
+    type Error = anyhow::Error;
+
+    fn try_from(invalid: &IotInvalidBeaconReport) -> Result<Self> {
+        let report = &invalid.report;
+        let received_unix_ms = unix_ms(invalid.received_timestamp);
+        Ok(Self {
+            _key: format!("{}_{}", report.pub_key, received_unix_ms),
+            name: get_name(&report.pub_key)?,
+            pub_key: report.pub_key.clone(),
+            reason: invalid.reason,
+            received_timestamp: invalid.received_timestamp,
+            received_unix_ms,
+            data: general_purpose::STANDARD.encode(&report.data),
+            frequency: report.frequency,
+            channel: report.channel,
+            tx_power: report.tx_power,
+            timestamp: report.timestamp,
+        })
+    }
+}
+
+/// Witness report which failed verification
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InvalidWitness {
+    /// `<pub_key>_<received_unix_ms>`
+    pub _key: String,
+    pub pub_key: PublicKeyBinary,
+    pub name: String,
+    pub reason: InvalidReason,
+    pub participant_side: InvalidParticipantSide,
+    pub received_timestamp: DateTime<Utc>,
+    pub received_unix_ms: i64,
+    /// Base64 data of the witnessed beacon
+    pub data: String,
+    pub signal: i32,
+    pub snr: i32,
+    pub frequency: u64,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl TryFrom<&IotInvalidWitnessReport> for InvalidWitness {
+    type Error = anyhow::Error;
+
+    fn try_from(invalid: &IotInvalidWitnessReport) -> Result<Self> {
+        let report = &invalid.report;
+        let received_unix_ms = unix_ms(invalid.received_timestamp);
+        Ok(Self {
+            _key: format!("{}_{}", report.pub_key, received_unix_ms),
+            name: get_name(&report.pub_key)?,
+            pub_key: report.pub_key.clone(),
+            reason: invalid.reason,
+            participant_side: invalid.participant_side,
+            received_timestamp: invalid.received_timestamp,
+            received_unix_ms,
+            data: general_purpose::STANDARD.encode(&report.data),
+            signal: report.signal,
+            snr: report.snr,
+            frequency: report.frequency,
+            timestamp: report.timestamp,
+        })
+    }
+}
@@ -1,15 +1,64 @@
+use crate::document::timestamp::unix_ms;
 use chrono::{DateTime, Utc};
 use file_store::FileInfo;
 use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// Processing state of a file record
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FileState {
+    /// Not being processed, e.g. failed or reset after its worker went away
+    #[default]
+    Pending,
+    /// Being processed by `worker_id`, which refreshes `heartbeat_unix_ms`
+    Processing,
+    Done,
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct IotPocFile {
     pub _key: String,
     pub timestamp: DateTime<Utc>,
-    pub unix_ts: i64,
+    #[serde(alias = "unix_ts")]
+    pub timestamp_unix_ms: i64,
     pub size: usize,
     pub done: bool,
     pub retries: u8,
+    #[serde(default)]
+    pub state: FileState,
+    /// Worker processing the file, see `worker_id`
+    #[serde(default)]
+    pub worker_id: Option<String>,
+    #[serde(default)]
+    pub heartbeat_unix_ms: Option<i64>,
+    /// Outcome of processing the file, recorded once it is done
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<FileStats>,
+}
+
+/// Counts and duration of processing a file (or a chunk of it)
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileStats {
+    /// Messages decoded, pocs of iot_poc files
+    pub decoded: u64,
+    /// Messages failing to be read, decoded or converted, which are skipped
+    pub decode_errors: u64,
+    /// Stored beacons, none are new for already ingested pocs
+    pub beacons: u64,
+    /// Witnesses of the stored beacons
+    pub witnesses: u64,
+    pub duration_ms: i64,
+}
+
+impl FileStats {
+    pub fn add(&mut self, other: &FileStats) {
+        self.decoded += other.decoded;
+        self.decode_errors += other.decode_errors;
+        self.beacons += other.beacons;
+        self.witnesses += other.witnesses;
+        self.duration_ms += other.duration_ms;
+    }
 }
 
 impl From<&FileInfo> for IotPocFile {
@@ -18,9 +67,88 @@ impl From<&FileInfo> for IotPocFile {
             _key: fi.key.clone(),
             size: fi.size,
             timestamp: fi.timestamp,
-            unix_ts: fi.timestamp.timestamp_millis(),
+            timestamp_unix_ms: unix_ms(fi.timestamp),
             done: false,
             retries: 0,
+            state: FileState::Pending,
+            worker_id: None,
+            heartbeat_unix_ms: None,
+            stats: None,
+        }
+    }
+}
+
+impl IotPocFile {
+    /// Record of `fi` being processed by this worker
+    pub fn processing(fi: &FileInfo) -> Self {
+        Self {
+            state: FileState::Processing,
+            worker_id: Some(worker_id().to_string()),
+            heartbeat_unix_ms: Some(unix_ms(Utc::now())),
+            ..Self::from(fi)
         }
     }
 }
+
+static WORKER_ID: OnceLock<String> = OnceLock::new();
+
+/// Id of this process on file records, `<hostname>-<pid>`
+pub fn worker_id() -> &'static str {
+    WORKER_ID.get_or_init(|| {
+        let hostname = std::env::var("HOSTNAME")
+            .ok()
+            .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+            .map(|hostname| hostname.trim().to_string())
+            .filter(|hostname| !hostname.is_empty())
+            .unwrap_or_else(|| "unknown".to_string());
+        format!("{hostname}-{}", std::process::id())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn processing_record() {
+        let fi = FileInfo::from_str("iot_poc.1687888130980.gz").unwrap();
+        let record = IotPocFile::processing(&fi);
+        assert_eq!(record.state, FileState::Processing);
+        assert_eq!(record.worker_id.as_deref(), Some(worker_id()));
+        assert!(worker_id().ends_with(&format!("-{}", std::process::id())));
+
+        // records written before states were tracked
+        let mut legacy = serde_json::to_value(IotPocFile::from(&fi)).unwrap();
+        for field in ["state", "worker_id", "heartbeat_unix_ms"] {
+            legacy.as_object_mut().unwrap().remove(field);
+        }
+        let legacy: IotPocFile = serde_json::from_value(legacy).unwrap();
+        assert_eq!(legacy.state, FileState::Pending);
+        assert_eq!(legacy.stats, None);
+    }
+
+    #[test]
+    fn add_stats() {
+        let chunk = FileStats {
+            decoded: 10,
+            decode_errors: 1,
+            beacons: 8,
+            witnesses: 40,
+            duration_ms: 0,
+        };
+        let mut stats = FileStats::default();
+        stats.add(&chunk);
+        stats.add(&chunk);
+        assert_eq!(
+            stats,
+            FileStats {
+                decoded: 20,
+                decode_errors: 2,
+                beacons: 16,
+                witnesses: 80,
+                duration_ms: 0,
+            }
+        );
+    }
+}
@@ -2,8 +2,62 @@ use anyhow::Result;
 use geojson::Geometry;
 use h3o::{geom::ToGeo, CellIndex, LatLng, Resolution};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-const PARENT_RESOLUTION: u8 = 5;
+pub const DEFAULT_PARENT_RESOLUTION: u8 = 5;
+
+/// H3 resolutions of the cells derived from the locations of decoded documents,
+/// see `PocTransforms::resolutions`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CellResolutions {
+    /// Resolution of the `parent_*` cells
+    pub parent: u8,
+    /// Resolutions of the `loc_res{N}` cells
+    pub loc_res: Vec<u8>,
+}
+
+impl CellResolutions {
+    /// Resolutions of the given (0-15) parent and `loc_res{N}` cells
+    pub fn new(parent: u8, loc_res: &[u8]) -> Result<Self> {
+        for resolution in std::iter::once(&parent).chain(loc_res) {
+            Resolution::try_from(*resolution)?;
+        }
+        Ok(Self {
+            parent,
+            loc_res: loc_res.to_vec(),
+        })
+    }
+
+    /// `loc_res{N}` cells of a location, see `loc_res_at`
+    pub fn loc_res(&self, location: Option<u64>) -> Result<BTreeMap<String, String>> {
+        loc_res_at(location, &self.loc_res)
+    }
+}
+
+impl Default for CellResolutions {
+    fn default() -> Self {
+        Self {
+            parent: DEFAULT_PARENT_RESOLUTION,
+            loc_res: vec![],
+        }
+    }
+}
+
+/// `loc_res{N}` cells (H3 strings) of a location at each of `resolutions`,
+/// leaving out the ones finer than the location
+pub fn loc_res_at(location: Option<u64>, resolutions: &[u8]) -> Result<BTreeMap<String, String>> {
+    let mut cells = BTreeMap::new();
+    let Some(h3index) = location else {
+        return Ok(cells);
+    };
+    let cell = CellIndex::try_from(h3index)?;
+    for resolution in resolutions {
+        if let Some(parent) = cell.parent(Resolution::try_from(*resolution)?) {
+            cells.insert(format!("loc_res{resolution}"), parent.to_string());
+        }
+    }
+    Ok(cells)
+}
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct LocData {
@@ -73,11 +127,12 @@ impl ParentLocData {
         }
     }
 
-    pub fn from_h3(location: Option<u64>) -> Result<ParentLocData> {
+    /// Parent cell at `resolution`, none if the location is coarser
+    pub fn from_h3_at(location: Option<u64>, resolution: u8) -> Result<ParentLocData> {
         match location {
             Some(h3index) => {
                 let cell = CellIndex::try_from(h3index)?;
-                match cell.parent(Resolution::try_from(PARENT_RESOLUTION)?) {
+                match cell.parent(Resolution::try_from(resolution)?) {
                     Some(parent) => {
                         let latlng = LatLng::from(parent);
                         let str_loc = parent.to_string();
@@ -126,4 +181,39 @@ mod test {
         // The latlng match upto 4 decimals (not a huge deal as long as the above asserts work)
         // assert_eq!(coord, cell_coords);
     }
+
+    #[test]
+    fn parent_resolutions() {
+        let cell = LatLng::new(37.769377, -122.388903)
+            .unwrap()
+            .to_cell(Resolution::Twelve);
+        let parent = ParentLocData::from_h3_at(Some(u64::from(cell)), 7).unwrap();
+        let expected = cell.parent(Resolution::Seven).unwrap();
+        assert_eq!(parent.loc, Some(u64::from(expected)));
+        assert_eq!(parent.str_loc, Some(expected.to_string()));
+
+        let coarse = cell.parent(Resolution::Three).unwrap();
+        let parent = ParentLocData::from_h3_at(Some(u64::from(coarse)), 7).unwrap();
+        assert!(parent.loc.is_none());
+        assert!(CellResolutions::new(16, &[]).is_err());
+    }
+
+    #[test]
+    fn multiple_resolutions() {
+        let cell = LatLng::new(37.769377, -122.388903)
+            .unwrap()
+            .to_cell(Resolution::Eight);
+        let cells = loc_res_at(Some(u64::from(cell)), &[5, 7, 9]).unwrap();
+        assert_eq!(cells.len(), 2);
+        assert_eq!(
+            cells["loc_res5"],
+            cell.parent(Resolution::Five).unwrap().to_string()
+        );
+        assert_eq!(
+            cells["loc_res7"],
+            cell.parent(Resolution::Seven).unwrap().to_string()
+        );
+        assert!(loc_res_at(None, &[5]).unwrap().is_empty());
+        assert!(CellResolutions::new(5, &[5, 16]).is_err());
+    }
 }
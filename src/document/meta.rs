@@ -0,0 +1,149 @@
+use crate::{
+    document::{
+        source::ETL_VERSION, timestamp::now_unix_ms, BEACON_COLLECTION, FILES_COLLECTION,
+        WITNESS_EDGE_COLLECTION,
+    },
+    settings::{ArangoDBSettings, IndexConfig},
+};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+/// Version of the stored document schemas, bumped on incompatible changes
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Self-description of a collection, refreshed whenever ingestion starts, so
+/// consumers can check what the data in it guarantees
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CollectionMeta {
+    /// Configured collection name
+    pub _key: String,
+    /// Default collection name, i.e. what the collection holds
+    pub kind: String,
+    pub schema_version: u32,
+    /// Version of the ETL which last refreshed the metadata
+    pub etl_version: String,
+    /// Indexes the ETL maintains on the collection
+    pub indexes: Vec<IndexConfig>,
+    /// Days after which documents are removed, None if they are kept forever
+    pub retention_days: Option<u32>,
+    pub updated_unix_ms: i64,
+}
+
+impl CollectionMeta {
+    /// Metadata of every collection as configured by `settings`
+    pub fn all(settings: &ArangoDBSettings) -> Vec<Self> {
+        let indexes = settings.all_indexes();
+        let updated_unix_ms = now_unix_ms();
+        settings
+            .collections
+            .all()
+            .into_iter()
+            .map(|(kind, name)| Self {
+                _key: name.to_string(),
+                kind: kind.to_string(),
+                schema_version: SCHEMA_VERSION,
+                etl_version: ETL_VERSION.to_string(),
+                indexes: indexes
+                    .iter()
+                    .filter(|index| index.collection == name)
+                    .cloned()
+                    .collect(),
+                retention_days: retention_days(settings, kind),
+                updated_unix_ms,
+            })
+            .collect()
+    }
+}
+
+/// How the metadata stored by earlier runs compares with this binary
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionCheck {
+    /// Written by this or older versions
+    Compatible,
+    /// Written by a newer ETL with the same schema, document shapes are unchanged
+    NewerEtl(String),
+    /// Written by a newer schema, this binary would downgrade document shapes
+    NewerSchema(u32),
+}
+
+/// Compare the stored collection metadata with this binary's versions.
+/// Unparsable ETL versions are ignored.
+pub fn check_versions(stored: &[CollectionMeta]) -> VersionCheck {
+    if let Some(schema_version) = stored
+        .iter()
+        .map(|meta| meta.schema_version)
+        .filter(|version| *version > SCHEMA_VERSION)
+        .max()
+    {
+        return VersionCheck::NewerSchema(schema_version);
+    }
+    let current = Version::parse(ETL_VERSION).ok();
+    stored
+        .iter()
+        .filter_map(|meta| Version::parse(&meta.etl_version).ok())
+        .filter(|version| current.as_ref().map_or(false, |current| version > current))
+        .max()
+        .map_or(VersionCheck::Compatible, |version| {
+            VersionCheck::NewerEtl(version.to_string())
+        })
+}
+
+fn retention_days(settings: &ArangoDBSettings, kind: &str) -> Option<u32> {
+    let retention = settings.retention.as_ref()?;
+    match kind {
+        BEACON_COLLECTION => retention.beacon_days,
+        FILES_COLLECTION => retention.file_days,
+        WITNESS_EDGE_COLLECTION => retention.edge_days,
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn describes_every_collection() {
+        let settings: ArangoDBSettings = serde_json::from_value(json!({
+            "collections": { "beacons": "iot_beacons" },
+            "retention": { "beacon_days": 90 },
+        }))
+        .unwrap();
+        let metas = CollectionMeta::all(&settings);
+        assert_eq!(metas.len(), settings.collections.all().len());
+
+        let beacons = metas.iter().find(|m| m.kind == "beacons").unwrap();
+        assert_eq!(beacons._key, "iot_beacons");
+        assert_eq!(beacons.retention_days, Some(90));
+        assert!(beacons
+            .indexes
+            .iter()
+            .all(|index| index.collection == "iot_beacons"));
+        assert!(!beacons.indexes.is_empty());
+
+        let hotspots = metas.iter().find(|m| m.kind == "hotspots").unwrap();
+        assert_eq!(hotspots.retention_days, None);
+    }
+
+    #[test]
+    fn versions() {
+        let settings: ArangoDBSettings = serde_json::from_value(json!({})).unwrap();
+        let mut metas = CollectionMeta::all(&settings);
+        assert_eq!(check_versions(&metas), VersionCheck::Compatible);
+
+        metas[0].etl_version = "0.0.1".to_string();
+        metas[1].etl_version = "999.0.0".to_string();
+        metas[2].etl_version = "unknown".to_string();
+        assert_eq!(
+            check_versions(&metas),
+            VersionCheck::NewerEtl("999.0.0".to_string())
+        );
+
+        metas[3].schema_version = SCHEMA_VERSION + 1;
+        assert_eq!(
+            check_versions(&metas),
+            VersionCheck::NewerSchema(SCHEMA_VERSION + 1)
+        );
+    }
+}
@@ -1,8 +1,20 @@
+pub mod annotation;
 pub mod beacon;
+pub mod checkpoint;
+pub mod dead_letter;
 pub mod edge;
+pub mod hex;
 pub mod hotspot;
+pub mod invalid;
 pub mod iot_poc_file;
 pub mod loc_data;
+pub mod meta;
+pub mod privacy;
+pub mod raw_poc;
+pub mod region;
+pub mod reward;
+pub mod source;
+pub mod timestamp;
 pub mod witness;
 
 pub use beacon::Beacon;
@@ -18,6 +30,18 @@ pub const BEACON_COLLECTION: &str = "beacons";
 pub const HOTSPOT_COLLECTION: &str = "hotspots";
 pub const WITNESS_EDGE_COLLECTION: &str = "witnesses";
 pub const FILES_COLLECTION: &str = "files";
+pub const DEAD_LETTER_COLLECTION: &str = "dead_letters";
+pub const MAINTENANCE_COLLECTION: &str = "maintenance";
+pub const HOTSPOT_STATS_COLLECTION: &str = "hotspot_stats";
+pub const REWARDS_COLLECTION: &str = "rewards";
+pub const INVALID_BEACON_COLLECTION: &str = "invalid_beacons";
+pub const INVALID_WITNESS_COLLECTION: &str = "invalid_witnesses";
+pub const RAW_POC_COLLECTION: &str = "raw_pocs";
+pub const VERIFICATION_RUNS_COLLECTION: &str = "verification_runs";
+pub const CHECKPOINTS_COLLECTION: &str = "checkpoints";
+pub const META_COLLECTION: &str = "collection_meta";
+pub const HEX_COLLECTION: &str = "hexes";
+pub const AUDIT_LOG_COLLECTION: &str = "audit_log";
 
 pub fn get_name(pub_key: &PublicKeyBinary) -> Result<String> {
     Ok(pub_key.to_string().parse::<AnimalName>()?.to_string())
@@ -0,0 +1,148 @@
+use crate::settings::{PrivacyMode, PrivacySettings};
+use anyhow::Result;
+use geojson::Geometry;
+use h3o::CellIndex;
+use sha2::{Digest, Sha256};
+
+/// Precise location fields shared by beacons and witnesses
+pub struct PreciseLocation<'a> {
+    pub location: &'a mut Option<u64>,
+    pub str_location: &'a mut Option<String>,
+    pub latitude: &'a mut Option<f64>,
+    pub longitude: &'a mut Option<f64>,
+    pub geo: &'a mut Option<Geometry>,
+}
+
+impl PreciseLocation<'_> {
+    /// Minimize the location if it is finer than the configured resolution.
+    /// Parent location fields are left untouched.
+    pub fn minimize(self, privacy: &PrivacySettings) -> Result<()> {
+        let Some(location) = *self.location else {
+            return Ok(());
+        };
+        let cell = CellIndex::try_from(location)?;
+        if u8::from(cell.resolution()) <= privacy.resolution {
+            return Ok(());
+        }
+
+        *self.str_location = match privacy.mode {
+            PrivacyMode::Omit => None,
+            PrivacyMode::Hash => Some(hash_location(&privacy.salt, &cell.to_string())),
+        };
+        *self.location = None;
+        *self.latitude = None;
+        *self.longitude = None;
+        *self.geo = None;
+        Ok(())
+    }
+}
+
+fn hash_location(salt: &str, str_location: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(str_location.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        doctor,
+        document::{edge::Edge, hotspot::Hotspot, source::Source, Beacon},
+        handler::sink::{decode_beacons, PocTransforms},
+    };
+    use bytes::BytesMut;
+    use file_store::FileInfo;
+    use helium_crypto::PublicKeyBinary;
+    use std::str::FromStr;
+
+    fn sample_beacon() -> Beacon {
+        let source = Source::new(
+            &FileInfo::from_str("iot_poc.1687888130980.gz").unwrap(),
+            "LoraPocV1",
+        );
+        let raw = doctor::sample_poc().unwrap();
+        decode_beacons(
+            vec![BytesMut::from(raw.as_slice())],
+            &source,
+            &PocTransforms::default(),
+        )
+        .pop()
+        .unwrap()
+    }
+
+    fn privacy(mode: PrivacyMode) -> PrivacySettings {
+        PrivacySettings {
+            resolution: 8,
+            mode,
+            salt: "salt".to_string(),
+        }
+    }
+
+    #[test]
+    fn omitted_beacon_and_hotspots() {
+        let mut beacon = sample_beacon();
+        beacon
+            .minimize_locations(&privacy(PrivacyMode::Omit))
+            .unwrap();
+        let json = serde_json::to_value(&beacon).unwrap();
+        for doc in [&json, &json["witnesses"][0]] {
+            for field in ["location", "str_location", "latitude", "longitude", "geo"] {
+                assert!(doc[field].is_null(), "{field} kept");
+            }
+            assert!(doc["parent_location"].is_u64());
+        }
+
+        let hotspots = [
+            Hotspot::try_from(&beacon).unwrap(),
+            Hotspot::try_from(&beacon.witnesses[0]).unwrap(),
+        ];
+        for hotspot in hotspots {
+            let fields = hotspot.location_fields().unwrap();
+            for field in ["location", "str_location", "latitude", "longitude", "geo"] {
+                assert!(fields[field].is_null(), "{field} kept");
+            }
+            assert!(fields["parent_location"].is_u64());
+        }
+    }
+
+    #[test]
+    fn omitted_edges_stay_apart() {
+        let mut beacon = sample_beacon();
+        let mut other = beacon.witnesses[0].clone();
+        other.pub_key = PublicKeyBinary::from(vec![3; 33]);
+        beacon.witnesses.push(other);
+        beacon
+            .minimize_locations(&privacy(PrivacyMode::Omit))
+            .unwrap();
+
+        let keys: Vec<_> = beacon
+            .witnesses
+            .iter()
+            .map(|witness| Edge::new(&beacon, witness).unwrap()._key)
+            .collect();
+        assert_ne!(keys[0], keys[1]);
+        assert!(keys.iter().all(|key| !key.contains("unknown")));
+        assert!(keys[0].contains(&beacon.witnesses[0].pub_key.to_string()));
+    }
+
+    #[test]
+    fn hashed_locations() {
+        let precise = sample_beacon();
+        let mut beacon = precise.clone();
+        beacon
+            .minimize_locations(&privacy(PrivacyMode::Hash))
+            .unwrap();
+        let hashed = beacon.str_location.clone().unwrap();
+        assert_eq!(hashed.len(), 64);
+        assert_ne!(Some(hashed.clone()), precise.str_location);
+        assert_eq!(beacon.location, None);
+        assert!(beacon.geo.is_none());
+
+        let edge = Edge::new(&beacon, &beacon.witnesses[0]).unwrap();
+        assert!(edge._key.starts_with(&format!("beacon_{hashed}_witness_")));
+        let hotspot = Hotspot::try_from(&beacon).unwrap();
+        assert_eq!(hotspot.str_location(), Some(hashed.as_str()));
+    }
+}
@@ -0,0 +1,24 @@
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+
+/// Original LoraPocV1 bytes of a poc, so it can be re-decoded without its S3 file
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RawPoc {
+    /// poc_id of the beacon
+    pub _key: String,
+    /// Base64 encoded LoraPocV1
+    pub raw: String,
+}
+
+impl RawPoc {
+    pub fn new(poc_id: &str, raw: &[u8]) -> Self {
+        Self {
+            _key: poc_id.to_string(),
+            raw: encode(raw),
+        }
+    }
+}
+
+pub fn encode(raw: &[u8]) -> String {
+    general_purpose::STANDARD.encode(raw)
+}
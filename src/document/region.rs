@@ -0,0 +1,76 @@
+//! LoRaWAN regional plan frequency bands, used to tell whether a witness
+//! reported a frequency its beacon's region plan can't have used.
+
+/// Frequency band (Hz, inclusive) of a regional plan
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegionBand {
+    pub region: &'static str,
+    pub min_hz: u64,
+    pub max_hz: u64,
+}
+
+const fn band(region: &'static str, min_hz: u64, max_hz: u64) -> RegionBand {
+    RegionBand {
+        region,
+        min_hz,
+        max_hz,
+    }
+}
+
+/// Bands of the regional plans in use, several overlap (e.g. US915, AU915 and AS923)
+pub const REGION_BANDS: &[RegionBand] = &[
+    band("US915", 902_000_000, 928_000_000),
+    band("AU915", 915_000_000, 928_000_000),
+    band("AS923", 915_000_000, 928_000_000),
+    band("KR920", 920_900_000, 923_300_000),
+    band("EU868", 863_000_000, 870_000_000),
+    band("RU864", 864_000_000, 870_000_000),
+    band("IN865", 865_000_000, 867_000_000),
+    band("CN470", 470_000_000, 510_000_000),
+    band("EU433", 433_050_000, 434_790_000),
+];
+
+impl RegionBand {
+    pub fn contains(&self, frequency_hz: u64) -> bool {
+        (self.min_hz..=self.max_hz).contains(&frequency_hz)
+    }
+}
+
+/// Regions whose band contains the frequency
+pub fn regions(frequency_hz: u64) -> Vec<&'static str> {
+    REGION_BANDS
+        .iter()
+        .filter(|band| band.contains(frequency_hz))
+        .map(|band| band.region)
+        .collect()
+}
+
+/// Whether the witness frequency lies outside every band containing the beacon
+/// frequency. Beacons on a frequency of no known band never mismatch.
+pub fn band_mismatch(beacon_hz: u64, witness_hz: u64) -> bool {
+    let mut beacon_bands = REGION_BANDS
+        .iter()
+        .filter(|band| band.contains(beacon_hz))
+        .peekable();
+    beacon_bands.peek().is_some() && !beacon_bands.any(|band| band.contains(witness_hz))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mismatch() {
+        // US915 beacon heard on a US915 frequency
+        assert!(!band_mismatch(904_100_000, 904_300_000));
+        // EU868 frequency reported for a US915 beacon
+        assert!(band_mismatch(904_100_000, 868_100_000));
+        // AS923 beacon, US915 witness frequency, overlapping bands
+        assert!(!band_mismatch(923_200_000, 915_200_000));
+        // unknown beacon band
+        assert!(!band_mismatch(1_000, 868_100_000));
+
+        assert_eq!(regions(868_100_000), vec!["EU868", "RU864"]);
+        assert!(regions(1_000).is_empty());
+    }
+}
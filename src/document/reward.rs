@@ -0,0 +1,50 @@
+use crate::{document::hotspot::hotspot_key, settings::KeyStrategy};
+use anyhow::{Context, Result};
+use helium_crypto::PublicKeyBinary;
+use helium_proto::services::poc_lora::{iot_reward_share::Reward as RewardShare, IotRewardShare};
+use serde::{Deserialize, Serialize};
+
+/// Gateway reward of a hotspot for a single reward epoch
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Reward {
+    /// `<pub_key>_<start_period>`
+    pub _key: String,
+    pub pub_key: PublicKeyBinary,
+    /// Document handle of the rewarded hotspot (`hotspots/<hotspot key>`)
+    pub hotspot_id: String,
+    pub start_period_unix_ms: i64,
+    pub end_period_unix_ms: i64,
+    pub beacon_amount: u64,
+    pub witness_amount: u64,
+    pub dc_transfer_amount: u64,
+    pub total_amount: u64,
+}
+
+impl Reward {
+    /// Gateway reward of the share, None for operational and unallocated rewards.
+    /// `hotspots` is the name of the hotspots collection, keyed with `keys`.
+    pub fn from_share(
+        share: IotRewardShare,
+        hotspots: &str,
+        keys: KeyStrategy,
+    ) -> Result<Option<Self>> {
+        let Some(RewardShare::GatewayReward(reward)) = share.reward else {
+            return Ok(None);
+        };
+        let pub_key = PublicKeyBinary::from(reward.hotspot_key);
+        let start_period = i64::try_from(share.start_period).context("invalid start_period")?;
+        let end_period = i64::try_from(share.end_period).context("invalid end_period")?;
+        Ok(Some(Self {
+            _key: format!("{}_{}", pub_key, start_period),
+            hotspot_id: format!("{}/{}", hotspots, hotspot_key(keys, &pub_key)),
+            pub_key,
+            // NOTE: reward periods are in seconds
+            start_period_unix_ms: start_period * 1000,
+            end_period_unix_ms: end_period * 1000,
+            beacon_amount: reward.beacon_amount,
+            witness_amount: reward.witness_amount,
+            dc_transfer_amount: reward.dc_transfer_amount,
+            total_amount: reward.beacon_amount + reward.witness_amount + reward.dc_transfer_amount,
+        }))
+    }
+}
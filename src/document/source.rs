@@ -0,0 +1,107 @@
+use crate::document::timestamp::unix_ms;
+use file_store::FileInfo;
+use serde::{Deserialize, Serialize};
+
+/// Version of the ETL writing the documents
+pub const ETL_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// File type of documents consumed from kafka, their file key is `<topic>/<partition>`
+pub const KAFKA_FILE_TYPE: &str = "kafka";
+
+/// Producer of a document: the oracle output file and message it was decoded
+/// from, and the ETL version which wrote it. Lets analysis segment data by
+/// producer version when oracles change semantics, and trace any document or
+/// event back to the message it came from by its lineage id.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Source {
+    /// Oracle output file type (file prefix), e.g. `iot_poc`
+    pub file_type: String,
+    pub file_key: String,
+    pub file_timestamp_unix_ms: i64,
+    /// Versioned proto message the document was decoded from, e.g. `LoraPocV1`
+    pub message: String,
+    pub etl_version: String,
+    /// Index of the message within its file, None for file level sources
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_index: Option<u64>,
+    /// `<file_key>:<message_index>:<etl_version>`, set with the message index
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lineage_id: Option<String>,
+}
+
+impl Source {
+    pub fn new(file_info: &FileInfo, message: &str) -> Self {
+        Self {
+            file_type: file_info.prefix.clone(),
+            file_key: file_info.key.clone(),
+            file_timestamp_unix_ms: unix_ms(file_info.timestamp),
+            message: message.to_string(),
+            etl_version: ETL_VERSION.to_string(),
+            message_index: None,
+            lineage_id: None,
+        }
+    }
+
+    /// Source of messages consumed from a kafka topic partition, whose message
+    /// indices are the offsets within the partition
+    pub fn kafka(topic: &str, partition: i32, timestamp_unix_ms: i64, message: &str) -> Self {
+        Self {
+            file_type: KAFKA_FILE_TYPE.to_string(),
+            file_key: format!("{topic}/{partition}"),
+            file_timestamp_unix_ms: timestamp_unix_ms,
+            message: message.to_string(),
+            etl_version: ETL_VERSION.to_string(),
+            message_index: None,
+            lineage_id: None,
+        }
+    }
+
+    /// Source of the message at `message_index` of the file
+    pub fn at(&self, message_index: u64) -> Self {
+        Self {
+            message_index: Some(message_index),
+            lineage_id: Some(lineage_id(&self.file_key, message_index, &self.etl_version)),
+            ..self.clone()
+        }
+    }
+
+    /// Source of the message `offset` messages after this one, e.g. of a message
+    /// within a chunk given the source of the chunk's first message
+    pub fn offset(&self, offset: usize) -> Self {
+        self.at(self.message_index.unwrap_or_default() + offset as u64)
+    }
+}
+
+/// Id of the ingest unit a document or event was derived from: the file, the
+/// index of the message within it and the ETL version which decoded it
+pub fn lineage_id(file_key: &str, message_index: u64, etl_version: &str) -> String {
+    format!("{file_key}:{message_index}:{etl_version}")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn from_file_info() {
+        let file_info = FileInfo::from_str("iot_poc.1687888130980.gz").unwrap();
+        let source = Source::new(&file_info, "LoraPocV1");
+        assert_eq!(source.file_type, "iot_poc");
+        assert_eq!(source.file_key, "iot_poc.1687888130980.gz");
+        assert_eq!(source.file_timestamp_unix_ms, 1687888130980);
+        assert_eq!(source.etl_version, ETL_VERSION);
+        assert_eq!(source.lineage_id, None);
+    }
+
+    #[test]
+    fn message_lineage() {
+        let file_info = FileInfo::from_str("iot_poc.1687888130980.gz").unwrap();
+        let chunk = Source::new(&file_info, "LoraPocV1").at(200);
+        let source = chunk.offset(3);
+        assert_eq!(source.message_index, Some(203));
+        assert_eq!(
+            source.lineage_id.unwrap(),
+            format!("iot_poc.1687888130980.gz:203:{ETL_VERSION}")
+        );
+    }
+}
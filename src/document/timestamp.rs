@@ -0,0 +1,122 @@
+//! Time field conventions for stored documents.
+//!
+//! Absolute times are stored as unix epoch milliseconds in fields suffixed
+//! `_unix_ms`, durations carry their unit as suffix (`_ms`, `_secs`). Fields
+//! stored under another name by earlier versions are listed in
+//! `RENAMED_FIELDS`; the document structs keep a serde alias for them and
+//! `migrate timestamps` rewrites existing documents.
+
+use crate::document::{
+    BEACON_COLLECTION, DEAD_LETTER_COLLECTION, FILES_COLLECTION, HOTSPOT_COLLECTION,
+    HOTSPOT_STATS_COLLECTION, MAINTENANCE_COLLECTION, WITNESS_EDGE_COLLECTION,
+};
+use chrono::{DateTime, Utc};
+
+/// Smallest magnitude treated as milliseconds, anything below is taken to be
+/// seconds (1e11 ms is early 1973, 1e11 s is far beyond any ingest time)
+pub const MIN_UNIX_MS: i64 = 100_000_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenamedField {
+    pub collection: &'static str,
+    pub from: &'static str,
+    pub to: &'static str,
+}
+
+impl RenamedField {
+    /// Whether the field holds the earliest time (`first_*`) rather than the
+    /// latest, when both the old and the new field are set
+    pub fn keeps_earliest(&self) -> bool {
+        self.to.starts_with("first_")
+    }
+}
+
+const fn renamed(collection: &'static str, from: &'static str, to: &'static str) -> RenamedField {
+    RenamedField {
+        collection,
+        from,
+        to,
+    }
+}
+
+/// Time fields renamed to the `_unix_ms` convention
+pub const RENAMED_FIELDS: &[RenamedField] = &[
+    renamed(BEACON_COLLECTION, "ingest_time_unix", "ingest_time_unix_ms"),
+    renamed(
+        HOTSPOT_COLLECTION,
+        "last_updated_at",
+        "last_updated_unix_ms",
+    ),
+    renamed(HOTSPOT_COLLECTION, "first_seen_unix", "first_seen_unix_ms"),
+    renamed(
+        WITNESS_EDGE_COLLECTION,
+        "first_seen_unix",
+        "first_seen_unix_ms",
+    ),
+    renamed(
+        WITNESS_EDGE_COLLECTION,
+        "last_seen_unix",
+        "last_seen_unix_ms",
+    ),
+    renamed(
+        WITNESS_EDGE_COLLECTION,
+        "last_updated_at",
+        "last_updated_unix_ms",
+    ),
+    renamed(FILES_COLLECTION, "unix_ts", "timestamp_unix_ms"),
+    renamed(
+        DEAD_LETTER_COLLECTION,
+        "ingest_time_unix",
+        "ingest_time_unix_ms",
+    ),
+    renamed(MAINTENANCE_COLLECTION, "last_run_unix", "last_run_unix_ms"),
+    renamed(HOTSPOT_STATS_COLLECTION, "updated_at", "updated_unix_ms"),
+];
+
+pub fn unix_ms(ts: DateTime<Utc>) -> i64 {
+    ts.timestamp_millis()
+}
+
+pub fn now_unix_ms() -> i64 {
+    unix_ms(Utc::now())
+}
+
+/// Coerce a unix timestamp of unknown unit (seconds or millis) to millis
+pub fn normalize_unix_ms(ts: i64) -> i64 {
+    if ts.abs() < MIN_UNIX_MS {
+        ts * 1000
+    } else {
+        ts
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn normalize() {
+        // 2023-06-27T17:48:50.980Z
+        assert_eq!(normalize_unix_ms(1687888130980), 1687888130980);
+        assert_eq!(normalize_unix_ms(1687888130), 1687888130000);
+        assert_eq!(normalize_unix_ms(0), 0);
+    }
+
+    #[test]
+    fn renamed_fields_follow_convention() {
+        for field in RENAMED_FIELDS {
+            assert!(field.to.ends_with("_unix_ms"), "{field:?}");
+            assert_ne!(field.from, field.to);
+        }
+    }
+
+    #[test]
+    fn first_fields_keep_earliest() {
+        let earliest: Vec<&str> = RENAMED_FIELDS
+            .iter()
+            .filter(|field| field.keeps_earliest())
+            .map(|field| field.from)
+            .collect();
+        assert_eq!(earliest, ["first_seen_unix", "first_seen_unix"]);
+    }
+}
@@ -1,6 +1,11 @@
-use crate::document::{
-    get_name,
-    loc_data::{LocData, ParentLocData},
+use crate::{
+    document::{
+        get_name,
+        loc_data::{CellResolutions, LocData, ParentLocData},
+        privacy::PreciseLocation,
+        timestamp::unix_ms,
+    },
+    settings::PrivacySettings,
 };
 use anyhow::Result;
 use chrono::{DateTime, Utc};
@@ -10,12 +15,16 @@ use helium_crypto::PublicKeyBinary;
 use helium_proto::services::poc_lora::{InvalidParticipantSide, InvalidReason, VerificationStatus};
 use rust_decimal::prelude::ToPrimitive;
 use serde::{Deserialize, Serialize};
-use std::ops::{Deref, DerefMut};
+use std::{
+    collections::BTreeMap,
+    ops::{Deref, DerefMut},
+};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Witness {
     pub ingest_time: DateTime<Utc>,
-    pub ingest_time_unix: i64,
+    #[serde(alias = "ingest_time_unix")]
+    pub ingest_time_unix_ms: i64,
     pub str_location: Option<String>,
     pub location: Option<u64>,
     pub latitude: Option<f64>,
@@ -26,6 +35,9 @@ pub struct Witness {
     pub parent_latitude: Option<f64>,
     pub parent_longitude: Option<f64>,
     pub parent_geo: Option<Geometry>,
+    /// Cells at the configured extra resolutions, as `loc_res{N}` fields
+    #[serde(flatten)]
+    pub loc_res: BTreeMap<String, String>,
     pub gain: i32,
     pub elevation: i32,
     pub hex_scale: Option<f64>,
@@ -44,20 +56,35 @@ pub struct Witness {
     pub distance: f64,
 }
 
-impl TryFrom<&IotVerifiedWitnessReport> for Witness {
-    type Error = anyhow::Error;
+impl Witness {
+    pub fn minimize_locations(&mut self, privacy: &PrivacySettings) -> Result<()> {
+        PreciseLocation {
+            location: &mut self.location,
+            str_location: &mut self.str_location,
+            latitude: &mut self.latitude,
+            longitude: &mut self.longitude,
+            geo: &mut self.geo,
+        }
+        .minimize(privacy)
+    }
+}
 
-    fn try_from(witness_report: &IotVerifiedWitnessReport) -> Result<Self> {
+impl Witness {
+    /// Witness of a report, with the cells derived from its location at `resolutions`
+    pub fn from_report(
+        witness_report: &IotVerifiedWitnessReport,
+        resolutions: &CellResolutions,
+    ) -> Result<Self> {
         let location = witness_report.location;
         let witness_ts = witness_report.received_timestamp;
-        let witness_ingest_unix = witness_ts.timestamp_millis();
+        let witness_ingest_unix_ms = unix_ms(witness_ts);
         let loc_data = LocData::from_h3(location)?;
-        let parent_loc_data = ParentLocData::from_h3(location)?;
+        let parent_loc_data = ParentLocData::from_h3_at(location, resolutions.parent)?;
         let name = get_name(&witness_report.report.pub_key)?;
 
         Ok(Self {
             ingest_time: witness_ts,
-            ingest_time_unix: witness_ingest_unix,
+            ingest_time_unix_ms: witness_ingest_unix_ms,
             location,
             str_location: loc_data.str_loc,
             latitude: loc_data.lat,
@@ -68,6 +95,7 @@ impl TryFrom<&IotVerifiedWitnessReport> for Witness {
             parent_latitude: parent_loc_data.lat,
             parent_longitude: parent_loc_data.lng,
             parent_geo: parent_loc_data.geo,
+            loc_res: resolutions.loc_res(location)?,
             name,
             hex_scale: witness_report.hex_scale.to_f64(),
             reward_unit: witness_report.reward_unit.to_f64(),
@@ -105,20 +133,19 @@ impl DerefMut for Witnesses {
     }
 }
 
-impl TryFrom<&IotPoc> for Witnesses {
-    type Error = anyhow::Error;
-
-    fn try_from(iot_poc: &IotPoc) -> Result<Self> {
+impl Witnesses {
+    /// Selected and unselected witnesses of a poc, see `Witness::from_report`
+    pub fn from_poc(iot_poc: &IotPoc, resolutions: &CellResolutions) -> Result<Self> {
         // gather all witnesses
         let mut witnesses = vec![];
         for witness_report in iot_poc.selected_witnesses.iter() {
-            let mut witness = Witness::try_from(witness_report)?;
+            let mut witness = Witness::from_report(witness_report, resolutions)?;
             witness.selected = true;
             witnesses.push(witness);
         }
 
         for witness_report in iot_poc.unselected_witnesses.iter() {
-            let mut witness = Witness::try_from(witness_report)?;
+            let mut witness = Witness::from_report(witness_report, resolutions)?;
             witness.selected = false;
             witnesses.push(witness);
         }
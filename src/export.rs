@@ -0,0 +1,380 @@
+use crate::{
+    handler::arangodb::DB,
+    settings::{CollectionNames, ExportFormat},
+};
+use anyhow::{Context, Result};
+#[cfg(feature = "parquet")]
+use arrow::{
+    array::{ArrayRef, Float64Builder, Int64Builder, StringBuilder},
+    datatypes::{DataType, Field, Schema},
+    record_batch::RecordBatch,
+};
+#[cfg(feature = "parquet")]
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use serde_json::Value;
+#[cfg(feature = "parquet")]
+use std::sync::Arc;
+use std::{fs::File, path::Path};
+
+/// Collections which can be exported, as rows of selected fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportCollection {
+    /// One row per hotspot, with its (parent) location
+    Hotspots,
+    /// One row per witness edge, with the beacon and witness locations
+    Witnesses,
+    /// One row per hotspot_stats document (see the stats command)
+    HotspotStats,
+}
+
+/// Type of an exported column, its parquet type and how json values are read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    /// Strings as is, other values as json
+    Text,
+    Int,
+    Float,
+}
+
+impl ExportCollection {
+    pub fn all() -> [Self; 2] {
+        [Self::Hotspots, Self::Witnesses]
+    }
+
+    /// The rollups exported on a schedule: hotspot stats and the witness edges,
+    /// which hold the per-edge counts and histograms
+    pub fn rollups() -> [Self; 2] {
+        [Self::HotspotStats, Self::Witnesses]
+    }
+
+    /// Name of the exported file, e.g. `hotspots.csv`
+    pub fn file_name(&self, extension: &str) -> String {
+        match self {
+            Self::Hotspots => format!("hotspots.{extension}"),
+            Self::Witnesses => format!("witnesses.{extension}"),
+            Self::HotspotStats => format!("hotspot_stats.{extension}"),
+        }
+    }
+
+    /// The (possibly namespaced) collection the rows are read from
+    pub fn collection<'a>(&self, names: &'a CollectionNames) -> &'a str {
+        match self {
+            Self::Hotspots => &names.hotspots,
+            Self::Witnesses => &names.witnesses,
+            Self::HotspotStats => &names.hotspot_stats,
+        }
+    }
+
+    /// Column names, the AQL expressions (over the document `d`, the edge's
+    /// beacon `b` and witness `w` hotspots) they are read from and their types.
+    /// The first column is always the document key, used to page through the
+    /// collection.
+    pub fn columns(&self) -> &'static [(&'static str, &'static str, ColumnType)] {
+        use ColumnType::*;
+        match self {
+            Self::Hotspots => &[
+                ("key", "d._key", Text),
+                ("pub_key", "d.pub_key || d._key", Text),
+                ("name", "d.name", Text),
+                ("str_location", "d.str_location", Text),
+                ("latitude", "d.latitude", Float),
+                ("longitude", "d.longitude", Float),
+                ("parent_str_location", "d.parent_str_location", Text),
+                ("gain", "d.gain", Int),
+                ("elevation", "d.elevation", Int),
+                ("first_seen_unix_ms", "d.first_seen_unix_ms", Int),
+                ("last_updated_unix_ms", "d.last_updated_unix_ms", Int),
+            ],
+            Self::Witnesses => &[
+                ("key", "d._key", Text),
+                (
+                    "beacon_pub_key",
+                    "b.pub_key || PARSE_IDENTIFIER(d._from).key",
+                    Text,
+                ),
+                (
+                    "witness_pub_key",
+                    "w.pub_key || PARSE_IDENTIFIER(d._to).key",
+                    Text,
+                ),
+                ("beacon_latitude", "b.latitude", Float),
+                ("beacon_longitude", "b.longitude", Float),
+                ("witness_latitude", "w.latitude", Float),
+                ("witness_longitude", "w.longitude", Float),
+                ("distance", "d.distance", Float),
+                ("count", "d.count", Int),
+                ("first_seen_unix_ms", "d.first_seen_unix_ms", Int),
+                ("last_seen_unix_ms", "d.last_seen_unix_ms", Int),
+                ("band_mismatch_count", "d.band_mismatch_count", Int),
+            ],
+            Self::HotspotStats => &[
+                ("key", "d._key", Text),
+                ("distinct_witnesses", "d.distinct_witnesses", Int),
+                ("witness_count", "d.witness_count", Int),
+                ("witness_entropy", "d.witness_entropy", Float),
+                (
+                    "witness_entropy_normalized",
+                    "d.witness_entropy_normalized",
+                    Float,
+                ),
+                ("hex_overlap_max", "d.hex_overlap_max", Float),
+                ("beacon_interval_count", "d.beacon_interval.count", Int),
+                (
+                    "beacon_interval_mean_secs",
+                    "d.beacon_interval.mean_secs",
+                    Float,
+                ),
+                ("beacon_interval_cv", "d.beacon_interval.cv", Float),
+                ("snapshot_unix_ms", "d.snapshot_unix_ms", Int),
+                ("watermark_unix_ms", "d.watermark_unix_ms", Int),
+            ],
+        }
+    }
+
+    /// AQL returning a page of rows (arrays in column order) with keys after
+    /// `@after`, binds `@@collection`, `@after` and `@limit`
+    pub fn query(&self) -> String {
+        let lets = match self {
+            Self::Hotspots | Self::HotspotStats => "",
+            Self::Witnesses => "LET b = DOCUMENT(d._from) LET w = DOCUMENT(d._to) ",
+        };
+        let exprs: Vec<&str> = self.columns().iter().map(|(_, expr, _)| *expr).collect();
+        format!(
+            "FOR d IN @@collection FILTER d._key > @after SORT d._key LIMIT @limit {lets}RETURN [{}]",
+            exprs.join(", ")
+        )
+    }
+
+    pub fn header(&self) -> Vec<&'static str> {
+        self.columns().iter().map(|(name, _, _)| *name).collect()
+    }
+
+    /// Arrow schema of the columns, all nullable
+    #[cfg(feature = "parquet")]
+    pub fn schema(&self) -> Schema {
+        let fields: Vec<Field> = self
+            .columns()
+            .iter()
+            .map(|(name, _, column_type)| {
+                let data_type = match column_type {
+                    ColumnType::Text => DataType::Utf8,
+                    ColumnType::Int => DataType::Int64,
+                    ColumnType::Float => DataType::Float64,
+                };
+                Field::new(*name, data_type, true)
+            })
+            .collect();
+        Schema::new(fields)
+    }
+}
+
+/// CSV cell of an exported value: strings as is, null as an empty cell and
+/// arrays or objects as json
+pub fn csv_cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        _ => value.to_string(),
+    }
+}
+
+/// Record batch of a page of exported rows (arrays in column order). Values
+/// which don't fit their column's type are null.
+#[cfg(feature = "parquet")]
+pub fn record_batch(collection: ExportCollection, rows: &[Vec<Value>]) -> Result<RecordBatch> {
+    let columns = collection.columns();
+    let mut arrays: Vec<ArrayRef> = Vec::with_capacity(columns.len());
+    for (i, (_, _, column_type)) in columns.iter().enumerate() {
+        let values = rows.iter().map(|row| row.get(i).unwrap_or(&Value::Null));
+        let array: ArrayRef = match column_type {
+            ColumnType::Text => {
+                let mut builder = StringBuilder::new();
+                for value in values {
+                    match value {
+                        Value::Null => builder.append_null(),
+                        value => builder.append_value(csv_cell(value)),
+                    }
+                }
+                Arc::new(builder.finish())
+            }
+            ColumnType::Int => {
+                let mut builder = Int64Builder::new();
+                for value in values {
+                    builder.append_option(value.as_i64());
+                }
+                Arc::new(builder.finish())
+            }
+            ColumnType::Float => {
+                let mut builder = Float64Builder::new();
+                for value in values {
+                    builder.append_option(value.as_f64());
+                }
+                Arc::new(builder.finish())
+            }
+        };
+        arrays.push(array);
+    }
+    Ok(RecordBatch::try_new(Arc::new(collection.schema()), arrays)?)
+}
+
+/// Writer of an export file, pages of rows are appended as they are read
+enum ExportWriter {
+    Csv(csv::Writer<File>),
+    #[cfg(feature = "parquet")]
+    Parquet(ArrowWriter<File>),
+}
+
+impl ExportWriter {
+    fn create(collection: ExportCollection, format: ExportFormat, path: &Path) -> Result<Self> {
+        let create =
+            || File::create(path).with_context(|| format!("failed to create {}", path.display()));
+        Ok(match format {
+            ExportFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(create()?);
+                writer.write_record(collection.header())?;
+                Self::Csv(writer)
+            }
+            #[cfg(feature = "parquet")]
+            ExportFormat::Parquet => {
+                let props = WriterProperties::builder()
+                    .set_compression(Compression::SNAPPY)
+                    .build();
+                Self::Parquet(ArrowWriter::try_new(
+                    create()?,
+                    Arc::new(collection.schema()),
+                    Some(props),
+                )?)
+            }
+            #[cfg(not(feature = "parquet"))]
+            ExportFormat::Parquet => return Err(crate::handler::feature_disabled("parquet")),
+        })
+    }
+
+    #[cfg_attr(not(feature = "parquet"), allow(unused_variables))]
+    fn write(&mut self, collection: ExportCollection, rows: &[Vec<Value>]) -> Result<()> {
+        match self {
+            Self::Csv(writer) => {
+                for row in rows {
+                    writer.write_record(row.iter().map(csv_cell))?;
+                }
+            }
+            #[cfg(feature = "parquet")]
+            Self::Parquet(writer) => writer.write(&record_batch(collection, rows)?)?,
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        match self {
+            Self::Csv(mut writer) => writer.flush()?,
+            #[cfg(feature = "parquet")]
+            Self::Parquet(writer) => {
+                writer.close()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Page through `collection` by key, writing its rows to the `format` file
+/// at `path`. Returns the number of rows written.
+pub async fn export_file(
+    db: &DB,
+    collection: ExportCollection,
+    format: ExportFormat,
+    path: &Path,
+    batch_size: u32,
+) -> Result<usize> {
+    let name = collection.collection(&db.names);
+    let query = collection.query();
+    let mut writer = ExportWriter::create(collection, format, path)?;
+
+    let mut after = String::new();
+    let mut written = 0;
+    loop {
+        let rows = db.export_page(&query, name, &after, batch_size).await?;
+        if !rows.is_empty() {
+            writer.write(collection, &rows)?;
+        }
+        written += rows.len();
+        match rows.last().and_then(|row| row.first()) {
+            Some(key) if rows.len() == batch_size as usize => after = csv_cell(key),
+            _ => break,
+        }
+        tracing::debug!("exported {} {:?} rows", written, collection);
+    }
+    writer.finish()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "parquet")]
+    use arrow::array::{Array, Float64Array, Int64Array};
+    use serde_json::json;
+
+    #[test]
+    fn query_columns() {
+        let query = ExportCollection::Hotspots.query();
+        assert!(query.starts_with("FOR d IN @@collection FILTER d._key > @after"));
+        assert!(query.ends_with("RETURN [d._key, d.pub_key || d._key, d.name, d.str_location, d.latitude, d.longitude, d.parent_str_location, d.gain, d.elevation, d.first_seen_unix_ms, d.last_updated_unix_ms]"));
+
+        let query = ExportCollection::Witnesses.query();
+        assert!(query.contains("LET b = DOCUMENT(d._from) LET w = DOCUMENT(d._to) RETURN"));
+        for collection in [
+            ExportCollection::Hotspots,
+            ExportCollection::Witnesses,
+            ExportCollection::HotspotStats,
+        ] {
+            assert_eq!(collection.columns()[0].1, "d._key");
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "parquet")]
+    fn typed_batch() {
+        let rows = vec![
+            vec![
+                json!("a"),
+                json!(12),
+                json!(40),
+                json!(3.2),
+                json!(0.9),
+                Value::Null,
+            ],
+            vec![json!("b"), json!("12"), Value::Null, json!(1)],
+        ];
+        let batch = record_batch(ExportCollection::HotspotStats, &rows).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(
+            batch.num_columns(),
+            ExportCollection::HotspotStats.columns().len()
+        );
+        let distinct = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(distinct.value(0), 12);
+        // not an int, and missing cells are null
+        assert!(distinct.is_null(1));
+        assert!(batch.column(10).is_null(1));
+        let entropy = batch
+            .column(3)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(entropy.value(0), 3.2);
+        assert_eq!(entropy.value(1), 1.0);
+    }
+
+    #[test]
+    fn cells() {
+        assert_eq!(csv_cell(&Value::Null), "");
+        assert_eq!(csv_cell(&json!("a,b")), "a,b");
+        assert_eq!(csv_cell(&json!(37.5)), "37.5");
+        assert_eq!(csv_cell(&json!(true)), "true");
+        assert_eq!(csv_cell(&json!(["us915"])), r#"["us915"]"#);
+    }
+}
@@ -0,0 +1,109 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Time windows without ingested files, compared against the bucket listing
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GapReport {
+    pub after: DateTime<Utc>,
+    pub before: DateTime<Utc>,
+    pub max_gap_secs: i64,
+    /// Number of done files in the files collection
+    pub ingested_files: usize,
+    /// Number of files in the bucket
+    pub bucket_files: usize,
+    pub gaps: Vec<Gap>,
+}
+
+/// Window between two consecutive ingested files (or the range bounds) longer than the max gap
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Gap {
+    pub after: DateTime<Utc>,
+    pub before: DateTime<Utc>,
+    pub duration_secs: i64,
+    pub kind: GapKind,
+    /// Number of files the bucket lists within the window
+    pub bucket_files: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GapKind {
+    /// The bucket has files which were never ingested, rehydrate the window
+    NotIngested,
+    /// The bucket has no files either, an upstream outage or listing failure
+    EmptyBucket,
+}
+
+/// Find the windows in [after, before] longer than `max_gap` without an
+/// ingested file. `ingested` and `listed` are the (sorted) timestamps of done
+/// files and of files listed in the bucket.
+pub fn find_gaps(
+    after: DateTime<Utc>,
+    before: DateTime<Utc>,
+    ingested: &[DateTime<Utc>],
+    listed: &[DateTime<Utc>],
+    max_gap: Duration,
+) -> Vec<Gap> {
+    let mut bounds = Vec::with_capacity(ingested.len() + 2);
+    bounds.push(after);
+    bounds.extend(ingested.iter().filter(|ts| **ts >= after && **ts <= before));
+    bounds.push(before);
+
+    bounds
+        .windows(2)
+        .enumerate()
+        .filter(|(_, window)| window[1] - window[0] > max_gap)
+        .map(|(i, window)| {
+            let (start, end) = (window[0], window[1]);
+            // NOTE: files at a bound were ingested, except for the range bounds
+            let bucket_files = listed
+                .iter()
+                .filter(|ts| **ts > start || (i == 0 && **ts == start))
+                .filter(|ts| **ts < end || (i == bounds.len() - 2 && **ts == end))
+                .count();
+            Gap {
+                after: start,
+                before: end,
+                duration_secs: (end - start).num_seconds(),
+                kind: if bucket_files > 0 {
+                    GapKind::NotIngested
+                } else {
+                    GapKind::EmptyBucket
+                },
+                bucket_files,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn ts(mins: i64) -> DateTime<Utc> {
+        Utc.timestamp_millis_opt(mins * 60_000).unwrap()
+    }
+
+    #[test]
+    fn gaps() {
+        let ingested = vec![ts(1), ts(2), ts(30), ts(31)];
+        let listed = vec![ts(1), ts(2), ts(10), ts(30), ts(31)];
+        let gaps = find_gaps(ts(0), ts(60), &ingested, &listed, Duration::minutes(15));
+
+        assert_eq!(gaps.len(), 2);
+        assert_eq!((gaps[0].after, gaps[0].before), (ts(2), ts(30)));
+        assert_eq!(gaps[0].kind, GapKind::NotIngested);
+        assert_eq!(gaps[0].bucket_files, 1);
+        assert_eq!((gaps[1].after, gaps[1].before), (ts(31), ts(60)));
+        assert_eq!(gaps[1].kind, GapKind::EmptyBucket);
+        assert_eq!(gaps[1].duration_secs, 29 * 60);
+
+        // nothing ingested at all
+        let gaps = find_gaps(ts(0), ts(60), &[], &[ts(0)], Duration::minutes(15));
+        assert_eq!(gaps.len(), 1);
+        assert_eq!(gaps[0].bucket_files, 1);
+
+        assert!(find_gaps(ts(0), ts(10), &[], &[], Duration::minutes(15)).is_empty());
+    }
+}
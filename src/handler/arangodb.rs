@@ -1,29 +1,92 @@
 use crate::{
+    audit::AuditEntry,
     document::{
-        iot_poc_file::IotPocFile, Beacon, Edge, Hotspot, BEACON_COLLECTION, FILES_COLLECTION,
-        HOTSPOT_COLLECTION, WITNESS_EDGE_COLLECTION,
+        checkpoint::Checkpoint,
+        dead_letter::DeadLetter,
+        edge::EdgeAggregate,
+        hex::HexAggregate,
+        hotspot::{hotspot_key, GatewayMetadata, HotspotFirstSeen},
+        invalid::{InvalidBeacon, InvalidWitness},
+        iot_poc_file::{worker_id, FileStats, IotPocFile},
+        meta::CollectionMeta,
+        raw_poc::{self, RawPoc},
+        region,
+        reward::Reward,
+        source::Source,
+        timestamp::{unix_ms, RenamedField, MIN_UNIX_MS},
+        Beacon, Edge, Hotspot,
     },
-    settings::ArangoDBSettings,
+    handler::{
+        metadata::HotspotMetadata,
+        retry::{is_connection_error, is_not_applied, is_retriable, is_unauthorized, RetryPolicy},
+        shadow::{edge_mismatches, mismatched_fields, ShadowMetrics, ShadowVerifier},
+        sink::{PocTransforms, Sink, Written},
+        tls::{self, TlsClient},
+    },
+    settings::{
+        ArangoAuth, ArangoDBSettings, CollectionNames, IndexConfig, IndexType, RawPocStorage,
+        WindowPeriod,
+    },
+    verify::VerificationReport,
 };
 use anyhow::Result;
 use arangors::{
+    client::ClientExt,
     document::options::InsertOptions,
     index::{Index, IndexSettings},
-    uclient::reqwest::ReqwestClient,
-    AqlQuery, ClientError, Collection, Connection, Database,
+    AqlQuery, ClientError, Collection, Database, GenericConnection,
+};
+use base64::{engine::general_purpose, Engine as _};
+use bytes::BytesMut;
+use chrono::{DateTime, Utc};
+use file_store::{
+    iot_invalid_poc::{IotInvalidBeaconReport, IotInvalidWitnessReport},
+    iot_valid_poc::IotPoc,
+    FileInfo, FileType,
+};
+use helium_crypto::PublicKeyBinary;
+use helium_proto::{
+    services::poc_lora::{
+        IotRewardShare, LoraInvalidBeaconReportV1, LoraInvalidWitnessReportV1, LoraPocV1,
+    },
+    Message,
+};
+use lru::LruCache;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex, RwLock},
 };
-use file_store::{iot_valid_poc::IotPoc, FileInfo};
-use helium_proto::services::poc_lora::LoraPocV1;
-use serde_json::Value;
+use tokio::time::{Duration, Instant};
+use tokio_graceful_shutdown::SubsystemHandle;
 
-type ArangoCollection = Collection<ReqwestClient>;
-type ArangoDatabase = Database<ReqwestClient>;
+type ArangoCollection = Collection<TlsClient>;
+type ArangoDatabase = Database<TlsClient>;
+type Connection = GenericConnection<TlsClient>;
 
 #[derive(Debug)]
 pub struct DB {
-    pub conn: Connection,
-    pub inner: ArangoDatabase,
-    pub collections: Collections,
+    // replaced as a whole when reconnecting, see `DB::reconnect`
+    handles: RwLock<Handles>,
+    // held while reconnecting, so concurrent failures share one reconnect
+    reconnecting: tokio::sync::Mutex<()>,
+    pub names: CollectionNames,
+    // connection settings, kept for reconnecting
+    settings: ArangoDBSettings,
+    indexes: Vec<IndexConfig>,
+    transforms: PocTransforms,
+    // maker and asserted metadata attached to hotspots, None if not configured
+    metadata: Option<HotspotMetadata>,
+    raw_pocs: RawPocStorage,
+    // known hotspot keys with their last written gain, elevation and location, None if disabled
+    known_hotspots: Option<Mutex<LruCache<String, KnownHotspot>>>,
+    // backoff for queries and inserts failing with transient errors
+    retry: RetryPolicy,
+    // read back of sampled written pocs, None if disabled
+    shadow: Option<Arc<ShadowVerifier>>,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -36,7 +99,37 @@ pub enum DBError {
     Other(#[from] anyhow::Error),
 }
 
-#[derive(Debug)]
+/// Connection and database handles of a DB
+#[derive(Debug, Clone)]
+pub struct Handles {
+    pub conn: Connection,
+    pub inner: ArangoDatabase,
+    // used for read-only queries, points at inner unless a read endpoint is configured
+    pub reader: ArangoDatabase,
+    pub collections: Collections,
+    // bumped by every reconnect, see `DB::reconnect_from`
+    generation: u64,
+}
+
+/// Database a query runs on. File bookkeeping and existence checks read the
+/// leader (`Inner`): a lagging follower would make done files look pending and
+/// stored beacons new, which are then ingested (and counted) again.
+#[derive(Debug, Clone, Copy)]
+enum Endpoint {
+    Inner,
+    Reader,
+}
+
+impl Handles {
+    fn db(&self, endpoint: Endpoint) -> &ArangoDatabase {
+        match endpoint {
+            Endpoint::Inner => &self.inner,
+            Endpoint::Reader => &self.reader,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Collections {
     // store beacon json (including a list of witnesses)
     pub beacons: ArangoCollection,
@@ -46,481 +139,2499 @@ pub struct Collections {
     pub witnesses: ArangoCollection,
     // store names of all processed (and in-process) iot-poc files
     pub files: ArangoCollection,
+    // store beacons which could not be inserted (e.g. too large)
+    pub dead_letters: ArangoCollection,
+    // store last run information of maintenance scripts
+    pub maintenance: ArangoCollection,
+    // store computed per-hotspot statistics
+    pub hotspot_stats: ArangoCollection,
+    // store per-epoch hotspot gateway rewards
+    pub rewards: ArangoCollection,
+    // store beacon reports which failed verification
+    pub invalid_beacons: ArangoCollection,
+    // store witness reports which failed verification
+    pub invalid_witnesses: ArangoCollection,
+    // store raw LoraPocV1 bytes keyed by poc_id (if enabled)
+    pub raw_pocs: ArangoCollection,
+    // store reports of `verify --record` runs
+    pub verification_runs: ArangoCollection,
+    // store named ingest watermarks
+    pub checkpoints: ArangoCollection,
+    // store a self-description of every collection, keyed by collection name
+    pub meta: ArangoCollection,
+    // store per parent hex activity (if enabled), keyed by cell
+    pub hexes: ArangoCollection,
+    // store operator-initiated CLI actions
+    pub audit_log: ArangoCollection,
+}
+
+const MAX_FLUSH_ATTEMPTS: u8 = 3;
+
+/// Beacons, hotspots, witness edges, rewards, invalid reports and dead letters
+/// staged for bulk writing
+#[derive(Debug, Default)]
+pub struct Batch {
+    beacons: Vec<Value>,
+    edges: HashMap<String, EdgeAggregate>,
+    hexes: HashMap<String, HexAggregate>,
+    rewards: Vec<Value>,
+    invalid_beacons: Vec<Value>,
+    invalid_witnesses: Vec<Value>,
+    raw_pocs: Vec<Value>,
+    dead_letters: Vec<Value>,
+    hotspot_updates: HashMap<String, HotspotUpdate>,
+    new_hotspots: HashMap<String, NewHotspot>,
+    // keys already stored, looked up per chunk by `DB::check_existing`
+    existing_beacons: HashSet<String>,
+    existing_hotspots: HashSet<String>,
+    // producer of the chunk's first message, recorded on the staged beacons
+    source: Option<Source>,
+    // beacon and witness edge keys of the pocs sampled for shadow verification
+    shadow_beacons: HashSet<String>,
+    shadow_edges: HashSet<String>,
+}
+
+/// Documents of sampled pocs as written by `flush`, to compare with the stored ones
+#[derive(Debug)]
+struct ShadowSample {
+    beacons: Vec<Value>,
+    edges: Vec<EdgeAggregate>,
+}
+
+impl Batch {
+    /// Staged documents of the pocs sampled for shadow verification, None if
+    /// none were sampled
+    fn shadow_sample(&self) -> Option<ShadowSample> {
+        if self.shadow_beacons.is_empty() {
+            return None;
+        }
+        let beacons = self
+            .beacons
+            .iter()
+            .filter(|beacon| {
+                beacon["_key"]
+                    .as_str()
+                    .map_or(false, |key| self.shadow_beacons.contains(key))
+            })
+            .cloned()
+            .collect();
+        let edges = self
+            .shadow_edges
+            .iter()
+            .filter_map(|key| self.edges.get(key))
+            .cloned()
+            .collect();
+        Some(ShadowSample { beacons, edges })
+    }
+
+    /// Counts of the staged documents, e.g. for dry runs
+    fn describe(&self) -> String {
+        format!(
+            "{} beacons, {} witness edges, {} hexes, {} new hotspots, {} hotspot updates, \
+             {} rewards, {} invalid beacons, {} invalid witnesses, {} raw pocs, {} dead letters",
+            self.beacons.len(),
+            self.edges.len(),
+            self.hexes.len(),
+            self.new_hotspots.len(),
+            self.hotspot_updates.len(),
+            self.rewards.len(),
+            self.invalid_beacons.len(),
+            self.invalid_witnesses.len(),
+            self.raw_pocs.len(),
+            self.dead_letters.len()
+        )
+    }
+}
+
+/// Update of an already stored hotspot, applied in bulk on `flush`. Gain and
+/// elevation are only set when they differ from the known hotspot cache's copy,
+/// so unchanged fields aren't rewritten.
+#[derive(Debug, Serialize)]
+struct HotspotUpdate {
+    _key: String,
+    poc_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gain: Option<Option<i32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    elevation: Option<Option<i32>>,
+    /// Location fields, only set when they differ from the known copy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    location: Option<Value>,
+    /// Ingest time of the poc the location was seen in
+    seen_unix_ms: i64,
+    /// Metadata, only set when it differs from the known copy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<GatewayMetadata>,
+}
+
+/// Hotspot not found by `check_existing`, upserted in bulk on `flush`. Later
+/// occurrences in the same batch are staged as a `HotspotUpdate` of it.
+#[derive(Debug, Serialize)]
+struct NewHotspot {
+    hotspot: Value,
+    location: Value,
+    seen_unix_ms: i64,
+    /// Fields written by the upsert
+    #[serde(skip)]
+    staged: KnownHotspot,
+}
+
+/// Last written gain, elevation and location of a hotspot, see `DB::known_hotspots`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct KnownHotspot {
+    gain: Option<i32>,
+    elevation: Option<i32>,
+    str_location: Option<String>,
+    metadata: Option<GatewayMetadata>,
+}
+
+impl From<&Hotspot> for KnownHotspot {
+    fn from(hotspot: &Hotspot) -> Self {
+        Self {
+            gain: hotspot.gain,
+            elevation: hotspot.elevation,
+            str_location: hotspot.str_location().map(str::to_string),
+            metadata: hotspot.metadata.clone(),
+        }
+    }
+}
+
+/// AQL expression of the location update of the `old` hotspot document, moved
+/// to the `loc` location fields in a poc ingested at `seen`: the new location
+/// fields with the move appended to its `location_history`, or nothing if it
+/// didn't move (or the poc is older than its last move)
+fn location_update(old: &str, loc: &str, seen: &str) -> String {
+    format!(
+        "({loc} != null AND {loc}.str_location != null AND {loc}.str_location != {old}.str_location \
+         AND {seen} >= ({old}.location_changed_unix_ms || 0)) ? MERGE({loc}, {{ \
+         location_changed_unix_ms: {seen}, \
+         location_history: APPEND({old}.location_history || [], [{{ \
+         previous_str_location: {old}.str_location, str_location: {loc}.str_location, \
+         changed_unix_ms: {seen} }}]) }}) : {{}}"
+    )
+}
+
+/// `value` if it differs from the `known` one
+fn changed<T: PartialEq + Copy>(known: Option<T>, value: T) -> Option<T> {
+    (known != Some(value)).then_some(value)
+}
+
+impl Batch {
+    /// Batch of documents decoded from the chunk of a file whose first message
+    /// is described by `source`
+    pub fn new(source: Source) -> Self {
+        Self {
+            source: Some(source),
+            ..Default::default()
+        }
+    }
+
+    /// Stage the update of a stored hotspot, with only the fields changed from
+    /// its `known` (cached gain, elevation and location) copy
+    fn add_hotspot_update(
+        &mut self,
+        hotspot: &Hotspot,
+        known: Option<KnownHotspot>,
+    ) -> serde_json::Result<()> {
+        let key = hotspot._key.clone();
+        let gain = changed(known.as_ref().map(|k| k.gain), hotspot.gain);
+        let elevation = changed(known.as_ref().map(|k| k.elevation), hotspot.elevation);
+        // NOTE: metadata is only ever added or replaced, never cleared
+        let metadata = match (&known, &hotspot.metadata) {
+            (Some(known), Some(metadata)) if known.metadata.as_ref() == Some(metadata) => None,
+            (_, metadata) => metadata.clone(),
+        };
+        let location = match known {
+            Some(known) if known.str_location.as_deref() == hotspot.str_location() => None,
+            _ => Some(hotspot.location_fields()?),
+        };
+        let seen_unix_ms = hotspot.seen_unix_ms();
+        match self.hotspot_updates.get_mut(&key) {
+            Some(update) => {
+                update.poc_ids.extend(hotspot.poc_ids.iter().cloned());
+                // NOTE: known is the previous occurrence in this batch then,
+                // an unchanged field keeps the change staged by an earlier one
+                if gain.is_some() {
+                    update.gain = gain;
+                }
+                if elevation.is_some() {
+                    update.elevation = elevation;
+                }
+                // the location seen last wins
+                if metadata.is_some() {
+                    update.metadata = metadata;
+                }
+                if location.is_some()
+                    && (update.location.is_none() || seen_unix_ms >= update.seen_unix_ms)
+                {
+                    update.location = location;
+                    update.seen_unix_ms = seen_unix_ms;
+                }
+            }
+            None => {
+                self.hotspot_updates.insert(
+                    key.clone(),
+                    HotspotUpdate {
+                        _key: key,
+                        poc_ids: hotspot.poc_ids.clone(),
+                        gain,
+                        elevation,
+                        location,
+                        seen_unix_ms,
+                        metadata,
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Stage the upsert of a hotspot not known to be stored
+    fn add_new_hotspot(&mut self, hotspot: &Hotspot) -> serde_json::Result<()> {
+        self.new_hotspots.insert(
+            hotspot._key.clone(),
+            NewHotspot {
+                hotspot: serde_json::to_value(hotspot)?,
+                location: hotspot.location_fields()?,
+                seen_unix_ms: hotspot.seen_unix_ms(),
+                staged: KnownHotspot::from(hotspot),
+            },
+        );
+        Ok(())
+    }
+
+    /// Aggregate of the parent hex `key`, staged on first use
+    fn hex(&mut self, key: &str) -> &mut HexAggregate {
+        self.hexes
+            .entry(key.to_string())
+            .or_insert_with(|| HexAggregate::new(key))
+    }
+
+    /// Stage `edge`, also in its window of `period` if edges are windowed
+    fn add_edge(&mut self, edge: Edge, period: Option<WindowPeriod>) {
+        let windowed = period.map(|period| (period, edge.clone()));
+        match self.edges.get_mut(&edge._key) {
+            Some(aggregate) => aggregate.add(edge),
+            None => {
+                self.edges
+                    .insert(edge._key.clone(), EdgeAggregate::from(edge));
+            }
+        }
+        if let Some((period, edge)) = windowed {
+            if let Some(aggregate) = self.edges.get_mut(&edge._key) {
+                aggregate.add_window(period, &edge);
+            }
+        }
+    }
+}
+
+/// Outcome of populating the collections from a single poc
+#[derive(Debug)]
+pub struct Populated {
+    pub poc_id: String,
+    /// pub_key of the beaconer
+    pub beaconer: String,
+    /// Lineage id of the message the poc was decoded from, see `Source`
+    pub lineage_id: Option<String>,
+    /// Hotspots seen for the first time ever
+    pub new_hotspots: Vec<HotspotFirstSeen>,
+    /// Beacon json, only kept if a notifier publishes it, see `PocTransforms::beacon_payloads`
+    pub beacon: Option<Value>,
+    /// Regions whose band contains the beacon frequency
+    pub regions: Vec<&'static str>,
+    /// Number of witnesses of the beacon
+    pub witnesses: usize,
+}
+
+impl Populated {
+    pub fn new(
+        beacon: &Beacon,
+        mut new_hotspots: Vec<HotspotFirstSeen>,
+        beacon_payload: bool,
+    ) -> Self {
+        let lineage_id = beacon
+            .source
+            .as_ref()
+            .and_then(|source| source.lineage_id.clone());
+        for hotspot in new_hotspots.iter_mut() {
+            hotspot.lineage_id = lineage_id.clone();
+        }
+        let beacon_json = if beacon_payload {
+            serde_json::to_value(beacon)
+                .map_err(|e| tracing::error!("error serializing beacon payload: {:?}", e))
+                .ok()
+        } else {
+            None
+        };
+        Self {
+            poc_id: beacon.poc_id.clone(),
+            beaconer: beacon.pub_key.to_string(),
+            lineage_id,
+            new_hotspots,
+            beacon: beacon_json,
+            regions: region::regions(beacon.frequency),
+            witnesses: beacon.witnesses.len(),
+        }
+    }
+}
+
+/// State of the files collection, see `DB::get_file_status`
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct FileStatus {
+    /// Timestamp of the latest file record, done or not
+    pub latest_unix_ms: Option<i64>,
+    /// Timestamp of the latest done file
+    pub latest_done_unix_ms: Option<i64>,
+    pub not_done: u64,
+    /// Files not done whose retries are used up
+    pub exhausted: u64,
+}
+
+/// Documents removed by `purge`, selected by their time fields
+#[derive(Debug, Clone, Copy)]
+pub enum PurgeTarget {
+    /// Beacons ingested within the range
+    Beacons,
+    /// Witness edges only seen within the range, i.e. without any remaining beacons
+    OrphanedEdges,
+    /// File records of files within the range, so they can be ingested again
+    Files,
+}
+
+impl PurgeTarget {
+    pub const ALL: [Self; 3] = [Self::Beacons, Self::OrphanedEdges, Self::Files];
+
+    pub fn collection<'a>(&self, names: &'a CollectionNames) -> &'a str {
+        match self {
+            Self::Beacons => &names.beacons,
+            Self::OrphanedEdges => &names.witnesses,
+            Self::Files => &names.files,
+        }
+    }
+
+    /// AQL condition on `d` in the [@after, @before) unix ms range
+    fn filter(&self) -> &'static str {
+        match self {
+            Self::Beacons => "d.ingest_time_unix_ms >= @after && d.ingest_time_unix_ms < @before",
+            Self::OrphanedEdges => {
+                "d.first_seen_unix_ms >= @after && d.last_seen_unix_ms < @before"
+            }
+            Self::Files => "d.timestamp_unix_ms >= @after && d.timestamp_unix_ms < @before",
+        }
+    }
 }
 
 #[derive(Debug)]
-enum HotspotType {
+pub enum HotspotType {
     Beacon,
     Witness,
 }
 
 impl DB {
     pub async fn from_settings(settings: &ArangoDBSettings) -> Result<Self> {
-        let conn = Connection::establish_basic_auth(
-            &settings.endpoint,
-            &settings.user,
-            &settings.password,
-        )
-        .await?;
+        let conn = establish(&settings.endpoint, settings).await?;
 
         let existing_databases = conn.accessible_databases().await?;
 
         let (inner, collections) = if !existing_databases.contains_key(&settings.database) {
             let inner = conn.create_database(&settings.database).await?;
-            let cols = create_new_db_and_collections(&inner).await?;
+            let cols = create_new_db_and_collections(
+                &inner,
+                &settings.collections,
+                &settings.all_indexes(),
+            )
+            .await?;
             (inner, cols)
         } else {
             let inner = conn.db(&settings.database).await?;
-            let cols = use_existing_db_and_collections(&inner).await?;
+            let cols = use_existing_db_and_collections(&inner, &settings.collections).await?;
             (inner, cols)
         };
 
+        if let Some(graph) = &settings.graph {
+            ensure_graph(&inner, graph, &settings.collections).await?;
+        }
+
+        if let Some(read_endpoint) = &settings.read_endpoint {
+            tracing::info!("using read endpoint: {:?}", read_endpoint);
+        }
+        let reader = connect_reader(settings, &inner).await?;
+
         Ok(Self {
+            handles: RwLock::new(Handles {
+                conn,
+                inner,
+                reader,
+                collections,
+                generation: 0,
+            }),
+            reconnecting: tokio::sync::Mutex::new(()),
+            names: settings.collections.clone(),
+            settings: settings.clone(),
+            indexes: settings.all_indexes(),
+            transforms: PocTransforms::from_settings(settings)?,
+            metadata: settings
+                .metadata
+                .as_ref()
+                .map(HotspotMetadata::from_settings)
+                .transpose()?,
+            raw_pocs: settings.raw_pocs,
+            known_hotspots: NonZeroUsize::new(settings.hotspot_cache_capacity)
+                .map(|capacity| Mutex::new(LruCache::new(capacity))),
+            retry: RetryPolicy::from_settings(&settings.retry),
+            shadow: settings
+                .shadow_verify
+                .as_ref()
+                .map(|settings| Arc::new(ShadowVerifier::new(settings))),
+        })
+    }
+
+    /// Apply `transforms` to the stored pocs instead of the ones of the settings
+    pub fn with_transforms(mut self, transforms: PocTransforms) -> Self {
+        self.transforms = transforms;
+        self
+    }
+
+    /// Current connection and database handles
+    pub fn handles(&self) -> Handles {
+        match self.handles.read() {
+            Ok(handles) => handles.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    pub fn conn(&self) -> Connection {
+        self.handles().conn
+    }
+
+    pub fn inner(&self) -> ArangoDatabase {
+        self.handles().inner
+    }
+
+    pub fn reader(&self) -> ArangoDatabase {
+        self.handles().reader
+    }
+
+    /// Re-establish the connections (and refresh the JWT, if used) to the
+    /// configured endpoints, e.g. after the server restarted
+    pub async fn reconnect(&self) -> Result<()> {
+        let _reconnecting = self.reconnecting.lock().await;
+        self.establish_handles().await
+    }
+
+    /// Reconnect after a request on the `failed` handles hit a connection
+    /// error, unless a concurrent request already reconnected since
+    async fn reconnect_from(&self, failed: &Handles) -> Result<()> {
+        let _reconnecting = self.reconnecting.lock().await;
+        if self.handles().generation != failed.generation {
+            return Ok(());
+        }
+        self.establish_handles().await
+    }
+
+    async fn establish_handles(&self) -> Result<()> {
+        tracing::info!("reconnecting to {:?}", self.settings.endpoint);
+        let conn = establish(&self.settings.endpoint, &self.settings).await?;
+        let inner = conn.db(&self.settings.database).await?;
+        let collections = use_existing_db_and_collections(&inner, &self.names).await?;
+        let reader = connect_reader(&self.settings, &inner).await?;
+        let handles = Handles {
             conn,
             inner,
+            reader,
             collections,
-        })
+            generation: self.handles().generation + 1,
+        };
+        match self.handles.write() {
+            Ok(mut current) => *current = handles,
+            Err(poisoned) => *poisoned.into_inner() = handles,
+        }
+        tracing::info!("reconnected to {:?}", self.settings.endpoint);
+        Ok(())
+    }
+
+    /// Refresh the JWT by reconnecting every `jwt_refresh_secs` until shutdown,
+    /// a no-op unless jwt auth is configured. A failed refresh is retried on the
+    /// next interval; requests rejected in between reconnect on their own.
+    pub async fn refresh_token(&self, subsys: SubsystemHandle) -> Result<()> {
+        if self.settings.auth != ArangoAuth::Jwt {
+            subsys.on_shutdown_requested().await;
+            return Ok(());
+        }
+        let period = Duration::from_secs(self.settings.jwt_refresh_secs.max(1));
+        let mut refresh = tokio::time::interval_at(Instant::now() + period, period);
+        loop {
+            tokio::select! {
+                _ = subsys.on_shutdown_requested() => break,
+                _ = refresh.tick() => {
+                    tracing::debug!("refreshing jwt");
+                    if let Err(err) = self.reconnect().await {
+                        tracing::error!("error refreshing jwt: {:?}", err);
+                    }
+                }
+            }
+        }
+        Ok(())
     }
 
+    /// Check the connection, reconnecting if the server can't be reached or
+    /// rejects it. Fails if it can't be re-established (yet).
+    pub async fn ensure_connected(&self) -> Result<()> {
+        match self.conn().accessible_databases().await {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                tracing::warn!("connection check failed: {:?}", err);
+                self.reconnect().await
+            }
+        }
+    }
+
+    /// (Re)create the configured indexes on an existing database.
+    /// Indexes which already exist with the same definition are left untouched.
+    pub async fn reindex(&self) -> Result<()> {
+        create_indices(&self.inner(), &self.indexes).await
+    }
+
+    /// Record the file as processing by this worker, unless it is already done
     pub async fn init_file(&self, file: &FileInfo) -> Result<(), DBError> {
         tracing::info!("init file: {:?}", file.key);
-        let iot_poc_file = IotPocFile::from(file);
-        let doc = serde_json::to_value(iot_poc_file)?;
-
-        if !self.file_exists(&file.key).await? {
-            self.insert_document(
-                &self.collections.files,
-                doc,
-                "file",
-                InsertOptions::builder().build(),
-            )
-            .await
-        } else {
-            Ok(())
-        }
+        let iot_poc_file = IotPocFile::processing(file);
+        let query = unindent(
+            r#"
+            UPSERT { _key: @file._key }
+            INSERT @file
+            UPDATE OLD.done ? {} : {
+                state: @file.state,
+                worker_id: @file.worker_id,
+                heartbeat_unix_ms: @file.heartbeat_unix_ms
+            }
+            IN @@collection"#,
+        );
+        let aql = AqlQuery::builder()
+            .query(&query)
+            .bind_var("@collection", self.names.files.as_str())
+            .bind_var("file", serde_json::to_value(iot_poc_file)?)
+            .build();
+
+        self.execute(aql).await
     }
 
-    pub async fn complete_file(&self, key: &str) -> Result<(), DBError> {
-        let query = r#"UPDATE @key WITH { done: @done } IN @@collection"#;
+    /// Refresh the heartbeat of a file this worker is processing
+    pub async fn heartbeat_file(&self, key: &str) -> Result<(), DBError> {
+        let query = unindent(
+            r#"
+            FOR f IN @@collection
+                FILTER f._key == @key AND f.state == "processing" AND f.worker_id == @worker_id
+                UPDATE f WITH { heartbeat_unix_ms: DATE_NOW() } IN @@collection"#,
+        );
         let aql = AqlQuery::builder()
-            .query(query)
-            .bind_var("@collection", FILES_COLLECTION)
+            .query(&query)
+            .bind_var("@collection", self.names.files.as_str())
             .bind_var("key", key)
-            .bind_var("done", true)
+            .bind_var("worker_id", worker_id())
             .build();
 
-        self.inner
-            .aql_query::<Vec<Value>>(aql)
-            .await
-            .map(|_| ())
-            .map_err(DBError::from)
+        self.execute(aql).await
     }
 
-    pub async fn get_done_file_keys(&self) -> Result<Vec<String>, DBError> {
-        let query = r#"FOR f IN @@collection FILTER f.done == @done RETURN f._key"#;
+    /// Reset files processing with a heartbeat older than `stale_before_unix_ms`
+    /// (their worker crashed or hung) back to pending, returning their keys
+    pub async fn reset_stale_files(
+        &self,
+        stale_before_unix_ms: i64,
+    ) -> Result<Vec<String>, DBError> {
+        let query = unindent(
+            r#"
+            FOR f IN @@collection
+                FILTER f.state == "processing" AND f.heartbeat_unix_ms < @stale_before
+                UPDATE f WITH { state: "pending", worker_id: null } IN @@collection
+                RETURN OLD._key"#,
+        );
         let aql = AqlQuery::builder()
-            .query(query)
-            .bind_var("@collection", FILES_COLLECTION)
-            .bind_var("done", true)
+            .query(&query)
+            .bind_var("@collection", self.names.files.as_str())
+            .bind_var("stale_before", stale_before_unix_ms)
             .build();
 
-        let keys: Vec<String> = self.inner.aql_query(aql).await?;
-        Ok(keys)
+        self.query_all(Endpoint::Inner, aql).await
     }
 
-    pub async fn get_file_retries(&self, key: &str) -> Result<u8, DBError> {
-        let query = r#"FOR f in @@collection FILTER f._key == @key RETURN f.retries"#;
+    pub async fn complete_file(&self, key: &str, stats: &FileStats) -> Result<(), DBError> {
+        let query =
+            r#"UPDATE @key WITH { done: @done, state: "done", stats: @stats } IN @@collection"#;
         let aql = AqlQuery::builder()
             .query(query)
-            .bind_var("@collection", FILES_COLLECTION)
+            .bind_var("@collection", self.names.files.as_str())
             .bind_var("key", key)
+            .bind_var("done", true)
+            .bind_var("stats", serde_json::to_value(stats)?)
             .build();
 
-        let retries: Vec<u8> = self.inner.aql_query(aql).await?;
-        if retries.is_empty() {
-            Ok(0)
-        } else {
-            Ok(retries[0])
-        }
+        self.execute(aql).await
     }
 
-    pub async fn file_exists(&self, key: &str) -> Result<bool, DBError> {
-        let query = r#"FOR f IN @@collection FILTER f._key == @key RETURN f._key"#;
+    pub async fn get_done_file_keys(&self) -> Result<Vec<String>, DBError> {
         let aql = AqlQuery::builder()
-            .query(query)
-            .bind_var("@collection", FILES_COLLECTION)
-            .bind_var("key", key)
+            .query(DONE_FILE_KEYS_QUERY)
+            .bind_var("@collection", self.names.files.as_str())
+            .bind_var("done", true)
             .build();
 
-        let keys: Vec<Option<String>> = self.inner.aql_query(aql).await?;
-        Ok(!keys.is_empty())
+        let keys: Vec<String> = self.query_all(Endpoint::Inner, aql).await?;
+        Ok(keys)
     }
 
-    pub async fn beacon_exists(&self, poc_id: &str) -> Result<bool, DBError> {
-        let query = r#"FOR b IN @@collection FILTER b._key == @poc_id RETURN b.poc_id"#;
+    /// Timestamp of the latest done file, i.e. how far ingested data is complete
+    pub async fn get_done_watermark(&self) -> Result<Option<i64>, DBError> {
+        let query = unindent(
+            r#"
+            FOR f IN @@collection
+                FILTER f.done == true
+                SORT f.timestamp_unix_ms DESC
+                LIMIT 1
+                RETURN f.timestamp_unix_ms"#,
+        );
         let aql = AqlQuery::builder()
-            .query(query)
-            .bind_var("@collection", BEACON_COLLECTION)
-            .bind_var("poc_id", poc_id)
+            .query(&query)
+            .bind_var("@collection", self.names.files.as_str())
             .build();
 
-        let keys: Vec<Option<String>> = self.inner.aql_query(aql).await?;
-        Ok(!keys.is_empty())
+        self.query_first(Endpoint::Inner, aql).await
     }
 
-    pub async fn increment_file_retry(&self, key: &str) -> Result<(), DBError> {
-        let query = r#"UPDATE @key WITH { retries: OLD.retries + 1 } IN @@collection"#;
+    /// Latest file timestamps and counts of the not done files, those with more
+    /// than `max_retries` failed attempts are exhausted
+    pub async fn get_file_status(&self, max_retries: u8) -> Result<FileStatus, DBError> {
+        let query = unindent(
+            r#"
+            LET latest = FIRST(
+                FOR f IN @@collection
+                    SORT f.timestamp_unix_ms DESC
+                    LIMIT 1
+                    RETURN f.timestamp_unix_ms)
+            LET latest_done = FIRST(
+                FOR f IN @@collection
+                    FILTER f.done == true
+                    SORT f.timestamp_unix_ms DESC
+                    LIMIT 1
+                    RETURN f.timestamp_unix_ms)
+            LET not_done = (
+                FOR f IN @@collection
+                    FILTER f.done != true
+                    COLLECT exhausted = f.retries > @max_retries WITH COUNT INTO files
+                    RETURN {exhausted, files})
+            RETURN {
+                latest_unix_ms: latest,
+                latest_done_unix_ms: latest_done,
+                not_done: SUM(not_done[*].files),
+                exhausted: SUM(not_done[* FILTER CURRENT.exhausted].files)
+            }"#,
+        );
         let aql = AqlQuery::builder()
-            .query(query)
-            .bind_var("@collection", FILES_COLLECTION)
-            .bind_var("key", key)
+            .query(&query)
+            .bind_var("@collection", self.names.files.as_str())
+            .bind_var("max_retries", max_retries)
             .build();
 
-        self.inner
-            .aql_query::<Vec<Value>>(aql)
-            .await
-            .map(|_| ())
-            .map_err(DBError::from)
+        let status: Option<FileStatus> = self.query_first(Endpoint::Inner, aql).await?;
+        Ok(status.unwrap_or_default())
     }
 
-    async fn insert_document(
+    /// Timestamps of the done files in the [after, before] unix ms range, oldest first
+    pub async fn get_done_file_timestamps(
         &self,
-        collection: &ArangoCollection,
-        doc: serde_json::Value,
-        doc_name: &str,
-        options: InsertOptions,
-    ) -> Result<(), DBError> {
-        match collection.create_document(doc, options).await {
-            Ok(_) => {
-                tracing::debug!("successfully inserted {:?} document", doc_name);
-                Ok(())
-            }
-            Err(ClientError::Arango(ae)) if [1210, 1200].contains(&ae.error_num()) => {
-                tracing::debug!(
-                    "error, doc: {:?}, {:?}: {:?}",
-                    doc_name,
-                    ae.error_num(),
-                    ae.message()
-                );
-                Ok(())
-            }
-            Err(err) => Err(DBError::ArangoClientError(err)),
-        }
+        after_unix_ms: i64,
+        before_unix_ms: i64,
+    ) -> Result<Vec<i64>, DBError> {
+        let query = unindent(
+            r#"
+            FOR f IN @@collection
+                FILTER f.timestamp_unix_ms >= @after && f.timestamp_unix_ms <= @before
+                FILTER f.done == true
+                SORT f.timestamp_unix_ms
+                RETURN f.timestamp_unix_ms"#,
+        );
+        let aql = AqlQuery::builder()
+            .query(&query)
+            .bind_var("@collection", self.names.files.as_str())
+            .bind_var("after", after_unix_ms)
+            .bind_var("before", before_unix_ms)
+            .build();
+
+        let timestamps: Vec<i64> = self.query_all(Endpoint::Reader, aql).await?;
+        Ok(timestamps)
     }
 
-    async fn populate_hotspot(
+    /// Page of up to `limit` export rows of `collection` with keys after `after`,
+    /// see `ExportCollection::query`
+    pub async fn export_page(
         &self,
-        hotspot_type: HotspotType,
-        hotspot: Hotspot,
-    ) -> Result<(), DBError> {
-        let (query, poc_id) = match hotspot_type {
-            HotspotType::Beacon => (
-                unindent(
-                    r#"
-                UPSERT { _key: @pub_key }
-                INSERT @hotspot
-                UPDATE { poc_ids: UNION_DISTINCT(OLD.poc_ids, [@poc_id]),
-                         last_updated_at: MAX([OLD.last_updated_at, DATE_NOW()]),
-                         gain: @gain,
-                         elevation: @elevation}
-                IN @@collection"#,
-                ),
-                // NOTE: we only have a single poc_id for a beacon
-                // The query takes care of adding it to the list of poc_ids
-                Some(hotspot.poc_ids[0].clone()),
-            ),
-            HotspotType::Witness => (
-                unindent(
-                    r#"
-                UPSERT { _key: @pub_key }
-                INSERT @hotspot
-                UPDATE { last_updated_at: MAX([OLD.last_updated_at, DATE_NOW()]), gain: @gain, elevation: @elevation }
-                IN @@collection"#,
-                ),
-                None,
-            ),
-        };
+        query: &str,
+        collection: &str,
+        after: &str,
+        limit: u32,
+    ) -> Result<Vec<Vec<Value>>, DBError> {
+        let aql = AqlQuery::builder()
+            .query(query)
+            .bind_var("@collection", collection)
+            .bind_var("after", after)
+            .bind_var("limit", limit)
+            .build();
 
-        let mut aql_builder = AqlQuery::builder()
-            .query(&query)
-            .bind_var("@collection", HOTSPOT_COLLECTION)
-            .bind_var("hotspot", serde_json::to_value(&hotspot)?)
-            .bind_var("pub_key", hotspot._key.to_string())
-            .bind_var("gain", hotspot.gain)
-            .bind_var("elevation", hotspot.elevation);
+        let rows: Vec<Vec<Value>> = self.query_all(Endpoint::Reader, aql).await?;
+        Ok(rows)
+    }
 
-        if let Some(poc_id) = poc_id {
-            aql_builder = aql_builder.bind_var("poc_id", poc_id);
-        }
+    /// File records of `keys`, read from the leader so files done just now
+    /// aren't reported missing or incomplete
+    pub async fn get_file_records(&self, keys: &[String]) -> Result<Vec<IotPocFile>, DBError> {
+        let query = r#"FOR f IN @@collection FILTER f._key IN @keys RETURN f"#;
+        let aql = AqlQuery::builder()
+            .query(query)
+            .bind_var("@collection", self.names.files.as_str())
+            .bind_var("keys", keys)
+            .build();
 
-        let aql = aql_builder.build();
+        let records: Vec<IotPocFile> = self.query_all(Endpoint::Inner, aql).await?;
+        Ok(records)
+    }
 
-        match self.inner.aql_query::<Vec<Value>>(aql).await {
-            Ok(_) => {
-                tracing::debug!("successfully populated {:?} hotspot", hotspot_type);
-                Ok(())
-            }
-            Err(ClientError::Arango(ae)) if [1210, 1200].contains(&ae.error_num()) => {
-                tracing::debug!(
-                    "warning, collection: {:?}, hotspot_type: {:?}, {:?}: {:?}",
-                    HOTSPOT_COLLECTION,
-                    hotspot_type,
-                    ae.error_num(),
-                    ae.message()
-                );
-                Ok(())
-            }
-            Err(err) => Err(DBError::ArangoClientError(err)),
-        }
+    pub async fn record_verification_run(
+        &self,
+        report: &VerificationReport,
+    ) -> Result<(), DBError> {
+        self.insert_document(
+            |c| &c.verification_runs,
+            serde_json::to_value(report)?,
+            "verification_run",
+        )
+        .await
     }
 
-    async fn populate_beacon(&self, beacon: Beacon) -> Result<(), DBError> {
-        if !self.beacon_exists(&beacon._key).await? {
-            self.insert_document(
-                &self.collections.beacons,
-                serde_json::to_value(beacon)?,
-                "beacon",
-                InsertOptions::builder().build(),
-            )
-            .await
-        } else {
-            Ok(())
-        }
+    pub async fn record_audit_entry(&self, entry: &AuditEntry) -> Result<(), DBError> {
+        self.insert_document(
+            |c| &c.audit_log,
+            serde_json::to_value(entry)?,
+            "audit_entry",
+        )
+        .await
     }
 
-    async fn populate_edge(&self, edge: Edge) -> Result<(), DBError> {
-        let witness_edge_key = edge._key;
-        let distance = edge.distance;
-        let beacon_pub_key = edge.beacon_pub_key;
-        let witness_pub_key = edge.witness_pub_key;
-        let witness_snr = edge.witness_snr;
-        let witness_signal = edge.witness_signal;
-        let ingest_latency = edge.ingest_latency;
+    /// Document of `collection` with `key`, read on the read endpoint
+    pub async fn get_document(
+        &self,
+        collection: &str,
+        key: &str,
+    ) -> Result<Option<Value>, DBError> {
+        let query = r#"FOR d IN @@collection FILTER d._key == @key RETURN d"#;
+        let aql = AqlQuery::builder()
+            .query(query)
+            .bind_var("@collection", collection)
+            .bind_var("key", key)
+            .build();
+
+        self.query_first(Endpoint::Reader, aql).await
+    }
 
+    /// Witness edges of the hotspot with `key`'s beacons, most witnessed first,
+    /// with the witness's pub_key and name
+    pub async fn get_hotspot_witnesses(
+        &self,
+        key: &str,
+        limit: u32,
+    ) -> Result<Vec<Value>, DBError> {
         let query = unindent(
             r#"
-             UPSERT { _key: @witness_edge_key }
+             FOR e IN @@witness_edge_collection
+             FILTER e._from == @from
+             SORT e.count DESC
+             LIMIT @limit
+             LET w = DOCUMENT(e._to)
+             RETURN MERGE(e, { witness_pub_key: w.pub_key || w._key, witness_name: w.name })
+             "#,
+        );
+        let aql = AqlQuery::builder()
+            .query(&query)
+            .bind_var("@witness_edge_collection", self.names.witnesses.as_str())
+            .bind_var("from", format!("{}/{}", self.names.hotspots, key))
+            .bind_var("limit", limit)
+            .build();
+
+        self.query_all(Endpoint::Reader, aql).await
+    }
+
+    pub async fn get_checkpoint(&self, name: &str) -> Result<Option<Checkpoint>, DBError> {
+        let query = r#"FOR c IN @@collection FILTER c._key == @name RETURN c"#;
+        let aql = AqlQuery::builder()
+            .query(query)
+            .bind_var("@collection", self.names.checkpoints.as_str())
+            .bind_var("name", name)
+            .build();
+
+        self.query_first(Endpoint::Inner, aql).await
+    }
+
+    pub async fn set_checkpoint(&self, checkpoint: &Checkpoint) -> Result<(), DBError> {
+        let query = r#"INSERT @checkpoint INTO @@collection OPTIONS { overwriteMode: "replace" }"#;
+        let aql = AqlQuery::builder()
+            .query(query)
+            .bind_var("@collection", self.names.checkpoints.as_str())
+            .bind_var("checkpoint", serde_json::to_value(checkpoint)?)
+            .build();
+
+        self.execute(aql).await
+    }
+
+    /// Stored metadata documents of the collections
+    pub async fn get_collection_meta(&self) -> Result<Vec<CollectionMeta>, DBError> {
+        let query = r#"FOR m IN @@collection RETURN m"#;
+        let aql = AqlQuery::builder()
+            .query(query)
+            .bind_var("@collection", self.names.meta.as_str())
+            .build();
+
+        self.query_all(Endpoint::Inner, aql).await
+    }
+
+    /// Write (replace) the metadata documents of the collections
+    pub async fn set_collection_meta(&self, metas: &[CollectionMeta]) -> Result<(), DBError> {
+        let query =
+            r#"FOR m IN @metas INSERT m INTO @@collection OPTIONS { overwriteMode: "replace" }"#;
+        let aql = AqlQuery::builder()
+            .query(query)
+            .bind_var("@collection", self.names.meta.as_str())
+            .bind_var("metas", serde_json::to_value(metas)?)
+            .build();
+
+        self.execute(aql).await
+    }
+
+    pub async fn get_file_retries(&self, key: &str) -> Result<u8, DBError> {
+        let query = r#"FOR f in @@collection FILTER f._key == @key RETURN f.retries"#;
+        let aql = AqlQuery::builder()
+            .query(query)
+            .bind_var("@collection", self.names.files.as_str())
+            .bind_var("key", key)
+            .build();
+
+        let retries: Option<u8> = self.query_first(Endpoint::Inner, aql).await?;
+        Ok(retries.unwrap_or(0))
+    }
+
+    pub async fn file_exists(&self, key: &str) -> Result<bool, DBError> {
+        let query = r#"FOR f IN @@collection FILTER f._key == @key RETURN f._key"#;
+        let aql = AqlQuery::builder()
+            .query(query)
+            .bind_var("@collection", self.names.files.as_str())
+            .bind_var("key", key)
+            .build();
+
+        let key: Option<Option<String>> = self.query_first(Endpoint::Inner, aql).await?;
+        Ok(key.is_some())
+    }
+
+    /// The keys of the given beacons (encoded poc_ids) which are already stored
+    pub async fn beacons_exist(&self, keys: &[String]) -> Result<HashSet<String>, DBError> {
+        self.keys_exist(&self.names.beacons, keys).await
+    }
+
+    /// Document key of the hotspot with `pub_key`, see `ArangoDBSettings::hotspot_keys`
+    pub fn hotspot_key(&self, pub_key: &PublicKeyBinary) -> String {
+        hotspot_key(self.settings.hotspot_keys, pub_key)
+    }
+
+    /// Key the hotspot built from a poc and attach its metadata, if configured
+    fn hotspot(&self, hotspot: Hotspot) -> Hotspot {
+        let metadata = self
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.get(&hotspot.pub_key));
+        hotspot
+            .with_metadata(metadata)
+            .with_key(self.settings.hotspot_keys)
+    }
+
+    /// Tag the stored hotspots of `keys` with `denylisted: true` and the denylist
+    /// `version`, and clear the tag of hotspots no longer on the list. Returns
+    /// the number of hotspots tagged and cleared.
+    pub async fn tag_denylisted(
+        &self,
+        keys: Vec<String>,
+        version: &str,
+    ) -> Result<(usize, usize), DBError> {
+        let tag = unindent(
+            r#"
+             FOR key IN @keys
+             LET h = DOCUMENT(@@hotspot_collection, key)
+             FILTER h != null
+             UPDATE h WITH { denylisted: true, denylist_version: @version } IN @@hotspot_collection
+             RETURN NEW._key
+             "#,
+        );
+        let aql = AqlQuery::builder()
+            .query(&tag)
+            .bind_var("@hotspot_collection", self.names.hotspots.as_str())
+            .bind_var("keys", serde_json::to_value(keys)?)
+            .bind_var("version", version)
+            .build();
+        let tagged: Vec<String> = self.query_all(Endpoint::Inner, aql).await?;
+
+        // NOTE: uses the sparse hotspot_denylisted index
+        let clear = unindent(
+            r#"
+             FOR h IN @@hotspot_collection
+             FILTER h.denylisted == true AND h.denylist_version != @version
+             UPDATE h WITH { denylisted: false, denylist_version: @version } IN @@hotspot_collection
+             RETURN NEW._key
+             "#,
+        );
+        let aql = AqlQuery::builder()
+            .query(&clear)
+            .bind_var("@hotspot_collection", self.names.hotspots.as_str())
+            .bind_var("version", version)
+            .build();
+        let cleared: Vec<String> = self.query_all(Endpoint::Inner, aql).await?;
+        Ok((tagged.len(), cleared.len()))
+    }
+
+    /// The keys of the given hotspots (see `hotspot_key`) which are already stored
+    pub async fn hotspots_exist(&self, keys: &[String]) -> Result<HashSet<String>, DBError> {
+        self.keys_exist(&self.names.hotspots, keys).await
+    }
+
+    async fn keys_exist(
+        &self,
+        collection: &str,
+        keys: &[String],
+    ) -> Result<HashSet<String>, DBError> {
+        if keys.is_empty() {
+            return Ok(HashSet::new());
+        }
+        let aql = AqlQuery::builder()
+            .query(KEYS_EXIST_QUERY)
+            .bind_var("@collection", collection)
+            .bind_var("keys", keys.to_vec())
+            .build();
+
+        let existing: Vec<String> = self.query_all(Endpoint::Inner, aql).await?;
+        Ok(existing.into_iter().collect())
+    }
+
+    /// Look up which beacons and hotspots of a chunk of pocs are already stored, with
+    /// a single query per collection. Staging then skips already ingested pocs and
+    /// updates known hotspots in bulk on `flush` instead of upserting them one by one.
+    pub async fn check_existing(
+        &self,
+        pocs: &[LoraPocV1],
+        batch: &mut Batch,
+    ) -> Result<(), DBError> {
+        let mut beacon_keys = Vec::with_capacity(pocs.len());
+        let mut hotspot_keys = HashSet::new();
+        for poc in pocs {
+            beacon_keys.push(general_purpose::URL_SAFE_NO_PAD.encode(&poc.poc_id));
+            let beacon_pub_key = poc
+                .beacon_report
+                .as_ref()
+                .and_then(|beacon| beacon.report.as_ref())
+                .map(|report| &report.pub_key);
+            let witness_pub_keys = poc
+                .selected_witnesses
+                .iter()
+                .filter_map(|witness| witness.report.as_ref())
+                .map(|report| &report.pub_key);
+            hotspot_keys.extend(
+                beacon_pub_key
+                    .into_iter()
+                    .chain(witness_pub_keys)
+                    .map(|pub_key| self.hotspot_key(&PublicKeyBinary::from(pub_key.clone()))),
+            );
+        }
+        let hotspot_keys: Vec<String> = hotspot_keys.into_iter().collect();
+        batch.existing_beacons = self.beacons_exist(&beacon_keys).await?;
+        batch.existing_hotspots = self.hotspots_exist(&hotspot_keys).await?;
+        Ok(())
+    }
+
+    pub async fn increment_file_retry(&self, key: &str) -> Result<(), DBError> {
+        let query =
+            r#"UPDATE @key WITH { retries: OLD.retries + 1, state: "pending" } IN @@collection"#;
+        let aql = AqlQuery::builder()
+            .query(query)
+            .bind_var("@collection", self.names.files.as_str())
+            .bind_var("key", key)
+            .build();
+
+        self.execute_increment(aql).await
+    }
+
+    /// Merge operator annotations into the `annotations` sub-document of `key` in `collection`.
+    /// Annotations set to `null` are removed.
+    pub async fn annotate(
+        &self,
+        collection: &str,
+        key: &str,
+        annotations: serde_json::Map<String, Value>,
+    ) -> Result<(), DBError> {
+        let query = unindent(
+            r#"
+            UPDATE @key WITH { annotations: @annotations } IN @@collection
+            OPTIONS { mergeObjects: true, keepNull: false }"#,
+        );
+        let aql = AqlQuery::builder()
+            .query(&query)
+            .bind_var("@collection", collection)
+            .bind_var("key", key)
+            .bind_var("annotations", Value::Object(annotations))
+            .build();
+
+        self.execute(aql).await
+    }
+
+    /// Move a renamed time field to its `_unix_ms` name, converting values
+    /// stored in seconds to millis. Documents already having the new field
+    /// (e.g. updated by a newer version meanwhile) keep the earliest of both
+    /// for `first_*` fields and the latest otherwise. Only counts them if
+    /// `dry_run`. Returns the number of (to be) migrated documents.
+    pub async fn migrate_field(
+        &self,
+        field: &RenamedField,
+        dry_run: bool,
+    ) -> Result<usize, DBError> {
+        let update = if dry_run {
+            ""
+        } else {
+            "UPDATE d WITH { [@to]: merged, [@from]: null } IN @@collection OPTIONS { keepNull: false }"
+        };
+        let query = unindent(&format!(
+            r#"
+            FOR d IN @@collection
+                FILTER HAS(d, @from)
+                LET ts = d[@from]
+                LET ts_ms = IS_NUMBER(ts) && ABS(ts) < @min_unix_ms ? ts * 1000 : ts
+                LET current = d[@to]
+                LET merged = current == null ? ts_ms : (
+                    @earliest ? MIN([current, ts_ms]) : MAX([current, ts_ms])
+                )
+                {update}
+                COLLECT WITH COUNT INTO migrated
+                RETURN migrated"#
+        ));
+        let aql = AqlQuery::builder()
+            .query(&query)
+            .bind_var("@collection", self.names.resolve(field.collection))
+            .bind_var("from", field.from)
+            .bind_var("to", field.to)
+            .bind_var("earliest", field.keeps_earliest())
+            .bind_var("min_unix_ms", MIN_UNIX_MS)
+            .build();
+
+        let migrated: Option<usize> = self.query_first(Endpoint::Inner, aql).await?;
+        Ok(migrated.unwrap_or_default())
+    }
+
+    /// Set the `witness_count` and `selected_witness_count` of beacons stored
+    /// before they were denormalized, counting the witnesses dropped by an
+    /// overflow strategy as well. Only counts them if `dry_run`. Returns the
+    /// number of (to be) migrated beacons.
+    pub async fn migrate_witness_counts(&self, dry_run: bool) -> Result<usize, DBError> {
+        let update = if dry_run {
+            ""
+        } else {
+            r#"UPDATE b WITH {
+                witness_count: LENGTH(witnesses) + (b.overflow.dropped_witnesses || 0),
+                selected_witness_count: LENGTH(witnesses[* FILTER CURRENT.selected])
+            } IN @@collection"#
+        };
+        let query = unindent(&format!(
+            r#"
+            FOR b IN @@collection
+                FILTER !HAS(b, "witness_count")
+                LET witnesses = b.witnesses || []
+                {update}
+                COLLECT WITH COUNT INTO migrated
+                RETURN migrated"#
+        ));
+        let aql = AqlQuery::builder()
+            .query(&query)
+            .bind_var("@collection", self.names.beacons.as_str())
+            .build();
+
+        let migrated: Option<usize> = self.query_first(Endpoint::Inner, aql).await?;
+        Ok(migrated.unwrap_or_default())
+    }
+
+    /// Re-key the hotspots (and their stats) whose keys don't follow the
+    /// configured `hotspot_keys` strategy, `page_size` hotspots at a time,
+    /// pointing their witness edges and rewards at the new keys. Documents are
+    /// copied before the old ones are removed, so an interrupted migration can
+    /// just be run again. Only counts them if `dry_run`. Returns the number of
+    /// (to be) re-keyed hotspots.
+    pub async fn migrate_hotspot_keys(
+        &self,
+        page_size: u32,
+        dry_run: bool,
+    ) -> Result<usize, DBError> {
+        let mut after = String::new();
+        let mut migrated = 0;
+        loop {
+            let query = r#"
+                FOR h IN @@collection
+                    FILTER h._key > @after
+                    SORT h._key
+                    LIMIT @limit
+                    RETURN [h._key, h.pub_key || h._key]"#;
+            let aql = AqlQuery::builder()
+                .query(query)
+                .bind_var("@collection", self.names.hotspots.as_str())
+                .bind_var("after", after.as_str())
+                .bind_var("limit", page_size)
+                .build();
+            let page: Vec<(String, PublicKeyBinary)> = self.query_all(Endpoint::Inner, aql).await?;
+            let Some((last, _)) = page.last() else {
+                break;
+            };
+            after = last.clone();
+
+            let moves: Vec<Value> = page
+                .into_iter()
+                .filter_map(|(key, pub_key)| {
+                    let to = self.hotspot_key(&pub_key);
+                    (to != key).then(|| {
+                        json!({
+                            "from": key,
+                            "to": to,
+                            "pub_key": pub_key.to_string(),
+                        })
+                    })
+                })
+                .collect();
+            if moves.is_empty() {
+                continue;
+            }
+            if dry_run {
+                migrated += moves.len();
+                tracing::info!("would re-key {} hotspots", migrated);
+                continue;
+            }
+            self.move_documents(&self.names.hotspots, &moves, true)
+                .await?;
+            for attribute in ["_from", "_to"] {
+                self.repoint_edges(attribute, &moves).await?;
+            }
+            self.repoint_rewards(&moves).await?;
+            self.move_documents(&self.names.hotspot_stats, &moves, false)
+                .await?;
+            self.remove_moved(&self.names.hotspot_stats, &moves).await?;
+            self.remove_moved(&self.names.hotspots, &moves).await?;
+            migrated += moves.len();
+            tracing::info!("re-keyed {} hotspots", migrated);
+        }
+        Ok(migrated)
+    }
+
+    /// Copy the documents of `moves` (`from` key to `to` key) within a collection,
+    /// with their `pub_key` field set if `set_pub_key`. Existing copies are kept.
+    async fn move_documents(
+        &self,
+        collection: &str,
+        moves: &[Value],
+        set_pub_key: bool,
+    ) -> Result<(), DBError> {
+        let query = unindent(
+            r#"
+            FOR m IN @moves
+                FOR d IN @@collection
+                    FILTER d._key == m.from
+                    INSERT MERGE(
+                        UNSET(d, "_key", "_id", "_rev"),
+                        { _key: m.to },
+                        @set_pub_key ? { pub_key: m.pub_key } : {}
+                    ) INTO @@collection OPTIONS { overwriteMode: "ignore" }"#,
+        );
+        let aql = AqlQuery::builder()
+            .query(&query)
+            .bind_var("@collection", collection)
+            .bind_var("moves", moves.to_vec())
+            .bind_var("set_pub_key", set_pub_key)
+            .build();
+        self.execute(aql).await
+    }
+
+    /// Point the `_from` or `_to` of the witness edges of moved hotspots at their new keys
+    async fn repoint_edges(&self, attribute: &str, moves: &[Value]) -> Result<(), DBError> {
+        let query = unindent(&format!(
+            r#"
+            FOR m IN @moves
+                FOR e IN @@witnesses
+                    FILTER e.{attribute} == CONCAT_SEPARATOR("/", @hotspots, m.from)
+                    UPDATE e WITH {{ {attribute}: CONCAT_SEPARATOR("/", @hotspots, m.to) }} IN @@witnesses"#
+        ));
+        let aql = AqlQuery::builder()
+            .query(&query)
+            .bind_var("@witnesses", self.names.witnesses.as_str())
+            .bind_var("hotspots", self.names.hotspots.as_str())
+            .bind_var("moves", moves.to_vec())
+            .build();
+        self.execute(aql).await
+    }
+
+    /// Point the `hotspot_id` of the rewards of moved hotspots at their new keys
+    async fn repoint_rewards(&self, moves: &[Value]) -> Result<(), DBError> {
+        let query = unindent(
+            r#"
+            FOR m IN @moves
+                FOR r IN @@rewards
+                    FILTER r.pub_key == m.pub_key
+                    UPDATE r WITH { hotspot_id: CONCAT_SEPARATOR("/", @hotspots, m.to) } IN @@rewards"#,
+        );
+        let aql = AqlQuery::builder()
+            .query(&query)
+            .bind_var("@rewards", self.names.rewards.as_str())
+            .bind_var("hotspots", self.names.hotspots.as_str())
+            .bind_var("moves", moves.to_vec())
+            .build();
+        self.execute(aql).await
+    }
+
+    async fn remove_moved(&self, collection: &str, moves: &[Value]) -> Result<(), DBError> {
+        let query =
+            r#"FOR m IN @moves REMOVE m.from IN @@collection OPTIONS { ignoreErrors: true }"#;
+        let aql = AqlQuery::builder()
+            .query(query)
+            .bind_var("@collection", collection)
+            .bind_var("moves", moves.to_vec())
+            .build();
+        self.execute(aql).await
+    }
+
+    /// Remove the documents of a purge target in the [after, before) unix ms range,
+    /// or only count them if `dry_run`. Returns the number of (to be) removed documents.
+    pub async fn purge(
+        &self,
+        target: PurgeTarget,
+        after_unix_ms: i64,
+        before_unix_ms: i64,
+        dry_run: bool,
+    ) -> Result<usize, DBError> {
+        let query = purge_query(target, dry_run);
+        let aql = AqlQuery::builder()
+            .query(&query)
+            .bind_var("@collection", target.collection(&self.names))
+            .bind_var("after", after_unix_ms)
+            .bind_var("before", before_unix_ms)
+            .build();
+
+        let purged: Option<usize> = self.query_first(Endpoint::Inner, aql).await?;
+        Ok(purged.unwrap_or_default())
+    }
+
+    /// Names of the indexes (`primary`, `edge` or configured index names) the
+    /// optimizer picks for a query, including its subqueries
+    pub async fn explain_indexes(&self, query: &str, bind_vars: Value) -> Result<Vec<String>> {
+        let inner = self.inner();
+        let url = inner.url().join("_api/explain")?;
+        let body = json!({ "query": query, "bindVars": bind_vars });
+        let resp = inner.session().post(url, &body.to_string()).await?;
+        if !resp.status().is_success() {
+            anyhow::bail!("failed to explain query: {}", resp.body());
+        }
+        let explained: Value = serde_json::from_str(resp.body())?;
+        let mut indexes = vec![];
+        collect_index_names(&explained["plan"], &mut indexes);
+        Ok(indexes)
+    }
+
+    /// Run an arbitrary AQL statement, discarding its results. Like the other
+    /// queries it is retried on transient errors and reconnects once.
+    pub async fn run_aql(&self, query: &str) -> Result<(), DBError> {
+        let aql = AqlQuery::builder().query(query).build();
+        self.execute(aql).await
+    }
+
+    pub async fn get_maintenance_last_run(&self, name: &str) -> Result<Option<i64>, DBError> {
+        let query = r#"FOR m IN @@collection FILTER m._key == @name RETURN m.last_run_unix_ms"#;
+        let aql = AqlQuery::builder()
+            .query(query)
+            .bind_var("@collection", self.names.maintenance.as_str())
+            .bind_var("name", name)
+            .build();
+
+        let last_run: Option<Option<i64>> = self.query_first(Endpoint::Inner, aql).await?;
+        Ok(last_run.flatten())
+    }
+
+    pub async fn record_maintenance_run(
+        &self,
+        name: &str,
+        ts: DateTime<Utc>,
+        duration_ms: i64,
+        error: Option<String>,
+    ) -> Result<(), DBError> {
+        let query = unindent(
+            r#"
+            UPSERT { _key: @name }
+            INSERT { _key: @name, last_run: @ts, last_run_unix_ms: @ts_unix_ms, last_duration_ms: @duration_ms, last_error: @error, runs: 1 }
+            UPDATE { last_run: @ts, last_run_unix_ms: @ts_unix_ms, last_duration_ms: @duration_ms, last_error: @error, runs: OLD.runs + 1 }
+            IN @@collection"#,
+        );
+        let aql = AqlQuery::builder()
+            .query(&query)
+            .bind_var("@collection", self.names.maintenance.as_str())
+            .bind_var("name", name)
+            .bind_var("ts", ts.to_rfc3339())
+            .bind_var("ts_unix_ms", unix_ms(ts))
+            .bind_var("duration_ms", duration_ms)
+            .bind_var("error", error)
+            .build();
+
+        self.execute_increment(aql).await
+    }
+
+    /// Run an idempotent bind-var query for its side effects, discarding any
+    /// results
+    async fn execute(&self, aql: AqlQuery<'_>) -> Result<(), DBError> {
+        self.query_all::<Value>(Endpoint::Inner, aql)
+            .await
+            .map(|_| ())
+    }
+
+    /// Run a non-idempotent bind-var query (e.g. incrementing counters) for its
+    /// side effects. It is only repeated after failures the server reported as
+    /// not applied, a timeout may have been committed and would count twice.
+    async fn execute_increment(&self, aql: AqlQuery<'_>) -> Result<(), DBError> {
+        self.run_query::<Value>(Endpoint::Inner, aql, false)
+            .await
+            .map(|_| ())
+    }
+
+    /// Run a bind-var query returning at most one result
+    async fn query_first<T: DeserializeOwned>(
+        &self,
+        endpoint: Endpoint,
+        aql: AqlQuery<'_>,
+    ) -> Result<Option<T>, DBError> {
+        let results: Vec<T> = self.query_all(endpoint, aql).await?;
+        Ok(results.into_iter().next())
+    }
+
+    /// Run a bind-var query, retrying transient errors and reconnecting once on
+    /// connection errors
+    async fn query_all<T: DeserializeOwned>(
+        &self,
+        endpoint: Endpoint,
+        aql: AqlQuery<'_>,
+    ) -> Result<Vec<T>, DBError> {
+        self.run_query(endpoint, aql, true).await
+    }
+
+    /// Run a bind-var query, retrying its failures if `idempotent`, otherwise
+    /// only those which weren't applied. Connection errors reconnect, after
+    /// which the query is only repeated if idempotent or it was rejected.
+    async fn run_query<T: DeserializeOwned>(
+        &self,
+        endpoint: Endpoint,
+        aql: AqlQuery<'_>,
+        idempotent: bool,
+    ) -> Result<Vec<T>, DBError> {
+        // NOTE: AqlQuery can't be cloned, so every attempt is rebuilt from its
+        // query and bind vars (no other query options are used)
+        let aql = serde_json::to_value(&aql)?;
+        let query = aql["query"].as_str().unwrap_or_default();
+        let bind_vars: HashMap<&str, Value> = aql["bindVars"]
+            .as_object()
+            .map(|vars| vars.iter().map(|(k, v)| (k.as_str(), v.clone())).collect())
+            .unwrap_or_default();
+        let mut reconnected = false;
+        loop {
+            let handles = self.handles();
+            let db = handles.db(endpoint);
+            let retriable = if idempotent {
+                is_retriable
+            } else {
+                is_not_applied
+            };
+            match self
+                .retry
+                .run_if("query", retriable, || {
+                    db.aql_bind_vars(query, bind_vars.clone())
+                })
+                .await
+            {
+                Err(err) if !reconnected && is_connection_error(&err) => {
+                    tracing::warn!("connection error, reconnecting: {:?}", err);
+                    self.reconnect_from(&handles).await?;
+                    // NOTE: the write may have been applied before the connection broke
+                    if !idempotent && !is_unauthorized(&err) {
+                        return Err(err.into());
+                    }
+                    reconnected = true;
+                }
+                res => return Ok(res?),
+            }
+        }
+    }
+
+    async fn insert_document(
+        &self,
+        collection: fn(&Collections) -> &ArangoCollection,
+        doc: serde_json::Value,
+        doc_name: &str,
+    ) -> Result<(), DBError> {
+        // NOTE: repeating the insert of a keyed document fails as a duplicate
+        // (ignored below) if the first attempt was applied, unkeyed ones would
+        // be stored twice
+        let keyed = doc.get("_key").is_some();
+        let retriable = if keyed { is_retriable } else { is_not_applied };
+        let mut reconnected = false;
+        let inserted = loop {
+            let handles = self.handles();
+            let collection = collection(&handles.collections);
+            match self
+                .retry
+                .run_if("insert", retriable, || {
+                    collection.create_document(doc.clone(), InsertOptions::builder().build())
+                })
+                .await
+            {
+                Err(err) if !reconnected && is_connection_error(&err) => {
+                    tracing::warn!("connection error, reconnecting: {:?}", err);
+                    self.reconnect_from(&handles).await?;
+                    if !keyed && !is_unauthorized(&err) {
+                        break Err(err);
+                    }
+                    reconnected = true;
+                }
+                res => break res,
+            }
+        };
+        match inserted {
+            Ok(_) => {
+                tracing::debug!("successfully inserted {:?} document", doc_name);
+                Ok(())
+            }
+            Err(ClientError::Arango(ae)) if [1210, 1200].contains(&ae.error_num()) => {
+                tracing::debug!(
+                    "error, doc: {:?}, {:?}: {:?}",
+                    doc_name,
+                    ae.error_num(),
+                    ae.message()
+                );
+                Ok(())
+            }
+            Err(err) => Err(DBError::ArangoClientError(err)),
+        }
+    }
+
+    /// The cached (gain, elevation) last written to the hotspot, None if not cached
+    fn known_hotspot(&self, hotspot: &Hotspot) -> Option<KnownHotspot> {
+        let known_hotspots = self.known_hotspots.as_ref()?;
+        let mut known_hotspots = match known_hotspots.lock() {
+            Ok(known_hotspots) => known_hotspots,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        known_hotspots.get(&hotspot._key).cloned()
+    }
+
+    /// Whether the hotspot is already known with the same gain, elevation and
+    /// location, in which case a witness upsert would only bump last_updated_unix_ms
+    fn is_known_hotspot(&self, hotspot: &Hotspot) -> bool {
+        self.known_hotspot(hotspot) == Some(KnownHotspot::from(hotspot))
+    }
+
+    fn remember_hotspot(&self, hotspot: &Hotspot) {
+        if let Some(known_hotspots) = &self.known_hotspots {
+            let mut known_hotspots = match known_hotspots.lock() {
+                Ok(known_hotspots) => known_hotspots,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            known_hotspots.put(hotspot._key.clone(), KnownHotspot::from(hotspot));
+        }
+    }
+
+    /// Stages the upsert of a hotspot in `batch`, returns whether it is new to it.
+    /// Witness hotspots already in the known hotspot cache are skipped; beacon
+    /// hotspots are always written since they carry the poc_id. Hotspots found by
+    /// `check_existing` or already staged as new are staged for a bulk update
+    /// instead, new ones for a bulk upsert (see `upsert_new_hotspots`).
+    fn stage_hotspot(
+        &self,
+        hotspot_type: HotspotType,
+        hotspot: &Hotspot,
+        batch: &mut Batch,
+    ) -> Result<bool, DBError> {
+        if matches!(hotspot_type, HotspotType::Witness) && self.is_known_hotspot(hotspot) {
+            tracing::debug!("skipped known witness hotspot");
+            return Ok(false);
+        }
+        let known = if batch.existing_hotspots.contains(&hotspot._key) {
+            self.known_hotspot(hotspot)
+        } else if let Some(new) = batch.new_hotspots.get(&hotspot._key) {
+            // NOTE: the staged upsert writes its fields, later occurrences
+            // only update the ones changed since
+            Some(new.staged.clone())
+        } else {
+            tracing::debug!("staged new {:?} hotspot", hotspot_type);
+            batch.add_new_hotspot(hotspot)?;
+            self.remember_hotspot(hotspot);
+            return Ok(true);
+        };
+        tracing::debug!("staged existing {:?} hotspot update", hotspot_type);
+        batch.add_hotspot_update(hotspot, known)?;
+        self.remember_hotspot(hotspot);
+        Ok(false)
+    }
+
+    /// Stage a beacon (and its `raw` poc if kept in the raw_pocs collection) for
+    /// bulk insertion, or its dead letter if it can't be shrunk to fit.
+    fn stage_beacon(
+        &self,
+        mut beacon: Beacon,
+        raw: &[u8],
+        batch: &mut Batch,
+    ) -> Result<(), DBError> {
+        if !self.transforms.fit(&mut beacon)? {
+            let size = beacon.doc_size()?;
+            tracing::warn!(
+                "beacon {:?} too large ({:?} bytes), dead-lettering",
+                beacon.poc_id,
+                size
+            );
+            let dead_letter = DeadLetter::new(&beacon, "document_too_large", size);
+            batch.dead_letters.push(serde_json::to_value(dead_letter)?);
+            return Ok(());
+        }
+        if let Some(overflow) = &beacon.overflow {
+            tracing::warn!("beacon {:?} overflowed: {:?}", beacon.poc_id, overflow);
+        }
+        if self.raw_pocs == RawPocStorage::Collection {
+            batch
+                .raw_pocs
+                .push(serde_json::to_value(RawPoc::new(&beacon.poc_id, raw))?);
+        }
+        batch.beacons.push(serde_json::to_value(beacon)?);
+        Ok(())
+    }
+
+    /// Bulk write rewards, replacing any previously stored reward of the same epoch
+    async fn insert_rewards(&self, rewards: Vec<Value>) -> Result<(), DBError> {
+        let query =
+            r#"FOR r IN @rewards INSERT r INTO @@collection OPTIONS { overwriteMode: "replace" }"#;
+        let aql = AqlQuery::builder()
+            .query(query)
+            .bind_var("@collection", self.names.rewards.as_str())
+            .bind_var("rewards", Value::Array(rewards))
+            .build();
+
+        tracing::debug!("inserting rewards");
+        self.execute(aql).await
+    }
+
+    /// Bulk insert documents, ignoring any which already exist
+    async fn insert_new(&self, collection: &str, docs: Vec<Value>) -> Result<(), DBError> {
+        let query = r#"FOR d IN @docs INSERT d INTO @@collection OPTIONS { ignoreErrors: true }"#;
+        let aql = AqlQuery::builder()
+            .query(query)
+            .bind_var("@collection", collection)
+            .bind_var("docs", Value::Array(docs))
+            .build();
+
+        tracing::debug!("inserting into {:?}", collection);
+        self.execute(aql).await
+    }
+
+    /// Bulk upsert the staged new hotspots, returns the keys of the inserted ones
+    /// (the others were stored since `check_existing` looked them up)
+    async fn upsert_new_hotspots(&self, hotspots: Vec<Value>) -> Result<HashSet<String>, DBError> {
+        let query = hotspot_upsert_query();
+        let aql = AqlQuery::builder()
+            .query(&query)
+            .bind_var("@collection", self.names.hotspots.as_str())
+            .bind_var("hotspots", Value::Array(hotspots))
+            .build();
+
+        tracing::debug!("upserting new hotspots");
+        let inserted: Vec<Option<String>> = self.query_all(Endpoint::Inner, aql).await?;
+        Ok(inserted.into_iter().flatten().collect())
+    }
+
+    /// Bulk update the staged already stored hotspots, writing only their
+    /// changed fields (and poc_ids only if any were added)
+    async fn update_hotspots(&self, hotspots: Vec<Value>) -> Result<(), DBError> {
+        let query = hotspot_update_query();
+        let aql = AqlQuery::builder()
+            .query(&query)
+            .bind_var("@collection", self.names.hotspots.as_str())
+            .bind_var("hotspots", Value::Array(hotspots))
+            .build();
+
+        tracing::debug!("updating hotspots");
+        self.execute(aql).await
+    }
+
+    /// Bulk upsert the staged (per key aggregated) witness edges
+    async fn upsert_edges(&self, edges: Vec<EdgeAggregate>) -> Result<(), DBError> {
+        let query = edge_upsert_query();
+
+        let aql = AqlQuery::builder()
+            .query(&query)
+            .bind_var("@witness_edge_collection", self.names.witnesses.as_str())
+            .bind_var("hotspot_collection", self.names.hotspots.as_str())
+            .bind_var("edges", serde_json::to_value(edges)?)
+            .bind_var(
+                "window_keep",
+                self.settings.edge_windows.map_or(0, |w| w.keep.max(1)),
+            )
+            .build();
+
+        tracing::debug!("upserting edges");
+        self.execute_increment(aql).await
+    }
+
+    /// Set the `reciprocal_count` of the upserted edges and of their reverse
+    /// edges (witness to beacon) to the count of the other direction, summed
+    /// over all its edges when the hotspots are linked by several locations
+    async fn update_reciprocal_edges(&self, keys: Vec<String>) -> Result<(), DBError> {
+        let query = unindent(
+            r#"
+             LET pairs = FLATTEN(
+                 FOR key IN @keys
+                 LET edge = DOCUMENT(@@witness_edge_collection, key)
+                 FILTER edge != null
+                 FOR rev IN @@witness_edge_collection
+                 FILTER rev._from == edge._to AND rev._to == edge._from
+                 RETURN [
+                     { _key: edge._key, other: rev._key, count: rev.count },
+                     { _key: rev._key, other: edge._key, count: edge.count }
+                 ]
+             )
+             FOR pair IN pairs
+             // both directions of a pair may have been upserted
+             COLLECT key = pair._key, other = pair.other AGGREGATE count = MAX(pair.count)
+             COLLECT edge_key = key AGGREGATE reciprocal_count = SUM(count)
+             UPDATE { _key: edge_key, reciprocal_count } IN @@witness_edge_collection
+             "#,
+        );
+
+        let aql = AqlQuery::builder()
+            .query(&query)
+            .bind_var("@witness_edge_collection", self.names.witnesses.as_str())
+            .bind_var("keys", serde_json::to_value(keys)?)
+            .build();
+
+        tracing::debug!("updating reciprocal edges");
+        self.execute(aql).await
+    }
+
+    /// Bulk upsert the staged (per parent hex aggregated) hex activity
+    async fn upsert_hexes(&self, hexes: Vec<HexAggregate>) -> Result<(), DBError> {
+        let query = unindent(
+            r#"
+             FOR h IN @hexes
+             UPSERT { _key: h._key }
              INSERT {
-                 _key: @witness_edge_key,
-                 _from: CONCAT_SEPARATOR("/", "hotspots", @beacon_pub_key),
-                 _to: CONCAT_SEPARATOR("/", "hotspots", @witness_pub_key),
-                 count: 1,
-                 distance: @distance,
-                 snr_hist: {@witness_snr: 1},
-                 signal_hist: {@witness_signal: 1},
-                 ingest_latency_hist: {@ingest_latency: 1},
-                 last_updated_at: DATE_NOW()
+                 _key: h._key,
+                 beaconers: h.beaconers,
+                 witnessers: h.witnessers,
+                 distinct_beaconers: LENGTH(h.beaconers),
+                 distinct_witnessers: LENGTH(h.witnessers),
+                 beacon_count: h.beacon_count,
+                 last_activity_unix_ms: h.last_activity_unix_ms
              }
              UPDATE {
-                 count: OLD.count + 1,
-                 snr_hist: MERGE(OLD.snr_hist, {@witness_snr: OLD.snr_hist[@witness_snr] ? OLD.snr_hist[@witness_snr] + 1 : 1}),
-                 signal_hist: MERGE(OLD.signal_hist, {@witness_signal: OLD.signal_hist[@witness_signal] ? OLD.signal_hist[@witness_signal] + 1 : 1}),
-                 ingest_latency_hist: MERGE(OLD.ingest_latency_hist, {@ingest_latency: OLD.ingest_latency_hist[@ingest_latency] ? OLD.ingest_latency_hist[@ingest_latency] + 1 : 1}),
-                 last_updated_at: MAX([OLD.last_updated_at, DATE_NOW()])
+                 beaconers: UNION_DISTINCT(OLD.beaconers, h.beaconers),
+                 witnessers: UNION_DISTINCT(OLD.witnessers, h.witnessers),
+                 distinct_beaconers: LENGTH(UNION_DISTINCT(OLD.beaconers, h.beaconers)),
+                 distinct_witnessers: LENGTH(UNION_DISTINCT(OLD.witnessers, h.witnessers)),
+                 beacon_count: OLD.beacon_count + h.beacon_count,
+                 last_activity_unix_ms: MAX([OLD.last_activity_unix_ms, h.last_activity_unix_ms])
              }
-             IN @@witness_edge_collection
+             IN @@hexes
              "#,
         );
 
         let aql = AqlQuery::builder()
             .query(&query)
-            .bind_var("@witness_edge_collection", WITNESS_EDGE_COLLECTION)
-            .bind_var("witness_edge_key", witness_edge_key)
-            .bind_var("beacon_pub_key", beacon_pub_key.to_string())
-            .bind_var("witness_pub_key", witness_pub_key.to_string())
-            .bind_var("distance", distance)
-            .bind_var("witness_snr", witness_snr)
-            .bind_var("witness_signal", witness_signal)
-            .bind_var("ingest_latency", ingest_latency)
-            .build();
-
-        tracing::debug!("upserting edge");
-        self.inner
-            .aql_query::<Vec<Value>>(aql)
-            .await
-            .map(|_| ())
-            .map_err(DBError::from)
+            .bind_var("@hexes", self.names.hexes.as_str())
+            .bind_var("hexes", serde_json::to_value(hexes)?)
+            .build();
+
+        tracing::debug!("upserting hexes");
+        self.execute_increment(aql).await
+    }
+
+    /// Write all staged documents of a batch, returns the keys of the hotspots it
+    /// inserted.
+    ///
+    /// Write-write conflicts (1200) of hotspot upserts and updates, hexes and edges
+    /// with concurrently flushed batches (and 1210 of upserts racing their inserts)
+    /// are retried up to `MAX_FLUSH_ATTEMPTS` times.
+    pub async fn flush(&self, batch: Batch) -> Result<HashSet<String>, DBError> {
+        if !batch.rewards.is_empty() {
+            self.insert_rewards(batch.rewards).await?;
+        }
+        if !batch.invalid_beacons.is_empty() {
+            self.insert_new(&self.names.invalid_beacons, batch.invalid_beacons)
+                .await?;
+        }
+        if !batch.invalid_witnesses.is_empty() {
+            self.insert_new(&self.names.invalid_witnesses, batch.invalid_witnesses)
+                .await?;
+        }
+        if !batch.raw_pocs.is_empty() {
+            self.insert_new(&self.names.raw_pocs, batch.raw_pocs)
+                .await?;
+        }
+        if !batch.dead_letters.is_empty() {
+            self.insert_new(&self.names.dead_letters, batch.dead_letters)
+                .await?;
+        }
+        if !batch.beacons.is_empty() {
+            self.insert_new(&self.names.beacons, batch.beacons).await?;
+        }
+        let mut inserted = HashSet::new();
+        if !batch.new_hotspots.is_empty() {
+            let hotspots = batch
+                .new_hotspots
+                .into_values()
+                .map(serde_json::to_value)
+                .collect::<Result<Vec<Value>, _>>()?;
+            inserted = retry_conflicts("upserting new hotspots", || {
+                self.upsert_new_hotspots(hotspots.clone())
+            })
+            .await?;
+        }
+        // NOTE: after the upserts, the updates may be of hotspots they inserted
+        if !batch.hotspot_updates.is_empty() {
+            let hotspots = batch
+                .hotspot_updates
+                .into_values()
+                .map(serde_json::to_value)
+                .collect::<Result<Vec<Value>, _>>()?;
+            retry_conflicts("updating hotspots", || {
+                self.update_hotspots(hotspots.clone())
+            })
+            .await?;
+        }
+        if !batch.hexes.is_empty() {
+            let hexes: Vec<HexAggregate> = batch.hexes.into_values().collect();
+            retry_conflicts("upserting hexes", || self.upsert_hexes(hexes.clone())).await?;
+        }
+        if batch.edges.is_empty() {
+            return Ok(inserted);
+        }
+        let edges: Vec<EdgeAggregate> = batch.edges.into_values().collect();
+        retry_conflicts("upserting edges", || self.upsert_edges(edges.clone())).await?;
+        if self.settings.reciprocal_edges {
+            let keys: Vec<String> = edges.into_iter().map(|e| e._key).collect();
+            retry_conflicts("updating reciprocal edges", || {
+                self.update_reciprocal_edges(keys.clone())
+            })
+            .await?;
+        }
+        Ok(inserted)
+    }
+
+    /// Verify a shadow sample in the background, so reading it back doesn't slow
+    /// down ingestion. While `MAX_IN_FLIGHT` samples are being verified further
+    /// ones are dropped.
+    fn spawn_shadow_verify(&self, sample: ShadowSample) {
+        let Some(shadow) = &self.shadow else {
+            return;
+        };
+        let Some(permit) = shadow.try_start() else {
+            tracing::debug!("shadow verification busy, dropped sample");
+            return;
+        };
+        let shadow = shadow.clone();
+        let inner = self.inner();
+        let beacons = self.names.beacons.clone();
+        let witnesses = self.names.witnesses.clone();
+        tokio::spawn(async move {
+            verify_shadow_sample(&shadow, &inner, &beacons, &witnesses, sample).await;
+            drop(permit);
+        });
+    }
+
+    /// Checked and mismatched counts of shadow verification, None if disabled
+    pub fn shadow_metrics(&self) -> Option<ShadowMetrics> {
+        self.shadow.as_ref().map(|shadow| shadow.metrics())
+    }
+
+    /// Stages the gateway reward of a reward share in `batch`, returns whether it had one
+    pub fn stage_reward(&self, share: IotRewardShare, batch: &mut Batch) -> Result<bool> {
+        match Reward::from_share(share, &self.names.hotspots, self.settings.hotspot_keys)? {
+            Some(reward) => {
+                batch.rewards.push(serde_json::to_value(reward)?);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    pub fn stage_invalid_beacon(
+        &self,
+        report: LoraInvalidBeaconReportV1,
+        batch: &mut Batch,
+    ) -> Result<()> {
+        let report = IotInvalidBeaconReport::try_from(report)?;
+        let invalid = InvalidBeacon::try_from(&report)?;
+        batch.invalid_beacons.push(serde_json::to_value(invalid)?);
+        Ok(())
     }
 
-    pub async fn populate_collections(&self, dec_msg: LoraPocV1) -> Result<Option<String>> {
-        let iot_poc = IotPoc::try_from(dec_msg)?;
+    pub fn stage_invalid_witness(
+        &self,
+        report: LoraInvalidWitnessReportV1,
+        batch: &mut Batch,
+    ) -> Result<()> {
+        let report = IotInvalidWitnessReport::try_from(report)?;
+        let invalid = InvalidWitness::try_from(&report)?;
+        batch.invalid_witnesses.push(serde_json::to_value(invalid)?);
+        Ok(())
+    }
 
-        // return early if no witnesses
-        if iot_poc.selected_witnesses.is_empty() {
-            tracing::debug!("ignored, no witnesses");
+    /// Stages the hotspots, beacon and witness edges of a poc in `batch`, which are
+    /// written on `flush`. `raw` is the encoded `dec_msg`, kept as configured
+    /// by `raw_pocs`, `source` the message it was read from.
+    pub fn stage_collections(
+        &self,
+        dec_msg: LoraPocV1,
+        raw: &[u8],
+        source: Option<Source>,
+        batch: &mut Batch,
+    ) -> Result<Option<Populated>> {
+        let mut iot_poc = IotPoc::try_from(dec_msg)?;
+
+        if !self.transforms.filter(&mut iot_poc) {
+            tracing::debug!("ignored, filtered");
             return Ok(None);
         }
 
-        let beacon = Beacon::try_from(&iot_poc)?;
+        // NOTE: beacons without (selected) witnesses are stored too, with their counts
+        let mut beacon = self.transforms.beacon(&iot_poc)?;
+        if batch.existing_beacons.contains(&beacon._key) {
+            tracing::debug!("ignored, already ingested");
+            return Ok(None);
+        }
+        beacon.source = source;
+        self.transforms.minimize(&mut beacon)?;
+        if self.raw_pocs == RawPocStorage::Inline {
+            beacon.raw_poc = Some(raw_poc::encode(raw));
+        }
 
         // insert beacon hotspot
-        let poc_id = beacon.poc_id.clone();
-        let beacon_hotspot = Hotspot::try_from(&beacon)?;
-        self.populate_hotspot(HotspotType::Beacon, beacon_hotspot)
-            .await?;
+        let mut new_hotspots = vec![];
+        let beacon_hotspot = self.hotspot(Hotspot::try_from(&beacon)?);
+        if self.stage_hotspot(HotspotType::Beacon, &beacon_hotspot, batch)? {
+            new_hotspots.push(HotspotFirstSeen::from(&beacon_hotspot));
+        }
+        let hex_coverage = self.settings.hex_coverage;
+        if let (true, Some(hex)) = (hex_coverage, &beacon.parent_str_location) {
+            batch
+                .hex(hex)
+                .add_beacon(&beacon_hotspot._key, beacon.ingest_time_unix_ms);
+        }
 
+        let shadow = self.shadow.as_ref().map_or(false, |shadow| shadow.sample());
+        if shadow {
+            batch.shadow_beacons.insert(beacon._key.clone());
+        }
         for witness in beacon.witnesses.iter() {
             // insert witness hotspot
-            let witness_hotspot = Hotspot::try_from(witness)?;
-            self.populate_hotspot(HotspotType::Witness, witness_hotspot)
-                .await?;
-            // insert beacon -> witness edge
-            let edge = Edge::new(&beacon, witness)?;
-            self.populate_edge(edge).await?;
+            let witness_hotspot = self.hotspot(Hotspot::try_from(witness)?);
+            if self.stage_hotspot(HotspotType::Witness, &witness_hotspot, batch)? {
+                new_hotspots.push(HotspotFirstSeen::from(&witness_hotspot));
+            }
+            if let (true, Some(hex)) = (hex_coverage, &witness.parent_str_location) {
+                batch
+                    .hex(hex)
+                    .add_witness(&witness_hotspot._key, witness.ingest_time_unix_ms);
+            }
+            // stage beacon -> witness edge
+            let edge = Edge::new(&beacon, witness)?.with_keys(self.settings.hotspot_keys);
+            if shadow {
+                batch.shadow_edges.insert(edge._key.clone());
+            }
+            batch.add_edge(edge, self.settings.edge_windows.map(|w| w.period));
+        }
+
+        // stage beacon itself
+        let populated = Populated::new(&beacon, new_hotspots, self.transforms.beacon_payloads);
+        self.stage_beacon(beacon, raw, batch)?;
+
+        Ok(Some(populated))
+    }
+
+    /// Decodes the messages of a chunk and stages them in `batch`
+    async fn stage_msgs(
+        &self,
+        file_type: FileType,
+        bufs: Vec<BytesMut>,
+        batch: &mut Batch,
+    ) -> Written {
+        match file_type {
+            FileType::IotRewardShare => Written {
+                populated: vec![],
+                decode_errors: bufs
+                    .into_iter()
+                    .map(|buf| self.stage_reward_msg(buf, batch))
+                    .filter(|staged| !staged)
+                    .count(),
+            },
+            FileType::IotInvalidBeaconReport | FileType::IotInvalidWitnessReport => Written {
+                populated: vec![],
+                decode_errors: bufs
+                    .into_iter()
+                    .map(|buf| self.stage_invalid_msg(file_type, buf, batch))
+                    .filter(|staged| !staged)
+                    .count(),
+            },
+            _ => self.stage_poc_msgs(bufs, batch).await,
+        }
+    }
+
+    /// Decodes a chunk of pocs, checks which of their documents already exist in a
+    /// single round trip and stages them in `batch`
+    async fn stage_poc_msgs(&self, bufs: Vec<BytesMut>, batch: &mut Batch) -> Written {
+        let msgs = bufs.len();
+        let (dec_msgs, bufs): (Vec<LoraPocV1>, Vec<(usize, BytesMut)>) = bufs
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, buf)| match LoraPocV1::decode(buf.as_ref()) {
+                Ok(dec_msg) => Some((dec_msg, (i, buf))),
+                Err(e) => {
+                    tracing::error!("error decoding message: {:?}", e);
+                    None
+                }
+            })
+            .unzip();
+        if let Err(e) = self.check_existing(&dec_msgs, batch).await {
+            // NOTE: staging falls back to upserting every hotspot
+            tracing::warn!("error checking existing documents: {:?}", e);
+        }
+        let mut written = Written {
+            decode_errors: msgs - dec_msgs.len(),
+            ..Default::default()
+        };
+        for (dec_msg, (i, buf)) in dec_msgs.into_iter().zip(bufs) {
+            let source = batch.source.as_ref().map(|source| source.offset(i));
+            match self.stage_collections(dec_msg, &buf, source, batch) {
+                Ok(Some(p)) => written.populated.push(p),
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::error!("error populating collections: {:?}", e);
+                    written.decode_errors += 1;
+                }
+            }
         }
+        written
+    }
 
-        // insert beacon itself
-        self.populate_beacon(beacon).await?;
+    /// Decodes a single reward share and stages its gateway reward in `batch`,
+    /// false if it fails to
+    fn stage_reward_msg(&self, buf: BytesMut, batch: &mut Batch) -> bool {
+        match IotRewardShare::decode(buf) {
+            Ok(share) => match self.stage_reward(share, batch) {
+                Ok(()) => true,
+                Err(e) => {
+                    tracing::error!("error staging reward: {:?}", e);
+                    false
+                }
+            },
+            Err(e) => {
+                tracing::error!("error decoding reward share: {:?}", e);
+                false
+            }
+        }
+    }
 
-        Ok(Some(poc_id))
+    /// Decodes a single invalid beacon or witness report and stages it in `batch`,
+    /// false if it fails to
+    fn stage_invalid_msg(&self, file_type: FileType, buf: BytesMut, batch: &mut Batch) -> bool {
+        let staged = match file_type {
+            FileType::IotInvalidBeaconReport => LoraInvalidBeaconReportV1::decode(buf)
+                .map_err(anyhow::Error::from)
+                .and_then(|report| self.stage_invalid_beacon(report, batch)),
+            _ => LoraInvalidWitnessReportV1::decode(buf)
+                .map_err(anyhow::Error::from)
+                .and_then(|report| self.stage_invalid_witness(report, batch)),
+        };
+        if let Err(e) = &staged {
+            tracing::error!("error staging invalid {}: {:?}", file_type, e);
+        }
+        staged.is_ok()
+    }
+}
+
+#[async_trait::async_trait]
+impl Sink for DB {
+    async fn init_file(&self, file: &FileInfo) -> Result<()> {
+        Ok(DB::init_file(self, file).await?)
+    }
+
+    async fn complete_file(&self, key: &str, stats: &FileStats) -> Result<()> {
+        Ok(DB::complete_file(self, key, stats).await?)
+    }
+
+    async fn get_file_retries(&self, key: &str) -> Result<u8> {
+        Ok(DB::get_file_retries(self, key).await?)
+    }
+
+    async fn increment_file_retry(&self, key: &str) -> Result<()> {
+        Ok(DB::increment_file_retry(self, key).await?)
+    }
+
+    async fn get_done_file_keys(&self) -> Result<Vec<String>> {
+        Ok(DB::get_done_file_keys(self).await?)
+    }
+
+    async fn heartbeat_file(&self, key: &str) -> Result<()> {
+        Ok(DB::heartbeat_file(self, key).await?)
+    }
+
+    async fn reset_stale_files(&self, stale_before: DateTime<Utc>) -> Result<Vec<String>> {
+        Ok(DB::reset_stale_files(self, unix_ms(stale_before)).await?)
+    }
+
+    async fn get_checkpoint(&self, name: &str) -> Result<Option<Checkpoint>> {
+        Ok(DB::get_checkpoint(self, name).await?)
+    }
+
+    async fn set_checkpoint(&self, checkpoint: &Checkpoint) -> Result<()> {
+        Ok(DB::set_checkpoint(self, checkpoint).await?)
+    }
+
+    fn transforms(&self) -> &PocTransforms {
+        &self.transforms
+    }
+
+    async fn write_chunk(
+        &self,
+        file_type: FileType,
+        bufs: Vec<BytesMut>,
+        source: &Source,
+    ) -> Result<Written> {
+        let mut batch = Batch::new(source.clone());
+        let mut written = self.stage_msgs(file_type, bufs, &mut batch).await;
+        let shadow_sample = batch.shadow_sample();
+        let inserted = self.flush(batch).await?;
+        // NOTE: hotspots staged as new may have been stored concurrently since
+        for populated in written.populated.iter_mut() {
+            populated
+                .new_hotspots
+                .retain(|hotspot| inserted.contains(&self.hotspot_key(&hotspot.pub_key)));
+        }
+        if let Some(sample) = shadow_sample {
+            self.spawn_shadow_verify(sample);
+        }
+        Ok(written)
+    }
+
+    /// Stages the chunk (looking up the existing documents) without flushing it
+    async fn dry_run_chunk(
+        &self,
+        file_type: FileType,
+        bufs: Vec<BytesMut>,
+        source: &Source,
+    ) -> Result<Written> {
+        let mut batch = Batch::new(source.clone());
+        let written = self.stage_msgs(file_type, bufs, &mut batch).await;
+        tracing::info!(
+            "dry run: would write {} of {}",
+            batch.describe(),
+            source.file_key
+        );
+        Ok(written)
     }
 }
 
 // Helper functions
 
-async fn create_new_db_and_collections(inner: &ArangoDatabase) -> Result<Collections> {
+/// Run a bulk write, retrying it on write-write conflicts (1200) and on unique
+/// constraint violations (1210) of upserts racing a concurrent insert
+async fn retry_conflicts<T, F, Fut>(what: &str, mut write: F) -> Result<T, DBError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, DBError>>,
+{
+    let mut attempt = 1;
+    loop {
+        match write().await {
+            Err(DBError::ArangoClientError(ClientError::Arango(ae)))
+                if [1200, 1210].contains(&ae.error_num()) && attempt < MAX_FLUSH_ATTEMPTS =>
+            {
+                tracing::debug!("write conflict {}, attempt: {:?}", what, attempt);
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
+
+/// Read back the documents of sampled pocs from `db` after they were written and
+/// compare them with the written ones, logging and counting mismatches. Failing
+/// to read them back is only logged.
+async fn verify_shadow_sample(
+    shadow: &ShadowVerifier,
+    db: &ArangoDatabase,
+    beacons: &str,
+    witnesses: &str,
+    sample: ShadowSample,
+) {
+    let beacon_keys: Vec<&Value> = sample.beacons.iter().map(|b| &b["_key"]).collect();
+    let edge_keys: Vec<&str> = sample.edges.iter().map(|e| e._key.as_str()).collect();
+    let (stored_beacons, stored_edges) = match tokio::try_join!(
+        get_documents(db, beacons, json!(beacon_keys)),
+        get_documents(db, witnesses, json!(edge_keys)),
+    ) {
+        Ok(stored) => stored,
+        Err(e) => {
+            tracing::warn!("error reading back shadow sample: {:?}", e);
+            return;
+        }
+    };
+    for (beacon, stored) in sample.beacons.iter().zip(stored_beacons) {
+        let fields = mismatched_fields(beacon, &stored);
+        if !fields.is_empty() {
+            tracing::warn!(
+                "shadow verification mismatch of beacon {}: {:?}",
+                beacon["_key"],
+                fields
+            );
+        }
+        shadow.record(!fields.is_empty());
+    }
+    for (edge, stored) in sample.edges.iter().zip(stored_edges) {
+        let fields = edge_mismatches(edge, &stored);
+        if !fields.is_empty() {
+            tracing::warn!(
+                "shadow verification mismatch of witness edge {}: {:?}",
+                edge._key,
+                fields
+            );
+        }
+        shadow.record(!fields.is_empty());
+    }
+}
+
+/// Documents of `collection` by key, null for missing ones
+async fn get_documents(
+    db: &ArangoDatabase,
+    collection: &str,
+    keys: Value,
+) -> Result<Vec<Value>, ClientError> {
+    let query = r#"FOR k IN @keys RETURN DOCUMENT(@@collection, k)"#;
+    let aql = AqlQuery::builder()
+        .query(query)
+        .bind_var("@collection", collection)
+        .bind_var("keys", keys)
+        .build();
+
+    db.aql_query(aql).await
+}
+
+/// Keys of the done file records of `@@collection`
+pub const DONE_FILE_KEYS_QUERY: &str =
+    r#"FOR f IN @@collection FILTER f.done == @done RETURN f._key"#;
+
+/// Which of `@keys` are stored in `@@collection`
+pub const KEYS_EXIST_QUERY: &str = r#"FOR d IN @@collection FILTER d._key IN @keys RETURN d._key"#;
+
+/// AQL upserting the staged new `@hotspots`, returning the keys of the inserted
+/// ones (null for the others). Only beacon hotspots carry poc_ids to add.
+pub fn hotspot_upsert_query() -> String {
+    unindent(
+        r#"
+         FOR h IN @hotspots
+         UPSERT { _key: h.hotspot._key }
+         INSERT h.hotspot
+         UPDATE MERGE(
+             LENGTH(h.hotspot.poc_ids) > 0 ? { poc_ids: UNION_DISTINCT(OLD.poc_ids, h.hotspot.poc_ids) } : {},
+             { last_updated_unix_ms: MAX([OLD.last_updated_unix_ms, DATE_NOW()]),
+               gain: h.hotspot.gain,
+               elevation: h.hotspot.elevation },
+             h.hotspot.metadata != null ? { metadata: h.hotspot.metadata } : {},
+             {location_update}
+         ) IN @@collection
+         RETURN OLD == null ? NEW._key : null"#,
+    )
+    .replace(
+        "{location_update}",
+        &location_update("OLD", "h.location", "h.seen_unix_ms"),
+    )
+}
+
+/// AQL updating the changed fields of already stored `@hotspots`
+pub fn hotspot_update_query() -> String {
+    unindent(
+        r#"
+         FOR h IN @hotspots
+         FOR old IN @@collection
+         FILTER old._key == h._key
+         UPDATE old WITH MERGE(
+             UNSET(h, "_key", "poc_ids", "location", "seen_unix_ms"),
+             LENGTH(h.poc_ids) > 0 ? { poc_ids: UNION_DISTINCT(old.poc_ids, h.poc_ids) } : {},
+             { last_updated_unix_ms: MAX([old.last_updated_unix_ms, DATE_NOW()]) },
+             {location_update}
+         ) IN @@collection"#,
+    )
+    .replace(
+        "{location_update}",
+        &location_update("old", "h.location", "h.seen_unix_ms"),
+    )
+}
+
+/// AQL upserting the per key aggregated witness `@edges`
+pub fn edge_upsert_query() -> String {
+    unindent(
+        r#"
+             FOR e IN @edges
+             UPSERT { _key: e._key }
+             INSERT MERGE({
+                 _key: e._key,
+                 _from: CONCAT_SEPARATOR("/", @hotspot_collection, e.beacon_hotspot_key),
+                 _to: CONCAT_SEPARATOR("/", @hotspot_collection, e.witness_hotspot_key),
+                 count: e.count,
+                 distance: e.distance,
+                 snr_hist: e.snr_hist,
+                 signal_hist: e.signal_hist,
+                 ingest_latency_hist: e.ingest_latency_hist,
+                 first_seen_unix_ms: e.first_seen_unix_ms,
+                 last_seen_unix_ms: e.last_seen_unix_ms,
+                 last_updated_unix_ms: DATE_NOW(),
+                 beacon_regions: e.beacon_regions,
+                 band_mismatch_count: e.band_mismatch_count,
+                 band_mismatch: e.band_mismatch_count * 2 > e.count
+             }, e.windows ? { windows: e.windows } : {})
+             UPDATE MERGE({
+                 count: OLD.count + e.count,
+                 snr_hist: MERGE(OLD.snr_hist, ZIP(ATTRIBUTES(e.snr_hist), (FOR k IN ATTRIBUTES(e.snr_hist) RETURN (OLD.snr_hist[k] || 0) + e.snr_hist[k]))),
+                 signal_hist: MERGE(OLD.signal_hist, ZIP(ATTRIBUTES(e.signal_hist), (FOR k IN ATTRIBUTES(e.signal_hist) RETURN (OLD.signal_hist[k] || 0) + e.signal_hist[k]))),
+                 ingest_latency_hist: MERGE(OLD.ingest_latency_hist, ZIP(ATTRIBUTES(e.ingest_latency_hist), (FOR k IN ATTRIBUTES(e.ingest_latency_hist) RETURN (OLD.ingest_latency_hist[k] || 0) + e.ingest_latency_hist[k]))),
+                 first_seen_unix_ms: MIN([OLD.first_seen_unix_ms, e.first_seen_unix_ms]),
+                 last_seen_unix_ms: MAX([OLD.last_seen_unix_ms, e.last_seen_unix_ms]),
+                 last_updated_unix_ms: MAX([OLD.last_updated_unix_ms, DATE_NOW()]),
+                 beacon_regions: UNION_DISTINCT(OLD.beacon_regions || [], e.beacon_regions),
+                 band_mismatch_count: (OLD.band_mismatch_count || 0) + e.band_mismatch_count,
+                 band_mismatch: ((OLD.band_mismatch_count || 0) + e.band_mismatch_count) * 2 > OLD.count + e.count
+             }, e.windows ? { windows: (
+                 LET merged = MERGE(OLD.windows || {}, ZIP(ATTRIBUTES(e.windows), (
+                     FOR w IN ATTRIBUTES(e.windows)
+                     LET prev = (OLD.windows || {})[w] || { count: 0, snr_hist: {}, signal_hist: {} }
+                     LET add = e.windows[w]
+                     RETURN {
+                         count: prev.count + add.count,
+                         snr_hist: MERGE(prev.snr_hist, ZIP(ATTRIBUTES(add.snr_hist), (FOR k IN ATTRIBUTES(add.snr_hist) RETURN (prev.snr_hist[k] || 0) + add.snr_hist[k]))),
+                         signal_hist: MERGE(prev.signal_hist, ZIP(ATTRIBUTES(add.signal_hist), (FOR k IN ATTRIBUTES(add.signal_hist) RETURN (prev.signal_hist[k] || 0) + add.signal_hist[k])))
+                     }
+                 )))
+                 RETURN KEEP(merged, SLICE(SORTED(ATTRIBUTES(merged)), -@window_keep))
+             )[0] } : {})
+             IN @@witness_edge_collection
+             // replace the windows as a whole, dropping the ones beyond the kept
+             OPTIONS { mergeObjects: false }
+             "#,
+    )
+}
+
+/// AQL removing (or with `dry_run` counting) the documents of a purge target in
+/// the [@after, @before) unix ms range of `@@collection`
+pub fn purge_query(target: PurgeTarget, dry_run: bool) -> String {
+    let remove = if dry_run {
+        ""
+    } else {
+        "REMOVE d IN @@collection"
+    };
+    format!(
+        "FOR d IN @@collection FILTER {} {} COLLECT WITH COUNT INTO purged RETURN purged",
+        target.filter(),
+        remove
+    )
+}
+
+/// Collect the index names of all (nested) plan nodes
+fn collect_index_names(value: &Value, names: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => {
+            if let Some(Value::Array(indexes)) = map.get("indexes") {
+                names.extend(
+                    indexes
+                        .iter()
+                        .filter_map(|index| index["name"].as_str())
+                        .map(str::to_string),
+                );
+            }
+            map.values().for_each(|v| collect_index_names(v, names));
+        }
+        Value::Array(values) => values.iter().for_each(|v| collect_index_names(v, names)),
+        _ => {}
+    }
+}
+
+async fn create_new_db_and_collections(
+    inner: &ArangoDatabase,
+    names: &CollectionNames,
+    indexes: &[IndexConfig],
+) -> Result<Collections> {
     let collections = Collections {
-        beacons: inner.create_collection(BEACON_COLLECTION).await?,
-        hotspots: inner.create_collection(HOTSPOT_COLLECTION).await?,
-        files: inner.create_collection(FILES_COLLECTION).await?,
-        witnesses: inner
-            .create_edge_collection(WITNESS_EDGE_COLLECTION)
-            .await?,
+        beacons: inner.create_collection(&names.beacons).await?,
+        hotspots: inner.create_collection(&names.hotspots).await?,
+        files: inner.create_collection(&names.files).await?,
+        witnesses: inner.create_edge_collection(&names.witnesses).await?,
+        dead_letters: inner.create_collection(&names.dead_letters).await?,
+        maintenance: inner.create_collection(&names.maintenance).await?,
+        hotspot_stats: inner.create_collection(&names.hotspot_stats).await?,
+        rewards: inner.create_collection(&names.rewards).await?,
+        invalid_beacons: inner.create_collection(&names.invalid_beacons).await?,
+        invalid_witnesses: inner.create_collection(&names.invalid_witnesses).await?,
+        raw_pocs: inner.create_collection(&names.raw_pocs).await?,
+        verification_runs: inner.create_collection(&names.verification_runs).await?,
+        checkpoints: inner.create_collection(&names.checkpoints).await?,
+        meta: inner.create_collection(&names.meta).await?,
+        hexes: inner.create_collection(&names.hexes).await?,
+        audit_log: inner.create_collection(&names.audit_log).await?,
     };
 
-    create_indices(inner).await?;
+    create_indices(inner, indexes).await?;
 
     Ok(collections)
 }
 
-async fn use_existing_db_and_collections(inner: &ArangoDatabase) -> Result<Collections> {
+/// Connect to `endpoint` with the configured credentials, auth method and TLS options
+async fn establish(endpoint: &str, settings: &ArangoDBSettings) -> Result<Connection> {
+    tls::configure(settings.tls.as_ref())?;
+    let conn = match settings.auth {
+        ArangoAuth::Basic => {
+            Connection::establish_basic_auth(endpoint, &settings.user, &settings.password).await?
+        }
+        ArangoAuth::Jwt => {
+            Connection::establish_jwt(endpoint, &settings.user, &settings.password).await?
+        }
+    };
+    Ok(conn)
+}
+
+/// Database for read-only queries: on the read endpoint if configured, else `inner`
+async fn connect_reader(
+    settings: &ArangoDBSettings,
+    inner: &ArangoDatabase,
+) -> Result<ArangoDatabase> {
+    match &settings.read_endpoint {
+        Some(read_endpoint) => Ok(establish(read_endpoint, settings)
+            .await?
+            .db(&settings.database)
+            .await?),
+        None => Ok(inner.clone()),
+    }
+}
+
+async fn use_existing_db_and_collections(
+    inner: &ArangoDatabase,
+    names: &CollectionNames,
+) -> Result<Collections> {
     Ok(Collections {
-        beacons: inner.collection(BEACON_COLLECTION).await?,
-        hotspots: inner.collection(HOTSPOT_COLLECTION).await?,
-        files: inner.collection(FILES_COLLECTION).await?,
-        witnesses: inner.collection(WITNESS_EDGE_COLLECTION).await?,
+        beacons: inner.collection(&names.beacons).await?,
+        hotspots: inner.collection(&names.hotspots).await?,
+        files: inner.collection(&names.files).await?,
+        witnesses: inner.collection(&names.witnesses).await?,
+        dead_letters: collection_or_create(inner, &names.dead_letters).await?,
+        maintenance: collection_or_create(inner, &names.maintenance).await?,
+        hotspot_stats: collection_or_create(inner, &names.hotspot_stats).await?,
+        rewards: collection_or_create(inner, &names.rewards).await?,
+        invalid_beacons: collection_or_create(inner, &names.invalid_beacons).await?,
+        invalid_witnesses: collection_or_create(inner, &names.invalid_witnesses).await?,
+        raw_pocs: collection_or_create(inner, &names.raw_pocs).await?,
+        verification_runs: collection_or_create(inner, &names.verification_runs).await?,
+        checkpoints: collection_or_create(inner, &names.checkpoints).await?,
+        meta: collection_or_create(inner, &names.meta).await?,
+        hexes: collection_or_create(inner, &names.hexes).await?,
+        audit_log: collection_or_create(inner, &names.audit_log).await?,
     })
 }
 
-async fn create_indices(inner: &ArangoDatabase) -> Result<()> {
-    create_beacon_indices(inner).await?;
-    create_file_indices(inner).await?;
-    create_witnes_indices(inner).await?;
-    create_hotspot_indices(inner).await?;
-    Ok(())
+/// Get an existing collection, creating it if it was added after the db was initialized
+async fn collection_or_create(inner: &ArangoDatabase, name: &str) -> Result<ArangoCollection> {
+    match inner.collection(name).await {
+        Ok(collection) => Ok(collection),
+        // 1203: collection or view not found
+        Err(ClientError::Arango(ae)) if ae.error_num() == 1203 => {
+            tracing::info!("creating missing collection {:?}", name);
+            Ok(inner.create_collection(name).await?)
+        }
+        Err(err) => Err(err.into()),
+    }
 }
 
-async fn create_file_indices(inner: &ArangoDatabase) -> Result<()> {
-    let file_ts_skiplist_index = Index::builder()
-        .name("file_ts")
-        .fields(vec!["unix_ts".to_string()])
-        .settings(IndexSettings::Skiplist {
-            unique: false,
-            sparse: true,
-            deduplicate: false,
-        })
-        .build();
-    let file_size_skiplist_index = Index::builder()
-        .name("file_size")
-        .fields(vec!["size".to_string()])
-        .settings(IndexSettings::Skiplist {
-            unique: false,
-            sparse: true,
-            deduplicate: false,
-        })
-        .build();
-    inner
-        .create_index(FILES_COLLECTION, &file_ts_skiplist_index)
-        .await?;
-    inner
-        .create_index(FILES_COLLECTION, &file_size_skiplist_index)
-        .await?;
+/// Create the named graph `name` of the hotspots and their witness edges unless
+/// it exists, so consumers can traverse edges in either direction by name
+async fn ensure_graph(inner: &ArangoDatabase, name: &str, names: &CollectionNames) -> Result<()> {
+    let url = inner.url().join(&format!("_api/gharial/{name}"))?;
+    let resp = inner.session().get(url, "").await?;
+    if resp.status().is_success() {
+        return Ok(());
+    }
+    if resp.status().as_u16() != 404 {
+        anyhow::bail!("failed to get graph {name}: {}", resp.body());
+    }
+    tracing::info!("creating graph {:?}", name);
+    let body = json!({
+        "name": name,
+        "edgeDefinitions": [{
+            "collection": names.witnesses,
+            "from": [names.hotspots],
+            "to": [names.hotspots],
+        }],
+    });
+    let url = inner.url().join("_api/gharial")?;
+    let resp = inner.session().post(url, &body.to_string()).await?;
+    if !resp.status().is_success() {
+        anyhow::bail!("failed to create graph {name}: {}", resp.body());
+    }
     Ok(())
 }
 
-async fn create_beacon_indices(inner: &ArangoDatabase) -> Result<()> {
-    let beacon_pub_key_hash_index = Index::builder()
-        .name("beacon_pub_key")
-        .fields(vec!["pub_key".to_string()])
-        .settings(IndexSettings::Persistent {
-            unique: false,
-            sparse: false,
-            deduplicate: false,
-        })
-        .build();
-    let beacon_ingest_skiplist_index = Index::builder()
-        .name("beacon_ingest_time")
-        .fields(vec!["ingest_time_unix".to_string()])
-        .settings(IndexSettings::Skiplist {
-            unique: false,
-            sparse: true,
-            deduplicate: false,
-        })
-        .build();
-    let beacon_geo_index = Index::builder()
-        .name("beacon_geo_index")
-        .fields(vec!["geo".to_string()])
-        .settings(IndexSettings::Geo { geo_json: true })
-        .build();
-    inner
-        .create_index(BEACON_COLLECTION, &beacon_pub_key_hash_index)
-        .await?;
-    inner
-        .create_index(BEACON_COLLECTION, &beacon_ingest_skiplist_index)
-        .await?;
-    inner
-        .create_index(BEACON_COLLECTION, &beacon_geo_index)
-        .await?;
+async fn create_indices(inner: &ArangoDatabase, indexes: &[IndexConfig]) -> Result<()> {
+    for index_config in indexes.iter() {
+        let index = build_index(index_config);
+        inner.create_index(&index_config.collection, &index).await?;
+        tracing::debug!(
+            "created index {:?} on {:?}",
+            index_config.name,
+            index_config.collection
+        );
+    }
     Ok(())
 }
 
-async fn create_witnes_indices(inner: &ArangoDatabase) -> Result<()> {
-    let witness_count_index = Index::builder()
-        .name("witness_count")
-        .fields(vec!["count".to_string()])
-        .settings(IndexSettings::Persistent {
-            unique: false,
-            sparse: false,
+fn build_index(index_config: &IndexConfig) -> Index {
+    let settings = match index_config.index_type {
+        IndexType::Persistent => IndexSettings::Persistent {
+            unique: index_config.unique,
+            sparse: index_config.sparse,
             deduplicate: false,
-        })
-        .build();
-    let beacon_witness_distance_index = Index::builder()
-        .name("beacon_witness_distance")
-        .fields(vec!["distance".to_string()])
-        .settings(IndexSettings::Persistent {
-            unique: false,
-            sparse: false,
+        },
+        IndexType::Skiplist => IndexSettings::Skiplist {
+            unique: index_config.unique,
+            sparse: index_config.sparse,
             deduplicate: false,
-        })
-        .build();
-    inner
-        .create_index(WITNESS_EDGE_COLLECTION, &witness_count_index)
-        .await?;
-    inner
-        .create_index(WITNESS_EDGE_COLLECTION, &beacon_witness_distance_index)
-        .await?;
-    Ok(())
-}
-
-async fn create_hotspot_indices(inner: &ArangoDatabase) -> Result<()> {
-    let hotspot_geo_index = Index::builder()
-        .name("hotspot_geo_index")
-        .fields(vec!["geo".to_string()])
-        .settings(IndexSettings::Geo { geo_json: true })
-        .build();
-    inner
-        .create_index(HOTSPOT_COLLECTION, &hotspot_geo_index)
-        .await?;
-
-    let hotspot_parent_geo_index = Index::builder()
-        .name("hotspot_parent_geo_index")
-        .fields(vec!["parent_geo".to_string()])
-        .settings(IndexSettings::Geo { geo_json: true })
-        .build();
-    inner
-        .create_index(HOTSPOT_COLLECTION, &hotspot_parent_geo_index)
-        .await?;
-    Ok(())
+        },
+        IndexType::Hash => IndexSettings::Hash {
+            unique: index_config.unique,
+            sparse: index_config.sparse,
+            deduplicate: false,
+        },
+        IndexType::Geo => IndexSettings::Geo {
+            geo_json: index_config.geo_json,
+        },
+        IndexType::Ttl => IndexSettings::Ttl {
+            expire_after: index_config.expire_after,
+        },
+    };
+    Index::builder()
+        .name(index_config.name.clone())
+        .fields(index_config.fields.clone())
+        .settings(settings)
+        .build()
 }
 
 fn unindent(s: &str) -> String {
@@ -529,3 +2640,36 @@ fn unindent(s: &str) -> String {
         .collect::<Vec<_>>()
         .join("\n")
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hotspot_update_fields() {
+        let update = HotspotUpdate {
+            _key: "a".to_string(),
+            poc_ids: vec![],
+            gain: changed(Some(Some(12)), Some(12)),
+            elevation: changed(Some(Some(5)), None),
+            location: None,
+            seen_unix_ms: 0,
+            metadata: Some(GatewayMetadata {
+                maker: Some("Nebra".to_string()),
+                ..Default::default()
+            }),
+        };
+        assert_eq!(
+            serde_json::to_value(&update).unwrap(),
+            json!({
+                "_key": "a",
+                "poc_ids": [],
+                "elevation": null,
+                "seen_unix_ms": 0,
+                "metadata": { "maker": "Nebra", "region": null, "gain": null, "elevation": null }
+            })
+        );
+        // not cached, written as is
+        assert_eq!(changed(None, Some(12)), Some(Some(12)));
+    }
+}
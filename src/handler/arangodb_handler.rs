@@ -1,58 +1,334 @@
+#[cfg(feature = "kafka")]
+use crate::handler::kafka_handler::KafkaHandler;
+#[cfg(feature = "nats")]
+use crate::handler::nats_handler::NatsHandler;
+#[cfg(feature = "postgres")]
+use crate::handler::postgres::PostgresSink;
+#[cfg(feature = "clickhouse")]
+use crate::handler::{clickhouse::ClickhouseSink, sink::Mirrored};
 use crate::{
-    handler::{arangodb::DB, RedisHandler},
-    settings::Settings,
+    document::{
+        checkpoint::Checkpoint,
+        iot_poc_file::FileStats,
+        loc_data::CellResolutions,
+        meta::{self, CollectionMeta, VersionCheck, SCHEMA_VERSION},
+        source::{Source, ETL_VERSION},
+    },
+    handler::{
+        arangodb::{Populated, DB},
+        dry_run::DryRunSink,
+        ingest_source,
+        live_feed::LiveFeed,
+        notifier::{
+            Notifier, FILE_COMPLETED_STREAM, FILE_EXHAUSTED_STREAM, FIRST_SEEN_STREAM,
+            SUMMARY_STREAM,
+        },
+        redis_handler::StreamMetrics,
+        shadow::ShadowMetrics,
+        sink::{PocTransforms, Sink},
+        stdout::StdoutSink,
+        webhook::WebhookHandler,
+        FileSource, RedisHandler,
+    },
+    settings::{RawPocStorage, Settings, SinkKind},
 };
+#[cfg(any(feature = "postgres", feature = "clickhouse"))]
+use anyhow::Context;
 use anyhow::Result;
+use bytes::BytesMut;
 use chrono::{DateTime, Utc};
-use file_store::{FileInfo, FileStore, FileType};
+use file_store::{BytesMutStream, FileInfo, FileType};
 use futures::stream::{self, StreamExt};
-use helium_proto::{services::poc_lora::LoraPocV1, Message};
-use std::sync::Arc;
-use tokio::sync::{Mutex, Semaphore};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+use tokio::{
+    sync::{Mutex, OwnedSemaphorePermit, Semaphore},
+    time::{self, Duration},
+};
+use tokio_util::sync::CancellationToken;
 
 pub struct ArangodbHandler {
-    store: FileStore,
-    db: Arc<DB>,
-    redis_handler: Arc<Option<RedisHandler>>,
+    store: Box<dyn FileSource>,
+    sink: Arc<dyn Sink>,
+    // the ArangoDB sink, None with another sink
+    db: Option<Arc<DB>>,
+    // for back-pressure and stream metrics, also one of the notifiers
+    redis_handler: Option<Arc<RedisHandler>>,
+    notifiers: Vec<Arc<dyn Notifier>>,
+    // also one of the notifiers, served by the current mode
+    live_feed: Option<Arc<LiveFeed>>,
     file_chunk_size: usize,
     max_concurrent_files: usize,
     max_processing_capacity: usize,
     max_retries: u8,
+    // how often files being processed refresh their heartbeat
+    file_heartbeat: Duration,
+    // heartbeat age after which a processing file is considered abandoned
+    stale_file: chrono::Duration,
+    prefetch_files: usize,
+    prefetch_memory_budget: usize,
+    // memory budget (in KiB permits) shared by prefetched files, None if prefetching is disabled
+    prefetch_budget: Option<Arc<Semaphore>>,
+    // ingested file types, iot_poc and optionally iot_reward_share
+    file_types: Vec<FileType>,
+    // once cancelled no new files are started, in-flight ones are finished
+    shutdown: CancellationToken,
+    // while set (maintenance mode) no new files are started either
+    paused: Arc<AtomicBool>,
+    // stored beacons and witnesses per file type and region since start
+    counts: std::sync::Mutex<IngestCounts>,
+    // files to process (not done yet when listed) and failed attempts since start
+    files_listed: AtomicUsize,
+    files_failed: AtomicUsize,
 }
 
 impl ArangodbHandler {
     pub async fn new(settings: &Settings) -> Result<Self> {
-        let store = FileStore::from_settings(&settings.ingest).await?;
+        let store = ingest_source(settings).await?;
+        Self::with_source(settings, store).await
+    }
+
+    /// Create a handler reading ingest files from `store` instead of the configured bucket
+    pub async fn with_source(settings: &Settings, store: Box<dyn FileSource>) -> Result<Self> {
+        if settings.dry_run {
+            tracing::info!("dry run: nothing is written to the sink or published");
+        }
+        if let Some(privacy) = &settings.arangodb.privacy {
+            if settings.parent_resolution > privacy.resolution {
+                anyhow::bail!(
+                    "parent_resolution {} is finer than the privacy resolution {}",
+                    settings.parent_resolution,
+                    privacy.resolution
+                );
+            }
+            if let Some(finest) = settings.resolutions.iter().max() {
+                if *finest > privacy.resolution {
+                    anyhow::bail!(
+                        "resolution {} is finer than the privacy resolution {}",
+                        finest,
+                        privacy.resolution
+                    );
+                }
+            }
+            // NOTE: the raw LoraPocV1 bytes hold the precise locations
+            if settings.arangodb.raw_pocs != RawPocStorage::Off {
+                anyhow::bail!("raw_pocs can't be stored with location privacy configured");
+            }
+        }
 
-        let redis_handler = if let Some(rh) = &settings.redis {
-            Arc::new(Some(RedisHandler::from_settings(rh)?))
+        // NOTE: every sink applies the filter, privacy and size guard of [arangodb]
+        let transforms = PocTransforms::from_settings(&settings.arangodb)?
+            .with_beacon_payloads(settings.publishes_beacons())
+            .with_resolutions(CellResolutions::new(
+                settings.parent_resolution,
+                &settings.resolutions,
+            )?);
+        let (sink, db): (Arc<dyn Sink>, _) = match settings.sink {
+            SinkKind::Arangodb => {
+                let db = Arc::new(
+                    DB::from_settings(&settings.arangodb)
+                        .await?
+                        .with_transforms(transforms.clone()),
+                );
+                if check_versions(&db, settings.arangodb.allow_downgrade).await?
+                    && !settings.dry_run
+                {
+                    if let Err(err) = db
+                        .set_collection_meta(&CollectionMeta::all(&settings.arangodb))
+                        .await
+                    {
+                        tracing::warn!("error writing collection metadata: {:?}", err);
+                    }
+                }
+                (db.clone(), Some(db))
+            }
+            #[cfg(feature = "postgres")]
+            SinkKind::Postgres => {
+                let postgres = settings
+                    .postgres
+                    .as_ref()
+                    .context("sink = \"postgres\" requires [postgres] settings")?;
+                (
+                    Arc::new(PostgresSink::from_settings(postgres, transforms.clone()).await?),
+                    None,
+                )
+            }
+            #[cfg(not(feature = "postgres"))]
+            SinkKind::Postgres => return Err(super::feature_disabled("postgres")),
+            #[cfg(feature = "clickhouse")]
+            SinkKind::Clickhouse => {
+                let clickhouse = settings
+                    .clickhouse
+                    .as_ref()
+                    .context("sink = \"clickhouse\" requires [clickhouse] settings")?;
+                (
+                    Arc::new(
+                        ClickhouseSink::from_settings(clickhouse, true, transforms.clone()).await?,
+                    ),
+                    None,
+                )
+            }
+            #[cfg(not(feature = "clickhouse"))]
+            SinkKind::Clickhouse => return Err(super::feature_disabled("clickhouse")),
+            SinkKind::StdoutJsonl => (Arc::new(StdoutSink::new(transforms.clone())), None),
+        };
+        // NOTE: a dry run only stages chunks for the primary sink
+        let mirror = settings.clickhouse.as_ref().filter(|clickhouse| {
+            clickhouse.mirror && settings.sink != SinkKind::Clickhouse && !settings.dry_run
+        });
+        let sink = match mirror {
+            #[cfg(feature = "clickhouse")]
+            Some(clickhouse) => {
+                let mirror = ClickhouseSink::from_settings(clickhouse, false, transforms).await?;
+                Arc::new(Mirrored::new(sink, Arc::new(mirror)))
+            }
+            #[cfg(not(feature = "clickhouse"))]
+            Some(_) => return Err(super::feature_disabled("clickhouse")),
+            None => sink,
+        };
+        let sink: Arc<dyn Sink> = if settings.dry_run {
+            Arc::new(DryRunSink::new(sink))
         } else {
-            Arc::new(None)
+            sink
         };
+        Self::with_sink(settings, store, sink, db).await
+    }
 
-        let db = Arc::new(DB::from_settings(&settings.arangodb).await?);
+    /// Create a handler writing to `sink` instead of the configured one, `db`
+    /// being the ArangoDB sink if it is one
+    pub async fn with_sink(
+        settings: &Settings,
+        store: Box<dyn FileSource>,
+        sink: Arc<dyn Sink>,
+        db: Option<Arc<DB>>,
+    ) -> Result<Self> {
+        // NOTE: a dry run publishes nothing
+        let notify = !settings.dry_run;
+        let redis_handler = settings
+            .redis
+            .as_ref()
+            .filter(|_| notify)
+            .map(RedisHandler::from_settings)
+            .transpose()?
+            .map(Arc::new);
+        let mut notifiers: Vec<Arc<dyn Notifier>> = vec![];
+        if let Some(rh) = &redis_handler {
+            notifiers.push(rh.clone());
+        }
+        match settings.kafka_notifier.as_ref().filter(|_| notify) {
+            #[cfg(feature = "kafka")]
+            Some(kafka) => notifiers.push(Arc::new(KafkaHandler::from_settings(kafka)?)),
+            #[cfg(not(feature = "kafka"))]
+            Some(_) => return Err(super::feature_disabled("kafka")),
+            None => {}
+        }
+        match settings.nats_notifier.as_ref().filter(|_| notify) {
+            #[cfg(feature = "nats")]
+            Some(nats) => notifiers.push(Arc::new(NatsHandler::from_settings(nats).await?)),
+            #[cfg(not(feature = "nats"))]
+            Some(_) => return Err(super::feature_disabled("nats")),
+            None => {}
+        }
+        if let Some(webhook) = settings.webhook.as_ref().filter(|_| notify) {
+            notifiers.push(Arc::new(WebhookHandler::from_settings(webhook)?));
+        }
+        let live_feed = settings
+            .live_feed
+            .as_ref()
+            .filter(|_| notify)
+            .map(|live_feed| Arc::new(LiveFeed::from_settings(live_feed)));
+        if let Some(live_feed) = &live_feed {
+            notifiers.push(live_feed.clone());
+        }
+        let file_types = ingest_file_types(settings);
+        if let Some(ft) = file_types.iter().find(|ft| !sink.supports(**ft)) {
+            anyhow::bail!("the {:?} sink does not support {ft} files", settings.sink);
+        }
+        let prefetch_budget = (settings.prefetch_files > 0).then(|| {
+            Arc::new(Semaphore::new(
+                (settings.prefetch_memory_budget / 1024).max(1),
+            ))
+        });
         Ok(Self {
+            sink,
             db,
             store,
             redis_handler,
+            notifiers,
+            live_feed,
             file_chunk_size: settings.file_chunk_size,
             max_concurrent_files: settings.max_concurrent_files,
             max_processing_capacity: settings.max_processing_capacity,
             max_retries: settings.max_retries,
+            file_heartbeat: Duration::from_secs(settings.file_heartbeat_secs.max(1)),
+            stale_file: chrono::Duration::seconds(settings.stale_file_secs as i64),
+            prefetch_files: settings.prefetch_files,
+            prefetch_memory_budget: settings.prefetch_memory_budget,
+            prefetch_budget,
+            file_types,
+            shutdown: CancellationToken::new(),
+            paused: Arc::new(AtomicBool::new(false)),
+            counts: Default::default(),
+            files_listed: AtomicUsize::new(0),
+            files_failed: AtomicUsize::new(0),
         })
     }
 
+    /// The ArangoDB sink, None if ingesting into another sink
+    pub fn db(&self) -> Option<Arc<DB>> {
+        self.db.clone()
+    }
+
+    /// The WebSocket feed of stored beacons, if configured
+    pub fn live_feed(&self) -> Option<Arc<LiveFeed>> {
+        self.live_feed.clone()
+    }
+
+    pub fn file_types(&self) -> &[FileType] {
+        &self.file_types
+    }
+
+    /// Token draining the handler when cancelled: files not started yet are skipped
+    /// (and left for the next run), in-flight files are processed to completion
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// Maintenance mode switch: while set no new files are started, in-flight
+    /// files are processed to completion
+    pub fn pause_switch(&self) -> Arc<AtomicBool> {
+        self.paused.clone()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Whether files not started yet are skipped, on shutdown or in maintenance mode
+    fn is_draining(&self) -> bool {
+        self.shutdown.is_cancelled() || self.is_paused()
+    }
+
     /// Processes a set of files within a specified timestamp range.
     ///
     /// This function performs the following steps:
-    /// 1. Lists all `IotPoc` files that have a timestamp between `after_ts` and `before_ts`.
+    /// 1. Lists all `IotPoc` (and, if enabled, reward share and invalid report) files that
+    ///    have a timestamp between `after_ts` and `before_ts`.
     /// 2. Excludes any files that have already been processed.
     /// 3. Processes the remaining files concurrently.
     ///
-    /// If all files are processed successfully, the function returns the timestamp of the latest file processed.
-    /// If there are files that failed during processing, it returns the timestamp of the earliest failed file,
+    /// If all files are processed successfully, the watermark is the timestamp of the latest file processed.
+    /// If there are files that failed during processing, it is the timestamp of the earliest failed file,
     /// enabling the next run to start processing from that file.
-    /// If an error occurs while processing the files, it returns the timestamp from which it started processing,
+    /// If an error occurs while processing the files, it is the timestamp from which it started processing,
     /// effectively enabling the next run to retry processing the same set of files.
     ///
     /// # Arguments
@@ -62,53 +338,144 @@ impl ArangodbHandler {
     ///
     /// # Returns
     ///
-    /// A Result containing the timestamp from which the next set of files should start processing.
+    /// A Result containing a summary of the run, whose `watermark` is the timestamp
+    /// from which the next set of files should start processing.
     pub async fn process(
         &self,
         after_ts: DateTime<Utc>,
         before_ts: Option<DateTime<Utc>>,
-    ) -> Result<DateTime<Utc>> {
+    ) -> Result<ProcessSummary> {
         tracing::debug!("before_ts: {:?}", before_ts);
         tracing::debug!("after_ts: {:?}", after_ts);
 
-        let ft = FileType::IotPoc;
-        let mut file_infos = self.store.list_all(ft, after_ts, before_ts).await?;
+        let summary = ProcessSummary::new(after_ts, before_ts);
+
+        let file_infos = self.list_files(after_ts, before_ts).await?;
 
         // return early if no files to process
         if file_infos.is_empty() {
-            tracing::info!("no available ingest files of type {ft}");
-            return Ok(after_ts);
+            tracing::info!("no available ingest files of types {:?}", self.file_types);
+            return Ok(summary.finish(after_ts));
+        }
+
+        self.process_listed(summary, after_ts, file_infos).await
+    }
+
+    /// Watermark stored under `name` in the checkpoints collection, None if never set
+    pub async fn get_watermark(&self, name: &str) -> Result<Option<DateTime<Utc>>> {
+        Ok(self
+            .sink
+            .get_checkpoint(name)
+            .await?
+            .map(|checkpoint| checkpoint.watermark))
+    }
+
+    /// Timestamp of the newest ingest file after `after`, None if there is none
+    pub async fn newest_file(&self, after: DateTime<Utc>) -> Result<Option<DateTime<Utc>>> {
+        let mut newest = None;
+        for file_type in &self.file_types {
+            let files = self.store.list_all(*file_type, after, None).await?;
+            newest = newest.max(files.iter().map(|fi| fi.timestamp).max());
+        }
+        Ok(newest)
+    }
+
+    /// Reset files left processing by a crashed or hung worker (heartbeat older
+    /// than `stale_file_secs`) back to pending, returning how many were reset
+    pub async fn recover_stale_files(&self) -> Result<usize> {
+        let stale = self
+            .sink
+            .reset_stale_files(Utc::now() - self.stale_file)
+            .await?;
+        for key in &stale {
+            tracing::warn!("reset stale processing file {key} to pending");
         }
+        Ok(stale.len())
+    }
+
+    /// Store `watermark` under `name` in the checkpoints collection
+    pub async fn set_watermark(&self, name: &str, watermark: DateTime<Utc>) -> Result<()> {
+        self.sink
+            .set_checkpoint(&Checkpoint::new(name, watermark))
+            .await?;
+        Ok(())
+    }
 
+    /// Lists the files of all ingested file types in a timestamp range, oldest first
+    pub async fn list_files(
+        &self,
+        after_ts: DateTime<Utc>,
+        before_ts: Option<DateTime<Utc>>,
+    ) -> Result<Vec<FileInfo>> {
+        let mut file_infos = vec![];
+        for ft in self.file_types.iter() {
+            file_infos.extend(self.store.list_all(*ft, after_ts, before_ts).await?);
+        }
+        file_infos.sort_by_key(|fi| fi.timestamp);
+        Ok(file_infos)
+    }
+
+    /// Processes files announced by new-object notifications rather than listed,
+    /// with the same done file exclusion and watermark semantics as `process`.
+    /// The run starts at the earliest notified file.
+    pub async fn process_notified(&self, file_infos: Vec<FileInfo>) -> Result<ProcessSummary> {
+        let Some(after_ts) = min_ts(&file_infos) else {
+            return Ok(ProcessSummary::new(Utc::now(), None).finish(Utc::now()));
+        };
+        let summary = ProcessSummary::new(after_ts, max_ts(&file_infos));
+        self.process_listed(summary, after_ts, file_infos).await
+    }
+
+    async fn process_listed(
+        &self,
+        mut summary: ProcessSummary,
+        after_ts: DateTime<Utc>,
+        mut file_infos: Vec<FileInfo>,
+    ) -> Result<ProcessSummary> {
         self.exclude_done_files(&mut file_infos).await?;
 
         // return early if all files are already processed
         if file_infos.is_empty() {
-            tracing::info!("all {ft} files processed!");
-            return Ok(after_ts);
+            tracing::info!("all {:?} files processed!", self.file_types);
+            return Ok(summary.finish(after_ts));
         }
 
-        // Set max_ts to the file with the highest timestamp
-        let max_ts = self.get_max_ts(&file_infos).unwrap_or(after_ts);
+        summary.files = file_infos.len();
+        self.files_listed
+            .fetch_add(file_infos.len(), Ordering::Relaxed);
 
-        match self.process_files(file_infos).await {
-            Ok(None) => Ok(max_ts),
-            Ok(Some(failed_files)) => {
-                let failed_files = self
-                    .filter_retry_exceeded_failed_files(failed_files)
+        let pocs = Arc::new(AtomicUsize::new(0));
+        let watermark = match self.process_files(file_infos.clone(), pocs.clone()).await {
+            Ok(None) => next_watermark(after_ts, &file_infos, Outcome::Done),
+            Ok(Some(unfinished)) => {
+                summary.files_failed = unfinished.failed.len();
+                summary.files_skipped = unfinished.skipped.len();
+                let mut pending_files = self
+                    .filter_retry_exceeded_failed_files(unfinished.failed)
                     .await?;
-                // If there are failed files, return the minimum timestamp of those files
-                // Subsequent duplicate files which are already processed will be ignored
-                // Files which failed will be reprocessed
-                let min_ts = self.get_min_ts(&failed_files).unwrap_or(max_ts);
-                Ok(min_ts)
+                pending_files.extend(unfinished.skipped);
+                next_watermark(after_ts, &file_infos, Outcome::Failed(&pending_files))
             }
             Err(err) => {
-                // If there is an error, return the initial after_ts
                 tracing::error!("error processing files: {:?}", err);
-                Ok(after_ts)
+                summary.files_failed = summary.files;
+                next_watermark(after_ts, &file_infos, Outcome::Error)
             }
+        };
+        summary.pocs = pocs.load(Ordering::Relaxed);
+        Ok(summary.finish(watermark))
+    }
+
+    /// Publish a run summary to the configured notifiers
+    pub async fn publish_summary(&self, summary: &ProcessSummary) -> Result<()> {
+        if self.notifiers.is_empty() {
+            return Ok(());
         }
+        let payload = serde_json::to_string(summary)?;
+        for notifier in &self.notifiers {
+            notifier.publish_event(SUMMARY_STREAM, &payload).await?;
+        }
+        Ok(())
     }
 
     /// Filter failed files which have reached the max number of retries
@@ -119,16 +486,11 @@ impl ArangodbHandler {
     ) -> Result<Vec<FileInfo>> {
         let failed_files = stream::iter(failed_files)
             .filter_map(|fi| {
-                let db = self.db.clone();
+                let sink = self.sink.clone();
                 async move {
-                    match db.get_file_retries(&fi.key).await {
-                        Ok(retries) => {
-                            if retries <= self.max_retries {
-                                Some(fi)
-                            } else {
-                                None
-                            }
-                        }
+                    match sink.get_file_retries(&fi.key).await {
+                        Ok(retries) if is_retryable(retries, self.max_retries) => Some(fi),
+                        Ok(_) => None,
                         Err(err) => {
                             tracing::error!("error getting file retries: {:?}", err);
                             None
@@ -141,26 +503,12 @@ impl ArangodbHandler {
         Ok(failed_files)
     }
 
-    fn get_max_ts(&self, file_infos: &[FileInfo]) -> Option<DateTime<Utc>> {
-        file_infos
-            .iter()
-            .max_by_key(|fi| fi.timestamp)
-            .map(|fi| fi.timestamp)
-    }
-
-    fn get_min_ts(&self, file_infos: &[FileInfo]) -> Option<DateTime<Utc>> {
-        file_infos
-            .iter()
-            .min_by_key(|fi| fi.timestamp)
-            .map(|fi| fi.timestamp)
-    }
-
     /// Exclude already done files
     async fn exclude_done_files(&self, file_infos: &mut Vec<FileInfo>) -> Result<()> {
         let before_len = file_infos.len();
         tracing::info!("# all files: {:#?}", before_len);
 
-        match self.db.get_done_file_keys().await {
+        match self.sink.get_done_file_keys().await {
             Ok(done_file_keys) if !done_file_keys.is_empty() => {
                 tracing::info!("# done files: {:#?}", done_file_keys.len());
                 file_infos.retain(|fi| !done_file_keys.contains(&fi.key));
@@ -179,11 +527,16 @@ impl ArangodbHandler {
     ///
     /// This function concurrently processes each file in the `file_infos` list.
     /// A semaphore is used to limit the number of concurrently processed files.
+    /// If prefetching is enabled, up to `prefetch_files` files are downloaded ahead of
+    /// processing (within the prefetch memory budget), overlapping S3 latency with
+    /// decoding and writing the current ones.
     /// Each file is processed using the `process_file` method. If processing a file
     /// fails or marking it as complete fails, the file is added to a list of failed files.
+    /// Once the shutdown token is cancelled or maintenance mode is entered, files not
+    /// started yet are skipped.
     ///
     /// After all files have been processed, the function returns either None,
-    /// if all files have been processed successfully, or a list of the files that failed to process.
+    /// if all files have been processed successfully, or the files that failed or were skipped.
     ///
     /// # Arguments
     ///
@@ -192,54 +545,91 @@ impl ArangodbHandler {
     /// # Returns
     ///
     /// A Result containing either None if all files have been processed successfully,
-    /// or a list of files that failed to process.
-    async fn process_files(&self, file_infos: Vec<FileInfo>) -> Result<Option<Vec<FileInfo>>> {
+    /// or the files that failed to process or were skipped.
+    async fn process_files(
+        &self,
+        file_infos: Vec<FileInfo>,
+        pocs: Arc<AtomicUsize>,
+    ) -> Result<Option<Unfinished>> {
         if file_infos.is_empty() {
             return Ok(None);
         }
 
         let semaphore = Arc::new(Semaphore::new(self.max_processing_capacity));
         let failed_files: Arc<Mutex<Vec<FileInfo>>> = Arc::new(Mutex::new(vec![]));
+        let skipped_files: Arc<Mutex<Vec<FileInfo>>> = Arc::new(Mutex::new(vec![]));
 
         stream::iter(file_infos)
-            .for_each_concurrent(self.max_concurrent_files, |file_info| {
+            // NOTE: budget is reserved in file order, so a later prefetch can't hold
+            // the budget an earlier (not yet yielded) one is waiting for
+            .then(|file_info| async move {
+                if self.is_draining() {
+                    return (file_info, Ok(None));
+                }
+                let reserved = self.reserve_prefetch(&file_info).await;
+                (file_info, reserved)
+            })
+            .map(|(file_info, reserved)| async move {
+                let prefetched = match reserved {
+                    Ok(Some(permit)) => self.prefetch_file(&file_info, permit).await.map(Some),
+                    Ok(None) => Ok(None),
+                    Err(err) => Err(err),
+                };
+                (file_info, prefetched)
+            })
+            .buffered(self.prefetch_files.max(1))
+            .for_each_concurrent(self.max_concurrent_files, |(file_info, prefetched)| {
                 let semaphore = semaphore.clone();
                 let failed_files = failed_files.clone();
+                let skipped_files = skipped_files.clone();
+                let pocs = pocs.clone();
 
                 async move {
                     match semaphore.acquire().await {
+                        Ok(_permit) if self.is_draining() => {
+                            tracing::info!("draining, skipped file ts: {}", file_info.timestamp);
+                            skipped_files.lock().await.push(file_info);
+                        }
                         Ok(_permit) => {
-                            match self.process_file(file_info.clone()).await {
-                                Ok(()) => match self.db.complete_file(&file_info.key).await {
-                                    Ok(()) => {
-                                        tracing::info!("completed file ts: {}", file_info.timestamp)
-                                    }
-                                    Err(err) => {
-                                        tracing::warn!(
-                                            "error completing file ts: {}, {err:?}",
-                                            file_info.timestamp
-                                        );
-                                        failed_files.lock().await.push(file_info.clone());
-                                        if let Err(e) =
-                                            self.db.increment_file_retry(&file_info.key).await
-                                        {
-                                            tracing::error!(
-                                                "error incrementing file retry: {:?}",
-                                                e
+                            let processed = match prefetched {
+                                Ok(prefetched) => {
+                                    self.process_file(file_info.clone(), prefetched).await
+                                }
+                                Err(err) => Err(err),
+                            };
+                            match processed {
+                                Ok(stats) => {
+                                    match self.sink.complete_file(&file_info.key, &stats).await {
+                                        Ok(()) => {
+                                            let num_pocs = stats.beacons as usize;
+                                            pocs.fetch_add(num_pocs, Ordering::Relaxed);
+                                            self.update_counts(|c| {
+                                                c.add_file(&file_info, num_pocs)
+                                            });
+                                            tracing::info!(
+                                                "completed file ts: {}",
+                                                file_info.timestamp
                                             );
+                                            let event = FileEvent::completed(&file_info, num_pocs);
+                                            self.publish_file_event(FILE_COMPLETED_STREAM, &event)
+                                                .await;
+                                        }
+                                        Err(err) => {
+                                            tracing::warn!(
+                                                "error completing file ts: {}, {err:?}",
+                                                file_info.timestamp
+                                            );
+                                            failed_files.lock().await.push(file_info.clone());
+                                            self.fail_file(&file_info).await;
                                         }
                                     }
-                                },
+                                }
                                 Err(err) => {
                                     tracing::warn!(
                                         "error while processing file ts: {}, err: {err:?}",
                                         file_info.timestamp
                                     );
-                                    if let Err(e) =
-                                        self.db.increment_file_retry(&file_info.key).await
-                                    {
-                                        tracing::error!("error incrementing file retry: {:?}", e);
-                                    }
+                                    self.fail_file(&file_info).await;
                                     failed_files.lock().await.push(file_info.clone());
                                 }
                             };
@@ -252,72 +642,913 @@ impl ArangodbHandler {
             })
             .await;
 
-        let failed_files = failed_files.lock().await.clone();
-        if failed_files.is_empty() {
+        let unfinished = Unfinished {
+            failed: failed_files.lock().await.clone(),
+            skipped: skipped_files.lock().await.clone(),
+        };
+        if unfinished.failed.is_empty() && unfinished.skipped.is_empty() {
             Ok(None)
         } else {
-            tracing::warn!("# failed_files {:?}", failed_files.len());
-            Ok(Some(failed_files))
+            tracing::warn!(
+                "# failed_files {:?}, # skipped_files {:?}",
+                unfinished.failed.len(),
+                unfinished.skipped.len()
+            );
+            Ok(Some(unfinished))
         }
     }
 
+    /// Count a failed attempt of a file, publishing a file_exhausted event if
+    /// it was its last
+    async fn fail_file(&self, file_info: &FileInfo) {
+        self.files_failed.fetch_add(1, Ordering::Relaxed);
+        if let Err(e) = self.sink.increment_file_retry(&file_info.key).await {
+            tracing::error!("error incrementing file retry: {:?}", e);
+            return;
+        }
+        if !self.notifiers.iter().any(|n| n.publishes_file_events()) {
+            return;
+        }
+        match self.sink.get_file_retries(&file_info.key).await {
+            Ok(retries) if !is_retryable(retries, self.max_retries) => {
+                tracing::warn!("file ts: {} exhausted its retries", file_info.timestamp);
+                let event = FileEvent::exhausted(file_info, retries);
+                self.publish_file_event(FILE_EXHAUSTED_STREAM, &event).await;
+            }
+            Ok(_) => {}
+            Err(e) => tracing::error!("error getting file retries: {:?}", e),
+        }
+    }
+
+    /// Publish a file event to the notifiers publishing them, see
+    /// `Notifier::publishes_file_events`
+    async fn publish_file_event(&self, stream: &str, event: &FileEvent<'_>) {
+        let mut notifiers = self
+            .notifiers
+            .iter()
+            .filter(|n| n.publishes_file_events())
+            .peekable();
+        if notifiers.peek().is_none() {
+            return;
+        }
+        let payload = match serde_json::to_string(event) {
+            Ok(payload) => payload,
+            Err(e) => {
+                tracing::error!("failed to serialize {} event: {:?}", stream, e);
+                return;
+            }
+        };
+        for notifier in notifiers {
+            if let Err(e) = notifier.publish_event(stream, &payload).await {
+                tracing::error!("failed to publish {} event: {:?}", stream, e);
+            }
+        }
+    }
+
+    /// Reserves the prefetch memory budget share of a file (by its compressed size),
+    /// None if prefetching is disabled
+    async fn reserve_prefetch(&self, file_info: &FileInfo) -> Result<Option<OwnedSemaphorePermit>> {
+        let Some(budget) = &self.prefetch_budget else {
+            return Ok(None);
+        };
+        let permits = budget_permits(file_info.size, self.prefetch_memory_budget);
+        Ok(Some(budget.clone().acquire_many_owned(permits).await?))
+    }
+
+    /// Downloads a file into memory ahead of processing
+    async fn prefetch_file(
+        &self,
+        file_info: &FileInfo,
+        permit: OwnedSemaphorePermit,
+    ) -> Result<Prefetched> {
+        let started = Instant::now();
+        let msgs: Vec<_> = self
+            .store
+            .stream_file(file_info.clone())
+            .await?
+            .collect()
+            .await;
+        // NOTE: the budget only accounts for the compressed size, log the
+        // decoded one to size it
+        let decoded: usize = msgs
+            .iter()
+            .filter_map(|msg| msg.as_ref().ok())
+            .map(|msg| msg.len())
+            .sum();
+        tracing::debug!(
+            "prefetched file ts: {} ({} bytes, {} decoded, {} msgs) in {:?}",
+            file_info.timestamp,
+            file_info.size,
+            decoded,
+            msgs.len(),
+            started.elapsed()
+        );
+        Ok(Prefetched {
+            msgs: stream::iter(msgs).boxed(),
+            permit,
+        })
+    }
+
     /// Processes an individual file.
     ///
     /// This function performs the following steps:
-    /// 1. Initializes the file in the database.
-    /// 2. Streams the file from the file store.
-    /// 3. For each message in the file, it decodes the message and populates the database with the decoded data.
-    /// 4. If a Redis handler is available, it sends the encoded `poc_id` to Redis.
+    /// 1. Initializes the file in the database, refreshing its heartbeat while it is processed.
+    /// 2. Streams the file from the file store, unless it was prefetched.
+    /// 3. For each chunk of messages in the file, it decodes the messages, stages the decoded data
+    ///    and writes the staged beacons and witness edges (or rewards, invalid reports) in bulk.
+    /// 4. If a Redis handler is available, it sends the encoded `poc_id`s of the chunk (with the
+    ///    lineage ids of their messages) to Redis.
     ///
     /// # Arguments
     ///
     /// * `file_info` - A `FileInfo` object representing the file to be processed.
+    /// * `prefetched` - The file's messages if it was downloaded ahead of processing.
     ///
     /// # Returns
     ///
-    /// A Result indicating whether the file has been processed successfully.
-    async fn process_file(&self, file_info: FileInfo) -> Result<()> {
-        self.db.init_file(&file_info).await?;
-        self.store
-            .stream_file(file_info)
-            .await?
+    /// A Result containing the counts and duration of processing the file.
+    async fn process_file(
+        &self,
+        file_info: FileInfo,
+        prefetched: Option<Prefetched>,
+    ) -> Result<FileStats> {
+        let started = Instant::now();
+        let file_type = FileType::from_str(&file_info.prefix)?;
+        let source = Source::new(&file_info, proto_message(file_type));
+        self.sink.init_file(&file_info).await?;
+        let key = file_info.key.clone();
+        let mut stats = tokio::select! {
+            res = self.process_msgs(file_info, file_type, source, prefetched) => res?,
+            _ = self.heartbeat(&key) => unreachable!("heartbeat never ends"),
+        };
+        stats.duration_ms = started.elapsed().as_millis() as i64;
+        Ok(stats)
+    }
+
+    /// Refresh the heartbeat of the file keyed `key` until dropped
+    async fn heartbeat(&self, key: &str) {
+        let mut interval = time::interval(self.file_heartbeat);
+        // the first tick completes immediately, init_file just set it
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(err) = self.sink.heartbeat_file(key).await {
+                tracing::warn!("error refreshing heartbeat of {key}: {:?}", err);
+            }
+        }
+    }
+
+    async fn process_msgs(
+        &self,
+        file_info: FileInfo,
+        file_type: FileType,
+        source: Source,
+        prefetched: Option<Prefetched>,
+    ) -> Result<FileStats> {
+        let stats = std::sync::Mutex::new(FileStats::default());
+        let failed_chunks = AtomicUsize::new(0);
+        // NOTE: a prefetched file keeps its budget share until it is processed
+        let (msgs, _permit) = match prefetched {
+            Some(prefetched) => (prefetched.msgs, Some(prefetched.permit)),
+            None => (self.store.stream_file(file_info).await?, None),
+        };
+        msgs.enumerate()
             .chunks(self.file_chunk_size)
-            .for_each_concurrent(self.max_concurrent_files, |msgs| async move {
-                for msg in msgs {
-                    match msg {
-                        Err(err) => {
-                            tracing::warn!("skipping report of due to error {err:?}")
-                        }
-                        Ok(buf) => {
-                            let db = self.db.clone();
-                            let rh = self.redis_handler.clone();
-                            match LoraPocV1::decode(buf) {
-                                Ok(dec_msg) => match (db.populate_collections(dec_msg).await, &*rh)
-                                {
-                                    (Err(e), _) => {
-                                        tracing::error!("error populating collections: {:?}", e)
-                                    }
-                                    (Ok(Some(poc_id)), Some(rh)) => {
-                                        tracing::debug!("storing poc_id: {:?} in redis", poc_id);
-                                        if let Err(e) = rh.xadd("poc_id", &poc_id).await {
-                                            tracing::error!(
-                                                "failed to store poc_id {:?} in redis, error: {:?}",
-                                                poc_id,
-                                                e
-                                            );
-                                        }
-                                    }
-                                    _ => (),
-                                },
-                                Err(e) => {
-                                    tracing::error!("error decoding message: {:?}", e);
-                                }
+            .for_each_concurrent(self.max_concurrent_files, |msgs| {
+                let stats = &stats;
+                let failed_chunks = &failed_chunks;
+                // NOTE: messages failing to be read are skipped, shifting the
+                // indices of the rest of their chunk
+                let first_index = msgs.first().map_or(0, |(i, _)| *i as u64);
+                let source = source.at(first_index);
+                async move {
+                    let read = msgs.len();
+                    let bufs: Vec<_> = msgs
+                        .into_iter()
+                        .filter_map(|(_, msg)| match msg {
+                            Err(err) => {
+                                tracing::warn!("skipping report of due to error {err:?}");
+                                None
                             }
+                            Ok(buf) => Some(buf),
+                        })
+                        .collect();
+                    let read_errors = (read - bufs.len()) as u64;
+                    match self.write_chunk(file_type, bufs, &source).await {
+                        Ok(mut chunk) => {
+                            chunk.decode_errors += read_errors;
+                            match stats.lock() {
+                                Ok(mut stats) => stats.add(&chunk),
+                                Err(poisoned) => poisoned.into_inner().add(&chunk),
+                            }
+                        }
+                        Err(e) => {
+                            tracing::error!("error flushing chunk: {:?}", e);
+                            failed_chunks.fetch_add(1, Ordering::Relaxed);
                         }
                     }
                 }
             })
             .await;
-        Ok(())
+
+        let stats = match stats.into_inner() {
+            Ok(stats) => stats,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        match failed_chunks.into_inner() {
+            0 => Ok(stats),
+            n => anyhow::bail!("failed to flush {n} chunks"),
+        }
+    }
+
+    /// Write a chunk of messages of `file_type`, the first of which is described
+    /// by `source`, to the sink and publish the stored pocs to redis. Waits for
+    /// the poc_id stream consumers first if back-pressure is configured, see
+    /// `RedisHandler::wait_for_consumers`.
+    /// Returns the counts of the chunk, see `FileStats`.
+    pub async fn write_chunk(
+        &self,
+        file_type: FileType,
+        bufs: Vec<BytesMut>,
+        source: &Source,
+    ) -> Result<FileStats> {
+        if let Some(rh) = &self.redis_handler {
+            rh.wait_for_consumers(rh.poc_stream(), &self.shutdown, &self.paused)
+                .await?;
+        }
+        let msgs = bufs.len();
+        let written = self.sink.write_chunk(file_type, bufs, source).await?;
+        let stats = FileStats {
+            decoded: (msgs - written.decode_errors) as u64,
+            decode_errors: written.decode_errors as u64,
+            beacons: written.populated.len() as u64,
+            witnesses: written.populated.iter().map(|p| p.witnesses as u64).sum(),
+            duration_ms: 0,
+        };
+        self.update_counts(|c| c.add_populated(&written.populated));
+        self.publish_populated(written.populated).await;
+        Ok(stats)
+    }
+
+    /// Publish the poc_ids and first seen hotspots of stored pocs to the
+    /// configured notifiers
+    async fn publish_populated(&self, populated: Vec<Populated>) {
+        if self.notifiers.is_empty() {
+            return;
+        }
+        let mut first_seen = vec![];
+        for hotspot in populated.iter().flat_map(|p| p.new_hotspots.iter()) {
+            tracing::info!("hotspot first seen: {:?}", hotspot.name);
+            match serde_json::to_string(hotspot) {
+                Ok(payload) => first_seen.push(payload),
+                Err(e) => tracing::error!("failed to serialize hotspot event: {:?}", e),
+            }
+        }
+        for notifier in &self.notifiers {
+            if let Err(e) = notifier.publish_pocs(&populated).await {
+                tracing::error!("failed to publish poc_ids: {:?}", e);
+            }
+            for payload in &first_seen {
+                if let Err(e) = notifier.publish_event(FIRST_SEEN_STREAM, payload).await {
+                    tracing::error!("failed to publish hotspot first seen: {:?}", e);
+                }
+            }
+        }
+    }
+
+    /// Publish and trim counts of the poc_id stream, None without redis
+    pub fn stream_metrics(&self) -> Option<StreamMetrics> {
+        self.redis_handler
+            .as_deref()
+            .map(RedisHandler::stream_metrics)
+    }
+
+    /// Shadow verification counts, None unless enabled on the ArangoDB sink
+    pub fn shadow_metrics(&self) -> Option<ShadowMetrics> {
+        self.db.as_ref().and_then(|db| db.shadow_metrics())
+    }
+
+    /// Stored pocs per file type and beacons and witnesses per region since start
+    pub fn ingest_counts(&self) -> IngestCounts {
+        match self.counts.lock() {
+            Ok(counts) => counts.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// Files listed, done and failed and pocs stored since start
+    pub fn progress(&self) -> IngestProgress {
+        let counts = self.ingest_counts();
+        IngestProgress {
+            files: self.files_listed.load(Ordering::Relaxed),
+            files_done: counts.file_types.values().map(|c| c.files).sum(),
+            files_failed: self.files_failed.load(Ordering::Relaxed),
+            pocs: counts.file_types.values().map(|c| c.pocs).sum(),
+        }
+    }
+
+    fn update_counts(&self, update: impl FnOnce(&mut IngestCounts)) {
+        match self.counts.lock() {
+            Ok(mut counts) => update(&mut counts),
+            Err(poisoned) => update(&mut poisoned.into_inner()),
+        }
+    }
+}
+
+/// A file downloaded ahead of processing
+struct Prefetched {
+    msgs: BytesMutStream,
+    // share of the prefetch memory budget, released when dropped
+    permit: OwnedSemaphorePermit,
+}
+
+/// Prefetch memory budget share (in KiB permits) of a file, based on its compressed size
+/// as listed, the decoded size isn't known before it is downloaded.
+/// Capped at the whole budget so a single large file can still be prefetched.
+fn budget_permits(size: usize, budget: usize) -> u32 {
+    (size.min(budget) / 1024).clamp(1, u32::MAX as usize) as u32
+}
+
+/// Refuse to start if a newer schema version wrote to the database, unless
+/// `allow_downgrade`. Returns whether the collection metadata may be refreshed,
+/// which isn't the case when downgrading so the newer version stays recorded.
+async fn check_versions(db: &DB, allow_downgrade: bool) -> Result<bool> {
+    let stored = match db.get_collection_meta().await {
+        Ok(stored) => stored,
+        Err(err) => {
+            tracing::warn!("error reading collection metadata: {:?}", err);
+            return Ok(true);
+        }
+    };
+    match meta::check_versions(&stored) {
+        VersionCheck::Compatible => Ok(true),
+        VersionCheck::NewerEtl(version) => {
+            tracing::warn!(
+                "database was written by a newer etl version {} (this is {})",
+                version,
+                ETL_VERSION
+            );
+            Ok(true)
+        }
+        VersionCheck::NewerSchema(version) if allow_downgrade => {
+            tracing::warn!(
+                "database has schema version {}, newer than {}, writing older documents",
+                version,
+                SCHEMA_VERSION
+            );
+            Ok(false)
+        }
+        VersionCheck::NewerSchema(version) => anyhow::bail!(
+            "database has schema version {version}, newer than {SCHEMA_VERSION} of etl {ETL_VERSION}, \
+             refusing to downgrade documents (see arangodb.allow_downgrade)"
+        ),
+    }
+}
+
+/// File types to ingest given the settings
+fn ingest_file_types(settings: &Settings) -> Vec<FileType> {
+    let mut file_types = vec![FileType::IotPoc];
+    if settings.ingest_rewards {
+        file_types.push(FileType::IotRewardShare);
+    }
+    if settings.ingest_invalid {
+        file_types.push(FileType::IotInvalidBeaconReport);
+        file_types.push(FileType::IotInvalidWitnessReport);
+    }
+    file_types
+}
+
+/// Files of a `process_files` run which didn't complete
+struct Unfinished {
+    /// Failed to process or to be marked as done
+    failed: Vec<FileInfo>,
+    /// Not started because of a shutdown or maintenance mode
+    skipped: Vec<FileInfo>,
+}
+
+enum Outcome<'a> {
+    /// All files processed
+    Done,
+    /// Some files failed or were skipped, only those which may still be processed
+    Failed(&'a [FileInfo]),
+    /// Processing errored as a whole
+    Error,
+}
+
+/// Timestamp from which the next run should start.
+///
+/// If all files are processed successfully, this is the timestamp of the latest file.
+/// If there are failed files (which may still be retried), it is the timestamp of the
+/// earliest failed file, subsequent duplicate files which are already processed will
+/// be ignored. If processing errored it is the initial `after_ts`, effectively
+/// retrying the same set of files.
+fn next_watermark(
+    after_ts: DateTime<Utc>,
+    file_infos: &[FileInfo],
+    outcome: Outcome,
+) -> DateTime<Utc> {
+    let max_ts = max_ts(file_infos).unwrap_or(after_ts);
+    match outcome {
+        Outcome::Done => max_ts,
+        Outcome::Failed(failed_files) => min_ts(failed_files).unwrap_or(max_ts),
+        Outcome::Error => after_ts,
+    }
+}
+
+/// Name of the proto message the files of a type contain
+fn proto_message(file_type: FileType) -> &'static str {
+    match file_type {
+        FileType::IotRewardShare => "IotRewardShare",
+        FileType::IotInvalidBeaconReport => "LoraInvalidBeaconReportV1",
+        FileType::IotInvalidWitnessReport => "LoraInvalidWitnessReportV1",
+        _ => "LoraPocV1",
+    }
+}
+
+fn is_retryable(retries: u8, max_retries: u8) -> bool {
+    retries <= max_retries
+}
+
+fn max_ts(file_infos: &[FileInfo]) -> Option<DateTime<Utc>> {
+    file_infos
+        .iter()
+        .max_by_key(|fi| fi.timestamp)
+        .map(|fi| fi.timestamp)
+}
+
+fn min_ts(file_infos: &[FileInfo]) -> Option<DateTime<Utc>> {
+    file_infos
+        .iter()
+        .min_by_key(|fi| fi.timestamp)
+        .map(|fi| fi.timestamp)
+}
+
+/// Files and pocs completed of one file type
+#[derive(Debug, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct FileTypeCounts {
+    pub files: u64,
+    pub pocs: u64,
+}
+
+/// Progress of the files processed since start, see `ArangodbHandler::progress`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IngestProgress {
+    /// Files to process, i.e. not done yet when listed
+    pub files: usize,
+    pub files_done: u64,
+    /// Failed attempts, a file failing on each retry counts once per attempt
+    pub files_failed: usize,
+    pub pocs: u64,
+}
+
+/// Beacons and their witnesses stored in one region
+#[derive(Debug, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct RegionCounts {
+    pub beacons: u64,
+    pub witnesses: u64,
+}
+
+/// Ingest counts since start, per file type (prefix) and per region of the
+/// beacon frequency. Beacons on a frequency of no known band count as
+/// `unknown`, ones in overlapping bands count in each of them.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct IngestCounts {
+    pub file_types: BTreeMap<String, FileTypeCounts>,
+    pub regions: BTreeMap<String, RegionCounts>,
+}
+
+impl IngestCounts {
+    pub fn add_file(&mut self, file_info: &FileInfo, pocs: usize) {
+        let counts = self.file_types.entry(file_info.prefix.clone()).or_default();
+        counts.files += 1;
+        counts.pocs += pocs as u64;
+    }
+
+    pub fn add_populated(&mut self, populated: &[Populated]) {
+        for p in populated {
+            let regions = if p.regions.is_empty() {
+                &["unknown"][..]
+            } else {
+                &p.regions[..]
+            };
+            for region in regions {
+                let counts = self.regions.entry(region.to_string()).or_default();
+                counts.beacons += 1;
+                counts.witnesses += p.witnesses as u64;
+            }
+        }
+    }
+}
+
+/// Event published when a file is completed (with its pocs) or failed its
+/// last retry (with its retries)
+#[derive(Debug, Serialize)]
+pub struct FileEvent<'a> {
+    pub key: &'a str,
+    pub prefix: &'a str,
+    pub timestamp: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pocs: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u8>,
+}
+
+impl<'a> FileEvent<'a> {
+    fn completed(file_info: &'a FileInfo, pocs: usize) -> Self {
+        Self {
+            key: &file_info.key,
+            prefix: &file_info.prefix,
+            timestamp: file_info.timestamp,
+            pocs: Some(pocs),
+            retries: None,
+        }
+    }
+
+    fn exhausted(file_info: &'a FileInfo, retries: u8) -> Self {
+        Self {
+            key: &file_info.key,
+            prefix: &file_info.prefix,
+            timestamp: file_info.timestamp,
+            pocs: None,
+            retries: Some(retries),
+        }
+    }
+}
+
+/// Summary of a single `process` run
+#[derive(Debug, Serialize, Clone)]
+pub struct ProcessSummary {
+    pub after_ts: DateTime<Utc>,
+    pub before_ts: Option<DateTime<Utc>>,
+    /// Number of not yet done files considered
+    pub files: usize,
+    pub files_done: usize,
+    pub files_failed: usize,
+    /// Number of files not started because of a shutdown or maintenance mode
+    pub files_skipped: usize,
+    /// Number of pocs stored
+    pub pocs: usize,
+    pub duration_ms: i64,
+    /// Timestamp to start the next run from
+    pub watermark: DateTime<Utc>,
+    #[serde(skip)]
+    started_at: DateTime<Utc>,
+}
+
+impl ProcessSummary {
+    fn new(after_ts: DateTime<Utc>, before_ts: Option<DateTime<Utc>>) -> Self {
+        Self {
+            after_ts,
+            before_ts,
+            files: 0,
+            files_done: 0,
+            files_failed: 0,
+            files_skipped: 0,
+            pocs: 0,
+            duration_ms: 0,
+            watermark: after_ts,
+            started_at: Utc::now(),
+        }
+    }
+
+    fn finish(mut self, watermark: DateTime<Utc>) -> Self {
+        self.files_done = self
+            .files
+            .saturating_sub(self.files_failed + self.files_skipped);
+        self.duration_ms = (Utc::now() - self.started_at).num_milliseconds();
+        self.watermark = watermark;
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        doctor,
+        handler::{
+            file_source::MemoryFileSource,
+            sink::{decode_beacons_counted, Written},
+        },
+    };
+    use chrono::TimeZone;
+    use std::collections::{HashMap, HashSet};
+
+    fn file_info(ts_millis: i64) -> FileInfo {
+        FileInfo::from_str(&format!("{}.{}.gz", FileType::IotPoc, ts_millis)).unwrap()
+    }
+
+    fn ts(ts_millis: i64) -> DateTime<Utc> {
+        Utc.timestamp_millis_opt(ts_millis).unwrap()
+    }
+
+    /// Sink keeping its bookkeeping in memory, failing every chunk of the
+    /// `failing` files
+    #[derive(Default)]
+    struct MemorySink {
+        failing: HashSet<String>,
+        // done and failed attempts by file key
+        files: std::sync::Mutex<HashMap<String, (bool, u8)>>,
+        checkpoints: std::sync::Mutex<HashMap<String, Checkpoint>>,
+        transforms: PocTransforms,
+    }
+
+    impl MemorySink {
+        fn failing(files: &[FileInfo]) -> Self {
+            Self {
+                failing: files.iter().map(|fi| fi.key.clone()).collect(),
+                ..Default::default()
+            }
+        }
+
+        /// Done and failed attempts of `file`
+        fn file(&self, file: &FileInfo) -> Option<(bool, u8)> {
+            self.files.lock().unwrap().get(&file.key).copied()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Sink for MemorySink {
+        async fn init_file(&self, file: &FileInfo) -> Result<()> {
+            self.files
+                .lock()
+                .unwrap()
+                .entry(file.key.clone())
+                .or_default();
+            Ok(())
+        }
+
+        async fn complete_file(&self, key: &str, _stats: &FileStats) -> Result<()> {
+            self.files
+                .lock()
+                .unwrap()
+                .entry(key.to_string())
+                .or_default()
+                .0 = true;
+            Ok(())
+        }
+
+        async fn get_file_retries(&self, key: &str) -> Result<u8> {
+            let files = self.files.lock().unwrap();
+            Ok(files.get(key).map_or(0, |(_, retries)| *retries))
+        }
+
+        async fn increment_file_retry(&self, key: &str) -> Result<()> {
+            self.files
+                .lock()
+                .unwrap()
+                .entry(key.to_string())
+                .or_default()
+                .1 += 1;
+            Ok(())
+        }
+
+        async fn get_done_file_keys(&self) -> Result<Vec<String>> {
+            let files = self.files.lock().unwrap();
+            Ok(files
+                .iter()
+                .filter(|(_, (done, _))| *done)
+                .map(|(key, _)| key.clone())
+                .collect())
+        }
+
+        async fn get_checkpoint(&self, name: &str) -> Result<Option<Checkpoint>> {
+            Ok(self.checkpoints.lock().unwrap().get(name).cloned())
+        }
+
+        async fn set_checkpoint(&self, checkpoint: &Checkpoint) -> Result<()> {
+            self.checkpoints
+                .lock()
+                .unwrap()
+                .insert(checkpoint._key.clone(), checkpoint.clone());
+            Ok(())
+        }
+
+        fn transforms(&self) -> &PocTransforms {
+            &self.transforms
+        }
+
+        async fn write_chunk(
+            &self,
+            _file_type: FileType,
+            bufs: Vec<BytesMut>,
+            source: &Source,
+        ) -> Result<Written> {
+            if self.failing.contains(&source.file_key) {
+                anyhow::bail!("failing chunk of {}", source.file_key);
+            }
+            let (beacons, decode_errors) = decode_beacons_counted(bufs, source, &self.transforms);
+            Ok(Written {
+                populated: beacons
+                    .iter()
+                    .map(|beacon| Populated::new(beacon, vec![], self.transforms.beacon_payloads))
+                    .collect(),
+                decode_errors,
+            })
+        }
+    }
+
+    fn settings() -> Settings {
+        serde_json::from_value(serde_json::json!({
+            "ingest": { "bucket": "test", "region": "us-west-2" },
+            "arangodb": {},
+            "tracker": {},
+            "current": {},
+            "file_chunk_size": 2,
+            "max_retries": 1,
+        }))
+        .unwrap()
+    }
+
+    /// `n` sample pocs
+    fn pocs(n: usize) -> Vec<BytesMut> {
+        let raw = doctor::sample_poc().unwrap();
+        vec![BytesMut::from(raw.as_slice()); n]
+    }
+
+    async fn handler(source: MemoryFileSource, sink: Arc<MemorySink>) -> ArangodbHandler {
+        ArangodbHandler::with_sink(&settings(), Box::new(source), sink, None)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn process_marks_files_done() {
+        let source = MemoryFileSource::new()
+            .with_file(file_info(2_000), pocs(3))
+            .with_file(file_info(3_000), pocs(2));
+        let sink = Arc::new(MemorySink::default());
+        let handler = handler(source, sink.clone()).await;
+
+        let summary = handler.process(ts(1_000), None).await.unwrap();
+        assert_eq!(summary.files, 2);
+        assert_eq!(summary.files_done, 2);
+        assert_eq!(summary.files_failed, 0);
+        assert_eq!(summary.pocs, 5);
+        assert_eq!(summary.watermark, ts(3_000));
+        assert_eq!(sink.file(&file_info(2_000)), Some((true, 0)));
+        assert_eq!(sink.file(&file_info(3_000)), Some((true, 0)));
+
+        // done files aren't processed again
+        let summary = handler.process(ts(1_000), None).await.unwrap();
+        assert_eq!(summary.files, 0);
+        assert_eq!(summary.pocs, 0);
+    }
+
+    #[tokio::test]
+    async fn process_retries_failed_files() {
+        let failing = file_info(3_000);
+        let source = MemoryFileSource::new()
+            .with_file(file_info(2_000), pocs(1))
+            .with_file(failing.clone(), pocs(3))
+            .with_file(file_info(4_000), pocs(1))
+            .with_file(file_info(5_000), pocs(1));
+        let sink = Arc::new(MemorySink::failing(&[failing.clone()]));
+        let handler = handler(source, sink.clone()).await;
+
+        // the next run starts at the failed file
+        let summary = handler.process(ts(1_000), Some(ts(4_000))).await.unwrap();
+        assert_eq!(summary.files, 3);
+        assert_eq!(summary.files_done, 2);
+        assert_eq!(summary.files_failed, 1);
+        assert_eq!(summary.pocs, 2);
+        assert_eq!(summary.watermark, ts(3_000));
+        assert_eq!(sink.file(&failing), Some((false, 1)));
+
+        // once out of retries it no longer holds the watermark back
+        let summary = handler.process(summary.watermark, None).await.unwrap();
+        assert_eq!(summary.files, 2);
+        assert_eq!(summary.files_done, 1);
+        assert_eq!(summary.files_failed, 1);
+        assert_eq!(summary.watermark, ts(5_000));
+        assert_eq!(sink.file(&failing), Some((false, 2)));
+    }
+
+    #[tokio::test]
+    async fn process_notified_files() {
+        let source = MemoryFileSource::new()
+            .with_file(file_info(2_000), pocs(1))
+            .with_file(file_info(3_000), pocs(1));
+        let sink = Arc::new(MemorySink::default());
+        let handler = handler(source, sink.clone()).await;
+
+        let summary = handler
+            .process_notified(vec![file_info(3_000)])
+            .await
+            .unwrap();
+        assert_eq!(summary.files_done, 1);
+        assert_eq!(summary.watermark, ts(3_000));
+        assert_eq!(sink.file(&file_info(2_000)), None);
+        assert_eq!(sink.file(&file_info(3_000)), Some((true, 0)));
+    }
+
+    #[test]
+    fn watermark() {
+        let after_ts = ts(1_000);
+        let files = vec![file_info(2_000), file_info(4_000), file_info(3_000)];
+
+        assert_eq!(next_watermark(after_ts, &files, Outcome::Done), ts(4_000));
+        assert_eq!(next_watermark(after_ts, &[], Outcome::Done), after_ts);
+        assert_eq!(next_watermark(after_ts, &files, Outcome::Error), after_ts);
+
+        let failed = vec![file_info(4_000), file_info(3_000)];
+        assert_eq!(
+            next_watermark(after_ts, &files, Outcome::Failed(&failed)),
+            ts(3_000)
+        );
+        // all failed files exceeded their retries
+        assert_eq!(
+            next_watermark(after_ts, &files, Outcome::Failed(&[])),
+            ts(4_000)
+        );
+    }
+
+    #[test]
+    fn summary_excludes_skipped_from_done() {
+        let mut summary = ProcessSummary::new(ts(1_000), None);
+        summary.files = 5;
+        summary.files_failed = 1;
+        summary.files_skipped = 2;
+        let summary = summary.finish(ts(2_000));
+        assert_eq!(summary.files_done, 2);
+        assert_eq!(summary.watermark, ts(2_000));
+    }
+
+    #[test]
+    fn retryable() {
+        assert!(is_retryable(0, 3));
+        assert!(is_retryable(3, 3));
+        assert!(!is_retryable(4, 3));
+    }
+
+    #[test]
+    fn prefetch_budget_permits() {
+        let budget = 1024 * 1024;
+        assert_eq!(budget_permits(10 * 1024, budget), 10);
+        assert_eq!(budget_permits(100, budget), 1);
+        // never more than the whole budget
+        assert_eq!(budget_permits(10 * budget, budget), 1024);
+        assert_eq!(budget_permits(4096, 100), 1);
+    }
+
+    #[tokio::test]
+    async fn memory_file_source() {
+        let source = MemoryFileSource::new()
+            .with_file(
+                file_info(2_000),
+                vec![BytesMut::from("a"), BytesMut::from("b")],
+            )
+            .with_file(file_info(5_000), vec![]);
+
+        let files = source
+            .list_all(FileType::IotPoc, ts(1_000), Some(ts(3_000)))
+            .await
+            .unwrap();
+        assert_eq!(files.len(), 1);
+
+        let msgs: Vec<_> = source
+            .stream_file(files[0].clone())
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(msgs.len(), 2);
+
+        assert!(source.stream_file(file_info(9_000)).await.is_err());
+    }
+
+    #[test]
+    fn ingest_counts() {
+        let populated = |regions: Vec<&'static str>, witnesses| Populated {
+            poc_id: "poc".to_string(),
+            beaconer: "beaconer".to_string(),
+            lineage_id: None,
+            new_hotspots: vec![],
+            beacon: None,
+            regions,
+            witnesses,
+        };
+        let mut counts = IngestCounts::default();
+        counts.add_file(&file_info(1_000), 2);
+        counts.add_file(&file_info(2_000), 3);
+        counts.add_populated(&[
+            populated(vec!["US915"], 4),
+            populated(vec!["AU915", "AS923"], 1),
+            populated(vec![], 2),
+        ]);
+        assert_eq!(
+            counts.file_types["iot_poc"],
+            FileTypeCounts { files: 2, pocs: 5 }
+        );
+        assert_eq!(
+            counts.regions["US915"],
+            RegionCounts {
+                beacons: 1,
+                witnesses: 4
+            }
+        );
+        assert_eq!(counts.regions["AS923"].beacons, 1);
+        assert_eq!(counts.regions["unknown"].witnesses, 2);
+        assert_eq!(counts.regions.len(), 4);
     }
 }
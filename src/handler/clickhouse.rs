@@ -0,0 +1,507 @@
+use crate::{
+    columnar,
+    document::{
+        checkpoint::Checkpoint,
+        iot_poc_file::FileStats,
+        source::Source,
+        timestamp::{now_unix_ms, unix_ms},
+        Beacon,
+    },
+    handler::{
+        arangodb::Populated,
+        sink::{decode_beacons_counted, PocTransforms, Sink, Written},
+    },
+    settings::{ClickhouseFormat, ClickhouseSettings},
+};
+use anyhow::{bail, Result};
+use arrow::datatypes::{DataType, FieldRef};
+use bytes::BytesMut;
+use chrono::{TimeZone, Utc};
+use file_store::{FileInfo, FileType};
+use reqwest::Client;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Flattened witness rows, one per witness of a stored poc. Rows of a poc
+/// written again (e.g. on a retried file) are deduplicated on merge.
+const WITNESS_ROWS_SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS {table} (
+    poc_id String,
+    ingest_time_unix_ms Int64,
+    beacon_pub_key String,
+    beacon_name String,
+    beacon_str_location Nullable(String),
+    beacon_latitude Nullable(Float64),
+    beacon_longitude Nullable(Float64),
+    beacon_gain Int32,
+    beacon_elevation Int32,
+    frequency UInt64,
+    channel Int32,
+    tx_power Int32,
+    witness_count UInt32,
+    selected_witness_count UInt32,
+    witness_pub_key String,
+    witness_name String,
+    witness_ingest_time_unix_ms Int64,
+    witness_str_location Nullable(String),
+    witness_latitude Nullable(Float64),
+    witness_longitude Nullable(Float64),
+    witness_gain Int32,
+    witness_elevation Int32,
+    signal Int32,
+    snr Int32,
+    witness_frequency UInt64,
+    selected Bool,
+    distance Float64,
+    file_key String,
+    etl_version String
+) ENGINE = ReplacingMergeTree
+PARTITION BY toYYYYMM(fromUnixTimestamp64Milli(ingest_time_unix_ms))
+ORDER BY (beacon_pub_key, poc_id, witness_pub_key)
+"#;
+
+/// Bookkeeping tables, only used if ClickHouse is the sink. Rows are updated by
+/// inserting a newer version and read with FINAL.
+const BOOKKEEPING_SCHEMA: &[&str] = &[
+    r#"CREATE TABLE IF NOT EXISTS files (
+    key String,
+    timestamp_unix_ms Int64,
+    size UInt64,
+    done Bool,
+    retries UInt8,
+    version Int64
+) ENGINE = ReplacingMergeTree(version) ORDER BY key"#,
+    r#"CREATE TABLE IF NOT EXISTS checkpoints (
+    name String,
+    watermark_unix_ms Int64,
+    updated_unix_ms Int64
+) ENGINE = ReplacingMergeTree(updated_unix_ms) ORDER BY name"#,
+];
+
+/// Row of the witness rows table
+#[derive(Debug, Serialize)]
+struct WitnessRow<'a> {
+    poc_id: &'a str,
+    ingest_time_unix_ms: i64,
+    beacon_pub_key: String,
+    beacon_name: &'a str,
+    beacon_str_location: &'a Option<String>,
+    beacon_latitude: Option<f64>,
+    beacon_longitude: Option<f64>,
+    beacon_gain: i32,
+    beacon_elevation: i32,
+    frequency: u64,
+    channel: i32,
+    tx_power: i32,
+    witness_count: u32,
+    selected_witness_count: u32,
+    witness_pub_key: String,
+    witness_name: &'a str,
+    witness_ingest_time_unix_ms: i64,
+    witness_str_location: &'a Option<String>,
+    witness_latitude: Option<f64>,
+    witness_longitude: Option<f64>,
+    witness_gain: i32,
+    witness_elevation: i32,
+    signal: i32,
+    snr: i32,
+    witness_frequency: u64,
+    selected: bool,
+    distance: f64,
+    file_key: &'a str,
+    etl_version: &'a str,
+}
+
+/// Arrow columns of `WitnessRow`, matching the witness rows table
+fn witness_row_fields() -> Vec<FieldRef> {
+    use DataType::*;
+    columnar::fields(&[
+        ("poc_id", Utf8, false),
+        ("ingest_time_unix_ms", Int64, false),
+        ("beacon_pub_key", Utf8, false),
+        ("beacon_name", Utf8, false),
+        ("beacon_str_location", Utf8, true),
+        ("beacon_latitude", Float64, true),
+        ("beacon_longitude", Float64, true),
+        ("beacon_gain", Int32, false),
+        ("beacon_elevation", Int32, false),
+        ("frequency", UInt64, false),
+        ("channel", Int32, false),
+        ("tx_power", Int32, false),
+        ("witness_count", UInt32, false),
+        ("selected_witness_count", UInt32, false),
+        ("witness_pub_key", Utf8, false),
+        ("witness_name", Utf8, false),
+        ("witness_ingest_time_unix_ms", Int64, false),
+        ("witness_str_location", Utf8, true),
+        ("witness_latitude", Float64, true),
+        ("witness_longitude", Float64, true),
+        ("witness_gain", Int32, false),
+        ("witness_elevation", Int32, false),
+        ("signal", Int32, false),
+        ("snr", Int32, false),
+        ("witness_frequency", UInt64, false),
+        ("selected", Boolean, false),
+        ("distance", Float64, false),
+        ("file_key", Utf8, false),
+        ("etl_version", Utf8, false),
+    ])
+}
+
+fn witness_rows(beacon: &Beacon) -> Vec<WitnessRow<'_>> {
+    let (file_key, etl_version) = match &beacon.source {
+        Some(source) => (source.file_key.as_str(), source.etl_version.as_str()),
+        None => ("", ""),
+    };
+    beacon
+        .witnesses
+        .iter()
+        .map(|witness| WitnessRow {
+            poc_id: &beacon.poc_id,
+            ingest_time_unix_ms: beacon.ingest_time_unix_ms,
+            beacon_pub_key: beacon.pub_key.to_string(),
+            beacon_name: &beacon.name,
+            beacon_str_location: &beacon.str_location,
+            beacon_latitude: beacon.latitude,
+            beacon_longitude: beacon.longitude,
+            beacon_gain: beacon.gain,
+            beacon_elevation: beacon.elevation,
+            frequency: beacon.frequency,
+            channel: beacon.channel,
+            tx_power: beacon.tx_power,
+            witness_count: beacon.witness_count as u32,
+            selected_witness_count: beacon.selected_witness_count as u32,
+            witness_pub_key: witness.pub_key.to_string(),
+            witness_name: &witness.name,
+            witness_ingest_time_unix_ms: witness.ingest_time_unix_ms,
+            witness_str_location: &witness.str_location,
+            witness_latitude: witness.latitude,
+            witness_longitude: witness.longitude,
+            witness_gain: witness.gain,
+            witness_elevation: witness.elevation,
+            signal: witness.signal,
+            snr: witness.snr,
+            witness_frequency: witness.frequency,
+            selected: witness.selected,
+            distance: witness.distance,
+            file_key,
+            etl_version,
+        })
+        .collect()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FileRow {
+    key: String,
+    timestamp_unix_ms: i64,
+    size: u64,
+    done: bool,
+    retries: u8,
+    version: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CheckpointRow {
+    name: String,
+    watermark_unix_ms: i64,
+    updated_unix_ms: i64,
+}
+
+/// Sink writing flattened witness rows into ClickHouse over its HTTP interface,
+/// for columnar analytics. Only iot_poc files are supported.
+pub struct ClickhouseSink {
+    client: Client,
+    settings: ClickhouseSettings,
+    transforms: PocTransforms,
+}
+
+impl ClickhouseSink {
+    /// Connect and create the witness rows table, plus the bookkeeping tables
+    /// if ClickHouse is the sink rather than a mirror
+    pub async fn from_settings(
+        settings: &ClickhouseSettings,
+        bookkeeping: bool,
+        transforms: PocTransforms,
+    ) -> Result<Self> {
+        let sink = Self {
+            client: Client::new(),
+            settings: settings.clone(),
+            transforms,
+        };
+        sink.query(
+            &WITNESS_ROWS_SCHEMA.replace("{table}", &settings.table),
+            &[],
+            None,
+        )
+        .await?;
+        if bookkeeping {
+            for schema in BOOKKEEPING_SCHEMA {
+                sink.query(schema, &[], None).await?;
+            }
+        }
+        Ok(sink)
+    }
+
+    /// Run `query` with `params` bound to its `{name:Type}` placeholders and
+    /// `data` as the body of an INSERT, returning the response body
+    async fn query(
+        &self,
+        query: &str,
+        params: &[(&str, &str)],
+        data: Option<Vec<u8>>,
+    ) -> Result<String> {
+        let mut url_params = vec![
+            ("database", self.settings.database.as_str()),
+            ("output_format_json_quote_64bit_integers", "0"),
+        ];
+        let params: Vec<(String, &str)> = params
+            .iter()
+            .map(|(name, value)| (format!("param_{name}"), *value))
+            .collect();
+        url_params.extend(params.iter().map(|(name, value)| (name.as_str(), *value)));
+        let body = match data {
+            Some(data) => {
+                url_params.push(("query", query));
+                data
+            }
+            None => query.as_bytes().to_vec(),
+        };
+        let resp = self
+            .client
+            .post(&self.settings.url)
+            .query(&url_params)
+            .header("X-ClickHouse-User", &self.settings.user)
+            .header("X-ClickHouse-Key", &self.settings.password)
+            .body(body)
+            .send()
+            .await?;
+        let status = resp.status();
+        let text = resp.text().await?;
+        if !status.is_success() {
+            bail!("clickhouse query failed ({status}): {}", text.trim());
+        }
+        Ok(text)
+    }
+
+    async fn select<T: DeserializeOwned>(
+        &self,
+        query: &str,
+        params: &[(&str, &str)],
+    ) -> Result<Vec<T>> {
+        let text = self
+            .query(&format!("{query} FORMAT JSONEachRow"), params, None)
+            .await?;
+        parse_rows(&text)
+    }
+
+    async fn insert<T: Serialize>(&self, table: &str, rows: &[T]) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let mut data = vec![];
+        for row in rows {
+            serde_json::to_writer(&mut data, row)?;
+            data.push(b'\n');
+        }
+        self.query(
+            &format!("INSERT INTO {table} FORMAT JSONEachRow"),
+            &[],
+            Some(data),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Insert witness rows in the configured format, as an arrow stream built
+    /// directly from the rows by default
+    async fn insert_witness_rows(&self, rows: &[WitnessRow<'_>]) -> Result<()> {
+        let table = &self.settings.table;
+        match self.settings.insert_format {
+            ClickhouseFormat::JsonEachRow => self.insert(table, rows).await,
+            ClickhouseFormat::Arrow => {
+                if rows.is_empty() {
+                    return Ok(());
+                }
+                let data = columnar::arrow_stream(&witness_row_fields(), rows)?;
+                self.query(
+                    &format!("INSERT INTO {table} FORMAT ArrowStream"),
+                    &[],
+                    Some(data),
+                )
+                .await?;
+                Ok(())
+            }
+        }
+    }
+
+    async fn get_file(&self, key: &str) -> Result<Option<FileRow>> {
+        let rows = self
+            .select(
+                "SELECT * FROM files FINAL WHERE key = {key:String}",
+                &[("key", key)],
+            )
+            .await?;
+        Ok(rows.into_iter().next())
+    }
+
+    /// Store a new version of the file keyed `key`, changed by `update`
+    async fn update_file(&self, key: &str, update: impl FnOnce(&mut FileRow)) -> Result<()> {
+        let Some(mut file) = self.get_file(key).await? else {
+            bail!("unknown file {key}");
+        };
+        update(&mut file);
+        file.version = now_unix_ms();
+        self.insert("files", &[file]).await
+    }
+}
+
+/// Rows of a JSONEachRow response
+fn parse_rows<T: DeserializeOwned>(text: &str) -> Result<Vec<T>> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
+
+#[async_trait::async_trait]
+impl Sink for ClickhouseSink {
+    fn supports(&self, file_type: FileType) -> bool {
+        file_type == FileType::IotPoc
+    }
+
+    async fn init_file(&self, file: &FileInfo) -> Result<()> {
+        tracing::info!("init file: {:?}", file.key);
+        if self.get_file(&file.key).await?.is_some() {
+            return Ok(());
+        }
+        let row = FileRow {
+            key: file.key.clone(),
+            timestamp_unix_ms: unix_ms(file.timestamp),
+            size: file.size as u64,
+            done: false,
+            retries: 0,
+            version: now_unix_ms(),
+        };
+        self.insert("files", &[row]).await
+    }
+
+    async fn complete_file(&self, key: &str, _stats: &FileStats) -> Result<()> {
+        self.update_file(key, |file| file.done = true).await
+    }
+
+    async fn get_file_retries(&self, key: &str) -> Result<u8> {
+        Ok(self
+            .get_file(key)
+            .await?
+            .map(|file| file.retries)
+            .unwrap_or_default())
+    }
+
+    async fn increment_file_retry(&self, key: &str) -> Result<()> {
+        self.update_file(key, |file| file.retries = file.retries.saturating_add(1))
+            .await
+    }
+
+    async fn get_done_file_keys(&self) -> Result<Vec<String>> {
+        #[derive(Deserialize)]
+        struct Key {
+            key: String,
+        }
+        let keys: Vec<Key> = self
+            .select("SELECT key FROM files FINAL WHERE done", &[])
+            .await?;
+        Ok(keys.into_iter().map(|k| k.key).collect())
+    }
+
+    async fn get_checkpoint(&self, name: &str) -> Result<Option<Checkpoint>> {
+        let rows: Vec<CheckpointRow> = self
+            .select(
+                "SELECT * FROM checkpoints FINAL WHERE name = {name:String}",
+                &[("name", name)],
+            )
+            .await?;
+        let Some(row) = rows.into_iter().next() else {
+            return Ok(None);
+        };
+        let Some(watermark) = Utc.timestamp_millis_opt(row.watermark_unix_ms).single() else {
+            bail!(
+                "invalid watermark {} of checkpoint {name}",
+                row.watermark_unix_ms
+            );
+        };
+        Ok(Some(Checkpoint {
+            _key: row.name,
+            watermark,
+            watermark_unix_ms: row.watermark_unix_ms,
+            updated_unix_ms: row.updated_unix_ms,
+        }))
+    }
+
+    async fn set_checkpoint(&self, checkpoint: &Checkpoint) -> Result<()> {
+        let row = CheckpointRow {
+            name: checkpoint._key.clone(),
+            watermark_unix_ms: checkpoint.watermark_unix_ms,
+            updated_unix_ms: checkpoint.updated_unix_ms,
+        };
+        self.insert("checkpoints", &[row]).await
+    }
+
+    fn transforms(&self) -> &PocTransforms {
+        &self.transforms
+    }
+
+    async fn write_chunk(
+        &self,
+        file_type: FileType,
+        bufs: Vec<BytesMut>,
+        source: &Source,
+    ) -> Result<Written> {
+        if !self.supports(file_type) {
+            bail!("the clickhouse sink does not support {file_type} files");
+        }
+        let (beacons, decode_errors) = decode_beacons_counted(bufs, source, &self.transforms);
+        let rows: Vec<WitnessRow> = beacons.iter().flat_map(witness_rows).collect();
+        self.insert_witness_rows(&rows).await?;
+        // NOTE: first seen hotspots aren't tracked
+        Ok(Written {
+            populated: beacons
+                .iter()
+                .map(|beacon| Populated::new(beacon, vec![], self.transforms.beacon_payloads))
+                .collect(),
+            decode_errors,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn witness_row_columns() {
+        let columns: Vec<&str> = WITNESS_ROWS_SCHEMA
+            .lines()
+            .skip_while(|line| !line.starts_with("CREATE TABLE"))
+            .skip(1)
+            .take_while(|line| !line.starts_with(')'))
+            .filter_map(|line| line.split_whitespace().next())
+            .collect();
+        let fields: Vec<String> = witness_row_fields()
+            .iter()
+            .map(|field| field.name().clone())
+            .collect();
+        assert_eq!(columns, fields);
+    }
+
+    #[test]
+    fn parse_json_each_row() {
+        let rows: Vec<CheckpointRow> = parse_rows(
+            "{\"name\":\"current\",\"watermark_unix_ms\":1687888130980,\"updated_unix_ms\":1}\n\
+             {\"name\":\"backfill\",\"watermark_unix_ms\":2,\"updated_unix_ms\":3}\n",
+        )
+        .unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].name, "current");
+        assert_eq!(rows[0].watermark_unix_ms, 1687888130980);
+        assert!(parse_rows::<CheckpointRow>("").unwrap().is_empty());
+    }
+}
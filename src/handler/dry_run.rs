@@ -0,0 +1,78 @@
+use crate::{
+    document::{checkpoint::Checkpoint, iot_poc_file::FileStats, source::Source},
+    handler::sink::{PocTransforms, Sink, Written},
+};
+use anyhow::Result;
+use bytes::BytesMut;
+use chrono::{DateTime, Utc};
+use file_store::{FileInfo, FileType};
+use std::sync::Arc;
+
+/// Sink of a dry run, see `Settings::dry_run`: reads the bookkeeping of the
+/// wrapped sink, so the same files are processed as by a real run, and decodes
+/// and stages their chunks, but only logs what would be written.
+pub struct DryRunSink {
+    inner: Arc<dyn Sink>,
+}
+
+impl DryRunSink {
+    pub fn new(inner: Arc<dyn Sink>) -> Self {
+        Self { inner }
+    }
+}
+
+#[async_trait::async_trait]
+impl Sink for DryRunSink {
+    fn supports(&self, file_type: FileType) -> bool {
+        self.inner.supports(file_type)
+    }
+
+    async fn init_file(&self, file: &FileInfo) -> Result<()> {
+        tracing::debug!("dry run: would record {} as processing", file.key);
+        Ok(())
+    }
+
+    async fn complete_file(&self, key: &str, stats: &FileStats) -> Result<()> {
+        tracing::info!("dry run: would record {} as done: {:?}", key, stats);
+        Ok(())
+    }
+
+    async fn get_file_retries(&self, key: &str) -> Result<u8> {
+        self.inner.get_file_retries(key).await
+    }
+
+    async fn increment_file_retry(&self, key: &str) -> Result<()> {
+        tracing::info!("dry run: would record a failed attempt of {}", key);
+        Ok(())
+    }
+
+    async fn get_done_file_keys(&self) -> Result<Vec<String>> {
+        self.inner.get_done_file_keys().await
+    }
+
+    async fn reset_stale_files(&self, _stale_before: DateTime<Utc>) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+
+    async fn get_checkpoint(&self, name: &str) -> Result<Option<Checkpoint>> {
+        self.inner.get_checkpoint(name).await
+    }
+
+    async fn set_checkpoint(&self, checkpoint: &Checkpoint) -> Result<()> {
+        tracing::info!("dry run: would set checkpoint {:?}", checkpoint);
+        Ok(())
+    }
+
+    fn transforms(&self) -> &PocTransforms {
+        self.inner.transforms()
+    }
+
+    async fn write_chunk(
+        &self,
+        file_type: FileType,
+        bufs: Vec<BytesMut>,
+        source: &Source,
+    ) -> Result<Written> {
+        self.inner.dry_run_chunk(file_type, bufs, source).await
+    }
+}
@@ -0,0 +1,374 @@
+use crate::{
+    handler::{gcs::GcsFileStore, local::LocalFileStore, rate_limiter::RateLimiter},
+    settings::{IngestLimitSettings, IngestSource, Settings},
+};
+use anyhow::{Context, Result};
+use async_compression::tokio::bufread::GzipDecoder;
+use async_trait::async_trait;
+use bytes::BytesMut;
+use chrono::{DateTime, Utc};
+use file_store::{BytesMutStream, FileInfo, FileStore, FileType};
+use futures::{
+    stream::{self, StreamExt},
+    TryStreamExt,
+};
+use std::{collections::HashMap, str::FromStr, sync::Arc};
+use tokio::{
+    io::AsyncBufRead,
+    sync::{Mutex, Semaphore},
+};
+use tokio_util::codec::{FramedRead, LengthDelimitedCodec};
+
+/// Source of ingest files, abstracted so the handler can be driven by
+/// something other than the S3 backed `FileStore` (e.g. in tests).
+#[async_trait]
+pub trait FileSource: Send + Sync {
+    async fn list_all(
+        &self,
+        file_type: FileType,
+        after: DateTime<Utc>,
+        before: Option<DateTime<Utc>>,
+    ) -> Result<Vec<FileInfo>>;
+
+    async fn stream_file(&self, file_info: FileInfo) -> Result<BytesMutStream>;
+}
+
+#[async_trait]
+impl FileSource for FileStore {
+    async fn list_all(
+        &self,
+        file_type: FileType,
+        after: DateTime<Utc>,
+        before: Option<DateTime<Utc>>,
+    ) -> Result<Vec<FileInfo>> {
+        Ok(FileStore::list_all(self, file_type, after, before).await?)
+    }
+
+    async fn stream_file(&self, file_info: FileInfo) -> Result<BytesMutStream> {
+        Ok(FileStore::stream_file(self, file_info).await?)
+    }
+}
+
+/// The configured ingest bucket, limited by `ingest_limits`
+pub async fn ingest_source(settings: &Settings) -> Result<Box<dyn FileSource>> {
+    let source = bucket_source(settings).await?;
+    let limits = &settings.ingest_limits;
+    if limits.requests_per_sec.is_none() && limits.max_concurrent_gets.is_none() {
+        return Ok(source);
+    }
+    Ok(Box::new(LimitedFileSource::new(source, limits)))
+}
+
+async fn bucket_source(settings: &Settings) -> Result<Box<dyn FileSource>> {
+    match settings.ingest_source {
+        IngestSource::S3 => {
+            let mut stores: Vec<Box<dyn FileSource>> = vec![];
+            for bucket in settings.ingest.all() {
+                stores.push(Box::new(FileStore::from_settings(bucket).await?));
+            }
+            match stores.len() {
+                0 => anyhow::bail!("[[ingest]] requires at least one bucket"),
+                1 => Ok(stores.remove(0)),
+                _ => Ok(Box::new(MergedFileSource::new(stores))),
+            }
+        }
+        IngestSource::Gcs => {
+            let gcs = settings
+                .gcs
+                .as_ref()
+                .context("ingest_source = \"gcs\" requires [gcs] settings")?;
+            Ok(Box::new(GcsFileStore::from_settings(gcs).await?))
+        }
+        IngestSource::Local => {
+            let local = settings
+                .local
+                .as_ref()
+                .context("ingest_source = \"local\" requires [local] settings")?;
+            Ok(Box::new(LocalFileStore::from_settings(local)?))
+        }
+    }
+}
+
+/// Source whose listings and downloads are spaced out to a max request rate,
+/// with a bounded number of files downloaded at once
+pub struct LimitedFileSource {
+    source: Box<dyn FileSource>,
+    limiter: Option<RateLimiter>,
+    gets: Option<Arc<Semaphore>>,
+}
+
+impl LimitedFileSource {
+    pub fn new(source: Box<dyn FileSource>, settings: &IngestLimitSettings) -> Self {
+        Self {
+            source,
+            limiter: settings.requests_per_sec.map(RateLimiter::new),
+            gets: settings
+                .max_concurrent_gets
+                .map(|max| Arc::new(Semaphore::new(max.max(1)))),
+        }
+    }
+
+    async fn acquire(&self) {
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire().await;
+        }
+    }
+}
+
+#[async_trait]
+impl FileSource for LimitedFileSource {
+    /// A listing counts as one request, however many pages it takes
+    async fn list_all(
+        &self,
+        file_type: FileType,
+        after: DateTime<Utc>,
+        before: Option<DateTime<Utc>>,
+    ) -> Result<Vec<FileInfo>> {
+        self.acquire().await;
+        self.source.list_all(file_type, after, before).await
+    }
+
+    /// The download slot is held until the returned stream is dropped
+    async fn stream_file(&self, file_info: FileInfo) -> Result<BytesMutStream> {
+        let permit = match &self.gets {
+            Some(gets) => Some(gets.clone().acquire_owned().await?),
+            None => None,
+        };
+        self.acquire().await;
+        let stream = self.source.stream_file(file_info).await?;
+        Ok(stream
+            .map(move |msg| {
+                let _permit = &permit;
+                msg
+            })
+            .boxed())
+    }
+}
+
+/// Files of several sources (e.g. per region buckets) listed as one, oldest
+/// first. A key listed by more than one source is only taken from the first.
+pub struct MergedFileSource {
+    sources: Vec<Box<dyn FileSource>>,
+    // index of the source each listed key is streamed from
+    owners: Mutex<HashMap<String, usize>>,
+}
+
+impl MergedFileSource {
+    pub fn new(sources: Vec<Box<dyn FileSource>>) -> Self {
+        Self {
+            sources,
+            owners: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl FileSource for MergedFileSource {
+    async fn list_all(
+        &self,
+        file_type: FileType,
+        after: DateTime<Utc>,
+        before: Option<DateTime<Utc>>,
+    ) -> Result<Vec<FileInfo>> {
+        let mut files: Vec<FileInfo> = vec![];
+        let mut seen = HashMap::new();
+        for (i, source) in self.sources.iter().enumerate() {
+            for file_info in source.list_all(file_type, after, before).await? {
+                if !seen.contains_key(&file_info.key) {
+                    seen.insert(file_info.key.clone(), i);
+                    files.push(file_info);
+                }
+            }
+        }
+        files.sort_by_key(|fi| fi.timestamp);
+        self.owners.lock().await.extend(seen);
+        Ok(files)
+    }
+
+    /// Streams from the source the file was listed from. Files which weren't
+    /// listed (e.g. notified ones) are streamed from the first source having them.
+    async fn stream_file(&self, file_info: FileInfo) -> Result<BytesMutStream> {
+        let owner = self.owners.lock().await.get(&file_info.key).copied();
+        if let Some(i) = owner {
+            return self.sources[i].stream_file(file_info).await;
+        }
+        let mut last_err = None;
+        for source in &self.sources {
+            match source.stream_file(file_info.clone()).await {
+                Ok(stream) => return Ok(stream),
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| anyhow::anyhow!("no ingest sources")))
+    }
+}
+
+/// In-memory file source seeded with fixture messages
+#[derive(Debug, Default, Clone)]
+pub struct MemoryFileSource {
+    files: Vec<FileInfo>,
+    msgs: HashMap<String, Vec<BytesMut>>,
+}
+
+impl MemoryFileSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file with the given (encoded) messages
+    pub fn with_file(mut self, file_info: FileInfo, msgs: Vec<BytesMut>) -> Self {
+        self.msgs.insert(file_info.key.clone(), msgs);
+        self.files.push(file_info);
+        self
+    }
+}
+
+#[async_trait]
+impl FileSource for MemoryFileSource {
+    async fn list_all(
+        &self,
+        file_type: FileType,
+        after: DateTime<Utc>,
+        before: Option<DateTime<Utc>>,
+    ) -> Result<Vec<FileInfo>> {
+        let prefix = file_type.to_string();
+        let mut files: Vec<FileInfo> = self
+            .files
+            .iter()
+            .filter(|fi| fi.prefix == prefix)
+            .filter(|fi| fi.timestamp >= after)
+            .filter(|fi| before.map_or(true, |before| fi.timestamp <= before))
+            .cloned()
+            .collect();
+        files.sort_by_key(|fi| fi.timestamp);
+        Ok(files)
+    }
+
+    async fn stream_file(&self, file_info: FileInfo) -> Result<BytesMutStream> {
+        let msgs = self
+            .msgs
+            .get(&file_info.key)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("no such file: {:?}", file_info.key))?;
+        Ok(stream::iter(msgs.into_iter().map(Ok)).boxed())
+    }
+}
+
+/// Messages of a gzipped file of length delimited messages, the format of all
+/// ingest files
+pub(crate) fn framed_gzip(reader: impl AsyncBufRead + Send + 'static) -> BytesMutStream {
+    Box::pin(
+        FramedRead::new(GzipDecoder::new(reader), LengthDelimitedCodec::new())
+            .map_err(file_store::Error::from),
+    )
+}
+
+/// Files of `file_type` among listed object (or file) names strictly after
+/// `after` up to `before`, oldest first, like `FileStore::list_all`. Names
+/// which aren't ingest files are skipped.
+pub(crate) fn listed(
+    names: Vec<String>,
+    file_type: FileType,
+    after: DateTime<Utc>,
+    before: Option<DateTime<Utc>>,
+) -> Vec<FileInfo> {
+    let prefix = file_type.to_string();
+    let mut files: Vec<FileInfo> = names
+        .iter()
+        .filter_map(|name| FileInfo::from_str(name).ok())
+        .filter(|fi| fi.prefix == prefix)
+        .filter(|fi| fi.timestamp > after)
+        .filter(|fi| before.map_or(true, |before| fi.timestamp <= before))
+        .collect();
+    files.sort_by_key(|fi| fi.timestamp);
+    files
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn listed_range() {
+        let names = vec![
+            "iot_poc.1687888130000.gz".to_string(),
+            "iot_poc.1687888190000.gz".to_string(),
+            "iot_poc.1687888160000.gz".to_string(),
+            "iot_poc_extra.1687888160000.gz".to_string(),
+            "iot_poc.1687888250000.gz".to_string(),
+            "README".to_string(),
+        ];
+        let after = Utc.timestamp_millis_opt(1_687_888_130_000).unwrap();
+        let before = Utc.timestamp_millis_opt(1_687_888_190_000).unwrap();
+        let keys: Vec<String> = listed(names, FileType::IotPoc, after, Some(before))
+            .into_iter()
+            .map(|fi| fi.key)
+            .collect();
+        assert_eq!(
+            keys,
+            vec!["iot_poc.1687888160000.gz", "iot_poc.1687888190000.gz"]
+        );
+    }
+
+    #[tokio::test]
+    async fn merged_sources() {
+        let file = |ts: u64| FileInfo::from_str(&format!("iot_poc.{ts}.gz")).unwrap();
+        let us915 = MemoryFileSource::new()
+            .with_file(file(1_687_888_130_000), vec![BytesMut::from("us")])
+            .with_file(file(1_687_888_190_000), vec![BytesMut::from("us")]);
+        let eu868 = MemoryFileSource::new()
+            .with_file(file(1_687_888_160_000), vec![BytesMut::from("eu")])
+            .with_file(file(1_687_888_190_000), vec![BytesMut::from("eu")]);
+        let merged = MergedFileSource::new(vec![Box::new(us915), Box::new(eu868)]);
+
+        let after = Utc.timestamp_millis_opt(1_687_888_000_000).unwrap();
+        let files = merged
+            .list_all(FileType::IotPoc, after, None)
+            .await
+            .unwrap();
+        let keys: Vec<&str> = files.iter().map(|fi| fi.key.as_str()).collect();
+        assert_eq!(
+            keys,
+            vec![
+                "iot_poc.1687888130000.gz",
+                "iot_poc.1687888160000.gz",
+                "iot_poc.1687888190000.gz"
+            ]
+        );
+        for (file_info, region) in files.into_iter().zip(["us", "eu", "us"]) {
+            let msgs: Vec<BytesMut> = merged
+                .stream_file(file_info)
+                .await
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+            assert_eq!(msgs, vec![region]);
+        }
+    }
+
+    #[tokio::test]
+    async fn limited_gets() {
+        let first = FileInfo::from_str("iot_poc.1687888130000.gz").unwrap();
+        let second = FileInfo::from_str("iot_poc.1687888160000.gz").unwrap();
+        let source = MemoryFileSource::new()
+            .with_file(first.clone(), vec![BytesMut::from("a")])
+            .with_file(second.clone(), vec![BytesMut::from("b")]);
+        let limits = IngestLimitSettings {
+            requests_per_sec: None,
+            max_concurrent_gets: Some(1),
+        };
+        let limited = LimitedFileSource::new(Box::new(source), &limits);
+
+        let stream = limited.stream_file(first).await.unwrap();
+        let blocked = tokio::time::timeout(
+            std::time::Duration::from_millis(50),
+            limited.stream_file(second.clone()),
+        )
+        .await;
+        assert!(blocked.is_err());
+        drop(stream);
+        assert!(limited.stream_file(second).await.is_ok());
+    }
+}
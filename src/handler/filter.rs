@@ -0,0 +1,162 @@
+use crate::settings::FilterSettings;
+use anyhow::{Context, Result};
+use file_store::iot_valid_poc::IotPoc;
+use helium_crypto::PublicKeyBinary;
+use std::{collections::HashSet, str::FromStr};
+
+/// Ingestion allow/deny list of hotspot pub_keys
+#[derive(Debug, Clone, Default)]
+pub struct PubKeyFilter {
+    allow: HashSet<PublicKeyBinary>,
+    deny: HashSet<PublicKeyBinary>,
+}
+
+impl PubKeyFilter {
+    pub fn from_settings(settings: &FilterSettings) -> Result<Self> {
+        Ok(Self {
+            allow: parse_pub_keys(&settings.allow)?,
+            deny: parse_pub_keys(&settings.deny)?,
+        })
+    }
+
+    /// Drops denied witnesses from the poc, returns whether the poc should be
+    /// ingested at all: its beaconer is not denied and, if an allowlist is set,
+    /// the beaconer or one of the remaining witnesses is allowed.
+    pub fn apply(&self, iot_poc: &mut IotPoc) -> bool {
+        let beaconer = &iot_poc.beacon_report.report.pub_key;
+        if self.deny.contains(beaconer) {
+            return false;
+        }
+        iot_poc
+            .selected_witnesses
+            .retain(|w| !self.deny.contains(&w.report.pub_key));
+        iot_poc
+            .unselected_witnesses
+            .retain(|w| !self.deny.contains(&w.report.pub_key));
+
+        self.allow.is_empty()
+            || self.allow.contains(beaconer)
+            || iot_poc
+                .selected_witnesses
+                .iter()
+                .chain(iot_poc.unselected_witnesses.iter())
+                .any(|w| self.allow.contains(&w.report.pub_key))
+    }
+}
+
+fn parse_pub_keys(pub_keys: &[String]) -> Result<HashSet<PublicKeyBinary>> {
+    pub_keys
+        .iter()
+        .map(|pub_key| {
+            PublicKeyBinary::from_str(pub_key).context(format!("invalid pub_key {pub_key:?}"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::doctor;
+    use file_store::iot_valid_poc::IotVerifiedWitnessReport;
+    use helium_proto::{services::poc_lora::LoraPocV1, Message};
+
+    /// Binary ed25519 key filled with `fill`, as in the doctor sample
+    fn key(fill: u8) -> PublicKeyBinary {
+        let mut key = vec![0x01];
+        key.extend([fill; 32]);
+        PublicKeyBinary::from(key)
+    }
+
+    fn filter(allow: &[u8], deny: &[u8]) -> PubKeyFilter {
+        PubKeyFilter {
+            allow: allow.iter().copied().map(key).collect(),
+            deny: deny.iter().copied().map(key).collect(),
+        }
+    }
+
+    /// Sample poc beaconed by key 1, with key 2 as selected and key 3 as
+    /// unselected witness
+    fn poc() -> IotPoc {
+        let raw = doctor::sample_poc().unwrap();
+        let mut iot_poc = IotPoc::try_from(LoraPocV1::decode(raw.as_slice()).unwrap()).unwrap();
+        let mut unselected = iot_poc.selected_witnesses[0].clone();
+        unselected.report.pub_key = key(3);
+        iot_poc.unselected_witnesses.push(unselected);
+        iot_poc
+    }
+
+    fn keys(witnesses: &[IotVerifiedWitnessReport]) -> Vec<PublicKeyBinary> {
+        witnesses.iter().map(|w| w.report.pub_key.clone()).collect()
+    }
+
+    /// Pub_keys of the selected and unselected witnesses
+    fn witnesses(iot_poc: &IotPoc) -> (Vec<PublicKeyBinary>, Vec<PublicKeyBinary>) {
+        (
+            keys(&iot_poc.selected_witnesses),
+            keys(&iot_poc.unselected_witnesses),
+        )
+    }
+
+    #[test]
+    fn empty_filter_keeps_everything() {
+        let mut iot_poc = poc();
+        assert!(filter(&[], &[]).apply(&mut iot_poc));
+        assert_eq!(witnesses(&iot_poc), (vec![key(2)], vec![key(3)]));
+    }
+
+    #[test]
+    fn allow_only() {
+        // allowed beaconer
+        assert!(filter(&[1], &[]).apply(&mut poc()));
+        // allowed selected or unselected witness
+        assert!(filter(&[2], &[]).apply(&mut poc()));
+        assert!(filter(&[3], &[]).apply(&mut poc()));
+        // none of its hotspots allowed
+        assert!(!filter(&[4], &[]).apply(&mut poc()));
+
+        // allowing doesn't drop the other witnesses
+        let mut iot_poc = poc();
+        filter(&[1], &[]).apply(&mut iot_poc);
+        assert_eq!(witnesses(&iot_poc), (vec![key(2)], vec![key(3)]));
+    }
+
+    #[test]
+    fn deny_only() {
+        // denied beaconer drops the whole poc
+        assert!(!filter(&[], &[1]).apply(&mut poc()));
+
+        // denied witnesses are dropped, the poc kept
+        let mut iot_poc = poc();
+        assert!(filter(&[], &[2]).apply(&mut iot_poc));
+        assert_eq!(witnesses(&iot_poc), (vec![], vec![key(3)]));
+
+        let mut iot_poc = poc();
+        assert!(filter(&[], &[3]).apply(&mut iot_poc));
+        assert_eq!(witnesses(&iot_poc), (vec![key(2)], vec![]));
+
+        // unrelated hotspots don't matter
+        let mut iot_poc = poc();
+        assert!(filter(&[], &[4]).apply(&mut iot_poc));
+        assert_eq!(witnesses(&iot_poc), (vec![key(2)], vec![key(3)]));
+    }
+
+    #[test]
+    fn allow_and_deny() {
+        // deny wins over allow for the beaconer
+        assert!(!filter(&[1], &[1]).apply(&mut poc()));
+
+        // an allowed but denied witness doesn't make the poc allowed
+        let mut iot_poc = poc();
+        assert!(!filter(&[2], &[2]).apply(&mut iot_poc));
+
+        // allowed through the remaining witness
+        let mut iot_poc = poc();
+        assert!(filter(&[3], &[2]).apply(&mut iot_poc));
+        assert_eq!(witnesses(&iot_poc), (vec![], vec![key(3)]));
+
+        // allowed beaconer with a denied witness
+        let mut iot_poc = poc();
+        assert!(filter(&[1], &[3]).apply(&mut iot_poc));
+        assert_eq!(witnesses(&iot_poc), (vec![key(2)], vec![]));
+    }
+}
@@ -0,0 +1,91 @@
+use crate::{
+    handler::{
+        file_source::{framed_gzip, listed},
+        FileSource,
+    },
+    settings::GcsSettings,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use file_store::{BytesMutStream, FileInfo, FileType};
+use futures::TryStreamExt;
+use google_cloud_storage::{
+    client::{google_cloud_auth::credentials::CredentialsFile, Client, ClientConfig},
+    http::objects::{download::Range, get::GetObjectRequest, list::ListObjectsRequest},
+};
+use tokio_util::io::StreamReader;
+
+/// Ingest files in a Google Cloud Storage bucket, named and framed like the
+/// ones of the S3 bucket (gzipped, length delimited messages)
+pub struct GcsFileStore {
+    client: Client,
+    bucket: String,
+}
+
+impl GcsFileStore {
+    pub async fn from_settings(settings: &GcsSettings) -> Result<Self> {
+        let config = match &settings.credentials_file {
+            Some(path) => {
+                let credentials = CredentialsFile::new_from_file(path.clone()).await?;
+                ClientConfig::default()
+                    .with_credentials(credentials)
+                    .await?
+            }
+            None => ClientConfig::default().with_auth().await?,
+        };
+        Ok(Self {
+            client: Client::new(config),
+            bucket: settings.bucket.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl FileSource for GcsFileStore {
+    /// Like `FileStore::list_all`, files strictly after `after` up to `before`
+    /// (inclusive), oldest first
+    async fn list_all(
+        &self,
+        file_type: FileType,
+        after: DateTime<Utc>,
+        before: Option<DateTime<Utc>>,
+    ) -> Result<Vec<FileInfo>> {
+        let mut names = vec![];
+        let mut page_token = None;
+        loop {
+            let resp = self
+                .client
+                .list_objects(&ListObjectsRequest {
+                    bucket: self.bucket.clone(),
+                    prefix: Some(file_type.to_string()),
+                    start_offset: Some(format!("{}.{}", file_type, after.timestamp_millis())),
+                    page_token,
+                    ..Default::default()
+                })
+                .await?;
+            names.extend(resp.items.unwrap_or_default().into_iter().map(|o| o.name));
+            match resp.next_page_token {
+                Some(token) if !token.is_empty() => page_token = Some(token),
+                _ => break,
+            }
+        }
+        Ok(listed(names, file_type, after, before))
+    }
+
+    async fn stream_file(&self, file_info: FileInfo) -> Result<BytesMutStream> {
+        let stream = self
+            .client
+            .download_streamed_object(
+                &GetObjectRequest {
+                    bucket: self.bucket.clone(),
+                    object: file_info.key,
+                    ..Default::default()
+                },
+                &Range::default(),
+            )
+            .await?
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err));
+        Ok(framed_gzip(StreamReader::new(stream)))
+    }
+}
@@ -0,0 +1,208 @@
+use crate::{
+    document::source::Source,
+    handler::ArangodbHandler,
+    settings::{KafkaSettings, Settings},
+};
+use anyhow::{Context, Result};
+use bytes::BytesMut;
+use file_store::FileType;
+use rdkafka::{
+    config::ClientConfig,
+    consumer::{CommitMode, Consumer, StreamConsumer},
+    message::{Message, OwnedMessage},
+    Offset, TopicPartitionList,
+};
+use std::collections::{HashMap, HashSet};
+use tokio::time::{self, Duration, Instant};
+use tokio_graceful_shutdown::SubsystemHandle;
+
+/// Ingests encoded `LoraPocV1` messages consumed from a kafka topic through the
+/// same sink as bucket files. Offsets are committed once the chunk they were
+/// in is written, a chunk failing to be written is consumed again.
+pub struct KafkaIngest {
+    consumer: StreamConsumer,
+    handler: ArangodbHandler,
+    settings: KafkaSettings,
+    batch_size: usize,
+    // offsets of a dry run aren't committed, see `Settings::dry_run`
+    dry_run: bool,
+}
+
+impl KafkaIngest {
+    pub async fn new(settings: &Settings) -> Result<Self> {
+        let kafka = settings
+            .kafka
+            .clone()
+            .context("kafka ingest requires the kafka settings")?;
+        let mut config = ClientConfig::new();
+        config
+            .set("bootstrap.servers", &kafka.brokers)
+            .set("group.id", &kafka.group_id)
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", "earliest");
+        for (key, value) in &kafka.properties {
+            config.set(key, value);
+        }
+        let consumer: StreamConsumer = config.create()?;
+        consumer.subscribe(&[kafka.topic.as_str()])?;
+        let handler = ArangodbHandler::new(settings).await?;
+        Ok(Self {
+            consumer,
+            handler,
+            batch_size: kafka.batch_size.unwrap_or(settings.file_chunk_size),
+            settings: kafka,
+            dry_run: settings.dry_run,
+        })
+    }
+
+    pub fn handler(&self) -> &ArangodbHandler {
+        &self.handler
+    }
+
+    pub async fn run(self, subsys: SubsystemHandle) -> Result<()> {
+        tracing::info!(
+            "consuming {:?} as group {:?}",
+            self.settings.topic,
+            self.settings.group_id
+        );
+        loop {
+            // NOTE: a batch being collected is dropped on shutdown, its offsets
+            // aren't committed so it is consumed again on the next start
+            let msgs = tokio::select! {
+                _ = subsys.on_shutdown_requested() => break,
+                msgs = self.next_batch() => msgs?,
+            };
+            if !self.write_batch(msgs).await? {
+                time::sleep(Duration::from_millis(self.settings.retry_ms)).await;
+            }
+        }
+        tracing::info!("stopping kafka ingest");
+        Ok(())
+    }
+
+    /// Wait for a message, then collect more until the batch is full or the
+    /// linger time is up
+    async fn next_batch(&self) -> Result<Vec<OwnedMessage>> {
+        let mut msgs = vec![self.consumer.recv().await?.detach()];
+        let deadline = Instant::now() + Duration::from_millis(self.settings.linger_ms);
+        while msgs.len() < self.batch_size {
+            match time::timeout_at(deadline, self.consumer.recv()).await {
+                Ok(msg) => msgs.push(msg?.detach()),
+                Err(_) => break,
+            }
+        }
+        Ok(msgs)
+    }
+
+    /// Write the runs of consecutive messages of each partition as chunks and
+    /// commit the offsets of the written ones. A partition whose chunk fails is
+    /// rewound to it, skipping its later chunks. Returns whether all were written.
+    async fn write_batch(&self, msgs: Vec<OwnedMessage>) -> Result<bool> {
+        let topic = &self.settings.topic;
+        let mut commits: HashMap<i32, i64> = HashMap::new();
+        let mut failed = HashSet::new();
+        // NOTE: tombstones (without payload) are skipped, they split runs so
+        // offsets stay exact and are committed with a later run
+        let msgs: Vec<_> = msgs
+            .into_iter()
+            .filter(|msg| msg.payload().is_some())
+            .collect();
+        for run in runs(msgs, |msg| (msg.partition(), msg.offset())) {
+            let (partition, first_offset) = (run[0].partition(), run[0].offset());
+            if failed.contains(&partition) {
+                continue;
+            }
+            let last_offset = run[run.len() - 1].offset();
+            let timestamp = run[0].timestamp().to_millis().unwrap_or_default();
+            let source =
+                Source::kafka(topic, partition, timestamp, "LoraPocV1").at(first_offset as u64);
+            let bufs: Vec<BytesMut> = run
+                .iter()
+                .filter_map(|msg| msg.payload().map(BytesMut::from))
+                .collect();
+            match self
+                .handler
+                .write_chunk(FileType::IotPoc, bufs, &source)
+                .await
+            {
+                Ok(stats) => {
+                    tracing::debug!(
+                        "stored {} pocs from {}/{} offsets {}..={}",
+                        stats.beacons,
+                        topic,
+                        partition,
+                        first_offset,
+                        last_offset
+                    );
+                    commits.insert(partition, last_offset + 1);
+                }
+                Err(e) => {
+                    tracing::error!(
+                        "error writing {}/{} offsets {}..={}, retrying: {:?}",
+                        topic,
+                        partition,
+                        first_offset,
+                        last_offset,
+                        e
+                    );
+                    failed.insert(partition);
+                    self.consumer.seek(
+                        topic,
+                        partition,
+                        Offset::Offset(first_offset),
+                        Duration::from_secs(10),
+                    )?;
+                }
+            }
+        }
+        if self.dry_run {
+            tracing::info!("dry run: would commit offsets {:?}", commits);
+        } else if !commits.is_empty() {
+            let mut offsets = TopicPartitionList::new();
+            for (partition, offset) in commits {
+                offsets.add_partition_offset(topic, partition, Offset::Offset(offset))?;
+            }
+            self.consumer.commit(&offsets, CommitMode::Async)?;
+        }
+        Ok(failed.is_empty())
+    }
+}
+
+/// Split messages into runs of consecutive offsets of the same partition,
+/// keeping the consumed order within each partition. A message's index within
+/// its run plus the run's first offset is then its offset.
+fn runs<T>(items: Vec<T>, key: impl Fn(&T) -> (i32, i64)) -> Vec<Vec<T>> {
+    let mut runs: Vec<Vec<T>> = vec![];
+    let mut open: HashMap<i32, usize> = HashMap::new();
+    for item in items {
+        let (partition, offset) = key(&item);
+        match open.get(&partition) {
+            Some(&i) if runs[i].last().map(|last| key(last).1 + 1) == Some(offset) => {
+                runs[i].push(item)
+            }
+            _ => {
+                open.insert(partition, runs.len());
+                runs.push(vec![item]);
+            }
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn consecutive_runs() {
+        let msgs = vec![(0, 10), (1, 5), (0, 11), (0, 13), (1, 6), (0, 14)];
+        assert_eq!(
+            runs(msgs, |msg| *msg),
+            vec![
+                vec![(0, 10), (0, 11)],
+                vec![(1, 5), (1, 6)],
+                vec![(0, 13), (0, 14)],
+            ]
+        );
+    }
+}
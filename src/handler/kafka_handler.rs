@@ -0,0 +1,82 @@
+use crate::{
+    handler::{
+        arangodb::Populated,
+        notifier::{poc_payload, Notifier},
+    },
+    settings::{KafkaNotifierSettings, PocPayload},
+};
+use anyhow::Result;
+use futures::future;
+use rdkafka::{
+    config::ClientConfig,
+    producer::{FutureProducer, FutureRecord},
+    util::Timeout,
+};
+
+/// Publishes the stored pocs and json events to kafka topics, for deployments
+/// consuming kafka rather than redis downstream
+pub struct KafkaHandler {
+    producer: FutureProducer,
+    poc_topic: String,
+    topic_prefix: String,
+    payload: PocPayload,
+}
+
+impl KafkaHandler {
+    pub fn from_settings(settings: &KafkaNotifierSettings) -> Result<Self> {
+        let mut config = ClientConfig::new();
+        config.set("bootstrap.servers", &settings.brokers);
+        for (key, value) in &settings.properties {
+            config.set(key, value);
+        }
+        Ok(Self {
+            producer: config.create()?,
+            poc_topic: settings.poc_topic.clone(),
+            topic_prefix: settings.topic_prefix.clone(),
+            payload: settings.payload,
+        })
+    }
+
+    async fn send(&self, topic: &str, key: &str, payload: &str) -> Result<()> {
+        let record = FutureRecord::to(topic).key(key).payload(payload);
+        self.producer
+            .send(record, Timeout::Never)
+            .await
+            .map(|_| ())
+            .map_err(|(err, _)| err.into())
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for KafkaHandler {
+    /// Produces one record per poc keyed by its poc_id, waiting for all of
+    /// them to be delivered
+    async fn publish_pocs(&self, populated: &[Populated]) -> Result<()> {
+        let sends = populated.iter().map(|populated| async move {
+            let payload = poc_payload(self.payload, populated)?;
+            self.send(&self.poc_topic, &populated.poc_id, &payload)
+                .await
+        });
+        for (populated, res) in populated.iter().zip(future::join_all(sends).await) {
+            if let Err(e) = res {
+                tracing::error!(
+                    "failed to publish poc_id {:?} to kafka, error: {:?}",
+                    populated.poc_id,
+                    e
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Produces to the topic `<topic_prefix><stream>`, unkeyed
+    async fn publish_event(&self, stream: &str, payload: &str) -> Result<()> {
+        let topic = format!("{}{}", self.topic_prefix, stream);
+        let record: FutureRecord<'_, (), str> = FutureRecord::to(&topic).payload(payload);
+        self.producer
+            .send(record, Timeout::Never)
+            .await
+            .map(|_| ())
+            .map_err(|(err, _)| err.into())
+    }
+}
@@ -0,0 +1,119 @@
+use crate::{
+    handler::{
+        arangodb::Populated,
+        notifier::{poc_payload, Notifier},
+    },
+    settings::{LiveFeedSettings, PocPayload},
+};
+use anyhow::Result;
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response,
+    routing::get,
+    Router,
+};
+use std::net::SocketAddr;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_graceful_shutdown::SubsystemHandle;
+
+/// Streams a json summary of every stored beacon to WebSocket clients, e.g.
+/// dashboards without access to redis. Clients only see beacons stored while
+/// they are connected, slow ones skip what they fell behind on.
+pub struct LiveFeed {
+    listen: SocketAddr,
+    tx: broadcast::Sender<String>,
+}
+
+impl LiveFeed {
+    pub fn from_settings(settings: &LiveFeedSettings) -> Self {
+        let (tx, _) = broadcast::channel(settings.buffer.max(1));
+        Self {
+            listen: settings.listen,
+            tx,
+        }
+    }
+
+    /// Serve the feed on `/pocs` until shutdown
+    pub async fn serve(&self, subsys: SubsystemHandle) -> Result<()> {
+        let app = Router::new()
+            .route("/pocs", get(pocs))
+            .with_state(self.tx.clone());
+        tracing::info!("serving live feed on ws://{}/pocs", self.listen);
+        axum::Server::try_bind(&self.listen)?
+            .serve(app.into_make_service())
+            .with_graceful_shutdown(subsys.on_shutdown_requested())
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for LiveFeed {
+    async fn publish_pocs(&self, populated: &[Populated]) -> Result<()> {
+        if self.tx.receiver_count() == 0 {
+            return Ok(());
+        }
+        for populated in populated {
+            // only fails without connected clients
+            let _ = self.tx.send(poc_payload(PocPayload::Summary, populated)?);
+        }
+        Ok(())
+    }
+
+    /// Events are not part of the feed
+    async fn publish_event(&self, _stream: &str, _payload: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+async fn pocs(ws: WebSocketUpgrade, State(tx): State<broadcast::Sender<String>>) -> Response {
+    ws.on_upgrade(move |socket| stream_pocs(socket, tx.subscribe()))
+}
+
+async fn stream_pocs(mut socket: WebSocket, mut rx: broadcast::Receiver<String>) {
+    loop {
+        tokio::select! {
+            msg = rx.recv() => match msg {
+                Ok(summary) => {
+                    if socket.send(Message::Text(summary)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::debug!("live feed client skipped {} pocs", skipped);
+                }
+                Err(RecvError::Closed) => break,
+            },
+            // clients only send pings and close frames
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn poc_summary() {
+        let populated = Populated {
+            poc_id: "poc".to_string(),
+            beaconer: "beaconer".to_string(),
+            lineage_id: None,
+            new_hotspots: vec![],
+            beacon: None,
+            regions: vec!["US915"],
+            witnesses: 3,
+        };
+        assert_eq!(
+            poc_payload(PocPayload::Summary, &populated).unwrap(),
+            r#"{"poc_id":"poc","beaconer":"beaconer","witnesses":3,"lineage_id":null}"#
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{
+    handler::{
+        file_source::{framed_gzip, listed},
+        FileSource,
+    },
+    settings::LocalSettings,
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use file_store::{BytesMutStream, FileInfo, FileType};
+use std::path::PathBuf;
+use tokio::{fs, io::BufReader};
+
+/// Ingest files in a local directory, named like the S3 keys (e.g. downloaded
+/// fixtures), to run the pipeline offline
+pub struct LocalFileStore {
+    dir: PathBuf,
+}
+
+impl LocalFileStore {
+    pub fn from_settings(settings: &LocalSettings) -> Result<Self> {
+        if !settings.dir.is_dir() {
+            anyhow::bail!("ingest dir {} is not a directory", settings.dir.display());
+        }
+        Ok(Self {
+            dir: settings.dir.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl FileSource for LocalFileStore {
+    async fn list_all(
+        &self,
+        file_type: FileType,
+        after: DateTime<Utc>,
+        before: Option<DateTime<Utc>>,
+    ) -> Result<Vec<FileInfo>> {
+        let mut names = vec![];
+        let mut entries = fs::read_dir(&self.dir)
+            .await
+            .with_context(|| format!("failed to read {}", self.dir.display()))?;
+        while let Some(entry) = entries.next_entry().await? {
+            if let Some(name) = entry.file_name().to_str() {
+                names.push(name.to_string());
+            }
+        }
+        Ok(listed(names, file_type, after, before))
+    }
+
+    async fn stream_file(&self, file_info: FileInfo) -> Result<BytesMutStream> {
+        let path = self.dir.join(&file_info.key);
+        let file = fs::File::open(&path)
+            .await
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        Ok(framed_gzip(BufReader::new(file)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use async_compression::tokio::write::GzipEncoder;
+    use chrono::TimeZone;
+    use futures::TryStreamExt;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn list_and_stream() {
+        let dir = std::env::temp_dir().join(format!("arango-etl-local-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut encoder = GzipEncoder::new(vec![]);
+        for msg in [&b"first"[..], &b"second"[..]] {
+            encoder
+                .write_all(&(msg.len() as u32).to_be_bytes())
+                .await
+                .unwrap();
+            encoder.write_all(msg).await.unwrap();
+        }
+        encoder.shutdown().await.unwrap();
+        std::fs::write(dir.join("iot_poc.1687888130980.gz"), encoder.into_inner()).unwrap();
+        std::fs::write(dir.join("notes.txt"), "not an ingest file").unwrap();
+
+        let store = LocalFileStore::from_settings(&LocalSettings { dir: dir.clone() }).unwrap();
+        let after = Utc.timestamp_millis_opt(1_687_888_000_000).unwrap();
+        let files = store.list_all(FileType::IotPoc, after, None).await.unwrap();
+        assert_eq!(files.len(), 1);
+        let msgs: Vec<_> = store
+            .stream_file(files[0].clone())
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(msgs, vec![&b"first"[..], &b"second"[..]]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,87 @@
+use crate::{document::hotspot::GatewayMetadata, settings::MetadataSettings};
+use anyhow::{Context, Result};
+use helium_crypto::PublicKeyBinary;
+use serde::Deserialize;
+use std::{collections::HashMap, io::Read, str::FromStr};
+
+/// Row of the metadata csv, columns other than pub_key may be missing or empty
+#[derive(Debug, Deserialize)]
+struct MetadataRow {
+    pub_key: String,
+    maker: Option<String>,
+    region: Option<String>,
+    gain: Option<i32>,
+    elevation: Option<i32>,
+}
+
+/// Maker and asserted metadata of hotspots by pub_key, attached to their
+/// documents when they are written
+#[derive(Debug, Clone, Default)]
+pub struct HotspotMetadata {
+    by_pub_key: HashMap<PublicKeyBinary, GatewayMetadata>,
+}
+
+impl HotspotMetadata {
+    pub fn from_settings(settings: &MetadataSettings) -> Result<Self> {
+        let file = std::fs::File::open(&settings.file)
+            .with_context(|| format!("failed to open {}", settings.file.display()))?;
+        let metadata = Self::from_reader(file)?;
+        tracing::info!(
+            "loaded metadata of {} hotspots from {}",
+            metadata.by_pub_key.len(),
+            settings.file.display()
+        );
+        Ok(metadata)
+    }
+
+    /// Read a csv with a header, columns `pub_key`, `maker`, `region`, `gain`
+    /// and `elevation`
+    pub fn from_reader(reader: impl Read) -> Result<Self> {
+        let mut by_pub_key = HashMap::new();
+        for row in csv::Reader::from_reader(reader).deserialize() {
+            let row: MetadataRow = row?;
+            let pub_key = PublicKeyBinary::from_str(&row.pub_key)
+                .context(format!("invalid pub_key {:?}", row.pub_key))?;
+            by_pub_key.insert(
+                pub_key,
+                GatewayMetadata {
+                    maker: row.maker,
+                    region: row.region,
+                    gain: row.gain,
+                    elevation: row.elevation,
+                },
+            );
+        }
+        Ok(Self { by_pub_key })
+    }
+
+    pub fn get(&self, pub_key: &PublicKeyBinary) -> Option<&GatewayMetadata> {
+        self.by_pub_key.get(pub_key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_csv() {
+        let mut bytes = vec![0x01];
+        bytes.extend([3; 32]);
+        let pub_key = PublicKeyBinary::from(bytes);
+        let csv = format!("pub_key,maker,region,gain,elevation\n{pub_key},RAKwireless,US915,23,\n");
+        let metadata = HotspotMetadata::from_reader(csv.as_bytes()).unwrap();
+        assert_eq!(
+            metadata.get(&pub_key),
+            Some(&GatewayMetadata {
+                maker: Some("RAKwireless".to_string()),
+                region: Some("US915".to_string()),
+                gain: Some(23),
+                elevation: None,
+            })
+        );
+
+        let invalid = "pub_key,maker\nnot a key,Nebra\n";
+        assert!(HotspotMetadata::from_reader(invalid.as_bytes()).is_err());
+    }
+}
@@ -1,6 +1,39 @@
 pub mod arangodb;
 pub mod arangodb_handler;
+#[cfg(feature = "clickhouse")]
+pub mod clickhouse;
+pub mod dry_run;
+pub mod file_source;
+pub mod filter;
+pub mod gcs;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "kafka")]
+pub mod kafka_handler;
+pub mod live_feed;
+pub mod local;
+pub mod metadata;
+#[cfg(feature = "nats")]
+pub mod nats_handler;
+pub mod notifications;
+pub mod notifier;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod rate_limiter;
 pub mod redis_handler;
+pub mod retry;
+pub mod shadow;
+pub mod sink;
+pub mod stdout;
+pub mod tls;
+pub mod webhook;
 
 pub use arangodb_handler::ArangodbHandler;
+pub use file_source::{ingest_source, FileSource, MemoryFileSource};
+pub use notifications::Notifications;
 pub use redis_handler::RedisHandler;
+
+/// Error of an integration configured in a build without its cargo feature
+pub fn feature_disabled(feature: &str) -> anyhow::Error {
+    anyhow::anyhow!("built without the {feature:?} feature, rebuild with `--features {feature}`")
+}
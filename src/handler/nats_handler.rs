@@ -0,0 +1,101 @@
+use crate::{
+    handler::{
+        arangodb::Populated,
+        notifier::{poc_payload, Notifier},
+    },
+    settings::{NatsNotifierSettings, PocPayload},
+};
+use anyhow::Result;
+use async_nats::{jetstream, ConnectOptions, HeaderMap};
+
+/// Publishes the stored pocs and json events as JetStream messages, on
+/// subjects rendered from the configured templates
+pub struct NatsHandler {
+    jetstream: jetstream::Context,
+    poc_subject: String,
+    event_subject: String,
+    payload: PocPayload,
+}
+
+impl NatsHandler {
+    pub async fn from_settings(settings: &NatsNotifierSettings) -> Result<Self> {
+        let options = match &settings.credentials_file {
+            Some(path) => ConnectOptions::with_credentials_file(path.into()).await?,
+            None => ConnectOptions::new(),
+        };
+        let client = options.connect(settings.url.as_str()).await?;
+        Ok(Self {
+            jetstream: jetstream::new(client),
+            poc_subject: settings.poc_subject.clone(),
+            event_subject: settings.event_subject.clone(),
+            payload: settings.payload,
+        })
+    }
+
+    /// Publish and wait for the stream's ack
+    async fn publish(&self, subject: String, headers: HeaderMap, payload: String) -> Result<()> {
+        self.jetstream
+            .publish_with_headers(subject, headers, payload.into())
+            .await?
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for NatsHandler {
+    /// Publishes one message per poc, with its poc_id as `Nats-Msg-Id` so the
+    /// stream drops duplicates of retried chunks within its window
+    async fn publish_pocs(&self, populated: &[Populated]) -> Result<()> {
+        for populated in populated {
+            let subject = render_subject(&self.poc_subject, &[("poc_id", &populated.poc_id)]);
+            let mut headers = HeaderMap::new();
+            headers.insert("Nats-Msg-Id", populated.poc_id.as_str());
+            let res = match poc_payload(self.payload, populated) {
+                Ok(payload) => self.publish(subject, headers, payload).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = res {
+                tracing::error!(
+                    "failed to publish poc_id {:?} to nats, error: {:?}",
+                    populated.poc_id,
+                    e
+                );
+            }
+        }
+        Ok(())
+    }
+
+    async fn publish_event(&self, stream: &str, payload: &str) -> Result<()> {
+        let subject = render_subject(&self.event_subject, &[("event", stream)]);
+        self.publish(subject, HeaderMap::new(), payload.to_string())
+            .await
+    }
+}
+
+/// Subject of a template with its `{name}` placeholders replaced by `vars`.
+/// Dots in values are replaced by underscores so they stay one subject token.
+fn render_subject(template: &str, vars: &[(&str, &str)]) -> String {
+    vars.iter()
+        .fold(template.to_string(), |subject, (name, value)| {
+            subject.replace(&format!("{{{name}}}"), &value.replace('.', "_"))
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn subjects() {
+        assert_eq!(
+            render_subject("iot.poc.{poc_id}", &[("poc_id", "abc")]),
+            "iot.poc.abc"
+        );
+        assert_eq!(
+            render_subject("iot.events.{event}", &[("event", "tick.summary")]),
+            "iot.events.tick_summary"
+        );
+        assert_eq!(render_subject("iot.pocs", &[("poc_id", "abc")]), "iot.pocs");
+    }
+}
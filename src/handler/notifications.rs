@@ -0,0 +1,173 @@
+use crate::settings::NotificationSettings;
+use anyhow::Result;
+use aws_sdk_sqs::{Client, Region};
+use file_store::{FileInfo, FileType};
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// Receives S3 new-object notifications for the ingest bucket from an SQS queue,
+/// either delivered directly or wrapped in an SNS notification.
+pub struct Notifications {
+    client: Client,
+    queue_url: String,
+    wait_secs: i32,
+    max_messages: i32,
+    // prefixes of the file types to process
+    prefixes: Vec<String>,
+}
+
+/// Files announced by a single receive, along with the receipts to ack them
+#[derive(Debug, Default)]
+pub struct Notified {
+    pub file_infos: Vec<FileInfo>,
+    receipts: Vec<String>,
+}
+
+impl Notified {
+    pub fn is_empty(&self) -> bool {
+        self.receipts.is_empty()
+    }
+}
+
+impl Notifications {
+    pub async fn from_settings(
+        settings: &NotificationSettings,
+        region: &str,
+        file_types: &[FileType],
+    ) -> Result<Self> {
+        let region = settings.region.as_deref().unwrap_or(region).to_string();
+        let config = aws_config::from_env()
+            .region(Region::new(region))
+            .load()
+            .await;
+        Ok(Self {
+            client: Client::new(&config),
+            queue_url: settings.queue_url.clone(),
+            wait_secs: settings.wait_secs,
+            max_messages: settings.max_messages,
+            prefixes: file_types.iter().map(|ft| ft.to_string()).collect(),
+        })
+    }
+
+    /// Long poll the queue for files of the ingested types. Messages which don't
+    /// announce any such file (e.g. s3:TestEvent) are returned with no files so they get acked.
+    pub async fn receive(&self) -> Result<Notified> {
+        let output = self
+            .client
+            .receive_message()
+            .queue_url(&self.queue_url)
+            .wait_time_seconds(self.wait_secs)
+            .max_number_of_messages(self.max_messages)
+            .send()
+            .await?;
+
+        let mut notified = Notified::default();
+        for message in output.messages().unwrap_or_default() {
+            if let Some(body) = message.body() {
+                match parse_files(body, &self.prefixes) {
+                    Ok(file_infos) => notified.file_infos.extend(file_infos),
+                    Err(err) => tracing::warn!("ignoring unparseable notification: {:?}", err),
+                }
+            }
+            if let Some(receipt) = message.receipt_handle() {
+                notified.receipts.push(receipt.to_string());
+            }
+        }
+        Ok(notified)
+    }
+
+    /// Delete processed messages from the queue, anything not acked is
+    /// redelivered once its visibility timeout expires
+    pub async fn ack(&self, notified: Notified) -> Result<()> {
+        for receipt in notified.receipts {
+            self.client
+                .delete_message()
+                .queue_url(&self.queue_url)
+                .receipt_handle(receipt)
+                .send()
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SnsEnvelope {
+    #[serde(rename = "Message")]
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct S3Event {
+    #[serde(rename = "Records", default)]
+    records: Vec<S3EventRecord>,
+}
+
+#[derive(Debug, Deserialize)]
+struct S3EventRecord {
+    s3: S3Entity,
+}
+
+#[derive(Debug, Deserialize)]
+struct S3Entity {
+    object: S3Object,
+}
+
+#[derive(Debug, Deserialize)]
+struct S3Object {
+    key: String,
+    #[serde(default)]
+    size: usize,
+}
+
+/// Parse the files with one of `prefixes` out of an S3 event notification body
+fn parse_files(body: &str, prefixes: &[String]) -> Result<Vec<FileInfo>> {
+    let event: S3Event = match serde_json::from_str::<SnsEnvelope>(body) {
+        Ok(envelope) => serde_json::from_str(&envelope.message)?,
+        Err(_) => serde_json::from_str(body)?,
+    };
+    Ok(event
+        .records
+        .into_iter()
+        .filter_map(|record| {
+            let mut file_info = FileInfo::from_str(&record.s3.object.key).ok()?;
+            file_info.size = record.s3.object.size;
+            Some(file_info)
+        })
+        .filter(|fi| prefixes.contains(&fi.prefix))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const EVENT: &str = r#"{"Records":[
+        {"eventName":"ObjectCreated:Put","s3":{"object":{"key":"iot_poc.1687888130980.gz","size":1024}}},
+        {"eventName":"ObjectCreated:Put","s3":{"object":{"key":"iot_beacon_ingest_report.1687888130980.gz","size":10}}}
+    ]}"#;
+
+    fn prefixes() -> Vec<String> {
+        vec![FileType::IotPoc.to_string()]
+    }
+
+    #[test]
+    fn parse_s3_event() {
+        let files = parse_files(EVENT, &prefixes()).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].key, "iot_poc.1687888130980.gz");
+        assert_eq!(files[0].size, 1024);
+    }
+
+    #[test]
+    fn parse_sns_wrapped_event() {
+        let body = serde_json::json!({ "Type": "Notification", "Message": EVENT }).to_string();
+        assert_eq!(parse_files(&body, &prefixes()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn parse_test_event() {
+        let body = r#"{"Service":"Amazon S3","Event":"s3:TestEvent","Bucket":"bucket"}"#;
+        assert!(parse_files(body, &prefixes()).unwrap().is_empty());
+    }
+}
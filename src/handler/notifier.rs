@@ -0,0 +1,66 @@
+use crate::{handler::arangodb::Populated, settings::PocPayload};
+use anyhow::Result;
+use serde::Serialize;
+
+/// Stream (or topic) receiving the poc_id of every stored poc
+pub const POC_ID_STREAM: &str = "poc_id";
+/// Stream (or topic) receiving a `ProcessSummary` after each run
+pub const SUMMARY_STREAM: &str = "tick_summary";
+/// Stream (or topic) receiving a `HotspotFirstSeen` event for each new hotspot
+pub const FIRST_SEEN_STREAM: &str = "hotspot_first_seen";
+/// Stream (or topic) receiving a `FileEvent` for each completed file
+pub const FILE_COMPLETED_STREAM: &str = "file_completed";
+/// Stream (or topic) receiving a `FileEvent` for each file failing its last retry
+pub const FILE_EXHAUSTED_STREAM: &str = "file_exhausted";
+
+/// Downstream publisher of ingest events: the stored pocs of each chunk plus
+/// json events such as first seen hotspots and run summaries. Implemented by
+/// `RedisHandler` (streams), `KafkaHandler` (topics), `NatsHandler`
+/// (subjects), `WebhookHandler` (POSTs) and `LiveFeed` (WebSocket).
+#[async_trait::async_trait]
+pub trait Notifier: Send + Sync {
+    /// Publish the pocs stored from a chunk, errors of single pocs are logged
+    async fn publish_pocs(&self, populated: &[Populated]) -> Result<()>;
+    /// Publish a json event to the stream (or topic) named `stream`
+    async fn publish_event(&self, stream: &str, payload: &str) -> Result<()>;
+    /// Whether file_completed and file_exhausted events are published, off by
+    /// default so existing streams and topics don't change
+    fn publishes_file_events(&self) -> bool {
+        false
+    }
+}
+
+/// Payload of poc messages with `payload = "poc_id"`
+#[derive(Debug, Serialize)]
+struct PocIdPayload<'a> {
+    poc_id: &'a str,
+    lineage_id: Option<&'a str>,
+}
+
+/// Payload of poc messages with `payload = "summary"`
+#[derive(Debug, Serialize)]
+struct PocSummaryPayload<'a> {
+    poc_id: &'a str,
+    beaconer: &'a str,
+    witnesses: usize,
+    lineage_id: Option<&'a str>,
+}
+
+/// Json payload of the message published for a stored poc: its beacon with
+/// `PocPayload::Beacon`, a summary with `PocPayload::Summary`, otherwise its
+/// poc_id and lineage id
+pub fn poc_payload(payload: PocPayload, populated: &Populated) -> Result<String> {
+    match (payload, &populated.beacon) {
+        (PocPayload::Beacon, Some(beacon)) => Ok(serde_json::to_string(beacon)?),
+        (PocPayload::Summary, _) => Ok(serde_json::to_string(&PocSummaryPayload {
+            poc_id: &populated.poc_id,
+            beaconer: &populated.beaconer,
+            witnesses: populated.witnesses,
+            lineage_id: populated.lineage_id.as_deref(),
+        })?),
+        _ => Ok(serde_json::to_string(&PocIdPayload {
+            poc_id: &populated.poc_id,
+            lineage_id: populated.lineage_id.as_deref(),
+        })?),
+    }
+}
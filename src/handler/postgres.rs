@@ -0,0 +1,606 @@
+use crate::{
+    document::{
+        checkpoint::Checkpoint,
+        edge::EdgeAggregate,
+        hotspot::HotspotFirstSeen,
+        iot_poc_file::FileStats,
+        source::Source,
+        timestamp::{now_unix_ms, unix_ms},
+        Beacon, Edge, Witness,
+    },
+    handler::{
+        arangodb::Populated,
+        sink::{decode_beacons_counted, PocTransforms, Sink, Written},
+    },
+    settings::PostgresSettings,
+};
+use anyhow::{bail, Result};
+use bytes::BytesMut;
+use chrono::{TimeZone, Utc};
+use deadpool_postgres::{Config, Pool, PoolConfig, Runtime, Transaction};
+use file_store::{FileInfo, FileType};
+use std::collections::{HashMap, HashSet};
+use tokio_postgres::NoTls;
+
+/// Tables of the postgres sink, created on startup if missing. Timestamps are
+/// unix ms like the ArangoDB documents.
+const SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS files (
+    key TEXT PRIMARY KEY,
+    timestamp_unix_ms BIGINT NOT NULL,
+    size BIGINT NOT NULL,
+    done BOOLEAN NOT NULL DEFAULT FALSE,
+    retries SMALLINT NOT NULL DEFAULT 0
+);
+CREATE TABLE IF NOT EXISTS checkpoints (
+    name TEXT PRIMARY KEY,
+    watermark_unix_ms BIGINT NOT NULL,
+    updated_unix_ms BIGINT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS hotspots (
+    pub_key TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    str_location TEXT,
+    latitude DOUBLE PRECISION,
+    longitude DOUBLE PRECISION,
+    gain INTEGER NOT NULL,
+    elevation INTEGER NOT NULL,
+    first_seen_unix_ms BIGINT NOT NULL,
+    last_updated_unix_ms BIGINT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS beacons (
+    poc_id TEXT PRIMARY KEY,
+    pub_key TEXT NOT NULL,
+    ingest_time_unix_ms BIGINT NOT NULL,
+    str_location TEXT,
+    latitude DOUBLE PRECISION,
+    longitude DOUBLE PRECISION,
+    frequency BIGINT NOT NULL,
+    channel INTEGER NOT NULL,
+    tx_power INTEGER NOT NULL,
+    witness_count INTEGER NOT NULL,
+    selected_witness_count INTEGER NOT NULL,
+    source JSONB
+);
+CREATE INDEX IF NOT EXISTS beacons_pub_key ON beacons (pub_key);
+CREATE INDEX IF NOT EXISTS beacons_ingest_time ON beacons (ingest_time_unix_ms);
+CREATE TABLE IF NOT EXISTS witnesses (
+    poc_id TEXT NOT NULL REFERENCES beacons ON DELETE CASCADE,
+    pub_key TEXT NOT NULL,
+    ingest_time_unix_ms BIGINT NOT NULL,
+    str_location TEXT,
+    signal INTEGER NOT NULL,
+    snr INTEGER NOT NULL,
+    frequency BIGINT NOT NULL,
+    selected BOOLEAN NOT NULL,
+    distance DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (poc_id, pub_key)
+);
+CREATE INDEX IF NOT EXISTS witnesses_pub_key ON witnesses (pub_key);
+CREATE TABLE IF NOT EXISTS edges (
+    key TEXT PRIMARY KEY,
+    beacon_pub_key TEXT NOT NULL,
+    witness_pub_key TEXT NOT NULL,
+    distance DOUBLE PRECISION NOT NULL,
+    count BIGINT NOT NULL,
+    band_mismatch_count BIGINT NOT NULL,
+    first_seen_unix_ms BIGINT NOT NULL,
+    last_seen_unix_ms BIGINT NOT NULL
+);
+"#;
+
+/// Sink writing pocs into relational beacons, witnesses, hotspots and edges
+/// tables. Only iot_poc files are supported.
+pub struct PostgresSink {
+    pool: Pool,
+    transforms: PocTransforms,
+}
+
+impl PostgresSink {
+    pub async fn from_settings(
+        settings: &PostgresSettings,
+        transforms: PocTransforms,
+    ) -> Result<Self> {
+        let mut config = Config::new();
+        config.url = Some(settings.url.clone());
+        config.pool = Some(PoolConfig::new(settings.pool_size));
+        let pool = config.create_pool(Some(Runtime::Tokio1), NoTls)?;
+        pool.get().await?.batch_execute(SCHEMA).await?;
+        Ok(Self { pool, transforms })
+    }
+
+    /// Writes the beacon and witness rows of a poc unless its beacon is already
+    /// stored, staging its edges in `edges`. Returns whether it was written.
+    async fn write_poc(
+        tx: &Transaction<'_>,
+        beacon: &Beacon,
+        edges: &mut HashMap<String, EdgeAggregate>,
+    ) -> Result<bool> {
+        let inserted = tx
+            .execute(
+                "INSERT INTO beacons (poc_id, pub_key, ingest_time_unix_ms, str_location,
+                     latitude, longitude, frequency, channel, tx_power, witness_count,
+                     selected_witness_count, source)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+                 ON CONFLICT (poc_id) DO NOTHING",
+                &[
+                    &beacon.poc_id,
+                    &beacon.pub_key.to_string(),
+                    &beacon.ingest_time_unix_ms,
+                    &beacon.str_location,
+                    &beacon.latitude,
+                    &beacon.longitude,
+                    &(beacon.frequency as i64),
+                    &beacon.channel,
+                    &beacon.tx_power,
+                    &(beacon.witness_count as i32),
+                    &(beacon.selected_witness_count as i32),
+                    &serde_json::to_value(&beacon.source)?,
+                ],
+            )
+            .await?;
+        if inserted == 0 {
+            return Ok(false);
+        }
+
+        for witness in beacon.witnesses.iter() {
+            tx.execute(
+                "INSERT INTO witnesses (poc_id, pub_key, ingest_time_unix_ms, str_location,
+                     signal, snr, frequency, selected, distance)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                 ON CONFLICT (poc_id, pub_key) DO NOTHING",
+                &[
+                    &beacon.poc_id,
+                    &witness.pub_key.to_string(),
+                    &witness.ingest_time_unix_ms,
+                    &witness.str_location,
+                    &witness.signal,
+                    &witness.snr,
+                    &(witness.frequency as i64),
+                    &witness.selected,
+                    &witness.distance,
+                ],
+            )
+            .await?;
+
+            let edge = Edge::new(beacon, witness)?;
+            match edges.get_mut(&edge._key) {
+                Some(aggregate) => aggregate.add(edge),
+                None => {
+                    edges.insert(edge._key.clone(), EdgeAggregate::from(edge));
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Row of a hotspot seen by the pocs of a chunk
+#[derive(Debug, Clone)]
+struct HotspotRow {
+    key: String,
+    hotspot: HotspotFirstSeen,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    gain: i32,
+    elevation: i32,
+    // ingest time of the report the location, gain and elevation are from
+    seen_unix_ms: i64,
+    // index of the first poc of the chunk seeing the hotspot
+    poc: usize,
+}
+
+impl HotspotRow {
+    fn beaconer(poc: usize, beacon: &Beacon) -> Self {
+        Self {
+            key: beacon.pub_key.to_string(),
+            hotspot: HotspotFirstSeen {
+                pub_key: beacon.pub_key.clone(),
+                name: beacon.name.clone(),
+                str_location: beacon.str_location.clone(),
+                first_seen_unix_ms: beacon.ingest_time_unix_ms,
+                lineage_id: None,
+            },
+            latitude: beacon.latitude,
+            longitude: beacon.longitude,
+            gain: beacon.gain,
+            elevation: beacon.elevation,
+            seen_unix_ms: beacon.ingest_time_unix_ms,
+            poc,
+        }
+    }
+
+    fn witness(poc: usize, witness: &Witness) -> Self {
+        Self {
+            key: witness.pub_key.to_string(),
+            hotspot: HotspotFirstSeen {
+                pub_key: witness.pub_key.clone(),
+                name: witness.name.clone(),
+                str_location: witness.str_location.clone(),
+                first_seen_unix_ms: witness.ingest_time_unix_ms,
+                lineage_id: None,
+            },
+            latitude: witness.latitude,
+            longitude: witness.longitude,
+            gain: witness.gain,
+            elevation: witness.elevation,
+            seen_unix_ms: witness.ingest_time_unix_ms,
+            poc,
+        }
+    }
+
+    /// Merges another report of the same hotspot: keeps the earliest first
+    /// seen and the location, gain and elevation of the latest report
+    fn merge(&mut self, other: HotspotRow) {
+        let first_seen_unix_ms = self
+            .hotspot
+            .first_seen_unix_ms
+            .min(other.hotspot.first_seen_unix_ms);
+        if other.seen_unix_ms > self.seen_unix_ms {
+            *self = Self {
+                poc: self.poc,
+                ..other
+            };
+        }
+        self.hotspot.first_seen_unix_ms = first_seen_unix_ms;
+    }
+}
+
+/// Hotspot rows of the beaconers and witnesses of `beacons`, one per hotspot
+/// in pub_key order
+fn hotspot_rows(beacons: &[&Beacon]) -> Vec<HotspotRow> {
+    let mut rows: HashMap<String, HotspotRow> = HashMap::new();
+    for (poc, beacon) in beacons.iter().enumerate() {
+        let seen = std::iter::once(HotspotRow::beaconer(poc, beacon)).chain(
+            beacon
+                .witnesses
+                .iter()
+                .map(|witness| HotspotRow::witness(poc, witness)),
+        );
+        for row in seen {
+            match rows.get_mut(&row.key) {
+                Some(existing) => existing.merge(row),
+                None => {
+                    rows.insert(row.key.clone(), row);
+                }
+            }
+        }
+    }
+    let mut rows: Vec<HotspotRow> = rows.into_values().collect();
+    rows.sort_by(|a, b| a.key.cmp(&b.key));
+    rows
+}
+
+/// Inserts or updates the hotspot rows of a chunk in a single statement, in
+/// pub_key order so concurrently written chunks lock shared hotspots in the
+/// same order, returning the pub_keys of the hotspots seen for the first time
+async fn upsert_hotspots(tx: &Transaction<'_>, rows: &[HotspotRow]) -> Result<HashSet<String>> {
+    if rows.is_empty() {
+        return Ok(HashSet::new());
+    }
+    let keys: Vec<&str> = rows.iter().map(|row| row.key.as_str()).collect();
+    let names: Vec<&str> = rows.iter().map(|row| row.hotspot.name.as_str()).collect();
+    let str_locations: Vec<Option<&str>> = rows
+        .iter()
+        .map(|row| row.hotspot.str_location.as_deref())
+        .collect();
+    let latitudes: Vec<Option<f64>> = rows.iter().map(|row| row.latitude).collect();
+    let longitudes: Vec<Option<f64>> = rows.iter().map(|row| row.longitude).collect();
+    let gains: Vec<i32> = rows.iter().map(|row| row.gain).collect();
+    let elevations: Vec<i32> = rows.iter().map(|row| row.elevation).collect();
+    let first_seen: Vec<i64> = rows
+        .iter()
+        .map(|row| row.hotspot.first_seen_unix_ms)
+        .collect();
+    // NOTE: xmax is 0 for rows inserted (rather than updated) by the statement
+    let upserted = tx
+        .query(
+            "INSERT INTO hotspots (pub_key, name, str_location, latitude, longitude, gain,
+                 elevation, first_seen_unix_ms, last_updated_unix_ms)
+             SELECT pub_key, name, str_location, latitude, longitude, gain, elevation,
+                 first_seen_unix_ms, $9::BIGINT
+             FROM UNNEST($1::TEXT[], $2::TEXT[], $3::TEXT[], $4::DOUBLE PRECISION[],
+                 $5::DOUBLE PRECISION[], $6::INTEGER[], $7::INTEGER[], $8::BIGINT[])
+                 AS t (pub_key, name, str_location, latitude, longitude, gain, elevation,
+                     first_seen_unix_ms)
+             ORDER BY pub_key
+             ON CONFLICT (pub_key) DO UPDATE SET
+                 str_location = EXCLUDED.str_location,
+                 latitude = EXCLUDED.latitude,
+                 longitude = EXCLUDED.longitude,
+                 gain = EXCLUDED.gain,
+                 elevation = EXCLUDED.elevation,
+                 first_seen_unix_ms = LEAST(hotspots.first_seen_unix_ms, EXCLUDED.first_seen_unix_ms),
+                 last_updated_unix_ms = EXCLUDED.last_updated_unix_ms
+             RETURNING pub_key, (xmax = 0) AS inserted",
+            &[
+                &keys,
+                &names,
+                &str_locations,
+                &latitudes,
+                &longitudes,
+                &gains,
+                &elevations,
+                &first_seen,
+                &now_unix_ms(),
+            ],
+        )
+        .await?;
+    Ok(upserted
+        .iter()
+        .filter(|row| row.get::<_, bool>("inserted"))
+        .map(|row| row.get("pub_key"))
+        .collect())
+}
+
+/// Adds the aggregated edges of a chunk to the stored ones, in key order so
+/// concurrently written chunks lock shared edges in the same order
+async fn upsert_edges(tx: &Transaction<'_>, edges: HashMap<String, EdgeAggregate>) -> Result<()> {
+    let mut edges: Vec<EdgeAggregate> = edges.into_values().collect();
+    edges.sort_by(|a, b| a._key.cmp(&b._key));
+    for edge in edges {
+        tx.execute(
+            "INSERT INTO edges (key, beacon_pub_key, witness_pub_key, distance, count,
+                 band_mismatch_count, first_seen_unix_ms, last_seen_unix_ms)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+             ON CONFLICT (key) DO UPDATE SET
+                 beacon_pub_key = EXCLUDED.beacon_pub_key,
+                 witness_pub_key = EXCLUDED.witness_pub_key,
+                 distance = EXCLUDED.distance,
+                 count = edges.count + EXCLUDED.count,
+                 band_mismatch_count = edges.band_mismatch_count + EXCLUDED.band_mismatch_count,
+                 first_seen_unix_ms = LEAST(edges.first_seen_unix_ms, EXCLUDED.first_seen_unix_ms),
+                 last_seen_unix_ms = GREATEST(edges.last_seen_unix_ms, EXCLUDED.last_seen_unix_ms)",
+            &[
+                &edge._key,
+                &edge.beacon_pub_key,
+                &edge.witness_pub_key,
+                &edge.distance,
+                &(edge.count as i64),
+                &(edge.band_mismatch_count as i64),
+                &edge.first_seen_unix_ms,
+                &edge.last_seen_unix_ms,
+            ],
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl Sink for PostgresSink {
+    fn supports(&self, file_type: FileType) -> bool {
+        file_type == FileType::IotPoc
+    }
+
+    async fn init_file(&self, file: &FileInfo) -> Result<()> {
+        tracing::info!("init file: {:?}", file.key);
+        self.pool
+            .get()
+            .await?
+            .execute(
+                "INSERT INTO files (key, timestamp_unix_ms, size) VALUES ($1, $2, $3)
+                 ON CONFLICT (key) DO NOTHING",
+                &[&file.key, &unix_ms(file.timestamp), &(file.size as i64)],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn complete_file(&self, key: &str, _stats: &FileStats) -> Result<()> {
+        self.pool
+            .get()
+            .await?
+            .execute("UPDATE files SET done = TRUE WHERE key = $1", &[&key])
+            .await?;
+        Ok(())
+    }
+
+    async fn get_file_retries(&self, key: &str) -> Result<u8> {
+        let row = self
+            .pool
+            .get()
+            .await?
+            .query_opt("SELECT retries FROM files WHERE key = $1", &[&key])
+            .await?;
+        let retries: i16 = row.map(|row| row.get("retries")).unwrap_or_default();
+        Ok(u8::try_from(retries).unwrap_or(u8::MAX))
+    }
+
+    async fn increment_file_retry(&self, key: &str) -> Result<()> {
+        self.pool
+            .get()
+            .await?
+            .execute(
+                "UPDATE files SET retries = retries + 1 WHERE key = $1",
+                &[&key],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_done_file_keys(&self) -> Result<Vec<String>> {
+        let rows = self
+            .pool
+            .get()
+            .await?
+            .query("SELECT key FROM files WHERE done", &[])
+            .await?;
+        Ok(rows.iter().map(|row| row.get("key")).collect())
+    }
+
+    async fn get_checkpoint(&self, name: &str) -> Result<Option<Checkpoint>> {
+        let row = self
+            .pool
+            .get()
+            .await?
+            .query_opt(
+                "SELECT watermark_unix_ms, updated_unix_ms FROM checkpoints WHERE name = $1",
+                &[&name],
+            )
+            .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let watermark_unix_ms: i64 = row.get("watermark_unix_ms");
+        let Some(watermark) = Utc.timestamp_millis_opt(watermark_unix_ms).single() else {
+            bail!("invalid watermark {watermark_unix_ms} of checkpoint {name}");
+        };
+        Ok(Some(Checkpoint {
+            _key: name.to_string(),
+            watermark,
+            watermark_unix_ms,
+            updated_unix_ms: row.get("updated_unix_ms"),
+        }))
+    }
+
+    async fn set_checkpoint(&self, checkpoint: &Checkpoint) -> Result<()> {
+        self.pool
+            .get()
+            .await?
+            .execute(
+                "INSERT INTO checkpoints (name, watermark_unix_ms, updated_unix_ms)
+                 VALUES ($1, $2, $3)
+                 ON CONFLICT (name) DO UPDATE SET
+                     watermark_unix_ms = EXCLUDED.watermark_unix_ms,
+                     updated_unix_ms = EXCLUDED.updated_unix_ms",
+                &[
+                    &checkpoint._key,
+                    &checkpoint.watermark_unix_ms,
+                    &checkpoint.updated_unix_ms,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    fn transforms(&self) -> &PocTransforms {
+        &self.transforms
+    }
+
+    async fn write_chunk(
+        &self,
+        file_type: FileType,
+        bufs: Vec<BytesMut>,
+        source: &Source,
+    ) -> Result<Written> {
+        if !self.supports(file_type) {
+            bail!("the postgres sink does not support {file_type} files");
+        }
+        let (beacons, decode_errors) = decode_beacons_counted(bufs, source, &self.transforms);
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        let mut inserted = vec![];
+        let mut edges = HashMap::new();
+        for beacon in beacons.iter() {
+            if Self::write_poc(&tx, beacon, &mut edges).await? {
+                inserted.push(beacon);
+            } else {
+                tracing::debug!("ignored, already ingested");
+            }
+        }
+        let rows = hotspot_rows(&inserted);
+        let first_seen = upsert_hotspots(&tx, &rows).await?;
+        upsert_edges(&tx, edges).await?;
+        tx.commit().await?;
+
+        let mut new_hotspots = vec![vec![]; inserted.len()];
+        for row in rows {
+            if first_seen.contains(&row.key) {
+                new_hotspots[row.poc].push(row.hotspot);
+            }
+        }
+        let populated = inserted
+            .into_iter()
+            .zip(new_hotspots)
+            .map(|(beacon, new_hotspots)| {
+                Populated::new(beacon, new_hotspots, self.transforms.beacon_payloads)
+            })
+            .collect();
+        Ok(Written {
+            populated,
+            decode_errors,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{doctor, handler::sink::decode_beacons};
+    use helium_proto::{services::poc_lora::LoraPocV1, Message};
+    use std::str::FromStr;
+
+    /// The sample poc, with its beaconer and witness swapped if `swapped` and
+    /// its reports received `offset_ms` later
+    fn beacon(poc_id: u8, swapped: bool, offset_ms: u64) -> Beacon {
+        let raw = doctor::sample_poc().unwrap();
+        let mut poc = LoraPocV1::decode(raw.as_slice()).unwrap();
+        poc.poc_id = vec![poc_id; 32];
+        let beacon_report = poc.beacon_report.as_mut().unwrap();
+        let witness_report = &mut poc.selected_witnesses[0];
+        beacon_report.received_timestamp += offset_ms;
+        witness_report.received_timestamp += offset_ms;
+        if swapped {
+            std::mem::swap(
+                &mut beacon_report.report.as_mut().unwrap().pub_key,
+                &mut witness_report.report.as_mut().unwrap().pub_key,
+            );
+        }
+        let source = Source::new(
+            &FileInfo::from_str("iot_poc.1687888130980.gz").unwrap(),
+            "LoraPocV1",
+        );
+        decode_beacons(
+            vec![BytesMut::from(poc.encode_to_vec().as_slice())],
+            &source,
+            &PocTransforms::default(),
+        )
+        .pop()
+        .unwrap()
+    }
+
+    #[test]
+    fn hotspot_rows_dedupe_in_key_order() {
+        let first = beacon(1, false, 0);
+        let second = beacon(2, true, 60_000);
+        let rows = hotspot_rows(&[&first, &second]);
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].key < rows[1].key);
+
+        // first seen beaconing the first poc, last seen witnessing the second
+        let beaconer = rows
+            .iter()
+            .find(|row| row.key == first.pub_key.to_string())
+            .unwrap();
+        let witness = &second.witnesses[0];
+        assert_eq!(beaconer.poc, 0);
+        assert_eq!(
+            beaconer.hotspot.first_seen_unix_ms,
+            first.ingest_time_unix_ms
+        );
+        assert_eq!(beaconer.seen_unix_ms, witness.ingest_time_unix_ms);
+        assert_eq!(beaconer.hotspot.str_location, witness.str_location);
+        assert_eq!(beaconer.latitude, witness.latitude);
+    }
+
+    #[test]
+    fn hotspot_rows_keep_earliest_first_seen() {
+        // the pocs of a chunk needn't be in time order
+        let late = beacon(1, false, 60_000);
+        let early = beacon(2, false, 0);
+        let rows = hotspot_rows(&[&late, &early]);
+        assert_eq!(rows.len(), 2);
+        for row in rows {
+            assert_eq!(row.poc, 0);
+            if row.key == early.pub_key.to_string() {
+                assert_eq!(row.hotspot.first_seen_unix_ms, early.ingest_time_unix_ms);
+                assert_eq!(row.seen_unix_ms, late.ingest_time_unix_ms);
+            } else {
+                let (early, late) = (&early.witnesses[0], &late.witnesses[0]);
+                assert_eq!(row.hotspot.first_seen_unix_ms, early.ingest_time_unix_ms);
+                assert_eq!(row.seen_unix_ms, late.ingest_time_unix_ms);
+            }
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use tokio::{
+    sync::Mutex,
+    time::{sleep_until, Duration, Instant},
+};
+
+/// Spaces out permits to at most `per_sec` per second, shared by all callers
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(per_sec: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / per_sec.max(1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait for the next free slot
+    pub async fn acquire(&self) {
+        let at = {
+            let mut next = self.next.lock().await;
+            let at = (*next).max(Instant::now());
+            *next = at + self.interval;
+            at
+        };
+        sleep_until(at).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn rate_limiter_spaces_out_permits() {
+        let limiter = RateLimiter::new(10);
+        let start = Instant::now();
+        for _ in 0..5 {
+            limiter.acquire().await;
+        }
+        // the first permit is immediate, then one every 100ms
+        assert_eq!(start.elapsed(), Duration::from_millis(400));
+    }
+}
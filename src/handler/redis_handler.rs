@@ -1,9 +1,57 @@
-use crate::settings::RedisSettings;
+use crate::{
+    document::hotspot::HotspotFirstSeen,
+    handler::{
+        arangodb::Populated,
+        notifier::{poc_payload, Notifier},
+        rate_limiter::RateLimiter,
+    },
+    settings::{BackpressureSettings, PocPayload, RedisSettings},
+};
 use anyhow::{Error, Result};
-use deadpool_redis::{redis::AsyncCommands, Config, Pool, Runtime};
+use deadpool_redis::{
+    redis::{self, streams::StreamMaxlen, AsyncCommands, FromRedisValue},
+    Config, Pool, Runtime,
+};
+use h3o::{CellIndex, LatLng};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+use tokio::time::{sleep, Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+/// Field of poc_id stream entries holding the poc's lineage id, next to the
+/// `<poc_id>: done` field
+pub const LINEAGE_ID_FIELD: &str = "lineage_id";
+
+/// Member of a GEO set: longitude, latitude and name
+pub type GeoMember = (f64, f64, String);
 
 pub struct RedisHandler {
     pool: Pool,
+    backpressure: Option<BackpressureSettings>,
+    poc_id_maxlen: Option<u64>,
+    event_maxlen: Option<u64>,
+    poc_id_limiter: Option<RateLimiter>,
+    hotspot_geo_key: Option<String>,
+    payload: PocPayload,
+    // poc stream name, including the key prefix
+    poc_stream: String,
+    poc_id_field: Option<String>,
+    key_prefix: String,
+    // poc stream entries sent per pipeline
+    pipeline_size: usize,
+    published: AtomicU64,
+    trimmed: AtomicU64,
+}
+
+/// Publish and trim counts of the poc_id stream since start
+#[derive(Debug, Serialize, Clone, Copy, Default)]
+pub struct StreamMetrics {
+    pub published: u64,
+    pub trimmed: u64,
 }
 
 impl RedisHandler {
@@ -13,13 +61,325 @@ impl RedisHandler {
             .max_size(settings.pool_size)
             .runtime(Runtime::Tokio1)
             .build()?;
-        Ok(Self { pool })
+        Ok(Self {
+            pool,
+            backpressure: settings.backpressure.clone(),
+            poc_id_maxlen: settings.poc_id_maxlen,
+            event_maxlen: settings.event_maxlen,
+            poc_id_limiter: settings.poc_id_rate.map(RateLimiter::new),
+            hotspot_geo_key: settings.hotspot_geo_key.clone(),
+            payload: settings.payload,
+            poc_stream: format!("{}{}", settings.key_prefix, settings.poc_stream),
+            poc_id_field: settings.poc_id_field.clone(),
+            key_prefix: settings.key_prefix.clone(),
+            pipeline_size: settings.pipeline_size.max(1),
+            published: AtomicU64::new(0),
+            trimmed: AtomicU64::new(0),
+        })
+    }
+
+    /// Name of the stream receiving the stored pocs, including the key prefix
+    pub fn poc_stream(&self) -> &str {
+        &self.poc_stream
+    }
+
+    /// Fields of the poc stream entry of a stored poc: its poc_id (with the
+    /// lineage id of the message it was decoded from), or its json payload as
+    /// a `json` field
+    fn poc_entry(&self, populated: &Populated) -> Result<Vec<(String, String)>> {
+        let poc_id = populated.poc_id.clone();
+        if self.payload != PocPayload::PocId {
+            return Ok(vec![(
+                "json".to_string(),
+                poc_payload(self.payload, populated)?,
+            )]);
+        }
+        let mut fields = match &self.poc_id_field {
+            Some(field) => vec![(field.clone(), poc_id)],
+            None => vec![(poc_id, "done".to_string())],
+        };
+        if let Some(lineage_id) = &populated.lineage_id {
+            fields.push((LINEAGE_ID_FIELD.to_string(), lineage_id.clone()));
+        }
+        Ok(fields)
+    }
+
+    /// Publish stored pocs with one pipelined round trip, waiting for the
+    /// configured publish rate. Returns the number of published entries.
+    pub async fn publish_poc_batch(
+        &self,
+        stream_name: &str,
+        populated: &[Populated],
+    ) -> Result<u64> {
+        let mut pipe = redis::pipe();
+        let mut entries = 0;
+        for populated in populated {
+            let fields = match self.poc_entry(populated) {
+                Ok(fields) => fields,
+                Err(e) => {
+                    tracing::error!("failed to encode poc_id {:?}: {:?}", populated.poc_id, e);
+                    continue;
+                }
+            };
+            if let Some(limiter) = &self.poc_id_limiter {
+                limiter.acquire().await;
+            }
+            tracing::debug!("storing poc_id: {:?} in redis", populated.poc_id);
+            pipe.xadd(stream_name, "*", &fields).ignore();
+            entries += 1;
+        }
+        if entries == 0 {
+            return Ok(0);
+        }
+        let mut conn = self.pool.get().await?;
+        pipe.query_async::<_, ()>(&mut conn).await?;
+        self.published.fetch_add(entries, Ordering::Relaxed);
+        Ok(entries)
+    }
+
+    /// Trim the poc_id stream to about the configured max length (MAXLEN ~),
+    /// returns the number of removed entries
+    pub async fn trim_poc_ids(&self, stream_name: &str) -> Result<u64> {
+        let Some(maxlen) = self.poc_id_maxlen else {
+            return Ok(0);
+        };
+        let mut conn = self.pool.get().await?;
+        let trimmed: u64 = redis::cmd("XTRIM")
+            .arg(stream_name)
+            .arg("MAXLEN")
+            .arg("~")
+            .arg(maxlen)
+            .query_async(&mut conn)
+            .await?;
+        self.trimmed.fetch_add(trimmed, Ordering::Relaxed);
+        Ok(trimmed)
+    }
+
+    pub fn stream_metrics(&self) -> StreamMetrics {
+        StreamMetrics {
+            published: self.published.load(Ordering::Relaxed),
+            trimmed: self.trimmed.load(Ordering::Relaxed),
+        }
+    }
+
+    pub async fn ping(&self) -> Result<()> {
+        let mut conn = self.pool.get().await?;
+        redis::cmd("PING")
+            .query_async::<_, String>(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Add (or move) members of the GEO set `key`, returns the number of new members
+    pub async fn geo_add(&self, key: &str, members: &[GeoMember]) -> Result<u64> {
+        if members.is_empty() {
+            return Ok(0);
+        }
+        let mut cmd = redis::cmd("GEOADD");
+        cmd.arg(key);
+        for (longitude, latitude, member) in members {
+            cmd.arg(longitude).arg(latitude).arg(member);
+        }
+        let mut conn = self.pool.get().await?;
+        Ok(cmd.query_async(&mut conn).await?)
+    }
+
+    /// Atomically replace `key` with `from`, or delete it if `from` doesn't exist
+    pub async fn replace_key(&self, from: &str, key: &str) -> Result<()> {
+        let mut conn = self.pool.get().await?;
+        let exists: bool = conn.exists(from).await?;
+        if exists {
+            conn.rename::<_, _, ()>(from, key).await?;
+        } else {
+            conn.del::<_, ()>(key).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn del(&self, key: &str) -> Result<()> {
+        let mut conn = self.pool.get().await?;
+        conn.del::<_, ()>(key).await?;
+        Ok(())
     }
 
-    pub async fn xadd(&self, stream_name: &str, poc_id: &str) -> Result<String> {
+    pub async fn xadd_json(&self, stream_name: &str, payload: &str) -> Result<String> {
         let mut conn = self.pool.get().await?;
-        conn.xadd(stream_name, "*", &[(&poc_id, "done".to_string())])
+        conn.xadd(stream_name, "*", &[("json", payload)])
             .await
             .map_err(Error::from)
     }
+
+    /// Number of entries in `stream_name` not yet delivered to `group`.
+    ///
+    /// Uses the `lag` reported by redis >= 7, None if the group doesn't exist or
+    /// its lag is unknown (older versions, or entries deleted from the stream).
+    pub async fn consumer_lag(&self, stream_name: &str, group: &str) -> Result<Option<u64>> {
+        let mut conn = self.pool.get().await?;
+        let groups: Vec<HashMap<String, redis::Value>> = redis::cmd("XINFO")
+            .arg("GROUPS")
+            .arg(stream_name)
+            .query_async(&mut conn)
+            .await?;
+
+        let Some(info) = groups.into_iter().find(|info| {
+            info.get("name")
+                .and_then(|name| String::from_redis_value(name).ok())
+                .map_or(false, |name| name == group)
+        }) else {
+            return Ok(None);
+        };
+
+        // NOTE: the pending count isn't a lag, undelivered entries aren't pending
+        Ok(info
+            .get("lag")
+            .and_then(|lag| Option::<u64>::from_redis_value(lag).ok())
+            .flatten())
+    }
+
+    /// Wait while the configured consumer group lags too far behind `stream_name`,
+    /// so stream trimming doesn't drop entries the consumer has not read yet.
+    /// Errors checking the lag, or an unknown lag, are logged and do not block
+    /// ingestion. Fails once waiting longer than `max_wait_secs`, on `shutdown`
+    /// or when `paused` (maintenance mode), so the file of the chunk is retried.
+    pub async fn wait_for_consumers(
+        &self,
+        stream_name: &str,
+        shutdown: &CancellationToken,
+        paused: &AtomicBool,
+    ) -> Result<()> {
+        let Some(backpressure) = &self.backpressure else {
+            return Ok(());
+        };
+        let start = Instant::now();
+        let max_wait = Duration::from_secs(backpressure.max_wait_secs);
+        loop {
+            match self.consumer_lag(stream_name, &backpressure.group).await {
+                Ok(Some(lag)) if lag > backpressure.max_lag => {
+                    if start.elapsed() >= max_wait {
+                        anyhow::bail!(
+                            "consumer group {:?} lag {} > {} for {}s",
+                            backpressure.group,
+                            lag,
+                            backpressure.max_lag,
+                            backpressure.max_wait_secs
+                        );
+                    }
+                    tracing::warn!(
+                        "consumer group {:?} lag {} > {}, pausing ingestion",
+                        backpressure.group,
+                        lag,
+                        backpressure.max_lag
+                    );
+                }
+                Ok(Some(_)) => return Ok(()),
+                Ok(None) => {
+                    tracing::warn!(
+                        "consumer group {:?} lag unknown, not pausing",
+                        backpressure.group
+                    );
+                    return Ok(());
+                }
+                Err(err) => {
+                    tracing::error!("error checking consumer lag: {:?}", err);
+                    return Ok(());
+                }
+            }
+            tokio::select! {
+                _ = shutdown.cancelled() => anyhow::bail!("shutdown while waiting for consumers"),
+                _ = sleep(Duration::from_millis(backpressure.pause_ms)) => {}
+            }
+            if paused.load(Ordering::Relaxed) {
+                anyhow::bail!("maintenance mode while waiting for consumers");
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for RedisHandler {
+    /// Adds the poc_ids to the poc_id stream in pipelines of `pipeline_size`
+    /// entries, then trims it as configured
+    async fn publish_pocs(&self, populated: &[Populated]) -> Result<()> {
+        for batch in populated.chunks(self.pipeline_size) {
+            if let Err(e) = self.publish_poc_batch(&self.poc_stream, batch).await {
+                tracing::error!(
+                    "failed to store {} poc_ids in redis, error: {:?}",
+                    batch.len(),
+                    e
+                );
+            }
+        }
+        match self.trim_poc_ids(&self.poc_stream).await {
+            Ok(0) => {}
+            Ok(trimmed) => tracing::debug!("trimmed {} poc_ids", trimmed),
+            Err(e) => tracing::error!("failed to trim poc_id stream: {:?}", e),
+        }
+        if let Some(key) = &self.hotspot_geo_key {
+            let members: Vec<GeoMember> = populated
+                .iter()
+                .flat_map(|populated| &populated.new_hotspots)
+                .filter_map(geo_member)
+                .collect();
+            if let Err(e) = self.geo_add(key, &members).await {
+                tracing::error!("failed to add hotspot locations to {:?}: {:?}", key, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the event to its (prefixed) stream, capped at about `event_maxlen` entries
+    async fn publish_event(&self, stream: &str, payload: &str) -> Result<()> {
+        let stream = format!("{}{}", self.key_prefix, stream);
+        let stream = stream.as_str();
+        let Some(maxlen) = self.event_maxlen else {
+            return self.xadd_json(stream, payload).await.map(|_| ());
+        };
+        let mut conn = self.pool.get().await?;
+        conn.xadd_maxlen::<_, _, _, _, ()>(
+            stream,
+            StreamMaxlen::Approx(maxlen as usize),
+            "*",
+            &[("json", payload)],
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+/// GEO set member of a hotspot at the center of its location cell, None if it
+/// has no (or only a minimized) location
+fn geo_member(hotspot: &HotspotFirstSeen) -> Option<GeoMember> {
+    let cell = CellIndex::from_str(hotspot.str_location.as_deref()?).ok()?;
+    let latlng = LatLng::from(cell);
+    Some((latlng.lng(), latlng.lat(), hotspot.pub_key.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use helium_crypto::PublicKeyBinary;
+
+    #[test]
+    fn hotspot_geo_members() {
+        let cell = LatLng::new(37.769377, -122.388903)
+            .unwrap()
+            .to_cell(h3o::Resolution::Twelve);
+        let mut hotspot = HotspotFirstSeen {
+            pub_key: PublicKeyBinary::from(vec![1; 33]),
+            name: "sample".to_string(),
+            str_location: Some(cell.to_string()),
+            first_seen_unix_ms: 0,
+            lineage_id: None,
+        };
+        let (longitude, latitude, member) = geo_member(&hotspot).unwrap();
+        assert!((longitude + 122.388903).abs() < 0.001);
+        assert!((latitude - 37.769377).abs() < 0.001);
+        assert_eq!(member, hotspot.pub_key.to_string());
+
+        // hashed by the privacy settings
+        hotspot.str_location = Some("9f86d081884c7d65".to_string());
+        assert!(geo_member(&hotspot).is_none());
+        hotspot.str_location = None;
+        assert!(geo_member(&hotspot).is_none());
+    }
 }
@@ -0,0 +1,180 @@
+use crate::settings::RetrySettings;
+use arangors::ClientError;
+use rand::Rng;
+use std::{future::Future, time::Duration};
+
+/// HTTP statuses of transient server side failures
+const RETRIABLE_STATUSES: &[u16] = &[408, 502, 503, 504];
+/// Arango error numbers of transient failures: lock timeout, cluster timeout,
+/// cluster backend unavailable, leadership challenge ongoing, not leader
+const RETRIABLE_ERROR_NUMS: &[u16] = &[18, 1457, 1478, 1495, 1496];
+/// Arango error numbers of failures which aborted a request before it was
+/// applied: lock timeout, cluster backend unavailable, leadership challenge
+/// ongoing, not leader
+const NOT_APPLIED_ERROR_NUMS: &[u16] = &[18, 1478, 1495, 1496];
+
+/// Whether a failed request may succeed when repeated. Transport errors
+/// (connection refused or reset, timeouts) are, errors returned by the server
+/// only if they are known to be transient.
+pub fn is_retriable(err: &ClientError) -> bool {
+    match err {
+        ClientError::HttpClient(_) => true,
+        ClientError::Arango(ae) => {
+            RETRIABLE_STATUSES.contains(&ae.code())
+                || RETRIABLE_ERROR_NUMS.contains(&ae.error_num())
+        }
+        _ => false,
+    }
+}
+
+/// Whether a failed non-idempotent request (e.g. incrementing counters) may be
+/// repeated: only if the server reported that it wasn't applied. Transport
+/// errors and timeouts are ambiguous, the server may have committed it.
+pub fn is_not_applied(err: &ClientError) -> bool {
+    match err {
+        ClientError::Arango(ae) => NOT_APPLIED_ERROR_NUMS.contains(&ae.error_num()),
+        _ => false,
+    }
+}
+
+/// Whether a request failed because the connection is broken: the server can't
+/// be reached or rejects the credentials (e.g. an expired JWT after a restart)
+pub fn is_connection_error(err: &ClientError) -> bool {
+    matches!(err, ClientError::HttpClient(_)) || is_unauthorized(err)
+}
+
+/// Whether the server rejected the credentials of a request, which therefore
+/// wasn't applied
+pub fn is_unauthorized(err: &ClientError) -> bool {
+    matches!(err, ClientError::Arango(ae) if ae.code() == 401)
+}
+
+/// Exponential backoff with jitter for transient Arango errors
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_settings(settings: &RetrySettings) -> Self {
+        Self {
+            max_attempts: settings.max_attempts.max(1),
+            base_delay: Duration::from_millis(settings.base_delay_ms),
+            max_delay: Duration::from_millis(settings.max_delay_ms),
+        }
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Delay before retrying after the (1 based) failed `attempt`: the base delay
+    /// doubled per attempt, capped at the max delay, of which a random half is
+    /// dropped so concurrent writers don't retry in lockstep
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let delay = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+
+    /// Run `request` until it succeeds, fails with a permanent error or the
+    /// attempts are exhausted, returning the last result
+    pub async fn run<T, F, Fut>(&self, what: &str, request: F) -> Result<T, ClientError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, ClientError>>,
+    {
+        self.run_if(what, is_retriable, request).await
+    }
+
+    /// Like `run`, but only repeating `request` after failures `retriable`
+    /// accepts, e.g. `is_not_applied` for non-idempotent requests
+    pub async fn run_if<T, F, Fut>(
+        &self,
+        what: &str,
+        retriable: fn(&ClientError) -> bool,
+        mut request: F,
+    ) -> Result<T, ClientError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, ClientError>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match request().await {
+                Err(err) if attempt < self.max_attempts && retriable(&err) => {
+                    let delay = self.backoff(attempt);
+                    tracing::warn!(
+                        "{} failed (attempt {}/{}), retrying in {:?}: {:?}",
+                        what,
+                        attempt,
+                        self.max_attempts,
+                        delay,
+                        err
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_with_jitter_up_to_max() {
+        let policy = RetryPolicy::from_settings(&RetrySettings {
+            max_attempts: 5,
+            base_delay_ms: 100,
+            max_delay_ms: 1_000,
+        });
+        let between = |attempt, min, max| {
+            let delay = policy.backoff(attempt);
+            assert!(
+                delay >= Duration::from_millis(min) && delay <= Duration::from_millis(max),
+                "attempt {attempt}: {delay:?}"
+            );
+        };
+        between(1, 50, 100);
+        between(2, 100, 200);
+        between(3, 200, 400);
+        between(5, 500, 1_000);
+        between(40, 500, 1_000);
+    }
+
+    #[test]
+    fn transport_errors_are_retriable() {
+        assert!(is_retriable(&ClientError::HttpClient(
+            "connection refused".to_string()
+        )));
+        assert!(!is_retriable(&ClientError::InvalidServer(
+            "nginx".to_string()
+        )));
+    }
+
+    #[test]
+    fn transport_errors_may_have_been_applied() {
+        assert!(!is_not_applied(&ClientError::HttpClient(
+            "operation timed out".to_string()
+        )));
+        assert!(!is_not_applied(&ClientError::InvalidServer(
+            "nginx".to_string()
+        )));
+    }
+
+    #[test]
+    fn unreachable_server_is_connection_error() {
+        assert!(is_connection_error(&ClientError::HttpClient(
+            "connection refused".to_string()
+        )));
+        assert!(!is_connection_error(&ClientError::InvalidServer(
+            "nginx".to_string()
+        )));
+    }
+}
@@ -0,0 +1,150 @@
+use crate::{document::edge::EdgeAggregate, settings::ShadowVerifySettings};
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Max samples verified concurrently, see `ShadowVerifier::try_start`
+pub const MAX_IN_FLIGHT: usize = 4;
+
+/// Counts of documents (beacons and witness edges) read back by shadow
+/// verification since start
+#[derive(Debug, Serialize, Clone, Copy, Default)]
+pub struct ShadowMetrics {
+    pub checked: u64,
+    pub mismatches: u64,
+}
+
+/// Samples written pocs whose documents are read back and compared with the
+/// in-memory ones, to catch serialization or upsert bugs early
+#[derive(Debug)]
+pub struct ShadowVerifier {
+    sample_rate: f64,
+    checked: AtomicU64,
+    mismatches: AtomicU64,
+    // bounds the samples verified in the background
+    in_flight: Arc<Semaphore>,
+}
+
+impl ShadowVerifier {
+    pub fn new(settings: &ShadowVerifySettings) -> Self {
+        Self {
+            sample_rate: settings.sample_rate,
+            checked: AtomicU64::new(0),
+            mismatches: AtomicU64::new(0),
+            in_flight: Arc::new(Semaphore::new(MAX_IN_FLIGHT)),
+        }
+    }
+
+    /// Whether to verify the next poc
+    pub fn sample(&self) -> bool {
+        rand::random::<f64>() < self.sample_rate
+    }
+
+    /// Permit to verify a sample, held until it is verified, None while
+    /// `MAX_IN_FLIGHT` samples are
+    pub fn try_start(&self) -> Option<OwnedSemaphorePermit> {
+        self.in_flight.clone().try_acquire_owned().ok()
+    }
+
+    pub fn record(&self, mismatched: bool) {
+        self.checked.fetch_add(1, Ordering::Relaxed);
+        if mismatched {
+            self.mismatches.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn metrics(&self) -> ShadowMetrics {
+        ShadowMetrics {
+            checked: self.checked.load(Ordering::Relaxed),
+            mismatches: self.mismatches.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Fields of the written document `expected` which are missing or differ in
+/// the `stored` one, which may have additional (e.g. `_rev`) fields
+pub fn mismatched_fields(expected: &Value, stored: &Value) -> Vec<String> {
+    let Some(expected) = expected.as_object() else {
+        return vec![];
+    };
+    expected
+        .iter()
+        .filter(|(field, value)| !value.is_null() && stored.get(field.as_str()) != Some(value))
+        .map(|(field, _)| field.clone())
+        .collect()
+}
+
+/// Fields of a stored witness edge inconsistent with the aggregate written to
+/// it. Edges are shared by pocs of other chunks, so counts may only be larger
+/// and the seen times only wider.
+pub fn edge_mismatches(expected: &EdgeAggregate, stored: &Value) -> Vec<String> {
+    let int = |field: &str| stored[field].as_i64();
+    let mut fields = vec![];
+    if stored["distance"].as_f64() != Some(expected.distance) {
+        fields.push("distance".to_string());
+    }
+    if stored["count"]
+        .as_u64()
+        .map_or(true, |c| c < expected.count)
+    {
+        fields.push("count".to_string());
+    }
+    if int("first_seen_unix_ms").map_or(true, |ts| ts > expected.first_seen_unix_ms) {
+        fields.push("first_seen_unix_ms".to_string());
+    }
+    if int("last_seen_unix_ms").map_or(true, |ts| ts < expected.last_seen_unix_ms) {
+        fields.push("last_seen_unix_ms".to_string());
+    }
+    fields
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    #[test]
+    fn beacon_fields() {
+        let expected =
+            json!({ "_key": "a", "snr": 1.5, "geo": null, "source": { "file_key": "f" } });
+        let stored = json!({ "_key": "a", "_rev": "1", "snr": 1.5, "source": { "file_key": "f" } });
+        assert!(mismatched_fields(&expected, &stored).is_empty());
+
+        let stored = json!({ "_key": "a", "snr": 2.0 });
+        assert_eq!(mismatched_fields(&expected, &stored), vec!["snr", "source"]);
+    }
+
+    #[test]
+    fn edge_fields() {
+        let expected = EdgeAggregate {
+            _key: "a-b".to_string(),
+            beacon_pub_key: "a".to_string(),
+            witness_pub_key: "b".to_string(),
+            beacon_hotspot_key: "a".to_string(),
+            witness_hotspot_key: "b".to_string(),
+            distance: 1.5,
+            count: 2,
+            snr_hist: HashMap::new(),
+            signal_hist: HashMap::new(),
+            ingest_latency_hist: HashMap::new(),
+            first_seen_unix_ms: 100,
+            last_seen_unix_ms: 200,
+            beacon_regions: vec![],
+            band_mismatch_count: 0,
+            windows: Default::default(),
+        };
+        let stored = json!({ "distance": 1.5, "count": 5, "first_seen_unix_ms": 50, "last_seen_unix_ms": 200 });
+        assert!(edge_mismatches(&expected, &stored).is_empty());
+
+        let stored = json!({ "distance": 1.5, "count": 1, "first_seen_unix_ms": 150 });
+        assert_eq!(
+            edge_mismatches(&expected, &stored),
+            vec!["count", "first_seen_unix_ms", "last_seen_unix_ms"]
+        );
+    }
+}
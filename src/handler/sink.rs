@@ -0,0 +1,330 @@
+use crate::{
+    document::{
+        checkpoint::Checkpoint, iot_poc_file::FileStats, loc_data::CellResolutions, source::Source,
+        Beacon,
+    },
+    handler::{arangodb::Populated, filter::PubKeyFilter},
+    settings::{self, ArangoDBSettings, OverflowStrategy, PrivacySettings},
+};
+use anyhow::Result;
+use bytes::BytesMut;
+use chrono::{DateTime, Utc};
+use file_store::{iot_valid_poc::IotPoc, FileInfo, FileType};
+use helium_proto::{services::poc_lora::LoraPocV1, Message};
+use std::sync::Arc;
+
+/// Storage of the ingest pipeline: the documents decoded from the ingest files
+/// plus the bookkeeping of which files are done and where ingestion resumes.
+/// Implemented by `DB` (ArangoDB), `PostgresSink` and `ClickhouseSink`, see
+/// `Settings::sink`.
+#[async_trait::async_trait]
+pub trait Sink: Send + Sync {
+    /// Whether files of `file_type` can be written, default: all
+    fn supports(&self, _file_type: FileType) -> bool {
+        true
+    }
+    /// Record `file` as being processed
+    async fn init_file(&self, file: &FileInfo) -> Result<()>;
+    /// Record the file keyed `key` as done, with its `stats` where the sink
+    /// keeps them (ArangoDB)
+    async fn complete_file(&self, key: &str, stats: &FileStats) -> Result<()>;
+    /// Number of failed attempts of the file keyed `key`
+    async fn get_file_retries(&self, key: &str) -> Result<u8>;
+    async fn increment_file_retry(&self, key: &str) -> Result<()>;
+    async fn get_done_file_keys(&self) -> Result<Vec<String>>;
+    /// Refresh the heartbeat of the file keyed `key` while it is processed,
+    /// default: not tracked
+    async fn heartbeat_file(&self, _key: &str) -> Result<()> {
+        Ok(())
+    }
+    /// Reset files whose processing heartbeat is older than `stale_before` back
+    /// to pending, returning their keys, default: not tracked
+    async fn reset_stale_files(&self, _stale_before: DateTime<Utc>) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+    async fn get_checkpoint(&self, name: &str) -> Result<Option<Checkpoint>>;
+    async fn set_checkpoint(&self, checkpoint: &Checkpoint) -> Result<()>;
+    /// Transforms applied to the decoded pocs before they are written
+    fn transforms(&self) -> &PocTransforms;
+    /// Decode and write a chunk of messages of `file_type`, the first of which
+    /// is described by `source`, returning the stored pocs
+    async fn write_chunk(
+        &self,
+        file_type: FileType,
+        bufs: Vec<BytesMut>,
+        source: &Source,
+    ) -> Result<Written>;
+    /// Decode a chunk like `write_chunk` but only log what would be written,
+    /// see `Settings::dry_run`, default: the decoded beacons of iot_poc chunks
+    async fn dry_run_chunk(
+        &self,
+        file_type: FileType,
+        bufs: Vec<BytesMut>,
+        source: &Source,
+    ) -> Result<Written> {
+        if file_type != FileType::IotPoc {
+            tracing::info!(
+                "dry run: would write {} {} messages of {}",
+                bufs.len(),
+                file_type,
+                source.file_key
+            );
+            return Ok(Written::default());
+        }
+        let (beacons, decode_errors) = decode_beacons_counted(bufs, source, self.transforms());
+        tracing::info!(
+            "dry run: would write {} beacons with {} witnesses of {}",
+            beacons.len(),
+            beacons.iter().map(|b| b.witnesses.len()).sum::<usize>(),
+            source.file_key
+        );
+        Ok(Written {
+            populated: beacons
+                .iter()
+                .map(|beacon| Populated::new(beacon, vec![], self.transforms().beacon_payloads))
+                .collect(),
+            decode_errors,
+        })
+    }
+}
+
+/// Pocs stored from a chunk and the number of its messages skipped because
+/// they failed to decode (or convert)
+#[derive(Debug, Default)]
+pub struct Written {
+    pub populated: Vec<Populated>,
+    pub decode_errors: usize,
+}
+
+/// Sink additionally writing chunks to a mirror, e.g. ClickHouse next to
+/// ArangoDB. The bookkeeping is only kept by the primary sink, so a chunk
+/// failing on the mirror fails its file, which is then retried on both.
+pub struct Mirrored {
+    primary: Arc<dyn Sink>,
+    mirror: Arc<dyn Sink>,
+}
+
+impl Mirrored {
+    pub fn new(primary: Arc<dyn Sink>, mirror: Arc<dyn Sink>) -> Self {
+        Self { primary, mirror }
+    }
+}
+
+#[async_trait::async_trait]
+impl Sink for Mirrored {
+    fn supports(&self, file_type: FileType) -> bool {
+        self.primary.supports(file_type)
+    }
+
+    async fn init_file(&self, file: &FileInfo) -> Result<()> {
+        self.primary.init_file(file).await
+    }
+
+    async fn complete_file(&self, key: &str, stats: &FileStats) -> Result<()> {
+        self.primary.complete_file(key, stats).await
+    }
+
+    async fn get_file_retries(&self, key: &str) -> Result<u8> {
+        self.primary.get_file_retries(key).await
+    }
+
+    async fn increment_file_retry(&self, key: &str) -> Result<()> {
+        self.primary.increment_file_retry(key).await
+    }
+
+    async fn get_done_file_keys(&self) -> Result<Vec<String>> {
+        self.primary.get_done_file_keys().await
+    }
+
+    async fn heartbeat_file(&self, key: &str) -> Result<()> {
+        self.primary.heartbeat_file(key).await
+    }
+
+    async fn reset_stale_files(&self, stale_before: DateTime<Utc>) -> Result<Vec<String>> {
+        self.primary.reset_stale_files(stale_before).await
+    }
+
+    async fn get_checkpoint(&self, name: &str) -> Result<Option<Checkpoint>> {
+        self.primary.get_checkpoint(name).await
+    }
+
+    async fn set_checkpoint(&self, checkpoint: &Checkpoint) -> Result<()> {
+        self.primary.set_checkpoint(checkpoint).await
+    }
+
+    fn transforms(&self) -> &PocTransforms {
+        self.primary.transforms()
+    }
+
+    /// Writes the chunk to the primary sink, then to the mirror if it supports
+    /// the file type
+    async fn write_chunk(
+        &self,
+        file_type: FileType,
+        bufs: Vec<BytesMut>,
+        source: &Source,
+    ) -> Result<Written> {
+        if !self.mirror.supports(file_type) {
+            return self.primary.write_chunk(file_type, bufs, source).await;
+        }
+        let written = self
+            .primary
+            .write_chunk(file_type, bufs.clone(), source)
+            .await?;
+        self.mirror.write_chunk(file_type, bufs, source).await?;
+        Ok(written)
+    }
+
+    async fn dry_run_chunk(
+        &self,
+        file_type: FileType,
+        bufs: Vec<BytesMut>,
+        source: &Source,
+    ) -> Result<Written> {
+        self.primary.dry_run_chunk(file_type, bufs, source).await
+    }
+}
+
+/// Transforms applied to every decoded poc before a sink stores it, so each
+/// sink stores the same beacons: the pub_key filter, the location privacy and
+/// the document size guard, all configured in `[arangodb]`, and the resolutions
+/// of the derived cells
+#[derive(Debug, Clone)]
+pub struct PocTransforms {
+    filter: Option<PubKeyFilter>,
+    privacy: Option<PrivacySettings>,
+    max_document_size: usize,
+    overflow_strategy: OverflowStrategy,
+    /// Keep the beacon json of stored pocs for the notifiers publishing it, see
+    /// `Populated::beacon` and `Settings::publishes_beacons`
+    pub beacon_payloads: bool,
+    /// Resolutions of the cells derived from the poc locations, see
+    /// `Settings::parent_resolution` and `Settings::resolutions`
+    pub resolutions: CellResolutions,
+}
+
+impl PocTransforms {
+    pub fn from_settings(settings: &ArangoDBSettings) -> Result<Self> {
+        Ok(Self {
+            filter: settings
+                .filter
+                .as_ref()
+                .map(PubKeyFilter::from_settings)
+                .transpose()?,
+            privacy: settings.privacy.clone(),
+            max_document_size: settings.max_document_size,
+            overflow_strategy: settings.overflow_strategy,
+            beacon_payloads: false,
+            resolutions: CellResolutions::default(),
+        })
+    }
+
+    pub fn with_beacon_payloads(mut self, beacon_payloads: bool) -> Self {
+        self.beacon_payloads = beacon_payloads;
+        self
+    }
+
+    pub fn with_resolutions(mut self, resolutions: CellResolutions) -> Self {
+        self.resolutions = resolutions;
+        self
+    }
+
+    /// Beacon of `iot_poc`, with its cells at the configured resolutions
+    pub fn beacon(&self, iot_poc: &IotPoc) -> Result<Beacon> {
+        Beacon::from_poc(iot_poc, &self.resolutions)
+    }
+
+    /// Applies the filter to `iot_poc`, returns whether it should be ingested
+    pub fn filter(&self, iot_poc: &mut IotPoc) -> bool {
+        self.filter
+            .as_ref()
+            .map_or(true, |filter| filter.apply(iot_poc))
+    }
+
+    /// Minimizes the locations of `beacon` if location privacy is configured
+    pub fn minimize(&self, beacon: &mut Beacon) -> Result<()> {
+        match &self.privacy {
+            Some(privacy) => beacon.minimize_locations(privacy),
+            None => Ok(()),
+        }
+    }
+
+    /// Shrinks `beacon` below the max document size, returns Ok(false) if it
+    /// is still too large to be stored, see `Beacon::fit_to_size`
+    pub fn fit(&self, beacon: &mut Beacon) -> Result<bool> {
+        beacon.fit_to_size(self.max_document_size, self.overflow_strategy)
+    }
+}
+
+impl Default for PocTransforms {
+    fn default() -> Self {
+        Self {
+            filter: None,
+            privacy: None,
+            max_document_size: settings::default_max_document_size(),
+            overflow_strategy: OverflowStrategy::default(),
+            beacon_payloads: false,
+            resolutions: CellResolutions::default(),
+        }
+    }
+}
+
+/// Beacons of a chunk of encoded pocs whose first message is described by
+/// `source`, with `transforms` applied. Pocs filtered out or too large are
+/// skipped, like ones failing to decode.
+pub fn decode_beacons(
+    bufs: Vec<BytesMut>,
+    source: &Source,
+    transforms: &PocTransforms,
+) -> Vec<Beacon> {
+    decode_beacons_counted(bufs, source, transforms).0
+}
+
+/// `decode_beacons`, plus the number of pocs failing to decode
+pub fn decode_beacons_counted(
+    bufs: Vec<BytesMut>,
+    source: &Source,
+    transforms: &PocTransforms,
+) -> (Vec<Beacon>, usize) {
+    let mut errors = 0;
+    let beacons = bufs
+        .into_iter()
+        .enumerate()
+        .filter_map(
+            |(i, buf)| match decode_beacon(buf, source.offset(i), transforms) {
+                Ok(beacon) => beacon,
+                Err(e) => {
+                    tracing::error!("error decoding poc: {:?}", e);
+                    errors += 1;
+                    None
+                }
+            },
+        )
+        .collect();
+    (beacons, errors)
+}
+
+fn decode_beacon(
+    buf: BytesMut,
+    source: Source,
+    transforms: &PocTransforms,
+) -> Result<Option<Beacon>> {
+    let mut iot_poc = IotPoc::try_from(LoraPocV1::decode(buf)?)?;
+    if !transforms.filter(&mut iot_poc) {
+        tracing::debug!("ignored, filtered");
+        return Ok(None);
+    }
+    let mut beacon = transforms.beacon(&iot_poc)?;
+    beacon.source = Some(source);
+    transforms.minimize(&mut beacon)?;
+    if !transforms.fit(&mut beacon)? {
+        // NOTE: only the arangodb sink keeps dead letters
+        tracing::warn!(
+            "beacon {:?} too large ({:?} bytes), skipped",
+            beacon.poc_id,
+            beacon.doc_size()?
+        );
+        return Ok(None);
+    }
+    Ok(Some(beacon))
+}
@@ -0,0 +1,149 @@
+use crate::{
+    document::{checkpoint::Checkpoint, iot_poc_file::FileStats, source::Source, Beacon},
+    handler::{
+        arangodb::Populated,
+        sink::{decode_beacons_counted, PocTransforms, Sink, Written},
+    },
+};
+use anyhow::{bail, Result};
+use bytes::BytesMut;
+use file_store::{FileInfo, FileType};
+use tokio::{
+    io::{AsyncWriteExt, Stdout},
+    sync::Mutex,
+};
+
+/// Beacon documents (with their witnesses) written to stdout as JSON Lines,
+/// e.g. to pipe the transformed pocs into other tools. Keeps no bookkeeping:
+/// files are never done and no checkpoint is stored, so every run writes all
+/// files of its range.
+pub struct StdoutSink {
+    // chunks of concurrently processed files are written whole
+    out: Mutex<Stdout>,
+    transforms: PocTransforms,
+}
+
+impl StdoutSink {
+    pub fn new(transforms: PocTransforms) -> Self {
+        Self {
+            out: Mutex::new(tokio::io::stdout()),
+            transforms,
+        }
+    }
+}
+
+/// One json document per line
+fn json_lines(beacons: &[Beacon]) -> Result<Vec<u8>> {
+    let mut lines = vec![];
+    for beacon in beacons {
+        serde_json::to_writer(&mut lines, beacon)?;
+        lines.push(b'\n');
+    }
+    Ok(lines)
+}
+
+#[async_trait::async_trait]
+impl Sink for StdoutSink {
+    fn supports(&self, file_type: FileType) -> bool {
+        file_type == FileType::IotPoc
+    }
+
+    async fn init_file(&self, _file: &FileInfo) -> Result<()> {
+        Ok(())
+    }
+
+    async fn complete_file(&self, _key: &str, _stats: &FileStats) -> Result<()> {
+        Ok(())
+    }
+
+    async fn get_file_retries(&self, _key: &str) -> Result<u8> {
+        Ok(0)
+    }
+
+    async fn increment_file_retry(&self, _key: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn get_done_file_keys(&self) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+
+    async fn get_checkpoint(&self, _name: &str) -> Result<Option<Checkpoint>> {
+        Ok(None)
+    }
+
+    async fn set_checkpoint(&self, _checkpoint: &Checkpoint) -> Result<()> {
+        Ok(())
+    }
+
+    fn transforms(&self) -> &PocTransforms {
+        &self.transforms
+    }
+
+    async fn write_chunk(
+        &self,
+        file_type: FileType,
+        bufs: Vec<BytesMut>,
+        source: &Source,
+    ) -> Result<Written> {
+        if !self.supports(file_type) {
+            bail!("the stdout_jsonl sink does not support {file_type} files");
+        }
+        let (beacons, decode_errors) = decode_beacons_counted(bufs, source, &self.transforms);
+        let lines = json_lines(&beacons)?;
+        let mut out = self.out.lock().await;
+        out.write_all(&lines).await?;
+        out.flush().await?;
+        // NOTE: first seen hotspots aren't tracked
+        Ok(Written {
+            populated: beacons
+                .iter()
+                .map(|beacon| Populated::new(beacon, vec![], self.transforms.beacon_payloads))
+                .collect(),
+            decode_errors,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{doctor, handler::sink::decode_beacons};
+    use helium_proto::{services::poc_lora::LoraPocV1, Message};
+    use std::str::FromStr;
+
+    #[test]
+    fn beacon_lines() {
+        let source = Source::new(
+            &FileInfo::from_str("iot_poc.1687888130980.gz").unwrap(),
+            "LoraPocV1",
+        );
+        let raw = doctor::sample_poc().unwrap();
+        let bufs = vec![BytesMut::from(raw.as_slice()); 2];
+        let lines = json_lines(&decode_beacons(bufs, &source, &PocTransforms::default())).unwrap();
+        let lines: Vec<serde_json::Value> = String::from_utf8(lines)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["witnesses"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn beacons_without_witnesses() {
+        let source = Source::new(
+            &FileInfo::from_str("iot_poc.1687888130980.gz").unwrap(),
+            "LoraPocV1",
+        );
+        let raw = doctor::sample_poc().unwrap();
+        let mut poc = LoraPocV1::decode(raw.as_slice()).unwrap();
+        poc.selected_witnesses.clear();
+        let bufs = vec![BytesMut::from(poc.encode_to_vec().as_slice())];
+        let beacons = decode_beacons(bufs, &source, &PocTransforms::default());
+        assert_eq!(beacons.len(), 1);
+        assert!(beacons[0].witnesses.is_empty());
+        assert_eq!(beacons[0].witness_count, 0);
+        assert_eq!(beacons[0].selected_witness_count, 0);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::settings::TlsSettings;
+use anyhow::{bail, Context, Result};
+use arangors::{client::ClientExt, ClientError};
+use http::{HeaderMap, HeaderValue, Request, Response};
+use reqwest::{Certificate, Client, Identity};
+use std::{fs, sync::RwLock};
+
+/// TLS options of the clients created by arangors. `ClientExt::new` only gets
+/// the request headers, so they are set process wide, see `configure`.
+static TLS: RwLock<Option<TlsConfig>> = RwLock::new(None);
+
+/// Loaded `TlsSettings`
+#[derive(Clone)]
+struct TlsConfig {
+    ca_cert: Option<Certificate>,
+    identity: Option<Identity>,
+    insecure_skip_verify: bool,
+}
+
+impl TlsConfig {
+    fn load(settings: &TlsSettings) -> Result<Self> {
+        let ca_cert = match &settings.ca_cert {
+            Some(path) => Some(Certificate::from_pem(&read(path)?)?),
+            None => None,
+        };
+        let identity = match (&settings.client_cert, &settings.client_key) {
+            (Some(cert), Some(key)) => Some(Identity::from_pkcs8_pem(&read(cert)?, &read(key)?)?),
+            (None, None) => None,
+            _ => bail!("tls client_cert and client_key must be set together"),
+        };
+        if settings.insecure_skip_verify {
+            tracing::warn!("tls certificate verification of arangodb endpoints is disabled");
+        }
+        Ok(Self {
+            ca_cert,
+            identity,
+            insecure_skip_verify: settings.insecure_skip_verify,
+        })
+    }
+}
+
+fn read(path: &str) -> Result<Vec<u8>> {
+    fs::read(path).with_context(|| format!("failed to read {path}"))
+}
+
+/// Load the TLS options used by clients created from now on. Certificate files
+/// are re-read on every call, so reconnecting picks up rotated certificates.
+pub fn configure(settings: Option<&TlsSettings>) -> Result<()> {
+    let config = settings.map(TlsConfig::load).transpose()?;
+    match TLS.write() {
+        Ok(mut tls) => *tls = config,
+        Err(poisoned) => *poisoned.into_inner() = config,
+    }
+    Ok(())
+}
+
+/// arangors client on reqwest, with the configured TLS options
+#[derive(Debug, Clone)]
+pub struct TlsClient {
+    client: Client,
+    headers: HeaderMap,
+}
+
+#[async_trait::async_trait]
+impl ClientExt for TlsClient {
+    fn new<U: Into<Option<HeaderMap>>>(headers: U) -> Result<Self, ClientError> {
+        let headers = headers.into().unwrap_or_default();
+        let mut builder = Client::builder()
+            .gzip(true)
+            .default_headers(headers.clone());
+        let tls = match TLS.read() {
+            Ok(tls) => tls.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        };
+        if let Some(tls) = tls {
+            if let Some(ca_cert) = tls.ca_cert {
+                builder = builder.add_root_certificate(ca_cert);
+            }
+            if let Some(identity) = tls.identity {
+                builder = builder.identity(identity);
+            }
+            builder = builder.danger_accept_invalid_certs(tls.insecure_skip_verify);
+        }
+        let client = builder
+            .build()
+            .map_err(|err| ClientError::HttpClient(format!("{err:?}")))?;
+        Ok(Self { client, headers })
+    }
+
+    fn headers(&mut self) -> &mut HeaderMap<HeaderValue> {
+        &mut self.headers
+    }
+
+    async fn request(&self, request: Request<String>) -> Result<Response<String>, ClientError> {
+        let request = reqwest::Request::try_from(request)
+            .map_err(|err| ClientError::HttpClient(format!("{err:?}")))?;
+        let resp = self
+            .client
+            .execute(request)
+            .await
+            .map_err(|err| ClientError::HttpClient(format!("{err:?}")))?;
+        let mut builder = Response::builder()
+            .status(resp.status())
+            .version(resp.version());
+        for (name, value) in resp.headers() {
+            builder = builder.header(name, value);
+        }
+        let body = resp
+            .text()
+            .await
+            .map_err(|err| ClientError::HttpClient(format!("{err:?}")))?;
+        builder
+            .body(body)
+            .map_err(|err| ClientError::HttpClient(format!("{err:?}")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn client_cert_needs_key() {
+        let settings = TlsSettings {
+            client_cert: Some("client.pem".to_string()),
+            ..Default::default()
+        };
+        assert!(TlsConfig::load(&settings).is_err());
+    }
+
+    #[test]
+    fn missing_ca_cert_fails() {
+        let settings = TlsSettings {
+            ca_cert: Some("/nonexistent/ca.pem".to_string()),
+            ..Default::default()
+        };
+        let err = TlsConfig::load(&settings).err().unwrap();
+        assert!(err.to_string().contains("/nonexistent/ca.pem"));
+    }
+}
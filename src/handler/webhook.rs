@@ -0,0 +1,163 @@
+use crate::{
+    handler::{
+        arangodb::Populated,
+        notifier::{poc_payload, Notifier, POC_ID_STREAM},
+        retry::RetryPolicy,
+    },
+    settings::{PocPayload, WebhookSettings},
+};
+use anyhow::Result;
+use hmac::{Hmac, Mac};
+use reqwest::{Client, StatusCode};
+use sha2::Sha256;
+use std::time::Duration;
+
+/// Header naming the event of a request
+const EVENT_HEADER: &str = "X-Arango-Etl-Event";
+/// Header with the `sha256=<hex>` HMAC of the body, if a secret is configured
+const SIGNATURE_HEADER: &str = "X-Signature-256";
+
+/// POSTs json events to a webhook, e.g. for services without redis. File
+/// events are always sent, the stored pocs of each chunk only if enabled.
+pub struct WebhookHandler {
+    client: Client,
+    url: String,
+    secret: Option<String>,
+    pocs: bool,
+    payload: PocPayload,
+    retry: RetryPolicy,
+}
+
+impl WebhookHandler {
+    pub fn from_settings(settings: &WebhookSettings) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(settings.timeout_secs))
+            .build()?;
+        Ok(Self {
+            client,
+            url: settings.url.clone(),
+            secret: settings.secret.clone(),
+            pocs: settings.pocs,
+            payload: settings.payload,
+            retry: RetryPolicy::from_settings(&settings.retry),
+        })
+    }
+
+    /// POST `payload` as `event`, retrying connection errors, 429 and 5xx
+    async fn post(&self, event: &str, payload: &str) -> Result<()> {
+        let body = body(event, payload)?;
+        let signature = self.secret.as_deref().map(|secret| sign(secret, &body));
+        let mut attempt = 1;
+        loop {
+            let mut request = self
+                .client
+                .post(&self.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(EVENT_HEADER, event)
+                .body(body.clone());
+            if let Some(signature) = &signature {
+                request = request.header(SIGNATURE_HEADER, signature);
+            }
+            let err = match request.send().await {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) if !is_retriable(response.status()) => {
+                    anyhow::bail!("webhook rejected {} event: {}", event, response.status())
+                }
+                Ok(response) => anyhow::anyhow!("webhook responded {}", response.status()),
+                Err(err) => err.into(),
+            };
+            if attempt >= self.retry.max_attempts() {
+                return Err(err.context(format!("posting {event} event")));
+            }
+            let delay = self.retry.backoff(attempt);
+            tracing::warn!(
+                "webhook {} event failed (attempt {}/{}), retrying in {:?}: {:?}",
+                event,
+                attempt,
+                self.retry.max_attempts(),
+                delay,
+                err
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for WebhookHandler {
+    /// Posts the stored pocs of a chunk as one `poc_id` event with an array payload
+    async fn publish_pocs(&self, populated: &[Populated]) -> Result<()> {
+        if !self.pocs || populated.is_empty() {
+            return Ok(());
+        }
+        let payloads = populated
+            .iter()
+            .map(|populated| poc_payload(self.payload, populated))
+            .collect::<Result<Vec<_>>>()?;
+        self.post(POC_ID_STREAM, &format!("[{}]", payloads.join(",")))
+            .await
+    }
+
+    async fn publish_event(&self, stream: &str, payload: &str) -> Result<()> {
+        self.post(stream, payload).await
+    }
+
+    fn publishes_file_events(&self) -> bool {
+        true
+    }
+}
+
+/// Body of a request, `{"event": ..., "payload": ...}` with the json payload
+/// as published to the other notifiers
+fn body(event: &str, payload: &str) -> Result<String> {
+    Ok(format!(
+        r#"{{"event":{},"payload":{}}}"#,
+        serde_json::to_string(event)?,
+        payload
+    ))
+}
+
+/// Whether a request rejected with `status` may succeed when repeated
+fn is_retriable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// `sha256=<hex>` HMAC-SHA256 of `body` keyed by `secret`
+fn sign(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts any key length");
+    mac.update(body.as_bytes());
+    let digest = mac.finalize().into_bytes();
+    format!("sha256={}", data_encoding::HEXLOWER.encode(&digest))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn signature() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", "what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn request_body() {
+        let body = body("file_completed", r#"{"key":"iot_poc.1.gz","pocs":3}"#).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["event"], "file_completed");
+        assert_eq!(json["payload"]["pocs"], 3);
+    }
+
+    #[test]
+    fn retriable_statuses() {
+        assert!(is_retriable(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retriable(StatusCode::BAD_GATEWAY));
+        assert!(!is_retriable(StatusCode::BAD_REQUEST));
+        assert!(!is_retriable(StatusCode::NOT_FOUND));
+    }
+}
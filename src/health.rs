@@ -0,0 +1,183 @@
+use crate::{
+    diagnostics::SharedDiagnostics,
+    doctor::Report,
+    handler::{arangodb::DB, ingest_source, FileSource, RedisHandler},
+    settings::{HealthSettings, Settings},
+};
+use anyhow::Result;
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use file_store::FileType;
+use serde_json::{json, Value};
+use std::{future::Future, sync::Arc};
+use tokio::time::{self, Duration, Instant};
+use tokio_graceful_shutdown::SubsystemHandle;
+
+/// Liveness and readiness endpoints of the current mode, e.g. for kubernetes
+/// probes
+pub struct Health {
+    settings: HealthSettings,
+    diagnostics: SharedDiagnostics,
+    // None with another sink than ArangoDB
+    db: Option<Arc<DB>>,
+    store: Box<dyn FileSource>,
+    redis: Option<RedisHandler>,
+}
+
+impl Health {
+    pub async fn new(
+        settings: &Settings,
+        health: HealthSettings,
+        diagnostics: SharedDiagnostics,
+        db: Option<Arc<DB>>,
+    ) -> Result<Self> {
+        Ok(Self {
+            settings: health,
+            diagnostics,
+            db,
+            store: ingest_source(settings).await?,
+            redis: settings
+                .redis
+                .as_ref()
+                .map(RedisHandler::from_settings)
+                .transpose()?,
+        })
+    }
+
+    /// Serve `/healthz` and `/readyz` until shutdown
+    pub async fn serve(self, subsys: SubsystemHandle) -> Result<()> {
+        let listen = self.settings.listen;
+        let app = Router::new()
+            .route("/healthz", get(healthz))
+            .route("/readyz", get(readyz))
+            .with_state(Arc::new(self));
+        tracing::info!("serving health endpoints on {}", listen);
+        axum::Server::try_bind(&listen)?
+            .serve(app.into_make_service())
+            .with_graceful_shutdown(subsys.on_shutdown_requested())
+            .await?;
+        Ok(())
+    }
+
+    /// Connect to ArangoDB and redis and list the ingest bucket
+    async fn readiness(&self) -> Report {
+        let mut report = Report::default();
+        if let Some(db) = &self.db {
+            self.check(&mut report, "arangodb", async {
+                db.ensure_connected().await?;
+                Ok("connected".to_string())
+            })
+            .await;
+        }
+        self.check(&mut report, "ingest bucket", async {
+            let after = Utc::now() - ChronoDuration::hours(1);
+            let files = self.store.list_all(FileType::IotPoc, after, None).await?;
+            Ok(format!("{} iot_poc files in the last hour", files.len()))
+        })
+        .await;
+        if let Some(redis) = &self.redis {
+            self.check(&mut report, "redis", async {
+                redis.ping().await?;
+                Ok("ping".to_string())
+            })
+            .await;
+        }
+        report
+    }
+
+    async fn check(
+        &self,
+        report: &mut Report,
+        name: &str,
+        fut: impl Future<Output = Result<String>>,
+    ) {
+        let start = Instant::now();
+        let timeout = Duration::from_secs(self.settings.check_timeout_secs);
+        let res = match time::timeout(timeout, fut).await {
+            Ok(res) => res,
+            Err(_) => Err(anyhow::anyhow!("timed out after {:?}", timeout)),
+        };
+        if let Err(err) = &res {
+            tracing::warn!("readiness check {} failed: {:?}", name, err);
+        }
+        report.push(name, start.elapsed().as_millis() as u64, res);
+    }
+}
+
+/// Whether the tracker is live: it finished a tick (or started) within
+/// `max_tick_age`, or ingestion is paused for maintenance
+fn is_live(
+    last_tick_at: Option<DateTime<Utc>>,
+    started_at: DateTime<Utc>,
+    maintenance: bool,
+    max_tick_age: ChronoDuration,
+    now: DateTime<Utc>,
+) -> bool {
+    maintenance || now - last_tick_at.unwrap_or(started_at) <= max_tick_age
+}
+
+async fn healthz(State(health): State<Arc<Health>>) -> (StatusCode, Json<Value>) {
+    let diagnostics = match health.diagnostics.lock() {
+        Ok(diagnostics) => diagnostics.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    };
+    let live = is_live(
+        diagnostics.last_tick_at,
+        diagnostics.started_at,
+        diagnostics.maintenance_since.is_some(),
+        ChronoDuration::seconds(health.settings.max_tick_age_secs as i64),
+        Utc::now(),
+    );
+    let status = if live {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = json!({
+        "ok": live,
+        "last_tick_at": diagnostics.last_tick_at,
+        "tick_started_at": diagnostics.tick_started_at,
+        "maintenance_since": diagnostics.maintenance_since,
+    });
+    (status, Json(body))
+}
+
+async fn readyz(State(health): State<Arc<Health>>) -> (StatusCode, Json<Value>) {
+    let report = health.readiness().await;
+    let last_tick_at = match health.diagnostics.lock() {
+        Ok(diagnostics) => diagnostics.last_tick_at,
+        Err(poisoned) => poisoned.into_inner().last_tick_at,
+    };
+    let status = if report.is_ok() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = json!({
+        "ok": report.is_ok(),
+        "checks": report.checks,
+        "last_tick_at": last_tick_at,
+    });
+    (status, Json(body))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn liveness() {
+        let started_at = Utc::now() - ChronoDuration::hours(3);
+        let now = Utc::now();
+        let max_age = ChronoDuration::hours(1);
+        let recent = Some(now - ChronoDuration::minutes(5));
+        let stale = Some(now - ChronoDuration::hours(2));
+        assert!(is_live(recent, started_at, false, max_age, now));
+        assert!(!is_live(stale, started_at, false, max_age, now));
+        // paused on purpose
+        assert!(is_live(stale, started_at, true, max_age, now));
+        // no tick yet, measured from the start
+        assert!(!is_live(None, started_at, false, max_age, now));
+        assert!(is_live(None, now, false, max_age, now));
+    }
+}
@@ -1,5 +1,21 @@
+pub mod analyze;
+pub mod api;
+pub mod audit;
 pub mod cli;
+#[cfg(feature = "clickhouse")]
+pub mod columnar;
+pub mod denylist;
+pub mod diagnostics;
+pub mod doctor;
 pub mod document;
+pub mod export;
+pub mod gaps;
 pub mod handler;
+pub mod health;
+pub mod maintenance;
+pub mod rollup;
 pub mod settings;
+pub mod stats;
+pub mod telemetry;
 pub mod tracker;
+pub mod verify;
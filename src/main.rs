@@ -1,9 +1,16 @@
 use anyhow::Result;
+#[cfg(feature = "kafka")]
+use arango_etl::cli::kafka;
 use arango_etl::{
-    cli::{current, history, rehydrate},
-    settings::Settings,
+    audit,
+    cli::{
+        analyze, annotate, completions, current, doctor, export, gaps, geo, history, mangen,
+        migrate, purge, rehydrate, reindex, serve, stats, status, verify, watermark,
+    },
+    settings::{RuntimeSettings, Settings, SinkKind},
+    telemetry,
 };
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 use std::path;
 
 #[derive(Debug, clap::Subcommand)]
@@ -14,14 +21,107 @@ pub enum Cmd {
     Rehydrate(rehydrate::Cmd),
     /// Run in current mode by starting a server
     Current(current::Server),
+    /// Ingest pocs consumed from a kafka topic
+    #[cfg(feature = "kafka")]
+    Kafka(kafka::Cmd),
+    /// Annotate hotspots or witness edges
+    Annotate(annotate::Cmd),
+    /// Create the configured indexes on an existing database
+    Reindex(reindex::Cmd),
+    /// Compute hotspot statistics into the hotspot_stats collection
+    Stats(stats::Cmd),
+    /// Migrate stored documents to the current schema
+    Migrate(migrate::Cmd),
+    /// Run graph analyses over the hotspot graph
+    Analyze(analyze::Cmd),
+    /// Audit the files collection against the bucket
+    Verify(verify::Cmd),
+    /// Get or set named ingest watermarks for external orchestrators
+    Watermark(watermark::Cmd),
+    /// Report time windows without ingested files
+    Gaps(gaps::Cmd),
+    /// Print the latest ingested and bucket files, failing files and the ingest lag
+    Status(status::Cmd),
+    /// Delete beacons, orphaned edges and file records by time range
+    Purge(purge::Cmd),
+    /// Export hotspots and witness edges for spreadsheets or GIS tools
+    Export(export::Cmd),
+    /// Maintain the redis GEO set of hotspot locations
+    Geo(geo::Cmd),
+    /// Check the sample decode, clock and configured integrations before deploying
+    Doctor(doctor::Cmd),
+    /// Serve a read-only REST API over hotspots, witness edges and beacons
+    Serve(serve::Cmd),
+}
+
+/// All subcommands: the ETL ones, which load the settings, and the generators,
+/// which only need the clap command
+#[derive(Debug, clap::Subcommand)]
+pub enum Command {
+    #[clap(flatten)]
+    Etl(Cmd),
+    /// Generate shell completions
+    Completions(completions::Cmd),
+    /// Generate man pages
+    Mangen(mangen::Cmd),
 }
 
 impl Cmd {
+    /// Sink given on the command line, overriding the configured one
+    fn sink(&self) -> Option<SinkKind> {
+        match self {
+            Self::History(cmd) => cmd.sink(),
+            Self::Rehydrate(cmd) => cmd.sink(),
+            _ => None,
+        }
+    }
+
+    /// Name the command is recorded under in the audit_log collection, None
+    /// for commands which only read, ingest or don't touch the database
+    fn audit_action(&self) -> Option<&'static str> {
+        match self {
+            Self::Rehydrate(_) => Some("rehydrate"),
+            Self::Annotate(_) => Some("annotate"),
+            Self::Reindex(_) => Some("reindex"),
+            Self::Migrate(_) => Some("migrate"),
+            Self::Watermark(_) => Some("watermark"),
+            Self::Purge(_) => Some("purge"),
+            _ => None,
+        }
+    }
+
     pub async fn run(self, settings: Settings) -> Result<()> {
+        match self.audit_action() {
+            // NOTE: a dry run changes nothing worth auditing
+            Some(action) if !settings.dry_run => {
+                let arangodb = settings.arangodb.clone();
+                audit::audited(&arangodb, action, self.run_cmd(settings)).await
+            }
+            _ => self.run_cmd(settings).await,
+        }
+    }
+
+    async fn run_cmd(self, settings: Settings) -> Result<()> {
         match self {
             Self::History(cmd) => cmd.run(&settings).await,
             Self::Rehydrate(cmd) => cmd.run(&settings).await,
             Self::Current(cmd) => cmd.run(&settings).await,
+            #[cfg(feature = "kafka")]
+            Self::Kafka(cmd) => cmd.run(&settings).await,
+            Self::Annotate(cmd) => cmd.run(&settings).await,
+            Self::Reindex(cmd) => cmd.run(&settings).await,
+            Self::Stats(cmd) => cmd.run(&settings).await,
+            Self::Migrate(cmd) => cmd.run(&settings).await,
+            Self::Analyze(cmd) => cmd.run(&settings).await,
+            Self::Verify(cmd) => cmd.run(&settings).await,
+            Self::Watermark(cmd) => cmd.run(&settings).await,
+            Self::Gaps(cmd) => cmd.run(&settings).await,
+            Self::Status(cmd) => cmd.run(&settings).await,
+            Self::Purge(cmd) => cmd.run(&settings).await,
+            Self::Export(cmd) => cmd.run(&settings).await,
+            Self::Geo(cmd) => cmd.run(&settings).await,
+            Self::Doctor(cmd) => cmd.run(&settings).await,
+            Self::Serve(cmd) => cmd.run(&settings).await,
         }
     }
 }
@@ -33,19 +133,56 @@ pub struct Cli {
     #[clap(short = 'c')]
     config: Option<path::PathBuf>,
 
+    /// Run as usual but only log what would be written, skipping the sink
+    /// writes and notifier publishing (every command supports it)
+    #[clap(long, global = true)]
+    dry_run: bool,
+
     #[clap(subcommand)]
-    cmd: Cmd,
+    cmd: Command,
 }
 
 impl Cli {
-    pub async fn run(self) -> Result<()> {
-        let settings = Settings::new(self.config)?;
-        self.cmd.run(settings).await
+    pub fn run(self) -> Result<()> {
+        match self.cmd {
+            Command::Completions(cmd) => cmd.run(Self::command()),
+            Command::Mangen(cmd) => cmd.run(Self::command()),
+            Command::Etl(cmd) => {
+                let mut settings = Settings::new(self.config)?;
+                settings.dry_run = self.dry_run;
+                if let Some(sink) = cmd.sink() {
+                    settings.sink = sink;
+                }
+                // NOTE: stdout is the sink's, keep the logs out of it
+                if settings.sink == SinkKind::StdoutJsonl {
+                    settings.telemetry.stderr = true;
+                }
+                let runtime = build_runtime(&settings.runtime)?;
+                runtime.block_on(async {
+                    let _telemetry = telemetry::init(&settings.log, &settings.telemetry)?;
+                    cmd.run(settings).await
+                })
+            }
+        }
+    }
+}
+
+fn build_runtime(settings: &RuntimeSettings) -> Result<tokio::runtime::Runtime> {
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    builder.enable_all();
+    if let Some(worker_threads) = settings.worker_threads {
+        builder.worker_threads(worker_threads);
+    }
+    if let Some(max_blocking_threads) = settings.max_blocking_threads {
+        builder.max_blocking_threads(max_blocking_threads);
+    }
+    if let Some(thread_stack_size) = settings.thread_stack_size {
+        builder.thread_stack_size(thread_stack_size);
     }
+    Ok(builder.build()?)
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
     let cli = Cli::parse();
-    cli.run().await
+    cli.run()
 }
@@ -0,0 +1,311 @@
+use crate::{
+    handler::arangodb::DB,
+    settings::{CollectionNames, MaintenanceScript, RetentionSettings},
+};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Datelike, Duration, DurationRound, TimeZone, Timelike, Utc};
+use std::sync::Arc;
+
+/// Upper bound when searching for the next schedule match
+const MAX_LOOKAHEAD_DAYS: i64 = 366 * 5;
+
+/// Minimal cron schedule: `minute hour day-of-month month day-of-week` (UTC).
+///
+/// Each field accepts `*`, `n`, `a-b`, `*/s`, `a-b/s` and comma separated lists thereof.
+/// Day-of-week is 0-7 with both 0 and 7 meaning Sunday. The `@hourly`, `@daily`,
+/// `@weekly` and `@monthly` shorthands are also accepted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    dom_restricted: bool,
+    dow_restricted: bool,
+}
+
+impl Schedule {
+    pub fn parse(s: &str) -> Result<Self> {
+        let expr = match s.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            expr => expr,
+        };
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            bail!("invalid schedule {s:?}, expected 5 fields");
+        }
+        let mut days_of_week = parse_field(fields[4], 0, 7)?;
+        // fold 7 (sunday) onto 0
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week & !(1 << 7)) | 1;
+        }
+        Ok(Self {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days_of_month: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            days_of_week,
+            dom_restricted: fields[2] != "*",
+            dow_restricted: fields[4] != "*",
+        })
+    }
+
+    fn matches_day<T: Datelike>(&self, date: &T) -> bool {
+        let dom = bit(self.days_of_month, date.day());
+        let dow = bit(self.days_of_week, date.weekday().num_days_from_sunday());
+        let day = match (self.dom_restricted, self.dow_restricted) {
+            // classic cron: either restricted day field may match
+            (true, true) => dom || dow,
+            _ => dom && dow,
+        };
+        day && bit(self.months, date.month())
+    }
+
+    /// First matching minute strictly after `after`
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = after.duration_trunc(Duration::minutes(1)).ok()? + Duration::minutes(1);
+        let limit = start + Duration::days(MAX_LOOKAHEAD_DAYS);
+        let mut t = start;
+        while t < limit {
+            if !self.matches_day(&t) {
+                let next_day = t.date_naive().succ_opt()?.and_hms_opt(0, 0, 0)?;
+                t = Utc.from_utc_datetime(&next_day);
+                continue;
+            }
+            if !bit(self.hours, t.hour()) {
+                t = t.with_minute(0)? + Duration::hours(1);
+                continue;
+            }
+            if bit(self.minutes, t.minute()) {
+                return Some(t);
+            }
+            t += Duration::minutes(1);
+        }
+        None
+    }
+}
+
+fn bit(set: u64, n: u32) -> bool {
+    set & (1 << n) != 0
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64> {
+    let mut set = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().context("invalid step")?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            bail!("invalid step 0 in {field:?}");
+        }
+        let (lo, hi) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((lo, hi)) => (lo.parse()?, hi.parse()?),
+                None => {
+                    let n = range
+                        .parse()
+                        .context(format!("invalid value in {field:?}"))?;
+                    (n, n)
+                }
+            },
+        };
+        if lo < min || hi > max || lo > hi {
+            bail!("{part:?} out of range {min}-{max}");
+        }
+        for n in (lo..=hi).step_by(step as usize) {
+            set |= 1 << n;
+        }
+    }
+    Ok(set)
+}
+
+/// Name of the built-in witness edge retention maintenance script
+pub const EDGE_RETENTION_SCRIPT: &str = "edge_retention";
+
+/// Removal of witness edges not seen for `edge_days` as a maintenance script,
+/// None if edges are kept forever. Beacons and file records expire through
+/// TTL indexes instead, which edges can't use as they are updated in place.
+pub fn edge_retention_script(
+    settings: &RetentionSettings,
+    names: &CollectionNames,
+) -> Option<MaintenanceScript> {
+    let days = settings.edge_days?;
+    let witnesses = &names.witnesses;
+    Some(MaintenanceScript {
+        name: EDGE_RETENTION_SCRIPT.to_string(),
+        schedule: settings.edge_schedule.clone(),
+        query: format!(
+            "FOR e IN {witnesses} FILTER e.last_seen_unix_ms < DATE_NOW() - {days} * 86400000 REMOVE e IN {witnesses}"
+        ),
+    })
+}
+
+/// Name of the geo polygon pruning script, see `geo_prune_script`
+pub const GEO_PRUNE_SCRIPT: &str = "geo_prune";
+
+/// Stripping of the geo polygons of beacons (and their witnesses) ingested more
+/// than `geo_days` ago as a maintenance script, None if they are kept forever.
+/// Polygons are most of an old beacon's size, the lat/lng and h3 location
+/// fields are kept.
+pub fn geo_prune_script(
+    settings: &RetentionSettings,
+    names: &CollectionNames,
+) -> Option<MaintenanceScript> {
+    let days = settings.geo_days?;
+    let beacons = &names.beacons;
+    Some(MaintenanceScript {
+        name: GEO_PRUNE_SCRIPT.to_string(),
+        schedule: settings.geo_schedule.clone(),
+        query: format!(
+            "FOR b IN {beacons} \
+             FILTER b.ingest_time_unix_ms < DATE_NOW() - {days} * 86400000 \
+             FILTER b.geo != null OR b.parent_geo != null \
+             OR LENGTH(b.witnesses[* FILTER CURRENT.geo != null OR CURRENT.parent_geo != null]) > 0 \
+             UPDATE b WITH {{ geo: null, parent_geo: null, \
+             witnesses: (FOR w IN b.witnesses RETURN UNSET(w, \"geo\", \"parent_geo\")) }} \
+             IN {beacons} OPTIONS {{ keepNull: false }}"
+        ),
+    })
+}
+
+/// Runs the configured AQL maintenance scripts when their schedule is due,
+/// tracking the last run of each script in the maintenance collection.
+pub struct Maintenance {
+    db: Arc<DB>,
+    scripts: Vec<(MaintenanceScript, Schedule)>,
+    started_at: DateTime<Utc>,
+}
+
+impl Maintenance {
+    pub fn new(db: Arc<DB>, scripts: &[MaintenanceScript]) -> Result<Self> {
+        let scripts = scripts
+            .iter()
+            .map(|script| {
+                Schedule::parse(&script.schedule)
+                    .context(format!("maintenance script {:?}", script.name))
+                    .map(|schedule| (script.clone(), schedule))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            db,
+            scripts,
+            started_at: Utc::now(),
+        })
+    }
+
+    /// Run every script whose next scheduled time since its last run (or since
+    /// startup, if it never ran) has passed. Script failures are recorded and
+    /// logged but do not stop other scripts or the caller.
+    pub async fn run_due(&self, now: DateTime<Utc>) -> Result<()> {
+        for (script, schedule) in self.scripts.iter() {
+            let last_run = self
+                .db
+                .get_maintenance_last_run(&script.name)
+                .await?
+                .and_then(|ts| Utc.timestamp_millis_opt(ts).single())
+                .unwrap_or(self.started_at);
+
+            if matches!(schedule.next_after(last_run), Some(due) if due <= now) {
+                tracing::info!("running maintenance script {:?}", script.name);
+                let started = Utc::now();
+                let result = self.db.run_aql(&script.query).await;
+                let duration_ms = (Utc::now() - started).num_milliseconds();
+                let error = match result {
+                    Ok(()) => None,
+                    Err(err) => {
+                        tracing::error!("maintenance script {:?}: {:?}", script.name, err);
+                        Some(format!("{err:?}"))
+                    }
+                };
+                self.db
+                    .record_maintenance_run(&script.name, now, duration_ms, error)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn next_after() {
+        let ts = Utc.with_ymd_and_hms(2023, 6, 27, 15, 1, 42).unwrap();
+
+        let daily = Schedule::parse("@daily").unwrap();
+        assert_eq!(
+            daily.next_after(ts),
+            Some(Utc.with_ymd_and_hms(2023, 6, 28, 0, 0, 0).unwrap())
+        );
+
+        // 2023-06-27 is a tuesday, next sunday is 2023-07-02
+        let weekly = Schedule::parse("30 3 * * 7").unwrap();
+        assert_eq!(
+            weekly.next_after(ts),
+            Some(Utc.with_ymd_and_hms(2023, 7, 2, 3, 30, 0).unwrap())
+        );
+
+        let every_15 = Schedule::parse("*/15 * * * *").unwrap();
+        assert_eq!(
+            every_15.next_after(ts),
+            Some(Utc.with_ymd_and_hms(2023, 6, 27, 15, 15, 0).unwrap())
+        );
+
+        assert!(Schedule::parse("* * *").is_err());
+        assert!(Schedule::parse("60 * * * *").is_err());
+    }
+
+    #[test]
+    fn edge_retention() {
+        let mut retention = RetentionSettings {
+            beacon_days: Some(30),
+            file_days: None,
+            edge_days: None,
+            edge_schedule: "@daily".to_string(),
+            geo_days: None,
+            geo_schedule: "@daily".to_string(),
+        };
+        let names = CollectionNames {
+            witnesses: "iot_witnesses_us915".to_string(),
+            ..Default::default()
+        };
+        assert!(edge_retention_script(&retention, &names).is_none());
+
+        retention.edge_days = Some(90);
+        let script = edge_retention_script(&retention, &names).unwrap();
+        assert!(Schedule::parse(&script.schedule).is_ok());
+        assert!(script.query.contains("90 * 86400000"));
+        assert!(script.query.contains("REMOVE e IN iot_witnesses_us915"));
+    }
+
+    #[test]
+    fn geo_prune() {
+        let mut retention = RetentionSettings {
+            beacon_days: None,
+            file_days: None,
+            edge_days: None,
+            edge_schedule: "@daily".to_string(),
+            geo_days: None,
+            geo_schedule: "@weekly".to_string(),
+        };
+        let names = CollectionNames::default();
+        assert!(geo_prune_script(&retention, &names).is_none());
+
+        retention.geo_days = Some(30);
+        let script = geo_prune_script(&retention, &names).unwrap();
+        assert_eq!(script.name, GEO_PRUNE_SCRIPT);
+        assert!(Schedule::parse(&script.schedule).is_ok());
+        assert!(script.query.contains("30 * 86400000"));
+        assert!(script
+            .query
+            .contains("IN beacons OPTIONS { keepNull: false }"));
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{
+    export::{self, ExportCollection},
+    handler::arangodb::DB,
+    maintenance::Schedule,
+    settings::{ExportFormat, RollupExportSettings},
+};
+use anyhow::{Context, Result};
+use aws_sdk_s3::{types::ByteStream, Client, Region};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use std::{path::Path, sync::Arc};
+use tokio_graceful_shutdown::SubsystemHandle;
+
+/// Name the export runs are tracked under in the maintenance collection
+pub const ROLLUP_EXPORT_RUN: &str = "rollup_export";
+
+/// Uploads the hotspot stats and witness edge rollups to S3 on a schedule, so
+/// consumers can read them without database credentials
+pub struct RollupExporter {
+    db: Arc<DB>,
+    client: Client,
+    settings: RollupExportSettings,
+    schedule: Schedule,
+}
+
+impl RollupExporter {
+    pub async fn new(db: Arc<DB>, settings: RollupExportSettings) -> Result<Self> {
+        let schedule = Schedule::parse(&settings.schedule).context("rollup export schedule")?;
+        let mut config = aws_config::from_env();
+        if let Some(region) = &settings.region {
+            config = config.region(Region::new(region.clone()));
+        }
+        Ok(Self {
+            db,
+            client: Client::new(&config.load().await),
+            settings,
+            schedule,
+        })
+    }
+
+    /// Export every rollup in every configured format under the `date` prefix.
+    /// Files are written to a temp file first, so a rollup is never held in
+    /// memory as a whole.
+    pub async fn export(&self, date: NaiveDate) -> Result<()> {
+        for collection in ExportCollection::rollups() {
+            for format in &self.settings.formats {
+                let file_name = collection.file_name(format.extension());
+                let path = std::env::temp_dir().join(format!(
+                    "arango-etl-{}-{}",
+                    std::process::id(),
+                    file_name
+                ));
+                let res = self
+                    .upload(collection, *format, &path, &file_name, date)
+                    .await;
+                if let Err(err) = std::fs::remove_file(&path) {
+                    tracing::warn!("failed to remove {}: {:?}", path.display(), err);
+                }
+                res?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn upload(
+        &self,
+        collection: ExportCollection,
+        format: ExportFormat,
+        path: &Path,
+        file_name: &str,
+        date: NaiveDate,
+    ) -> Result<()> {
+        let rows =
+            export::export_file(&self.db, collection, format, path, self.settings.batch_size)
+                .await?;
+        let key = object_key(&self.settings.prefix, date, file_name);
+        self.client
+            .put_object()
+            .bucket(&self.settings.bucket)
+            .key(&key)
+            .body(ByteStream::from_path(path).await?)
+            .send()
+            .await
+            .with_context(|| format!("uploading s3://{}/{}", self.settings.bucket, key))?;
+        tracing::info!(
+            "exported {} rows to s3://{}/{}",
+            rows,
+            self.settings.bucket,
+            key
+        );
+        Ok(())
+    }
+
+    /// Export whenever the schedule is due since the last run (or since
+    /// startup, if it never ran). Runs are recorded like maintenance scripts.
+    pub async fn run(self, subsys: SubsystemHandle) -> Result<()> {
+        let mut last_run = self
+            .db
+            .get_maintenance_last_run(ROLLUP_EXPORT_RUN)
+            .await?
+            .and_then(|ts| Utc.timestamp_millis_opt(ts).single())
+            .unwrap_or_else(Utc::now);
+        loop {
+            let Some(due) = self.schedule.next_after(last_run) else {
+                tracing::warn!("rollup export schedule never matches");
+                subsys.on_shutdown_requested().await;
+                return Ok(());
+            };
+            let wait = (due - Utc::now()).to_std().unwrap_or_default();
+            tokio::select! {
+                _ = subsys.on_shutdown_requested() => break,
+                _ = tokio::time::sleep(wait) => {
+                    last_run = Utc::now();
+                    if let Err(err) = self.run_once(last_run).await {
+                        tracing::error!("error recording rollup export: {:?}", err);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Export, recording the run (and its error, if any)
+    async fn run_once(&self, now: DateTime<Utc>) -> Result<()> {
+        tracing::info!("exporting rollups");
+        let result = self.export(now.date_naive()).await;
+        let duration_ms = (Utc::now() - now).num_milliseconds();
+        let error = match result {
+            Ok(()) => None,
+            Err(err) => {
+                tracing::error!("rollup export: {:?}", err);
+                Some(format!("{err:?}"))
+            }
+        };
+        self.db
+            .record_maintenance_run(ROLLUP_EXPORT_RUN, now, duration_ms, error)
+            .await?;
+        Ok(())
+    }
+}
+
+/// S3 key of an exported file, e.g. `rollups/2023-06-27/hotspot_stats.csv`
+pub fn object_key(prefix: &str, date: NaiveDate, file_name: &str) -> String {
+    let prefix = prefix.trim_matches('/');
+    let date = date.format("%Y-%m-%d");
+    if prefix.is_empty() {
+        format!("{date}/{file_name}")
+    } else {
+        format!("{prefix}/{date}/{file_name}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn object_keys() {
+        let date = NaiveDate::from_ymd_opt(2023, 6, 27).unwrap();
+        assert_eq!(
+            object_key("rollups/", date, "hotspot_stats.parquet"),
+            "rollups/2023-06-27/hotspot_stats.parquet"
+        );
+        assert_eq!(
+            object_key("", date, "witnesses.csv"),
+            "2023-06-27/witnesses.csv"
+        );
+    }
+}
@@ -1,8 +1,19 @@
+use crate::document::{
+    loc_data::DEFAULT_PARENT_RESOLUTION, AUDIT_LOG_COLLECTION, BEACON_COLLECTION,
+    CHECKPOINTS_COLLECTION, DEAD_LETTER_COLLECTION, FILES_COLLECTION, HEX_COLLECTION,
+    HOTSPOT_COLLECTION, HOTSPOT_STATS_COLLECTION, INVALID_BEACON_COLLECTION,
+    INVALID_WITNESS_COLLECTION, MAINTENANCE_COLLECTION, META_COLLECTION, RAW_POC_COLLECTION,
+    REWARDS_COLLECTION, VERIFICATION_RUNS_COLLECTION, WITNESS_EDGE_COLLECTION,
+};
 use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
 use config::{Config, Environment, File};
 use file_store::Settings as FSettings;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
 
 /// We are doing this here instead of CLI args to make
 /// it easier to use with systemd unit files.
@@ -11,12 +22,51 @@ pub struct CurrentSettings {
     /// After timestamp to start from
     #[serde(default = "default_after_ts")]
     pub after: NaiveDateTime,
+    /// Optional before timestamp (inclusive) bounding a catch-up run, which exits
+    /// once all files up to it are processed, default: unbounded
+    pub before: Option<NaiveDateTime>,
+    /// Optional S3 new-object notifications, polls every tracker interval if unset
+    pub notifications: Option<NotificationSettings>,
+    /// Secs in-flight files may take to finish on shutdown before being cut off, default: 60
+    #[serde(default = "default_drain_timeout")]
+    pub drain_timeout: u64,
+    /// Start in maintenance mode, ingesting nothing until toggled by SIGUSR2, default: false
+    #[serde(default)]
+    pub maintenance: bool,
+    /// Secs the starting after timestamp may lie ahead of the local clock, default: 300
+    #[serde(default = "default_max_ahead_secs")]
+    pub max_ahead_secs: i64,
+    /// Hours the starting after timestamp may lag the newest ingest file of an
+    /// unbounded run, default: unchecked
+    pub max_lag_hours: Option<i64>,
+    /// Clamp an after timestamp out of these bounds to the bound instead of
+    /// refusing to start, default: false
+    #[serde(default)]
+    pub clamp_after: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NotificationSettings {
+    /// SQS queue receiving S3 new-object notifications (direct or via SNS) for the ingest bucket
+    pub queue_url: String,
+    /// Queue region, default: ingest region
+    pub region: Option<String>,
+    /// Long poll duration (secs, max 20), default: 20
+    #[serde(default = "default_notification_wait_secs")]
+    pub wait_secs: i32,
+    /// Max messages per receive (max 10), default: 10
+    #[serde(default = "default_notification_max_messages")]
+    pub max_messages: i32,
 }
 
 impl CurrentSettings {
     pub fn after_utc(&self) -> DateTime<Utc> {
         Utc.from_utc_datetime(&self.after)
     }
+
+    pub fn before_utc(&self) -> Option<DateTime<Utc>> {
+        self.before.map(|before| Utc.from_utc_datetime(&before))
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -27,6 +77,457 @@ pub struct RedisSettings {
     /// redis connection pool size, default: 16
     #[serde(default = "default_redis_pool_size")]
     pub pool_size: usize,
+    /// Optional back-pressure based on the poc_id stream consumer group lag
+    pub backpressure: Option<BackpressureSettings>,
+    /// Trim the poc_id stream to about this many entries (MAXLEN ~) after each
+    /// published chunk, default: unbounded
+    pub poc_id_maxlen: Option<u64>,
+    /// Cap the event streams (tick_summary, hotspot_first_seen) at about this
+    /// many entries (XADD MAXLEN ~), default: unbounded
+    pub event_maxlen: Option<u64>,
+    /// Max poc_ids published per second across all files, default: unlimited
+    pub poc_id_rate: Option<u32>,
+    /// Optional GEO set of hotspot locations (pub_key members), new hotspots
+    /// are added as they are stored, see `geo rebuild`, default: none
+    pub hotspot_geo_key: Option<String>,
+    /// Entries of the poc_id stream: `<poc_id>: done` (and `lineage_id`) with
+    /// poc_id, otherwise a `json` field with the beacon or summary, default: poc_id
+    #[serde(default)]
+    pub payload: PocPayload,
+    /// Stream receiving an entry per stored poc, default: poc_id
+    #[serde(default = "default_redis_poc_stream")]
+    pub poc_stream: String,
+    /// Field holding the poc_id of poc_id stream entries, as `<field>: <poc_id>`
+    /// instead of `<poc_id>: done`, default: none
+    pub poc_id_field: Option<String>,
+    /// Prefix of every stream name (the poc stream and the event streams), e.g.
+    /// `us-east:` for ETL instances sharing a redis, default: ""
+    #[serde(default)]
+    pub key_prefix: String,
+    /// Poc stream entries of a chunk sent per pipelined round trip, 1 sends
+    /// them one XADD at a time, default: 100
+    #[serde(default = "default_redis_pipeline_size")]
+    pub pipeline_size: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackpressureSettings {
+    /// Consumer group on the poc_id stream to monitor
+    pub group: String,
+    /// Pause ingestion while the group's lag exceeds this many entries, default: 100000
+    #[serde(default = "default_backpressure_max_lag")]
+    pub max_lag: u64,
+    /// Time to pause (ms) before checking the lag again, default: 1000
+    #[serde(default = "default_backpressure_pause_ms")]
+    pub pause_ms: u64,
+    /// Fail the chunk (retrying its file) once paused this many secs, default: 600
+    #[serde(default = "default_backpressure_max_wait_secs")]
+    pub max_wait_secs: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MaintenanceScript {
+    /// Unique script name, used as the key in the maintenance collection
+    pub name: String,
+    /// Cron-like schedule (UTC), e.g. "0 3 * * *" or "@daily"
+    pub schedule: String,
+    /// AQL statement to run
+    pub query: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EdgeWeightSettings {
+    /// Cron-like schedule (UTC) for refreshing edge weights, default: "@hourly"
+    #[serde(default = "default_edge_weight_schedule")]
+    pub schedule: String,
+    /// AQL expression of `count`, `age_days` (since last seen) and `distance` (km),
+    /// normalized to [0, 1] across all edges, default: see default_edge_weight_formula
+    #[serde(default = "default_edge_weight_formula")]
+    pub formula: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DenylistSettings {
+    /// Url the denylist csv is fetched from, e.g. a helium/denylist release's
+    /// denylist.csv, default: none (read `file`)
+    pub url: Option<String>,
+    /// Local denylist csv, used without a `url`, default: none
+    pub file: Option<PathBuf>,
+    /// Version the hotspots are tagged with, default: a hash of the list
+    pub version: Option<String>,
+    /// How often the list is reloaded and the hotspots retagged (secs), default: 3600
+    #[serde(default = "default_denylist_refresh_secs")]
+    pub refresh_secs: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    /// Snappy compressed, with typed columns
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Parquet => "parquet",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RollupExportSettings {
+    /// S3 bucket the rollups are uploaded to
+    pub bucket: String,
+    /// Key prefix, files are stored as `{prefix}/{date}/hotspot_stats.csv`,
+    /// default: "rollups"
+    #[serde(default = "default_rollup_export_prefix")]
+    pub prefix: String,
+    /// Bucket region, default: the region of the aws environment
+    pub region: Option<String>,
+    /// Formats each rollup is written in, default: ["csv", "parquet"]
+    #[serde(default = "default_rollup_export_formats")]
+    pub formats: Vec<ExportFormat>,
+    /// Cron-like schedule (UTC), default: "@daily"
+    #[serde(default = "default_rollup_export_schedule")]
+    pub schedule: String,
+    /// Documents read per query, default: 10000
+    #[serde(default = "default_rollup_export_batch_size")]
+    pub batch_size: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CommunitySettings {
+    /// Pregel algorithm, default: label_propagation
+    #[serde(default)]
+    pub algorithm: CommunityAlgorithm,
+    /// Max number of global supersteps, default: 50
+    #[serde(default = "default_community_max_gss")]
+    pub max_gss: u64,
+    /// Hotspot attribute the community id is stored in, default: "community"
+    #[serde(default = "default_community_result_field")]
+    pub result_field: String,
+    /// Pregel parallelism, default: server default
+    pub parallelism: Option<u64>,
+    /// Job status poll interval (secs), default: 5
+    #[serde(default = "default_community_poll_secs")]
+    pub poll_secs: u64,
+}
+
+impl Default for CommunitySettings {
+    fn default() -> Self {
+        Self {
+            algorithm: CommunityAlgorithm::default(),
+            max_gss: default_community_max_gss(),
+            result_field: default_community_result_field(),
+            parallelism: None,
+            poll_secs: default_community_poll_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum CommunityAlgorithm {
+    /// Label propagation, communities of densely connected hotspots
+    #[default]
+    LabelPropagation,
+    /// Weakly connected components
+    Wcc,
+    /// Strongly connected components
+    Scc,
+}
+
+/// Storage backend of the ingested documents
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum SinkKind {
+    /// Graph of beacon and hotspot documents and witness edges
+    #[default]
+    Arangodb,
+    /// Relational beacons, witnesses, hotspots and edges tables
+    Postgres,
+    /// Flattened witness rows for columnar analytics
+    Clickhouse,
+    /// Beacon documents as JSON Lines on stdout, without any bookkeeping
+    StdoutJsonl,
+}
+
+/// One or more S3 ingest buckets, a single `[ingest]` table or an `[[ingest]]`
+/// array of them (e.g. the US915 and EU868 buckets)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum IngestBuckets {
+    One(FSettings),
+    Many(Vec<FSettings>),
+}
+
+impl IngestBuckets {
+    pub fn all(&self) -> Vec<&FSettings> {
+        match self {
+            Self::One(bucket) => vec![bucket],
+            Self::Many(buckets) => buckets.iter().collect(),
+        }
+    }
+
+    /// The first bucket, whose region is the default one of S3 notifications
+    pub fn primary(&self) -> Option<&FSettings> {
+        self.all().into_iter().next()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct IngestLimitSettings {
+    /// Max ingest bucket requests (listings and file downloads) per second,
+    /// default: unlimited
+    pub requests_per_sec: Option<u32>,
+    /// Max files downloaded at once, default: unlimited (bounded by
+    /// max_concurrent_files and prefetch_files)
+    pub max_concurrent_gets: Option<usize>,
+}
+
+/// Bucket the ingest files are listed and streamed from
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IngestSource {
+    /// The `[ingest]` S3 bucket
+    #[default]
+    S3,
+    /// The `[gcs]` Google Cloud Storage bucket
+    Gcs,
+    /// The `[local]` directory
+    Local,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GcsSettings {
+    /// Bucket holding the oracle files, named like in the S3 bucket
+    pub bucket: String,
+    /// Service account key (json) file, default: application default
+    /// credentials (GOOGLE_APPLICATION_CREDENTIALS or the metadata server)
+    pub credentials_file: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LocalSettings {
+    /// Directory holding the ingest files, named like the S3 keys
+    /// (e.g. iot_poc.1687888130980.gz)
+    pub dir: PathBuf,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PostgresSettings {
+    /// Connection url, default: postgres://postgres@localhost:5432/iot
+    #[serde(default = "default_postgres_url")]
+    pub url: String,
+    /// Connection pool size, default: 16
+    #[serde(default = "default_postgres_pool_size")]
+    pub pool_size: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ClickhouseSettings {
+    /// HTTP interface url, default: http://localhost:8123
+    #[serde(default = "default_clickhouse_url")]
+    pub url: String,
+    /// default: default
+    #[serde(default = "default_clickhouse_database")]
+    pub database: String,
+    /// default: default
+    #[serde(default = "default_clickhouse_user")]
+    pub user: String,
+    /// default: ""
+    #[serde(default)]
+    pub password: String,
+    /// Table of the flattened witness rows, default: witness_rows
+    #[serde(default = "default_clickhouse_table")]
+    pub table: String,
+    /// Also write the witness rows when ingesting into another sink, default: false
+    #[serde(default)]
+    pub mirror: bool,
+    /// Format witness rows are inserted in, default: arrow
+    #[serde(default)]
+    pub insert_format: ClickhouseFormat,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClickhouseFormat {
+    /// Arrow record batches built directly from the rows (`FORMAT ArrowStream`)
+    #[default]
+    Arrow,
+    /// One json object per row (`FORMAT JSONEachRow`)
+    JsonEachRow,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct KafkaNotifierSettings {
+    /// Comma separated bootstrap servers, default: localhost:9092
+    #[serde(default = "default_kafka_brokers")]
+    pub brokers: String,
+    /// Topic receiving a record (keyed by poc_id) per stored poc, default: poc_id
+    #[serde(default = "default_kafka_poc_topic")]
+    pub poc_topic: String,
+    /// Prefix of the event topics (hotspot_first_seen, tick_summary), default: ""
+    #[serde(default)]
+    pub topic_prefix: String,
+    /// Payload of the poc topic records, default: poc_id
+    #[serde(default)]
+    pub payload: PocPayload,
+    /// Additional librdkafka producer properties, e.g. `security.protocol`
+    #[serde(default)]
+    pub properties: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NatsNotifierSettings {
+    /// Server url, default: nats://localhost:4222
+    #[serde(default = "default_nats_url")]
+    pub url: String,
+    /// Optional credentials (.creds) file
+    pub credentials_file: Option<String>,
+    /// Subject of the message per stored poc, `{poc_id}` is replaced, default: arango_etl.poc_id
+    #[serde(default = "default_nats_poc_subject")]
+    pub poc_subject: String,
+    /// Subject of events, `{event}` is replaced by hotspot_first_seen or
+    /// tick_summary, default: arango_etl.{event}
+    #[serde(default = "default_nats_event_subject")]
+    pub event_subject: String,
+    /// Payload of the poc messages, default: poc_id
+    #[serde(default)]
+    pub payload: PocPayload,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebhookSettings {
+    /// Url the events are POSTed to
+    pub url: String,
+    /// Secret signing the request bodies (HMAC-SHA256, `X-Signature-256`
+    /// header), default: unsigned
+    pub secret: Option<String>,
+    /// Also POST the stored pocs of each chunk, default: false
+    #[serde(default)]
+    pub pocs: bool,
+    /// Payload of the stored pocs, default: poc_id
+    #[serde(default)]
+    pub payload: PocPayload,
+    /// Request timeout (secs), default: 10
+    #[serde(default = "default_webhook_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Retries of requests failing with a connection error, 429 or 5xx, default: see RetrySettings
+    #[serde(default)]
+    pub retry: RetrySettings,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HealthSettings {
+    /// Address `/healthz` and `/readyz` listen on, default: 0.0.0.0:8082
+    #[serde(default = "default_health_listen")]
+    pub listen: SocketAddr,
+    /// Secs since the last finished tick (or the start) after which the
+    /// tracker is considered wedged and `/healthz` fails, default: 3600
+    #[serde(default = "default_health_max_tick_age_secs")]
+    pub max_tick_age_secs: u64,
+    /// Timeout of each `/readyz` check (secs), default: 5
+    #[serde(default = "default_health_check_timeout_secs")]
+    pub check_timeout_secs: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LiveFeedSettings {
+    /// Address the WebSocket feed listens on, default: 127.0.0.1:8081
+    #[serde(default = "default_live_feed_listen")]
+    pub listen: SocketAddr,
+    /// Summaries buffered for slow clients before they skip ahead, default: 1024
+    #[serde(default = "default_live_feed_buffer")]
+    pub buffer: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PocPayload {
+    /// `{"poc_id": ..., "lineage_id": ...}`
+    #[default]
+    PocId,
+    /// The stored beacon json (with its witnesses)
+    Beacon,
+    /// `{"poc_id": ..., "beaconer": ..., "witnesses": ..., "lineage_id": ...}`,
+    /// with the beaconer's pub_key and the witness count
+    Summary,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct KafkaSettings {
+    /// Comma separated bootstrap servers, default: localhost:9092
+    #[serde(default = "default_kafka_brokers")]
+    pub brokers: String,
+    /// Topic of the encoded LoraPocV1 messages
+    pub topic: String,
+    /// Consumer group, default: arango-etl
+    #[serde(default = "default_kafka_group_id")]
+    pub group_id: String,
+    /// Max messages written per chunk, default: file_chunk_size
+    pub batch_size: Option<usize>,
+    /// Max time (ms) to wait for a chunk to fill up, default: 1000
+    #[serde(default = "default_kafka_linger_ms")]
+    pub linger_ms: u64,
+    /// Time (ms) to wait before retrying a chunk which failed to be written, default: 5000
+    #[serde(default = "default_kafka_retry_ms")]
+    pub retry_ms: u64,
+    /// Additional librdkafka consumer properties, e.g. `security.protocol`
+    #[serde(default)]
+    pub properties: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RuntimeSettings {
+    /// Number of tokio worker threads, default: number of cpu cores
+    pub worker_threads: Option<usize>,
+    /// Max number of tokio blocking threads, default: 512
+    pub max_blocking_threads: Option<usize>,
+    /// Thread stack size (bytes), default: 2MiB
+    pub thread_stack_size: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// Human readable lines
+    #[default]
+    Fmt,
+    /// One json object per event, for log aggregators
+    Json,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TelemetrySettings {
+    /// Format of the logs written to stdout, default: fmt
+    #[serde(default)]
+    pub format: LogFormat,
+    /// Write the logs to stderr instead, default: false (always with the
+    /// stdout_jsonl sink)
+    #[serde(default)]
+    pub stderr: bool,
+    /// OTLP (grpc) endpoint spans are exported to, e.g. http://localhost:4317,
+    /// default: none (no export)
+    pub otlp_endpoint: Option<String>,
+    /// Service name of the exported spans, default: arango-etl
+    #[serde(default = "default_telemetry_service_name")]
+    pub service_name: String,
+}
+
+impl Default for TelemetrySettings {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::default(),
+            stderr: false,
+            otlp_endpoint: None,
+            service_name: default_telemetry_service_name(),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -40,12 +541,463 @@ pub struct TrackerSettings {
 pub struct ArangoDBSettings {
     #[serde(default = "default_arangodb_endpoint")]
     pub endpoint: String,
+    /// Optional read-only endpoint (e.g. follower/analytics instance) used for
+    /// stats, analytics, export and API queries, default: endpoint
+    pub read_endpoint: Option<String>,
     #[serde(default = "default_arangodb_user")]
     pub user: String,
     #[serde(default = "default_arangodb_password")]
     pub password: String,
     #[serde(default = "default_arangodb_database")]
     pub database: String,
+    /// Authentication method, default: basic
+    #[serde(default)]
+    pub auth: ArangoAuth,
+    /// Secs between JWT refreshes in `current` mode (the server's session timeout
+    /// defaults to an hour), only with jwt auth, default: 1800
+    #[serde(default = "default_jwt_refresh_secs")]
+    pub jwt_refresh_secs: u64,
+    /// Max serialized beacon document size (bytes), default: 4MiB
+    #[serde(default = "default_max_document_size")]
+    pub max_document_size: usize,
+    /// What to do with beacon documents above max_document_size, default: strip_geo
+    #[serde(default)]
+    pub overflow_strategy: OverflowStrategy,
+    /// Indexes to create on the collections, default: see default_indexes
+    #[serde(default = "default_indexes")]
+    pub indexes: Vec<IndexConfig>,
+    /// Optional location data minimization applied to all stored documents
+    pub privacy: Option<PrivacySettings>,
+    /// Optional allow/deny list of hotspot pub_keys applied during ingestion
+    pub filter: Option<FilterSettings>,
+    /// Optional maker and asserted metadata attached to hotspot documents
+    pub metadata: Option<MetadataSettings>,
+    /// Where to keep the raw LoraPocV1 bytes of each poc, default: off
+    #[serde(default)]
+    pub raw_pocs: RawPocStorage,
+    /// Number of known hotspots cached in-process to skip redundant witness
+    /// hotspot upserts, 0 disables the cache, default: 100000
+    #[serde(default = "default_hotspot_cache_capacity")]
+    pub hotspot_cache_capacity: usize,
+    /// How hotspot document keys (and so witness edge `_from`/`_to`) are derived
+    /// from pub_keys, changing it needs `migrate hotspot-keys`, default: pub_key
+    #[serde(default)]
+    pub hotspot_keys: KeyStrategy,
+    /// Also bucket witness edge histograms per day or week, default: disabled
+    pub edge_windows: Option<EdgeWindowSettings>,
+    /// Aggregate per parent hex activity into the hexes collection while
+    /// ingesting, default: false
+    #[serde(default)]
+    pub hex_coverage: bool,
+    /// Keep the `reciprocal_count` of witness edges whose reverse edge exists
+    /// up to date while ingesting, default: false
+    #[serde(default)]
+    pub reciprocal_edges: bool,
+    /// Start even if a newer schema version already wrote to the database,
+    /// leaving its collection metadata as is, default: false
+    #[serde(default)]
+    pub allow_downgrade: bool,
+    /// Optional expiry of old beacons, file records and witness edges
+    pub retention: Option<RetentionSettings>,
+    /// Collection names, default: see CollectionNames
+    #[serde(default)]
+    pub collections: CollectionNames,
+    /// Optional named graph of the hotspots and witness edges, created on startup
+    /// if missing, for traversals in any direction, default: none
+    pub graph: Option<String>,
+    /// Retries of transient errors, default: see RetrySettings
+    #[serde(default)]
+    pub retry: RetrySettings,
+    /// Optional TLS options for https endpoints, default: system roots
+    pub tls: Option<TlsSettings>,
+    /// Optional read back of a sample of written pocs, default: off
+    pub shadow_verify: Option<ShadowVerifySettings>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ShadowVerifySettings {
+    /// Fraction of pocs whose beacon and witness edges are read back right
+    /// after being written and compared, default: 0.001
+    #[serde(default = "default_shadow_verify_sample_rate")]
+    pub sample_rate: f64,
+}
+
+impl ArangoDBSettings {
+    /// Configured indexes plus the TTL indexes of the retention policy,
+    /// on the configured collection names
+    pub fn all_indexes(&self) -> Vec<IndexConfig> {
+        let mut indexes = self.indexes.clone();
+        if let Some(retention) = &self.retention {
+            indexes.extend(retention.ttl_indexes());
+        }
+        if self.hotspot_keys != KeyStrategy::PubKey {
+            indexes.push(IndexConfig::persistent(
+                HOTSPOT_COLLECTION,
+                "hotspot_pub_key",
+                "pub_key",
+            ));
+        }
+        for index in indexes.iter_mut() {
+            index.collection = self.collections.resolve(&index.collection).to_string();
+        }
+        indexes
+    }
+}
+
+/// Collection names, so deployments can namespace their collections (e.g.
+/// `iot_beacons_us915`) and run multiple instances against one database.
+/// Unset names default to the plain collection name (e.g. `beacons`).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct CollectionNames {
+    pub beacons: String,
+    pub hotspots: String,
+    pub witnesses: String,
+    pub files: String,
+    pub dead_letters: String,
+    pub maintenance: String,
+    pub hotspot_stats: String,
+    pub rewards: String,
+    pub invalid_beacons: String,
+    pub invalid_witnesses: String,
+    pub raw_pocs: String,
+    pub verification_runs: String,
+    pub checkpoints: String,
+    pub meta: String,
+    pub hexes: String,
+    pub audit_log: String,
+}
+
+impl Default for CollectionNames {
+    fn default() -> Self {
+        Self {
+            beacons: BEACON_COLLECTION.to_string(),
+            hotspots: HOTSPOT_COLLECTION.to_string(),
+            witnesses: WITNESS_EDGE_COLLECTION.to_string(),
+            files: FILES_COLLECTION.to_string(),
+            dead_letters: DEAD_LETTER_COLLECTION.to_string(),
+            maintenance: MAINTENANCE_COLLECTION.to_string(),
+            hotspot_stats: HOTSPOT_STATS_COLLECTION.to_string(),
+            rewards: REWARDS_COLLECTION.to_string(),
+            invalid_beacons: INVALID_BEACON_COLLECTION.to_string(),
+            invalid_witnesses: INVALID_WITNESS_COLLECTION.to_string(),
+            raw_pocs: RAW_POC_COLLECTION.to_string(),
+            verification_runs: VERIFICATION_RUNS_COLLECTION.to_string(),
+            checkpoints: CHECKPOINTS_COLLECTION.to_string(),
+            meta: META_COLLECTION.to_string(),
+            hexes: HEX_COLLECTION.to_string(),
+            audit_log: AUDIT_LOG_COLLECTION.to_string(),
+        }
+    }
+}
+
+impl CollectionNames {
+    /// Configured name of a collection given its default name (as used by index
+    /// configs and migrations), any other name is returned as is
+    pub fn resolve<'a>(&'a self, name: &'a str) -> &'a str {
+        match name {
+            BEACON_COLLECTION => &self.beacons,
+            HOTSPOT_COLLECTION => &self.hotspots,
+            WITNESS_EDGE_COLLECTION => &self.witnesses,
+            FILES_COLLECTION => &self.files,
+            DEAD_LETTER_COLLECTION => &self.dead_letters,
+            MAINTENANCE_COLLECTION => &self.maintenance,
+            HOTSPOT_STATS_COLLECTION => &self.hotspot_stats,
+            REWARDS_COLLECTION => &self.rewards,
+            INVALID_BEACON_COLLECTION => &self.invalid_beacons,
+            INVALID_WITNESS_COLLECTION => &self.invalid_witnesses,
+            RAW_POC_COLLECTION => &self.raw_pocs,
+            VERIFICATION_RUNS_COLLECTION => &self.verification_runs,
+            CHECKPOINTS_COLLECTION => &self.checkpoints,
+            META_COLLECTION => &self.meta,
+            HEX_COLLECTION => &self.hexes,
+            AUDIT_LOG_COLLECTION => &self.audit_log,
+            name => name,
+        }
+    }
+
+    /// Default and configured name of every collection
+    pub fn all(&self) -> Vec<(&'static str, &str)> {
+        [
+            BEACON_COLLECTION,
+            HOTSPOT_COLLECTION,
+            WITNESS_EDGE_COLLECTION,
+            FILES_COLLECTION,
+            DEAD_LETTER_COLLECTION,
+            MAINTENANCE_COLLECTION,
+            HOTSPOT_STATS_COLLECTION,
+            REWARDS_COLLECTION,
+            INVALID_BEACON_COLLECTION,
+            INVALID_WITNESS_COLLECTION,
+            RAW_POC_COLLECTION,
+            VERIFICATION_RUNS_COLLECTION,
+            CHECKPOINTS_COLLECTION,
+            META_COLLECTION,
+            HEX_COLLECTION,
+            AUDIT_LOG_COLLECTION,
+        ]
+        .into_iter()
+        .map(|name| (name, self.resolve(name)))
+        .collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RetrySettings {
+    /// Attempts of a query or document insert failing with a transient error
+    /// (connection errors, timeouts, 503), 1 disables retries, default: 5
+    #[serde(default = "default_retry_max_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry (ms), doubled for each further one, default: 100
+    #[serde(default = "default_retry_base_delay_ms")]
+    pub base_delay_ms: u64,
+    /// Upper bound of the delay between retries (ms), default: 10000
+    #[serde(default = "default_retry_max_delay_ms")]
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetrySettings {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_retry_max_attempts(),
+            base_delay_ms: default_retry_base_delay_ms(),
+            max_delay_ms: default_retry_max_delay_ms(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TlsSettings {
+    /// PEM file of an additional CA to trust, e.g. of self-signed server certs
+    pub ca_cert: Option<String>,
+    /// PEM files of a client certificate and its (PKCS#8) key for mutual TLS
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+    /// Accept any server certificate, for testing only, default: false
+    #[serde(default)]
+    pub insecure_skip_verify: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct EdgeWindowSettings {
+    /// Length of a bucket, default: day
+    #[serde(default)]
+    pub period: WindowPeriod,
+    /// Most recent buckets kept per edge, older ones are dropped when the edge
+    /// is next upserted, default: 30
+    #[serde(default = "default_edge_window_keep")]
+    pub keep: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowPeriod {
+    /// UTC days, keyed like 2023-06-27
+    #[default]
+    Day,
+    /// ISO weeks, keyed like 2023-W26
+    Week,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RetentionSettings {
+    /// Days after their ingest time beacons expire (TTL index), default: never
+    pub beacon_days: Option<u32>,
+    /// Days after the file timestamp file records expire (TTL index), default: never
+    pub file_days: Option<u32>,
+    /// Days after they were last seen witness edges are removed, default: never
+    pub edge_days: Option<u32>,
+    /// Cron-like schedule (UTC) for removing witness edges, default: "@daily"
+    #[serde(default = "default_retention_edge_schedule")]
+    pub edge_schedule: String,
+    /// Days after their ingest time the geo polygons (`geo`, `parent_geo`) of
+    /// beacons and their witnesses are stripped, default: never
+    pub geo_days: Option<u32>,
+    /// Cron-like schedule (UTC) for stripping geo polygons, default: "@daily"
+    #[serde(default = "default_retention_geo_schedule")]
+    pub geo_schedule: String,
+}
+
+impl RetentionSettings {
+    /// TTL indexes expiring beacons and file records. ArangoDB TTL indexes need
+    /// seconds or date strings, so these are on the RFC3339 time fields.
+    pub fn ttl_indexes(&self) -> Vec<IndexConfig> {
+        let mut indexes = vec![];
+        if let Some(days) = self.beacon_days {
+            indexes.push(IndexConfig::ttl(
+                "beacons",
+                "beacon_ttl",
+                "ingest_time",
+                days_secs(days),
+            ));
+        }
+        if let Some(days) = self.file_days {
+            indexes.push(IndexConfig::ttl(
+                "files",
+                "file_ttl",
+                "timestamp",
+                days_secs(days),
+            ));
+        }
+        indexes
+    }
+}
+
+fn days_secs(days: u32) -> u32 {
+    days.saturating_mul(86_400)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FilterSettings {
+    /// Only ingest pocs beaconed or witnessed by one of these pub_keys, default: all
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Never ingest pocs beaconed by, or witnesses of, these pub_keys, default: none
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MetadataSettings {
+    /// Csv with a header and the columns pub_key, maker, region, gain (dBi * 10)
+    /// and elevation (m), e.g. exported from iot-config's gateway info
+    pub file: PathBuf,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PrivacySettings {
+    /// Locations finer than this H3 resolution are minimized, default: 8
+    #[serde(default = "default_privacy_resolution")]
+    pub resolution: u8,
+    /// How to minimize precise locations, default: omit
+    #[serde(default)]
+    pub mode: PrivacyMode,
+    /// Salt prepended to locations before hashing, default: ""
+    #[serde(default)]
+    pub salt: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PrivacyMode {
+    /// Drop precise location fields, keeping parent-level data
+    #[default]
+    Omit,
+    /// Replace str_location with a salted sha256 hash, dropping the other precise fields
+    Hash,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexType {
+    Persistent,
+    Skiplist,
+    Hash,
+    Geo,
+    Ttl,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IndexConfig {
+    /// Collection the index belongs to
+    pub collection: String,
+    /// Index name
+    pub name: String,
+    /// Indexed attribute paths
+    pub fields: Vec<String>,
+    #[serde(rename = "type")]
+    pub index_type: IndexType,
+    /// Ignored for geo indexes, default: false
+    #[serde(default)]
+    pub unique: bool,
+    /// Ignored for geo indexes, default: false
+    #[serde(default)]
+    pub sparse: bool,
+    /// Only used by geo indexes, default: true
+    #[serde(default = "default_geo_json")]
+    pub geo_json: bool,
+    /// Only used by ttl indexes, seconds after the indexed time a document expires, default: 0
+    #[serde(default)]
+    pub expire_after: u32,
+}
+
+impl IndexConfig {
+    fn new(collection: &str, name: &str, field: &str, index_type: IndexType, sparse: bool) -> Self {
+        Self {
+            collection: collection.to_string(),
+            name: name.to_string(),
+            fields: vec![field.to_string()],
+            index_type,
+            unique: false,
+            sparse,
+            geo_json: true,
+            expire_after: 0,
+        }
+    }
+
+    pub fn persistent(collection: &str, name: &str, field: &str) -> Self {
+        Self::new(collection, name, field, IndexType::Persistent, false)
+    }
+
+    pub fn skiplist(collection: &str, name: &str, field: &str) -> Self {
+        Self::new(collection, name, field, IndexType::Skiplist, true)
+    }
+
+    pub fn geo(collection: &str, name: &str, field: &str) -> Self {
+        Self::new(collection, name, field, IndexType::Geo, false)
+    }
+
+    pub fn ttl(collection: &str, name: &str, field: &str, expire_after: u32) -> Self {
+        Self {
+            expire_after,
+            ..Self::new(collection, name, field, IndexType::Ttl, false)
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowStrategy {
+    /// Drop witness geo polygons, then truncate witnesses if still too large
+    #[default]
+    StripGeo,
+    /// Drop trailing (unselected first) witnesses until the document fits
+    Truncate,
+    /// Skip the beacon document and record it in the dead letter collection
+    DeadLetter,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyStrategy {
+    /// The pub_key string itself
+    #[default]
+    PubKey,
+    /// First 16 bytes of the blake3 hash of the binary pub_key, lowercase
+    /// base32 (26 chars), with the pub_key in an indexed `pub_key` field
+    Blake3,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArangoAuth {
+    /// Basic auth header on every request
+    #[default]
+    Basic,
+    /// JWT obtained with the user and password, renewed on reconnect
+    Jwt,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RawPocStorage {
+    /// Don't store the raw poc
+    #[default]
+    Off,
+    /// Attach the base64 raw poc to the beacon document as `raw_poc`
+    Inline,
+    /// Write the base64 raw poc to the raw_pocs collection, keyed by poc_id
+    Collection,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -53,6 +1005,15 @@ pub struct Settings {
     // Configure logging level = debug
     #[serde(default = "default_log")]
     pub log: String,
+    // Configure log format and span export
+    #[serde(default)]
+    pub telemetry: TelemetrySettings,
+    // Configure the H3 resolution (0-15) of the parent_* location fields, default 5
+    #[serde(default = "default_parent_resolution")]
+    pub parent_resolution: u8,
+    // Configure extra H3 resolutions (0-15) stored as loc_res{N} fields, e.g. [5, 7, 9], default none
+    #[serde(default)]
+    pub resolutions: Vec<u8>,
     // Configure max_concurrent_files when sourcing iot-poc files from S3 bucket
     #[serde(default = "default_max_concurrent_files")]
     pub max_concurrent_files: usize,
@@ -62,19 +1023,85 @@ pub struct Settings {
     // Configure max_processing_capacity (limit number of concurrent tasks)
     #[serde(default = "default_max_processing_capacity")]
     pub max_processing_capacity: usize,
+    // Configure prefetch_files (files downloaded ahead of processing, 0 = stream on demand)
+    #[serde(default)]
+    pub prefetch_files: usize,
+    // Configure prefetch_memory_budget (max compressed bytes, as listed in the bucket, of
+    // prefetched files held in memory; decoded files take a multiple of that)
+    #[serde(default = "default_prefetch_memory_budget")]
+    pub prefetch_memory_budget: usize,
     // Configure max_retries for one poc file
     #[serde(default = "default_max_retries")]
     pub max_retries: u8,
-    // Configure ingest file store settings
-    pub ingest: FSettings,
+    // Configure file_heartbeat_secs (how often a file being processed is marked alive)
+    #[serde(default = "default_file_heartbeat_secs")]
+    pub file_heartbeat_secs: u64,
+    // Configure stale_file_secs (heartbeat age after which a processing file is reset to pending)
+    #[serde(default = "default_stale_file_secs")]
+    pub stale_file_secs: u64,
+    // Configure whether iot_reward_share files are ingested into the rewards collection
+    #[serde(default)]
+    pub ingest_rewards: bool,
+    // Configure whether invalid beacon/witness report files are ingested
+    #[serde(default)]
+    pub ingest_invalid: bool,
+    // Configure ingest file store settings, one bucket or a list of them
+    pub ingest: IngestBuckets,
+    // Configure where ingest files are read from (s3 or gcs)
+    #[serde(default)]
+    pub ingest_source: IngestSource,
+    // Configure the google cloud storage bucket of ingest_source = "gcs"
+    pub gcs: Option<GcsSettings>,
+    // Configure the directory of ingest_source = "local"
+    pub local: Option<LocalSettings>,
+    // Configure rate and concurrency limits of ingest bucket requests
+    #[serde(default)]
+    pub ingest_limits: IngestLimitSettings,
     // Configure arangodb settings
     pub arangodb: ArangoDBSettings,
+    // Configure where ingested documents are stored (arangodb, postgres or clickhouse)
+    #[serde(default)]
+    pub sink: SinkKind,
+    // Configure postgres settings, used with sink = "postgres"
+    pub postgres: Option<PostgresSettings>,
+    // Configure clickhouse settings, used with sink = "clickhouse" or as a mirror
+    pub clickhouse: Option<ClickhouseSettings>,
     // Configure current tracker settings
     pub tracker: TrackerSettings,
     // Configure current mode settings
     pub current: CurrentSettings,
     // Configure redis settings
     pub redis: Option<RedisSettings>,
+    // Configure the kafka topic consumed by the kafka command
+    pub kafka: Option<KafkaSettings>,
+    // Configure publishing stored pocs and events to kafka, next to or instead of redis
+    pub kafka_notifier: Option<KafkaNotifierSettings>,
+    // Configure publishing stored pocs and events to nats jetstream
+    pub nats_notifier: Option<NatsNotifierSettings>,
+    // Configure POSTing file events (and optionally stored pocs) to a webhook
+    pub webhook: Option<WebhookSettings>,
+    // Configure the liveness and readiness endpoints of the current mode
+    pub health: Option<HealthSettings>,
+    // Configure the WebSocket feed of stored beacons in current mode
+    pub live_feed: Option<LiveFeedSettings>,
+    // Configure tokio runtime settings
+    #[serde(default)]
+    pub runtime: RuntimeSettings,
+    // Configure AQL maintenance scripts run by the current tracker
+    #[serde(default)]
+    pub maintenance: Vec<MaintenanceScript>,
+    // Configure periodic edge weight computation
+    pub edge_weight: Option<EdgeWeightSettings>,
+    // Configure pregel community detection (analyze communities)
+    #[serde(default)]
+    pub communities: CommunitySettings,
+    // Configure tagging denylisted hotspots, refreshed by the current tracker
+    pub denylist: Option<DenylistSettings>,
+    // Configure the scheduled export of hotspot stats and witness edges to S3
+    pub rollup_export: Option<RollupExportSettings>,
+    // Set by --dry-run: process files without writing to the sink or publishing
+    #[serde(skip)]
+    pub dry_run: bool,
 }
 
 pub fn default_after_ts() -> NaiveDateTime {
@@ -83,10 +1110,62 @@ pub fn default_after_ts() -> NaiveDateTime {
     NaiveDateTime::from_timestamp_millis(1687888130980).unwrap()
 }
 
+pub fn default_webhook_timeout_secs() -> u64 {
+    10
+}
+
+pub fn default_health_listen() -> SocketAddr {
+    SocketAddr::from(([0, 0, 0, 0], 8082))
+}
+
+pub fn default_health_max_tick_age_secs() -> u64 {
+    3600
+}
+
+pub fn default_health_check_timeout_secs() -> u64 {
+    5
+}
+
+pub fn default_live_feed_listen() -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], 8081))
+}
+
+pub fn default_live_feed_buffer() -> usize {
+    1024
+}
+
+pub fn default_denylist_refresh_secs() -> u64 {
+    3600
+}
+
+pub fn default_rollup_export_prefix() -> String {
+    "rollups".to_string()
+}
+
+pub fn default_rollup_export_formats() -> Vec<ExportFormat> {
+    vec![ExportFormat::Csv, ExportFormat::Parquet]
+}
+
+pub fn default_rollup_export_schedule() -> String {
+    "@daily".to_string()
+}
+
+pub fn default_rollup_export_batch_size() -> u32 {
+    10_000
+}
+
 pub fn default_max_retries() -> u8 {
     3
 }
 
+pub fn default_file_heartbeat_secs() -> u64 {
+    30
+}
+
+pub fn default_stale_file_secs() -> u64 {
+    300
+}
+
 pub fn default_max_concurrent_files() -> usize {
     16
 }
@@ -99,6 +1178,11 @@ pub fn default_max_processing_capacity() -> usize {
     32
 }
 
+pub fn default_prefetch_memory_budget() -> usize {
+    // 256MiB
+    256 * 1024 * 1024
+}
+
 pub fn default_interval() -> i64 {
     10
 }
@@ -107,14 +1191,102 @@ pub fn default_log() -> String {
     "arango_etl=debug".to_string()
 }
 
+pub fn default_parent_resolution() -> u8 {
+    DEFAULT_PARENT_RESOLUTION
+}
+
+pub fn default_telemetry_service_name() -> String {
+    "arango-etl".to_string()
+}
+
 pub fn default_redis_pool_size() -> usize {
     16
 }
 
+pub fn default_backpressure_max_lag() -> u64 {
+    100_000
+}
+
+pub fn default_backpressure_pause_ms() -> u64 {
+    1000
+}
+
+pub fn default_backpressure_max_wait_secs() -> u64 {
+    600
+}
+
 pub fn default_redis_endpoint() -> String {
     "redis://localhost:6739".to_string()
 }
 
+pub fn default_postgres_url() -> String {
+    "postgres://postgres@localhost:5432/iot".to_string()
+}
+
+pub fn default_postgres_pool_size() -> usize {
+    16
+}
+
+pub fn default_clickhouse_url() -> String {
+    "http://localhost:8123".to_string()
+}
+
+pub fn default_clickhouse_database() -> String {
+    "default".to_string()
+}
+
+pub fn default_clickhouse_user() -> String {
+    "default".to_string()
+}
+
+pub fn default_clickhouse_table() -> String {
+    "witness_rows".to_string()
+}
+
+pub fn default_shadow_verify_sample_rate() -> f64 {
+    0.001
+}
+
+pub fn default_kafka_brokers() -> String {
+    "localhost:9092".to_string()
+}
+
+pub fn default_nats_url() -> String {
+    "nats://localhost:4222".to_string()
+}
+
+pub fn default_nats_poc_subject() -> String {
+    "arango_etl.poc_id".to_string()
+}
+
+pub fn default_nats_event_subject() -> String {
+    "arango_etl.{event}".to_string()
+}
+
+pub fn default_redis_pipeline_size() -> usize {
+    100
+}
+
+pub fn default_redis_poc_stream() -> String {
+    "poc_id".to_string()
+}
+
+pub fn default_kafka_poc_topic() -> String {
+    "poc_id".to_string()
+}
+
+pub fn default_kafka_group_id() -> String {
+    "arango-etl".to_string()
+}
+
+pub fn default_kafka_linger_ms() -> u64 {
+    1000
+}
+
+pub fn default_kafka_retry_ms() -> u64 {
+    5000
+}
+
 pub fn default_arangodb_endpoint() -> String {
     "http://localhost:8925".to_string()
 }
@@ -131,6 +1303,120 @@ pub fn default_arangodb_database() -> String {
     "iot".to_string()
 }
 
+pub fn default_privacy_resolution() -> u8 {
+    8
+}
+
+pub fn default_geo_json() -> bool {
+    true
+}
+
+pub fn default_indexes() -> Vec<IndexConfig> {
+    vec![
+        IndexConfig::persistent("beacons", "beacon_pub_key", "pub_key"),
+        IndexConfig::skiplist("beacons", "beacon_ingest_time_ms", "ingest_time_unix_ms"),
+        IndexConfig::geo("beacons", "beacon_geo_index", "geo"),
+        IndexConfig::persistent("beacons", "beacon_witness_count", "witness_count"),
+        IndexConfig::persistent(
+            "beacons",
+            "beacon_selected_witness_count",
+            "selected_witness_count",
+        ),
+        IndexConfig::skiplist("files", "file_ts_ms", "timestamp_unix_ms"),
+        IndexConfig::skiplist("files", "file_size", "size"),
+        IndexConfig::persistent("files", "file_state", "state"),
+        IndexConfig::persistent("files", "file_done", "done"),
+        IndexConfig::persistent("witnesses", "witness_count", "count"),
+        IndexConfig::persistent("witnesses", "beacon_witness_distance", "distance"),
+        IndexConfig::skiplist("witnesses", "witness_last_seen_ms", "last_seen_unix_ms"),
+        IndexConfig::geo("hotspots", "hotspot_geo_index", "geo"),
+        IndexConfig::geo("hotspots", "hotspot_parent_geo_index", "parent_geo"),
+        IndexConfig::skiplist("hotspots", "hotspot_denylisted", "denylisted"),
+        IndexConfig::persistent("rewards", "reward_pub_key", "pub_key"),
+        IndexConfig::skiplist("rewards", "reward_start_period", "start_period_unix_ms"),
+        IndexConfig::persistent("invalid_beacons", "invalid_beacon_reason", "reason"),
+        IndexConfig::persistent("invalid_beacons", "invalid_beacon_pub_key", "pub_key"),
+        IndexConfig::persistent("invalid_witnesses", "invalid_witness_reason", "reason"),
+        IndexConfig::persistent("invalid_witnesses", "invalid_witness_pub_key", "pub_key"),
+        IndexConfig::persistent("audit_log", "audit_action", "action"),
+        IndexConfig::skiplist("audit_log", "audit_started_ms", "started_unix_ms"),
+    ]
+}
+
+pub fn default_max_document_size() -> usize {
+    4 * 1024 * 1024
+}
+
+pub fn default_hotspot_cache_capacity() -> usize {
+    100_000
+}
+
+pub fn default_jwt_refresh_secs() -> u64 {
+    1800
+}
+
+pub fn default_retry_max_attempts() -> u32 {
+    5
+}
+
+pub fn default_retry_base_delay_ms() -> u64 {
+    100
+}
+
+pub fn default_retry_max_delay_ms() -> u64 {
+    10_000
+}
+
+pub fn default_drain_timeout() -> u64 {
+    60
+}
+
+pub fn default_max_ahead_secs() -> i64 {
+    300
+}
+
+pub fn default_notification_wait_secs() -> i32 {
+    20
+}
+
+pub fn default_notification_max_messages() -> i32 {
+    10
+}
+
+pub fn default_community_max_gss() -> u64 {
+    50
+}
+
+pub fn default_community_result_field() -> String {
+    "community".to_string()
+}
+
+pub fn default_community_poll_secs() -> u64 {
+    5
+}
+
+pub fn default_edge_window_keep() -> usize {
+    30
+}
+
+pub fn default_retention_edge_schedule() -> String {
+    "@daily".to_string()
+}
+
+pub fn default_retention_geo_schedule() -> String {
+    "@daily".to_string()
+}
+
+pub fn default_edge_weight_schedule() -> String {
+    "@hourly".to_string()
+}
+
+// NOTE: grows with the log of the witness count, halves every 30 days since the edge
+// was last seen and decays with distance (halved at 10km)
+pub fn default_edge_weight_formula() -> String {
+    "LOG2(1 + count) * POW(0.5, age_days / 30) / (1 + distance / 10)".to_string()
+}
+
 impl Settings {
     pub fn new<P: AsRef<Path>>(path: Option<P>) -> Result<Self, config::ConfigError> {
         let mut builder = Config::builder();
@@ -148,4 +1434,16 @@ impl Settings {
     pub fn interval(&self) -> Duration {
         Duration::seconds(self.tracker.interval)
     }
+
+    /// Whether a notifier publishes the full beacon json of each stored poc,
+    /// so the sinks keep it, see `Populated::beacon`. A dry run publishes nothing.
+    pub fn publishes_beacons(&self) -> bool {
+        let payloads = [
+            self.redis.as_ref().map(|s| s.payload),
+            self.kafka_notifier.as_ref().map(|s| s.payload),
+            self.nats_notifier.as_ref().map(|s| s.payload),
+            self.webhook.as_ref().filter(|s| s.pocs).map(|s| s.payload),
+        ];
+        !self.dry_run && payloads.contains(&Some(PocPayload::Beacon))
+    }
 }
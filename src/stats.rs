@@ -0,0 +1,467 @@
+use crate::{
+    document::{checkpoint::Checkpoint, timestamp::now_unix_ms},
+    handler::arangodb::DB,
+    settings::{CollectionNames, EdgeWeightSettings, MaintenanceScript},
+};
+use anyhow::{Context, Result};
+use arangors::{AqlOptions, AqlQuery};
+use chrono::{TimeZone, Utc};
+use helium_crypto::PublicKeyBinary;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+
+/// Number of hotspot stats read and written per batch
+const STATS_BATCH_SIZE: u32 = 1000;
+
+/// Checkpoint of the latest beacon ingest time folded into the beacon intervals
+pub const BEACON_INTERVALS_CHECKPOINT: &str = "beacon_intervals";
+
+/// Upper bounds (secs) and labels of the beacon interval histogram buckets, longer
+/// intervals are counted in `gt_24h`
+const INTERVAL_BUCKETS: [(f64, &str); 8] = [
+    (300.0, "le_5m"),
+    (900.0, "le_15m"),
+    (1800.0, "le_30m"),
+    (3600.0, "le_1h"),
+    (7200.0, "le_2h"),
+    (14400.0, "le_4h"),
+    (28800.0, "le_8h"),
+    (86400.0, "le_24h"),
+];
+
+/// Per-hotspot witness diversity, see `compute_hotspot_stats`
+pub const HOTSPOT_STATS_QUERY: &str = r#"
+    FOR h IN @@hotspots
+        LET counts = (FOR e IN @@witnesses FILTER e._from == h._id RETURN e.count)
+        LET total = SUM(counts)
+        FILTER total > 0
+        LET n = LENGTH(counts)
+        LET entropy = -SUM(FOR c IN counts LET p = c / total RETURN p * LOG2(p))
+        RETURN {
+            _key: h._key,
+            distinct_witnesses: n,
+            witness_count: total,
+            witness_entropy: entropy,
+            witness_entropy_normalized: n > 1 ? entropy / LOG2(n) : 0,
+            updated_unix_ms: DATE_NOW(),
+            snapshot_unix_ms: @snapshot_unix_ms,
+            watermark_unix_ms: @watermark_unix_ms
+        }"#;
+
+/// Pairwise coverage overlap within parent hexes, see `compute_hotspot_overlaps`
+pub const HOTSPOT_OVERLAPS_QUERY: &str = r#"
+    FOR h IN @@hotspots
+        FILTER h.parent_str_location != null
+        COLLECT hex = h.parent_str_location INTO group = h._id
+        LET sets = (
+            FOR id IN group
+                LET witnesses = UNIQUE(FOR e IN @@witnesses FILTER e._from == id RETURN e._to)
+                LET footprint = UNIQUE(
+                    FOR w IN witnesses
+                        LET loc = DOCUMENT(w).parent_str_location
+                        FILTER loc != null
+                        RETURN loc
+                )
+                RETURN { id, pub_key: DOCUMENT(id).pub_key || PARSE_IDENTIFIER(id).key, witnesses, footprint }
+        )
+        FOR a IN sets
+            LET overlaps = (
+                FOR b IN sets
+                    FILTER b.id != a.id
+                    LET witness_overlap = LENGTH(a.witnesses) + LENGTH(b.witnesses) > 0
+                        ? JACCARD(a.witnesses, b.witnesses) : 0
+                    LET footprint_overlap = LENGTH(a.footprint) + LENGTH(b.footprint) > 0
+                        ? JACCARD(a.footprint, b.footprint) : 0
+                    LET score = (witness_overlap + footprint_overlap) / 2
+                    FILTER score > 0
+                    SORT score DESC
+                    RETURN {
+                        pub_key: b.pub_key,
+                        witness_overlap,
+                        footprint_overlap,
+                        score
+                    }
+            )
+            RETURN {
+                _key: PARSE_IDENTIFIER(a.id).key,
+                hex: hex,
+                hex_overlaps: overlaps,
+                hex_overlap_max: MAX(overlaps[*].score) || 0,
+                hex_overlaps_snapshot_unix_ms: @snapshot_unix_ms
+            }"#;
+
+/// Compute per-hotspot statistics into the hotspot_stats collection.
+///
+/// Currently computed:
+/// - `distinct_witnesses`: number of distinct hotspots which witnessed the hotspot's beacons
+/// - `witness_count`: total number of witnesses of the hotspot's beacons
+/// - `witness_entropy`: shannon entropy (bits) of the distribution of those witnesses
+///   across distinct hotspots, low values mean the same few hotspots keep witnessing
+/// - `witness_entropy_normalized`: witness_entropy / log2(distinct_witnesses), in [0, 1]
+///
+/// Stats are read through a single streaming cursor (on the read endpoint, if
+/// configured), which sees one consistent snapshot for its whole lifetime, so
+/// edges written by concurrent ingestion are never partially counted. Every
+/// document records that snapshot: `snapshot_unix_ms` (when it was taken) and
+/// `watermark_unix_ms` (latest done ingest file at that time).
+///
+/// With `dry_run` the stats are computed but only counted.
+pub async fn compute_hotspot_stats(db: &DB, dry_run: bool) -> Result<()> {
+    let snapshot_unix_ms = now_unix_ms();
+    let watermark_unix_ms = db.get_done_watermark().await?;
+    let aql = AqlQuery::builder()
+        .query(HOTSPOT_STATS_QUERY)
+        .bind_var("@hotspots", db.names.hotspots.as_str())
+        .bind_var("@witnesses", db.names.witnesses.as_str())
+        .bind_var("snapshot_unix_ms", snapshot_unix_ms)
+        .bind_var("watermark_unix_ms", watermark_unix_ms)
+        .batch_size(STATS_BATCH_SIZE)
+        .options(AqlOptions::builder().stream(true).build())
+        .build();
+
+    let written = stream_hotspot_stats(db, aql, dry_run).await?;
+    tracing::info!(
+        "{} {} hotspot stats, snapshot: {}, watermark: {:?}",
+        wrote(dry_run),
+        written,
+        snapshot_unix_ms,
+        watermark_unix_ms
+    );
+    Ok(())
+}
+
+/// Compute pairwise coverage overlap scores between hotspots sharing a parent hex
+/// into the hotspot_stats collection.
+///
+/// For every other hotspot in its parent hex, a hotspot's `hex_overlaps` list holds
+/// (highest score first, only pairs with any overlap):
+/// - `witness_overlap`: jaccard index of the sets of hotspots witnessing each one's beacons
+/// - `footprint_overlap`: jaccard index of the sets of parent hexes those witnesses are in
+/// - `score`: mean of the two, in [0, 1]
+///
+/// and `hex_overlap_max` the highest score. Hotspots which (nearly) duplicate each
+/// other's coverage add little to the network, and whole clusters of them are a
+/// common sign of spoofing. Read from a single snapshot like `compute_hotspot_stats`,
+/// with `dry_run` only counted.
+pub async fn compute_hotspot_overlaps(db: &DB, dry_run: bool) -> Result<()> {
+    let snapshot_unix_ms = now_unix_ms();
+    let aql = AqlQuery::builder()
+        .query(HOTSPOT_OVERLAPS_QUERY)
+        .bind_var("@hotspots", db.names.hotspots.as_str())
+        .bind_var("@witnesses", db.names.witnesses.as_str())
+        .bind_var("snapshot_unix_ms", snapshot_unix_ms)
+        .batch_size(STATS_BATCH_SIZE)
+        .options(AqlOptions::builder().stream(true).build())
+        .build();
+
+    let written = stream_hotspot_stats(db, aql, dry_run).await?;
+    tracing::info!(
+        "{} {} hotspot overlaps, snapshot: {}",
+        wrote(dry_run),
+        written,
+        snapshot_unix_ms
+    );
+    Ok(())
+}
+
+/// "wrote", or "would write" on a dry run, for the stats logs
+fn wrote(dry_run: bool) -> &'static str {
+    if dry_run {
+        "dry run: would write"
+    } else {
+        "wrote"
+    }
+}
+
+/// Run a streaming stats query on the reader, upserting each result batch
+/// into hotspot_stats (unless `dry_run`). Returns the number of written documents.
+async fn stream_hotspot_stats(db: &DB, aql: AqlQuery<'_>, dry_run: bool) -> Result<usize> {
+    // NOTE: the cursor lives on the reader it was created on
+    let reader = db.reader();
+    let mut cursor = reader.aql_query_batch::<Value>(aql).await?;
+    let mut written = 0;
+    loop {
+        written += cursor.result.len();
+        if !dry_run {
+            write_hotspot_stats(db, cursor.result).await?;
+        }
+        match (cursor.more, cursor.id) {
+            (true, Some(id)) => cursor = reader.aql_next_batch(&id).await?,
+            _ => break,
+        }
+    }
+    Ok(written)
+}
+
+async fn write_hotspot_stats(db: &DB, stats: Vec<Value>) -> Result<()> {
+    if stats.is_empty() {
+        return Ok(());
+    }
+    let query = r#"
+        FOR s IN @stats
+            UPSERT { _key: s._key }
+            INSERT s
+            UPDATE s
+            IN @@hotspot_stats"#;
+    let aql = AqlQuery::builder()
+        .query(query)
+        .bind_var("@hotspot_stats", db.names.hotspot_stats.as_str())
+        .bind_var("stats", Value::Array(stats))
+        .build();
+
+    db.inner().aql_query::<Value>(aql).await?;
+    Ok(())
+}
+
+/// Running statistics of the intervals between a hotspot's consecutive beacons,
+/// folded one beacon at a time (Welford's algorithm)
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct BeaconIntervals {
+    /// Number of intervals
+    pub count: u64,
+    pub mean_secs: f64,
+    pub stddev_secs: f64,
+    /// Sum of squared deviations from the mean, kept to fold in later beacons
+    pub m2: f64,
+    /// Coefficient of variation (stddev / mean), high for off-schedule hotspots
+    pub cv: f64,
+    pub hist: BTreeMap<String, u64>,
+    pub last_beacon_unix_ms: Option<i64>,
+}
+
+impl BeaconIntervals {
+    /// Fold in a beacon, which must not be older than the last one
+    pub fn add(&mut self, beacon_unix_ms: i64) {
+        let Some(last) = self.last_beacon_unix_ms.replace(beacon_unix_ms) else {
+            return;
+        };
+        let secs = (beacon_unix_ms - last) as f64 / 1000.0;
+        self.count += 1;
+        let delta = secs - self.mean_secs;
+        self.mean_secs += delta / self.count as f64;
+        self.m2 += delta * (secs - self.mean_secs);
+        self.stddev_secs = (self.m2 / self.count as f64).sqrt();
+        self.cv = if self.mean_secs > 0.0 {
+            self.stddev_secs / self.mean_secs
+        } else {
+            0.0
+        };
+        let bucket = INTERVAL_BUCKETS
+            .iter()
+            .find(|(le, _)| secs <= *le)
+            .map_or("gt_24h", |(_, label)| label);
+        *self.hist.entry(bucket.to_string()).or_default() += 1;
+    }
+}
+
+/// Fold the beacons ingested since the last run into each hotspot's
+/// `beacon_interval` in hotspot_stats.
+///
+/// Beacons up to the latest done ingest file are read in ingest time order from
+/// the beacons collection, after the `beacon_intervals` checkpoint, which then
+/// advances. Beacons ingested later with an older ingest time are skipped, so
+/// the fold never sees a negative interval.
+///
+/// With `dry_run` the intervals are folded but neither they nor the checkpoint
+/// are written.
+pub async fn compute_beacon_intervals(db: &DB, dry_run: bool) -> Result<()> {
+    let Some(before) = db.get_done_watermark().await? else {
+        tracing::info!("no done ingest files, skipping beacon intervals");
+        return Ok(());
+    };
+    let after = db
+        .get_checkpoint(BEACON_INTERVALS_CHECKPOINT)
+        .await?
+        .map_or(0, |checkpoint| checkpoint.watermark_unix_ms);
+    if after >= before {
+        return Ok(());
+    }
+
+    let query = r#"
+        FOR b IN @@beacons
+            FILTER b.ingest_time_unix_ms > @after && b.ingest_time_unix_ms <= @before
+            SORT b.ingest_time_unix_ms
+            RETURN [b.pub_key, b.ingest_time_unix_ms]"#;
+    let aql = AqlQuery::builder()
+        .query(query)
+        .bind_var("@beacons", db.names.beacons.as_str())
+        .bind_var("after", after)
+        .bind_var("before", before)
+        .batch_size(STATS_BATCH_SIZE)
+        .options(AqlOptions::builder().stream(true).build())
+        .build();
+    let reader = db.reader();
+    let mut cursor = reader
+        .aql_query_batch::<(PublicKeyBinary, i64)>(aql)
+        .await?;
+    let mut beacons: HashMap<String, Vec<i64>> = HashMap::new();
+    loop {
+        for (pub_key, ingest_time_unix_ms) in cursor.result {
+            beacons
+                .entry(db.hotspot_key(&pub_key))
+                .or_default()
+                .push(ingest_time_unix_ms);
+        }
+        match (cursor.more, cursor.id) {
+            (true, Some(id)) => cursor = reader.aql_next_batch(&id).await?,
+            _ => break,
+        }
+    }
+
+    let keys: Vec<String> = beacons.keys().cloned().collect();
+    for chunk in keys.chunks(STATS_BATCH_SIZE as usize) {
+        let mut intervals = get_beacon_intervals(db, chunk).await?;
+        let stats = chunk
+            .iter()
+            .map(|key| {
+                let intervals = intervals.entry(key.clone()).or_default();
+                for ts in &beacons[key] {
+                    intervals.add(*ts);
+                }
+                json!({
+                    "_key": key,
+                    "beacon_interval": intervals,
+                    "beacon_interval_updated_unix_ms": now_unix_ms(),
+                })
+            })
+            .collect();
+        if !dry_run {
+            write_hotspot_stats(db, stats).await?;
+        }
+    }
+    if dry_run {
+        tracing::info!(
+            "dry run: would fold beacons of {} hotspots into beacon intervals, up to {}",
+            keys.len(),
+            before
+        );
+        return Ok(());
+    }
+
+    let watermark = Utc
+        .timestamp_millis_opt(before)
+        .single()
+        .context("invalid done watermark")?;
+    db.set_checkpoint(&Checkpoint::new(BEACON_INTERVALS_CHECKPOINT, watermark))
+        .await?;
+    tracing::info!(
+        "folded beacons of {} hotspots into beacon intervals, up to {}",
+        keys.len(),
+        before
+    );
+    Ok(())
+}
+
+/// Stored beacon intervals of the given hotspots, missing ones are left out
+async fn get_beacon_intervals(
+    db: &DB,
+    keys: &[String],
+) -> Result<HashMap<String, BeaconIntervals>> {
+    let query = r#"
+        FOR s IN @@hotspot_stats
+            FILTER s._key IN @keys && s.beacon_interval != null
+            RETURN [s._key, s.beacon_interval]"#;
+    let aql = AqlQuery::builder()
+        .query(query)
+        .bind_var("@hotspot_stats", db.names.hotspot_stats.as_str())
+        .bind_var("keys", keys.to_vec())
+        .build();
+    let stored: Vec<(String, BeaconIntervals)> = db.inner().aql_query(aql).await?;
+    Ok(stored.into_iter().collect())
+}
+
+/// Name of the built-in edge weight maintenance script
+pub const EDGE_WEIGHT_SCRIPT: &str = "edge_weight";
+
+/// AQL refreshing the `weight` of every witness edge from the configured formula,
+/// normalized by the max across all edges so weights are in [0, 1].
+///
+/// The formula is an AQL expression over `count`, `age_days` (since the edge was
+/// last seen) and `distance` (km). It is operator supplied config, like
+/// maintenance scripts, so it is inlined into the query as is, as is the name of
+/// the `witnesses` collection.
+pub fn edge_weight_query(formula: &str, witnesses: &str) -> String {
+    format!(
+        r#"
+        LET now = DATE_NOW()
+        LET raw = (
+            FOR e IN {witnesses}
+                LET count = e.count
+                LET distance = e.distance
+                LET age_days = (now - e.last_seen_unix_ms) / 86400000
+                RETURN {{ _key: e._key, weight: ({formula}) }}
+        )
+        LET max_weight = MAX(raw[*].weight)
+        FOR r IN raw
+            UPDATE r._key WITH {{
+                weight: max_weight > 0 ? r.weight / max_weight : 0,
+                weight_updated_unix_ms: now
+            }} IN {witnesses}"#
+    )
+}
+
+/// Edge weight refresh as a maintenance script, so it is scheduled and tracked
+/// like any other
+pub fn edge_weight_script(
+    settings: &EdgeWeightSettings,
+    names: &CollectionNames,
+) -> MaintenanceScript {
+    MaintenanceScript {
+        name: EDGE_WEIGHT_SCRIPT.to_string(),
+        schedule: settings.schedule.clone(),
+        query: edge_weight_query(&settings.formula, &names.witnesses),
+    }
+}
+
+/// Refresh the edge weights, with `dry_run` only count the edges it would update
+pub async fn compute_edge_weights(
+    db: &DB,
+    settings: &EdgeWeightSettings,
+    dry_run: bool,
+) -> Result<()> {
+    if dry_run {
+        let edges: Vec<usize> = db
+            .reader()
+            .aql_str(&format!("RETURN LENGTH({})", db.names.witnesses))
+            .await?;
+        tracing::info!(
+            "dry run: would refresh the weights of {} witness edges",
+            edges.first().copied().unwrap_or_default()
+        );
+        return Ok(());
+    }
+    db.run_aql(&edge_weight_query(&settings.formula, &db.names.witnesses))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn beacon_intervals() {
+        let mut intervals = BeaconIntervals::default();
+        intervals.add(0);
+        assert_eq!(intervals.count, 0);
+        // 4 intervals of 10m, 20m, 30m and 2 days
+        for ts in [600_000, 1_800_000, 3_600_000, 176_400_000] {
+            intervals.add(ts);
+        }
+        assert_eq!(intervals.count, 4);
+        assert_eq!(intervals.mean_secs, 44_100.0);
+        let mean_sq =
+            (600f64.powi(2) + 1200f64.powi(2) + 1800f64.powi(2) + 172_800f64.powi(2)) / 4.0;
+        assert!((intervals.stddev_secs - (mean_sq - 44_100f64.powi(2)).sqrt()).abs() < 1e-6);
+        assert_eq!(intervals.last_beacon_unix_ms, Some(176_400_000));
+        assert_eq!(
+            intervals.hist,
+            BTreeMap::from([
+                ("le_15m".to_string(), 1),
+                ("le_30m".to_string(), 2),
+                ("gt_24h".to_string(), 1),
+            ])
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use crate::settings::{LogFormat, TelemetrySettings};
+use anyhow::Result;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use tracing_subscriber::{
+    fmt::{self, writer::BoxMakeWriter},
+    layer::SubscriberExt,
+    util::SubscriberInitExt,
+    EnvFilter,
+};
+
+/// Flushes exported spans when dropped, keep it for the life of the process
+#[must_use]
+pub struct Telemetry {
+    otlp: bool,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if self.otlp {
+            opentelemetry::global::shutdown_tracer_provider();
+        }
+    }
+}
+
+/// Install the global subscriber, filtered by `log` (an env filter directive).
+/// Only the binary calls this, library users set up their own subscriber.
+/// Must be called within a tokio runtime when exporting spans.
+pub fn init(log: &str, settings: &TelemetrySettings) -> Result<Telemetry> {
+    let otel = match &settings.otlp_endpoint {
+        Some(endpoint) => {
+            let tracer = opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(trace::config().with_resource(Resource::new(vec![
+                    KeyValue::new("service.name", settings.service_name.clone()),
+                ])))
+                .install_batch(runtime::Tokio)?;
+            Some(tracing_opentelemetry::layer().with_tracer(tracer))
+        }
+        None => None,
+    };
+    let writer = || {
+        if settings.stderr {
+            BoxMakeWriter::new(std::io::stderr)
+        } else {
+            BoxMakeWriter::new(std::io::stdout)
+        }
+    };
+    let (plain, json) = match settings.format {
+        LogFormat::Fmt => (Some(fmt::layer().with_writer(writer())), None),
+        LogFormat::Json => (None, Some(fmt::layer().json().with_writer(writer()))),
+    };
+    tracing_subscriber::registry()
+        .with(EnvFilter::new(log))
+        .with(plain)
+        .with(json)
+        .with(otel)
+        .try_init()?;
+    Ok(Telemetry {
+        otlp: settings.otlp_endpoint.is_some(),
+    })
+}
@@ -1,44 +1,474 @@
-use crate::{handler::ArangodbHandler, settings::Settings};
+use crate::{
+    diagnostics::{Diagnostics, SharedDiagnostics},
+    document::timestamp::unix_ms,
+    handler::{
+        arangodb::DB, live_feed::LiveFeed, notifications::Notified, ArangodbHandler, Notifications,
+    },
+    maintenance::{self, Maintenance},
+    settings::Settings,
+    stats,
+};
 use anyhow::{Context, Result};
 use chrono::{DateTime, Duration, Utc};
+use std::sync::{atomic::AtomicBool, Arc};
 use tokio::time;
 use tokio_graceful_shutdown::SubsystemHandle;
+use tokio_util::sync::CancellationToken;
+
+/// Checkpoint the tracker persists its after timestamp in
+pub const CURSOR_CHECKPOINT: &str = "current";
+
+/// Checkpoint of a tracker, bounded runs keep their own so they don't move the
+/// cursor of an unbounded tracker on the same database
+pub fn cursor_checkpoint(before_utc: Option<DateTime<Utc>>) -> String {
+    match before_utc {
+        Some(before_utc) => format!("{CURSOR_CHECKPOINT}_before_{}", unix_ms(before_utc)),
+        None => CURSOR_CHECKPOINT.to_string(),
+    }
+}
 
 pub struct Tracker {
     after_utc: DateTime<Utc>,
+    // bound of a catch-up run, None to keep following new files
+    before_utc: Option<DateTime<Utc>>,
+    // set once all files up to before_utc are processed
+    finished: bool,
+    cursor: String,
     interval_duration: Duration,
     arangodb_handler: ArangodbHandler,
+    // None with a sink other than ArangoDB
+    maintenance: Option<Maintenance>,
+    diagnostics: SharedDiagnostics,
+    notifications: Option<Notifications>,
+    // whether to wait for notifications rather than poll on the interval,
+    // cleared when receiving fails until the next poll
+    notifications_healthy: bool,
 }
 
 impl Tracker {
-    pub async fn new(settings: &Settings, after_utc: DateTime<Utc>) -> Result<Self> {
+    /// Create a tracker resuming from its persisted cursor, or starting from
+    /// `after_utc` if there is none (or `resume` is false)
+    pub async fn new(settings: &Settings, after_utc: DateTime<Utc>, resume: bool) -> Result<Self> {
+        let before_utc = settings.current.before_utc();
+        if let Some(before_utc) = before_utc {
+            if after_utc > before_utc {
+                anyhow::bail!("after ({after_utc}) is later than before ({before_utc})");
+            }
+            tracing::info!("catching up until {:?}", before_utc);
+        }
         let arangodb_handler = ArangodbHandler::new(settings).await?;
+        let cursor = cursor_checkpoint(before_utc);
+        let resumed = if resume {
+            arangodb_handler.get_watermark(&cursor).await?
+        } else {
+            None
+        };
+        let after_utc = match resumed {
+            Some(cursor) => {
+                tracing::info!("resuming from persisted cursor {:?}", cursor);
+                cursor
+            }
+            None => after_utc,
+        };
+        let after_utc = guard_after(settings, &arangodb_handler, after_utc).await?;
+        arangodb_handler.recover_stale_files().await?;
+        let mut scripts = settings.maintenance.clone();
+        if let Some(edge_weight) = &settings.edge_weight {
+            scripts.push(stats::edge_weight_script(
+                edge_weight,
+                &settings.arangodb.collections,
+            ));
+        }
+        if let Some(retention) = &settings.arangodb.retention {
+            scripts.extend(maintenance::edge_retention_script(
+                retention,
+                &settings.arangodb.collections,
+            ));
+            scripts.extend(maintenance::geo_prune_script(
+                retention,
+                &settings.arangodb.collections,
+            ));
+        }
+        // NOTE: maintenance scripts write, a dry run skips them
+        let maintenance = match arangodb_handler.db().filter(|_| !settings.dry_run) {
+            Some(db) => Some(Maintenance::new(db, &scripts)?),
+            None => None,
+        };
+        let notifications = match &settings.current.notifications {
+            Some(_) if before_utc.is_some() => {
+                tracing::warn!("ignoring notifications of a bounded catch-up run");
+                None
+            }
+            // consuming would take the notifications from the real deployment
+            Some(_) if settings.dry_run => {
+                tracing::info!("dry run: listing the bucket instead of consuming notifications");
+                None
+            }
+            Some(notification_settings) => Some(
+                Notifications::from_settings(
+                    notification_settings,
+                    &settings
+                        .ingest
+                        .primary()
+                        .context("notifications require an ingest bucket")?
+                        .region,
+                    arangodb_handler.file_types(),
+                )
+                .await?,
+            ),
+            None => None,
+        };
         Ok(Self {
             interval_duration: settings.interval(),
             after_utc,
+            before_utc,
+            finished: false,
+            cursor,
             arangodb_handler,
+            maintenance,
+            diagnostics: Diagnostics::new(after_utc, settings.tracker.interval),
+            notifications,
+            notifications_healthy: false,
         })
     }
+
+    pub fn diagnostics(&self) -> SharedDiagnostics {
+        self.diagnostics.clone()
+    }
+
+    /// The ArangoDB sink, None if ingesting into another sink
+    pub fn db(&self) -> Option<Arc<DB>> {
+        self.arangodb_handler.db()
+    }
+
+    /// The WebSocket feed of stored beacons, see `ArangodbHandler::live_feed`
+    pub fn live_feed(&self) -> Option<Arc<LiveFeed>> {
+        self.arangodb_handler.live_feed()
+    }
+
+    /// Maintenance mode switch of the handler, see `ArangodbHandler::pause_switch`
+    pub fn pause_switch(&self) -> Arc<AtomicBool> {
+        self.arangodb_handler.pause_switch()
+    }
+
+    fn update_diagnostics(&self, update: impl FnOnce(&mut Diagnostics)) {
+        match self.diagnostics.lock() {
+            Ok(mut diagnostics) => update(&mut diagnostics),
+            Err(poisoned) => update(&mut poisoned.into_inner()),
+        }
+    }
+
+    /// Poll for files after the current after timestamp
+    async fn tick(&mut self) -> Result<()> {
+        if self.arangodb_handler.is_paused() {
+            tracing::debug!("maintenance mode, skipping tick");
+            return Ok(());
+        }
+        if !self.connected().await {
+            return Ok(());
+        }
+        self.update_diagnostics(|d| d.tick_started_at = Some(Utc::now()));
+        let summary = self
+            .arangodb_handler
+            .process(self.after_utc, self.before_utc)
+            .await?;
+        if let Err(err) = self.arangodb_handler.publish_summary(&summary).await {
+            tracing::error!("error publishing tick summary: {:?}", err);
+        }
+        let max_ts = summary.watermark;
+        let next_utc = self
+            .after_utc
+            .checked_add_signed(self.interval_duration)
+            .context("failed to add interval")?;
+        tracing::info!("start processing next tick @ {:?}", next_utc);
+        self.after_utc = max_ts;
+        // NOTE: every file up to the bound was listed, so it is reached unless
+        // some of them failed or were skipped
+        self.finished =
+            self.before_utc.is_some() && summary.files_failed == 0 && summary.files_skipped == 0;
+        let poc_id_stream = self.arangodb_handler.stream_metrics();
+        let shadow_verify = self.arangodb_handler.shadow_metrics();
+        let ingest_counts = self.arangodb_handler.ingest_counts();
+        self.update_diagnostics(|d| {
+            d.ticks += 1;
+            d.tick_started_at = None;
+            d.last_tick_at = Some(Utc::now());
+            d.after_utc = max_ts;
+            d.last_summary = Some(summary);
+            d.poc_id_stream = poc_id_stream;
+            d.shadow_verify = shadow_verify;
+            d.ingest_counts = ingest_counts;
+        });
+        tracing::info!("scheduling next tick @ {:?} for ts: {:?}", next_utc, max_ts);
+        self.save_cursor().await;
+        self.sweep_stale_files().await;
+        self.run_maintenance().await;
+        Ok(())
+    }
+
+    /// Process files announced by notifications. Messages are only acked if all
+    /// their files were processed, failed files are retried on redelivery.
+    ///
+    /// The after timestamp is left to polling: notifications arrive out of order
+    /// or get lost, so advancing it to their files would skip the earlier files
+    /// of late or lost notifications when falling back to polling.
+    async fn handle_notified(&mut self, notified: Notified) -> Result<()> {
+        if notified.file_infos.is_empty() {
+            return self.ack(notified).await;
+        }
+        if !self.connected().await {
+            // NOTE: unacked notifications are redelivered
+            return Ok(());
+        }
+        self.update_diagnostics(|d| d.tick_started_at = Some(Utc::now()));
+        let summary = self
+            .arangodb_handler
+            .process_notified(notified.file_infos.clone())
+            .await?;
+        if let Err(err) = self.arangodb_handler.publish_summary(&summary).await {
+            tracing::error!("error publishing tick summary: {:?}", err);
+        }
+        if summary.files_failed == 0 && summary.files_skipped == 0 {
+            self.ack(notified).await?;
+        } else {
+            tracing::warn!(
+                "{} notified files failed, {} skipped, leaving notifications for redelivery",
+                summary.files_failed,
+                summary.files_skipped
+            );
+        }
+        let poc_id_stream = self.arangodb_handler.stream_metrics();
+        let shadow_verify = self.arangodb_handler.shadow_metrics();
+        let ingest_counts = self.arangodb_handler.ingest_counts();
+        self.update_diagnostics(|d| {
+            d.ticks += 1;
+            d.tick_started_at = None;
+            d.last_tick_at = Some(Utc::now());
+            d.last_summary = Some(summary);
+            d.poc_id_stream = poc_id_stream;
+            d.shadow_verify = shadow_verify;
+            d.ingest_counts = ingest_counts;
+        });
+        self.sweep_stale_files().await;
+        self.run_maintenance().await;
+        Ok(())
+    }
+
+    async fn ack(&self, notified: Notified) -> Result<()> {
+        match &self.notifications {
+            Some(notifications) => notifications.ack(notified).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the database is reachable, reconnecting if needed. While it isn't
+    /// ticks are skipped rather than failing all their files.
+    async fn connected(&self) -> bool {
+        let Some(db) = self.arangodb_handler.db() else {
+            return true;
+        };
+        match db.ensure_connected().await {
+            Ok(()) => true,
+            Err(err) => {
+                tracing::warn!("database unavailable, skipping: {:?}", err);
+                false
+            }
+        }
+    }
+
+    /// Persist the after timestamp, so a restart resumes from it
+    async fn save_cursor(&self) {
+        if let Err(err) = self
+            .arangodb_handler
+            .set_watermark(&self.cursor, self.after_utc)
+            .await
+        {
+            tracing::error!("error persisting cursor: {:?}", err);
+        }
+    }
+
+    /// Reset files abandoned by crashed workers, e.g. of other trackers on the
+    /// same database
+    async fn sweep_stale_files(&self) {
+        if let Err(err) = self.arangodb_handler.recover_stale_files().await {
+            tracing::error!("error resetting stale files: {:?}", err);
+        }
+    }
+
+    async fn run_maintenance(&self) {
+        let Some(maintenance) = &self.maintenance else {
+            return;
+        };
+        if self.arangodb_handler.shutdown_token().is_cancelled()
+            || self.arangodb_handler.is_paused()
+        {
+            return;
+        }
+        if let Err(err) = maintenance.run_due(Utc::now()).await {
+            tracing::error!("error running maintenance scripts: {:?}", err);
+        }
+    }
+}
+
+/// Check the starting after timestamp against the local clock and, for
+/// unbounded runs with `max_lag_hours`, the newest ingest file
+async fn guard_after(
+    settings: &Settings,
+    handler: &ArangodbHandler,
+    after_utc: DateTime<Utc>,
+) -> Result<DateTime<Utc>> {
+    let now = Utc::now();
+    let current = &settings.current;
+    let lag = match current.max_lag_hours {
+        Some(hours) if current.before.is_none() => {
+            let lag = Duration::hours(hours);
+            // NOTE: only files within the lag are listed, an older newest file
+            // means ingestion stalled upstream rather than a skewed watermark
+            let newest = if after_utc < now - lag {
+                handler.newest_file(now - lag).await?
+            } else {
+                None
+            };
+            newest.map(|newest| (newest, lag))
+        }
+        _ => None,
+    };
+    let bounded = bound_after(
+        after_utc,
+        now,
+        Duration::seconds(current.max_ahead_secs),
+        lag,
+    );
+    match bounded {
+        Ok(after_utc) => Ok(after_utc),
+        Err((bound, problem)) if current.clamp_after => {
+            tracing::warn!("{problem}, clamping it to {bound}");
+            Ok(bound)
+        }
+        Err((_, problem)) => anyhow::bail!(
+            "{problem}; fix the clock or reposition the cursor with `watermark set --name {}`, \
+             start from current.after with --ignore-cursor, or set current.clamp_after",
+            cursor_checkpoint(current.before_utc())
+        ),
+    }
 }
 
-pub async fn run(mut tracker: Tracker, subsys: SubsystemHandle) -> Result<()> {
+/// `after_utc` if it is at most `max_ahead` ahead of `now` and lags the newest
+/// file by at most the given duration, else the bound it crossed and the problem
+fn bound_after(
+    after_utc: DateTime<Utc>,
+    now: DateTime<Utc>,
+    max_ahead: Duration,
+    lag: Option<(DateTime<Utc>, Duration)>,
+) -> std::result::Result<DateTime<Utc>, (DateTime<Utc>, String)> {
+    if after_utc > now + max_ahead {
+        return Err((
+            now,
+            format!(
+                "after timestamp {after_utc} is more than {}s ahead of the local clock ({now})",
+                max_ahead.num_seconds()
+            ),
+        ));
+    }
+    match lag {
+        Some((newest, lag)) if newest - after_utc > lag => Err((
+            newest - lag,
+            format!(
+                "after timestamp {after_utc} lags the newest file ({newest}) by more than {} hours",
+                lag.num_hours()
+            ),
+        )),
+        _ => Ok(after_utc),
+    }
+}
+
+async fn receive(notifications: &Option<Notifications>) -> Result<Notified> {
+    match notifications {
+        Some(notifications) => notifications.receive().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Run the tracker until shutdown, or until a bounded run reached its before
+/// timestamp, which shuts the process down. On shutdown the current tick (or
+/// notification batch) drains: its in-flight files are finished and the cursor
+/// saved, files not started yet are left for the next start.
+pub async fn run(tracker: Tracker, subsys: SubsystemHandle) -> Result<()> {
+    let shutdown = tracker.arangodb_handler.shutdown_token();
+    let drain_on_shutdown = async {
+        subsys.on_shutdown_requested().await;
+        tracing::info!("shutdown requested, draining in-flight files");
+        shutdown.cancel();
+        std::future::pending().await
+    };
+    tokio::select! {
+        res = run_until(tracker, &shutdown) => {
+            if !shutdown.is_cancelled() {
+                subsys.request_shutdown();
+            }
+            res
+        }
+        res = drain_on_shutdown => res,
+    }
+}
+
+async fn run_until(mut tracker: Tracker, shutdown: &CancellationToken) -> Result<()> {
     let mut trigger = time::interval(tracker.interval_duration.to_std()?);
 
     loop {
+        // NOTE: in maintenance mode notifications are left queued and the trigger
+        // keeps (skipped) ticking to notice when it is left
+        let paused = tracker.arangodb_handler.is_paused();
         tokio::select! {
-            _ = subsys.on_shutdown_requested() => {
-                subsys.request_shutdown();
-                break;
+            _ = shutdown.cancelled() => break,
+            // NOTE: with notifications this is the initial catch up from the after
+            // timestamp, and the fallback while notifications are unavailable
+            _ = trigger.tick(), if !tracker.notifications_healthy || paused => {
+                tracker.tick().await?;
+                tracker.notifications_healthy = tracker.notifications.is_some();
+                if tracker.finished {
+                    tracing::info!("reached before {:?}", tracker.before_utc);
+                    break;
+                }
             }
-            _ = trigger.tick() => {
-                let max_ts = tracker.arangodb_handler.process(tracker.after_utc, None).await?;
-                let next_utc = tracker.after_utc.checked_add_signed(tracker.interval_duration).context("failed to add interval")?;
-                tracing::info!("start processing next tick @ {:?}", next_utc);
-                tracker.after_utc = max_ts;
-                tracing::info!("scheduling next tick @ {:?} for ts: {:?}", next_utc, max_ts);
+            notified = receive(&tracker.notifications), if tracker.notifications_healthy && !paused => {
+                match notified {
+                    Ok(notified) => tracker.handle_notified(notified).await?,
+                    Err(err) => {
+                        tracing::warn!("notifications unavailable, falling back to polling: {:?}", err);
+                        tracker.notifications_healthy = false;
+                    }
+                }
             }
         }
     }
     tracing::info!("stopping current tracker for {:?}", tracker.after_utc);
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn ts(ts_millis: i64) -> DateTime<Utc> {
+        Utc.timestamp_millis_opt(ts_millis).unwrap()
+    }
+
+    #[test]
+    fn after_bounds() {
+        let now = ts(10_000);
+        let ahead = Duration::seconds(1);
+        assert_eq!(bound_after(ts(9_000), now, ahead, None), Ok(ts(9_000)));
+        assert_eq!(bound_after(ts(11_000), now, ahead, None), Ok(ts(11_000)));
+        let (bound, problem) = bound_after(ts(11_001), now, ahead, None).unwrap_err();
+        assert_eq!(bound, now);
+        assert!(problem.contains("ahead of the local clock"));
+
+        let lag = Some((ts(8_000), Duration::seconds(5)));
+        assert_eq!(bound_after(ts(3_000), now, ahead, lag), Ok(ts(3_000)));
+        let (bound, problem) = bound_after(ts(2_000), now, ahead, lag).unwrap_err();
+        assert_eq!(bound, ts(3_000));
+        assert!(problem.contains("lags the newest file"));
+    }
+}
@@ -0,0 +1,100 @@
+use crate::document::{iot_poc_file::IotPocFile, timestamp::unix_ms};
+use chrono::{DateTime, Utc};
+use file_store::FileInfo;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Result of comparing the bucket's files in a time range against the files collection
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VerificationReport {
+    pub after: DateTime<Utc>,
+    pub before: DateTime<Utc>,
+    pub verified_unix_ms: i64,
+    /// Number of files in the bucket
+    pub files: usize,
+    pub done: usize,
+    /// Files without a record
+    pub missing: Vec<String>,
+    /// Files which are not done but may still be retried
+    pub incomplete: Vec<String>,
+    /// Files which are not done and have exhausted their retries
+    pub retry_exhausted: Vec<String>,
+}
+
+impl VerificationReport {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.incomplete.is_empty() && self.retry_exhausted.is_empty()
+    }
+}
+
+pub fn verify(
+    after: DateTime<Utc>,
+    before: DateTime<Utc>,
+    file_infos: &[FileInfo],
+    records: Vec<IotPocFile>,
+    max_retries: u8,
+) -> VerificationReport {
+    let records: HashMap<String, IotPocFile> = records
+        .into_iter()
+        .map(|record| (record._key.clone(), record))
+        .collect();
+    let mut report = VerificationReport {
+        after,
+        before,
+        verified_unix_ms: unix_ms(Utc::now()),
+        files: file_infos.len(),
+        done: 0,
+        missing: vec![],
+        incomplete: vec![],
+        retry_exhausted: vec![],
+    };
+    for file_info in file_infos {
+        let key = file_info.key.clone();
+        match records.get(&key) {
+            None => report.missing.push(key),
+            Some(record) if record.done => report.done += 1,
+            // NOTE: matches the handler, a file is retried while retries <= max_retries
+            Some(record) if record.retries > max_retries => report.retry_exhausted.push(key),
+            Some(_) => report.incomplete.push(key),
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+    use file_store::FileType;
+    use std::str::FromStr;
+
+    fn file_info(ts_millis: i64) -> FileInfo {
+        FileInfo::from_str(&format!("{}.{}.gz", FileType::IotPoc, ts_millis)).unwrap()
+    }
+
+    fn record(file_info: &FileInfo, done: bool, retries: u8) -> IotPocFile {
+        let mut record = IotPocFile::from(file_info);
+        record.done = done;
+        record.retries = retries;
+        record
+    }
+
+    #[test]
+    fn classify_files() {
+        let files: Vec<FileInfo> = (1..=4).map(|n| file_info(n * 1_000)).collect();
+        let records = vec![
+            record(&files[0], true, 0),
+            record(&files[1], false, 1),
+            record(&files[2], false, 4),
+        ];
+        let ts = Utc.timestamp_millis_opt(0).unwrap();
+        let report = verify(ts, ts, &files, records, 3);
+
+        assert_eq!(report.files, 4);
+        assert_eq!(report.done, 1);
+        assert_eq!(report.incomplete, vec![files[1].key.clone()]);
+        assert_eq!(report.retry_exhausted, vec![files[2].key.clone()]);
+        assert_eq!(report.missing, vec![files[3].key.clone()]);
+        assert!(!report.is_ok());
+    }
+}
@@ -0,0 +1,85 @@
+//! Asserts a dry run of a chunk only reads: its beacon, hotspots, witness edge
+//! and dead letter are staged but nothing is written.
+//!
+//! Runs against the ArangoDB at `ARANGODB_TEST_ENDPOINT` (credentials from
+//! `ARANGODB_TEST_USER` / `ARANGODB_TEST_PASSWORD`, default root/arangodb) in
+//! a throwaway database, and is skipped if that is unset.
+
+use arango_etl::{
+    doctor,
+    document::source::Source,
+    handler::{arangodb::DB, sink::Sink},
+    settings::ArangoDBSettings,
+};
+use bytes::BytesMut;
+use chrono::Utc;
+use file_store::{FileInfo, FileType};
+use serde_json::json;
+use std::str::FromStr;
+
+fn settings(endpoint: String) -> ArangoDBSettings {
+    let user = std::env::var("ARANGODB_TEST_USER").unwrap_or_else(|_| "root".to_string());
+    let password =
+        std::env::var("ARANGODB_TEST_PASSWORD").unwrap_or_else(|_| "arangodb".to_string());
+    serde_json::from_value(json!({
+        "endpoint": endpoint,
+        "user": user,
+        "password": password,
+        "database": format!("dry_run_{}", Utc::now().timestamp_millis()),
+        // dead-letter the sample beacon
+        "max_document_size": 1,
+        "overflow_strategy": "dead_letter",
+    }))
+    .expect("test settings")
+}
+
+#[tokio::test]
+async fn dry_run_chunk_writes_nothing() {
+    let Ok(endpoint) = std::env::var("ARANGODB_TEST_ENDPOINT") else {
+        eprintln!("ARANGODB_TEST_ENDPOINT not set, skipping dry run tests");
+        return;
+    };
+    let settings = settings(endpoint);
+    let db = DB::from_settings(&settings).await.expect("test database");
+
+    let file = FileInfo::from_str("iot_poc.1687888130980.gz").unwrap();
+    let source = Source::new(&file, "LoraPocV1");
+    let raw = doctor::sample_poc().unwrap();
+    let written = db
+        .dry_run_chunk(
+            FileType::IotPoc,
+            vec![BytesMut::from(raw.as_slice())],
+            &source,
+        )
+        .await
+        .expect("dry run chunk");
+    assert_eq!(written.decode_errors, 0);
+    // both hotspots would be new
+    assert_eq!(written.populated[0].new_hotspots.len(), 2);
+
+    let names = &settings.collections;
+    let inner = db.handles().inner;
+    let mut written_to = vec![];
+    for collection in [
+        &names.beacons,
+        &names.hotspots,
+        &names.witnesses,
+        &names.dead_letters,
+        &names.hexes,
+        &names.raw_pocs,
+    ] {
+        let count: Vec<usize> = inner
+            .aql_str(&format!("RETURN LENGTH({collection})"))
+            .await
+            .expect("count documents");
+        if count != [0] {
+            written_to.push(collection.clone());
+        }
+    }
+
+    db.conn()
+        .drop_database(&settings.database)
+        .await
+        .expect("drop test database");
+    assert!(written_to.is_empty(), "dry run wrote to {written_to:?}");
+}
@@ -0,0 +1,181 @@
+//! Asserts our canonical queries are planned with the expected indexes, so a
+//! schema or query change which silently degrades one to a full collection
+//! scan fails the tests.
+//!
+//! Runs against the ArangoDB at `ARANGODB_TEST_ENDPOINT` (e.g. the
+//! docker-compose instance, credentials from `ARANGODB_TEST_USER` /
+//! `ARANGODB_TEST_PASSWORD`, default root/arangodb) in a throwaway database,
+//! and is skipped if that is unset.
+
+use arango_etl::{
+    handler::arangodb::{
+        edge_upsert_query, hotspot_update_query, hotspot_upsert_query, purge_query, PurgeTarget,
+        DB, DONE_FILE_KEYS_QUERY, KEYS_EXIST_QUERY,
+    },
+    maintenance::{edge_retention_script, geo_prune_script},
+    settings::{ArangoDBSettings, RetentionSettings},
+    stats::{HOTSPOT_OVERLAPS_QUERY, HOTSPOT_STATS_QUERY},
+};
+use chrono::Utc;
+use serde_json::{json, Value};
+
+struct Case {
+    name: &'static str,
+    query: String,
+    bind_vars: Value,
+    index: &'static str,
+}
+
+fn settings(endpoint: String) -> ArangoDBSettings {
+    let user = std::env::var("ARANGODB_TEST_USER").unwrap_or_else(|_| "root".to_string());
+    let password =
+        std::env::var("ARANGODB_TEST_PASSWORD").unwrap_or_else(|_| "arangodb".to_string());
+    serde_json::from_value(json!({
+        "endpoint": endpoint,
+        "user": user,
+        "password": password,
+        "database": format!("index_usage_{}", Utc::now().timestamp_millis()),
+    }))
+    .expect("test settings")
+}
+
+fn cases(settings: &ArangoDBSettings) -> Vec<Case> {
+    let names = &settings.collections;
+    let range = |collection: &str| json!({ "@collection": collection, "after": 0, "before": 1 });
+    let retention = RetentionSettings {
+        beacon_days: None,
+        file_days: None,
+        edge_days: Some(30),
+        edge_schedule: "@daily".to_string(),
+        geo_days: Some(30),
+        geo_schedule: "@daily".to_string(),
+    };
+    let edge_retention = edge_retention_script(&retention, names).expect("edge retention script");
+    let geo_prune = geo_prune_script(&retention, names).expect("geo prune script");
+    vec![
+        Case {
+            name: "done files",
+            query: DONE_FILE_KEYS_QUERY.to_string(),
+            bind_vars: json!({ "@collection": names.files, "done": true }),
+            index: "file_done",
+        },
+        Case {
+            name: "existing beacons",
+            query: KEYS_EXIST_QUERY.to_string(),
+            bind_vars: json!({ "@collection": names.beacons, "keys": ["poc"] }),
+            index: "primary",
+        },
+        Case {
+            name: "hotspot upsert",
+            query: hotspot_upsert_query(),
+            bind_vars: json!({
+                "@collection": names.hotspots,
+                "hotspots": [{
+                    "hotspot": { "_key": "hotspot", "poc_ids": ["poc"] },
+                    "location": null,
+                    "seen_unix_ms": 0,
+                }],
+            }),
+            index: "primary",
+        },
+        Case {
+            name: "hotspot update",
+            query: hotspot_update_query(),
+            bind_vars: json!({
+                "@collection": names.hotspots,
+                "hotspots": [{ "_key": "hotspot", "poc_ids": [] }],
+            }),
+            index: "primary",
+        },
+        Case {
+            name: "edge upsert",
+            query: edge_upsert_query(),
+            bind_vars: json!({
+                "@witness_edge_collection": names.witnesses,
+                "hotspot_collection": names.hotspots,
+                "edges": [{ "_key": "edge" }],
+                "window_keep": 1,
+            }),
+            index: "primary",
+        },
+        Case {
+            name: "hotspot stats",
+            query: HOTSPOT_STATS_QUERY.to_string(),
+            bind_vars: json!({
+                "@hotspots": names.hotspots,
+                "@witnesses": names.witnesses,
+                "snapshot_unix_ms": 0,
+                "watermark_unix_ms": null,
+            }),
+            index: "edge",
+        },
+        Case {
+            name: "hotspot overlaps",
+            query: HOTSPOT_OVERLAPS_QUERY.to_string(),
+            bind_vars: json!({
+                "@hotspots": names.hotspots,
+                "@witnesses": names.witnesses,
+                "snapshot_unix_ms": 0,
+            }),
+            index: "edge",
+        },
+        Case {
+            name: "purge beacons",
+            query: purge_query(PurgeTarget::Beacons, true),
+            bind_vars: range(&names.beacons),
+            index: "beacon_ingest_time_ms",
+        },
+        Case {
+            name: "purge orphaned edges",
+            query: purge_query(PurgeTarget::OrphanedEdges, true),
+            bind_vars: range(&names.witnesses),
+            index: "witness_last_seen_ms",
+        },
+        Case {
+            name: "purge files",
+            query: purge_query(PurgeTarget::Files, true),
+            bind_vars: range(&names.files),
+            index: "file_ts_ms",
+        },
+        Case {
+            name: "edge retention",
+            query: edge_retention.query,
+            bind_vars: json!({}),
+            index: "witness_last_seen_ms",
+        },
+        Case {
+            name: "geo prune",
+            query: geo_prune.query,
+            bind_vars: json!({}),
+            index: "beacon_ingest_time_ms",
+        },
+    ]
+}
+
+#[tokio::test]
+async fn canonical_queries_use_indexes() {
+    let Ok(endpoint) = std::env::var("ARANGODB_TEST_ENDPOINT") else {
+        eprintln!("ARANGODB_TEST_ENDPOINT not set, skipping index usage tests");
+        return;
+    };
+    let settings = settings(endpoint);
+    let db = DB::from_settings(&settings).await.expect("test database");
+
+    let mut failures = vec![];
+    for case in cases(&settings) {
+        match db.explain_indexes(&case.query, case.bind_vars).await {
+            Ok(indexes) if indexes.iter().any(|index| index == case.index) => {}
+            Ok(indexes) => failures.push(format!(
+                "{}: expected index {:?}, planned with {:?}",
+                case.name, case.index, indexes
+            )),
+            Err(err) => failures.push(format!("{}: {err:?}", case.name)),
+        }
+    }
+
+    db.conn()
+        .drop_database(&settings.database)
+        .await
+        .expect("drop test database");
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
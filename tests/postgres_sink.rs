@@ -0,0 +1,93 @@
+//! Writes chunks sharing hotspots concurrently through the postgres sink, so
+//! chunks locking the shared hotspot rows in different orders deadlock and
+//! fail the test.
+//!
+//! Runs against the postgres at `POSTGRES_TEST_URL` (e.g.
+//! `postgres://postgres@localhost:5432/iot`), creating the sink tables if
+//! missing, and is skipped if that is unset. Each run writes its own pocs and
+//! hotspots.
+#![cfg(feature = "postgres")]
+
+use arango_etl::{
+    doctor,
+    document::source::Source,
+    handler::{
+        postgres::PostgresSink,
+        sink::{PocTransforms, Sink},
+    },
+    settings::PostgresSettings,
+};
+use bytes::BytesMut;
+use chrono::Utc;
+use file_store::{FileInfo, FileType};
+use helium_proto::{services::poc_lora::LoraPocV1, Message};
+use serde_json::json;
+use std::str::FromStr;
+
+const HOTSPOTS: u8 = 4;
+const CHUNKS: u8 = 8;
+
+/// Binary ed25519 key of `hotspot`, unique to the `run`
+fn key(run: u64, hotspot: u8) -> Vec<u8> {
+    let mut key = vec![0x01];
+    key.extend(run.to_be_bytes());
+    key.extend([hotspot; 24]);
+    key
+}
+
+/// The sample poc with its id and hotspots unique to the `run`
+fn poc(run: u64, id: u8, beaconer: u8, witness: u8) -> BytesMut {
+    let raw = doctor::sample_poc().expect("sample poc");
+    let mut poc = LoraPocV1::decode(raw.as_slice()).expect("decode sample poc");
+    poc.poc_id = [run.to_be_bytes().to_vec(), vec![id; 24]].concat();
+    if let Some(report) = poc
+        .beacon_report
+        .as_mut()
+        .and_then(|beacon| beacon.report.as_mut())
+    {
+        report.pub_key = key(run, beaconer);
+    }
+    if let Some(report) = poc.selected_witnesses[0].report.as_mut() {
+        report.pub_key = key(run, witness);
+    }
+    BytesMut::from(poc.encode_to_vec().as_slice())
+}
+
+#[tokio::test]
+async fn concurrent_chunks_share_hotspots() {
+    let Ok(url) = std::env::var("POSTGRES_TEST_URL") else {
+        eprintln!("POSTGRES_TEST_URL not set, skipping postgres sink tests");
+        return;
+    };
+    let settings: PostgresSettings =
+        serde_json::from_value(json!({ "url": url })).expect("test settings");
+    let sink = PostgresSink::from_settings(&settings, PocTransforms::default())
+        .await
+        .expect("test sink");
+    let source = Source::new(
+        &FileInfo::from_str("iot_poc.1687888130980.gz").expect("file info"),
+        "LoraPocV1",
+    );
+    let run = Utc::now().timestamp_millis() as u64;
+
+    // every chunk sees all hotspots, each starting at a different one
+    let chunks = (0..CHUNKS).map(|chunk| {
+        (0..HOTSPOTS)
+            .map(|i| {
+                let beaconer = (chunk + i) % HOTSPOTS;
+                let witness = HOTSPOTS - 1 - beaconer;
+                poc(run, chunk * HOTSPOTS + i, beaconer, witness)
+            })
+            .collect::<Vec<_>>()
+    });
+    let written = futures::future::try_join_all(
+        chunks.map(|bufs| sink.write_chunk(FileType::IotPoc, bufs, &source)),
+    )
+    .await
+    .expect("concurrent chunks");
+
+    let populated: Vec<_> = written.iter().flat_map(|w| w.populated.iter()).collect();
+    assert_eq!(populated.len(), (CHUNKS * HOTSPOTS) as usize);
+    let first_seen: usize = populated.iter().map(|p| p.new_hotspots.len()).sum();
+    assert_eq!(first_seen, HOTSPOTS as usize);
+}